@@ -31,7 +31,11 @@ fn feature_conflicts() {
 
     // Database
     let db_sqlite = std::env::var("CARGO_FEATURE_DB_SQLITE").is_ok();
-    if !db_sqlite {
+    let db_postgres = std::env::var("CARGO_FEATURE_DB_POSTGRES").is_ok();
+    if db_sqlite && db_postgres {
+        panic!("Cannot enable both 'db-sqlite' and 'db-postgres' features simultaneously. Choose one (use --no-default-features for Postgres).");
+    }
+    if !db_sqlite && !db_postgres {
         panic!("At least one database backend feature must be enabled!");
     }
 }
@@ -41,6 +45,7 @@ fn feature_conflicts() {
 fn get_version() -> String {
     let mut suffixes = Vec::new();
     let feature_names = vec![
+        ("DB_POSTGRES", "pg"),
         ("GPIO", "g"),
         ("HTTP_SERVER", "h"),
         ("SENTRY", "s"),
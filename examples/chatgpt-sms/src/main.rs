@@ -2,10 +2,12 @@ mod types;
 
 use std::collections::{HashMap, VecDeque};
 use std::env;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
-use axum::extract::{Json, State};
-use axum::http::StatusCode;
+use std::time::{Duration, Instant};
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
 use axum::response::Json as ResponseJson;
 use axum::Router;
 use axum::routing::post;
@@ -14,37 +16,85 @@ use tokio::net::TcpListener;
 use tokio::sync::{Mutex, mpsc};
 use tracing::{error, debug, warn, info, instrument};
 use dashmap::DashMap;
+use futures_util::StreamExt;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 use crate::types::*;
-
-const CHATGPT_MODEL: &str = "gpt-4.1-mini";
-const HISTORY_LIMIT: usize = 20;
-const CHATGPT_TEMPERATURE: f32 = 0.8;
-const CHATGPT_SYSTEM_PROMPT: &str = "You are an SMS assistant named Dexter, Always reply in short, clear SMS-style messages—never write more than 2-3 sentences per reply. Keep your tone friendly, upbeat, and a little bit witty, like a helpful buddy. Use contractions, emojis (if appropriate), and text as real people do via SMS. Never use formal or overly technical language. No long explanations or paragraphs—keep it brief but helpful! Do not reference that you are an AI or digital assistant. Always sound personable and natural.";
+use sms_api_types::events::Event;
+use sms_api_types::http::{HttpResponse, SendSmsRequest, SendSmsResponse};
+use sms_api_types::sms::{DeliveryStatusCategory, IncomingDeliveryReport};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+
+const DEFAULT_CHATGPT_MODEL: &str = "gpt-4.1-mini";
+const HISTORY_TOKEN_BUDGET: usize = 2000;
+const DEFAULT_CHATGPT_TEMPERATURE: f32 = 0.8;
+const DEFAULT_CHATGPT_SYSTEM_PROMPT: &str = "You are an SMS assistant named Dexter, Always reply in short, clear SMS-style messages—never write more than 2-3 sentences per reply. Keep your tone friendly, upbeat, and a little bit witty, like a helpful buddy. Use contractions, emojis (if appropriate), and text as real people do via SMS. Never use formal or overly technical language. No long explanations or paragraphs—keep it brief but helpful! Do not reference that you are an AI or digital assistant. Always sound personable and natural.";
 const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const OPENAI_MAX_ATTEMPTS: u32 = 3;
+const OPENAI_RETRY_DELAY: Duration = Duration::from_secs(1);
+const OPENAI_MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+const MAX_REPLY_ATTEMPTS: u8 = 2;
+const REPLY_TRACKING_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const WS_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const DEFAULT_RATE_LIMIT_MESSAGES: usize = 10;
+const DEFAULT_RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+const RATE_LIMIT_REPLY: &str = "Whoa, slow down! 🐢 I'll be ready to chat again in a bit.";
 
 #[derive(thiserror::Error, Debug)]
 enum AppError {
     #[error("Missing required {0} environment variable!")]
     MissingEnvironmentVariable(&'static str),
+    #[error("Invalid {0} environment variable, expected {1}!")]
+    InvalidEnvironmentVariable(&'static str, &'static str),
     #[error("OpenAI API error: {0}")]
     OpenAI(String),
+    #[error("OpenAI API busy: {0}")]
+    OpenAIBusy(String),
     #[error("SMS API error: {0}")]
     Sms(String),
     #[error("Network error: {0}")]
     Network(#[from] reqwest::Error),
+    #[error("WebSocket error: {0}")]
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
     #[error("Serialization error: {0}")]
-    Serialization(#[from] serde_json::Error)
+    Serialization(#[from] serde_json::Error),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error)
+}
+
+// Boxed, as the tungstenite error is much larger than the others.
+impl From<tokio_tungstenite::tungstenite::Error> for AppError {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        AppError::WebSocket(Box::new(e))
+    }
 }
 
 type Result<T> = std::result::Result<T, AppError>;
 
+type MessageHistory = HashMap<String, VecDeque<ChatMessage>>;
+
 #[derive(Clone)]
 struct AppState {
-    message_history: Arc<Mutex<HashMap<String, VecDeque<ChatMessage>>>>,
+    /// A single lock over every number's history, so each turn (and its write to `history_path`)
+    /// is serialized. This means concurrent numbers briefly wait on each other, which is fine for
+    /// an example but would want a lock per number under real load.
+    message_history: Arc<Mutex<MessageHistory>>,
+    history_path: Option<PathBuf>,
+    rate_limits: Arc<Mutex<HashMap<String, RateLimit>>>,
+    rate_limit_messages: usize,
+    rate_limit_window: Duration,
     phone_queues: Arc<DashMap<String, mpsc::UnboundedSender<MessageTask>>>,
+    sent_replies: Arc<DashMap<i64, SentReply>>,
     sms_send_url: String,
     sms_send_auth: Option<String>,
+    sms_ws_url: Option<String>,
+    webhook_secret: String,
     openai_key: String,
+    chatgpt_model: String,
+    chatgpt_temperature: f32,
+    chatgpt_system_prompt: String,
     http_client: Client
 }
 
@@ -55,12 +105,29 @@ impl AppState {
             .build()
             .expect("Failed to create HTTP client");
 
+        let history_path = env_string("HISTORY_PATH").map(PathBuf::from);
+        let message_history = match &history_path {
+            Some(path) => load_history(path)?,
+            None => HashMap::new(),
+        };
+
         let state = Self {
-            message_history: Arc::new(Mutex::new(HashMap::new())),
+            message_history: Arc::new(Mutex::new(message_history)),
+            history_path,
+            rate_limits: Arc::new(Mutex::new(HashMap::new())),
+            rate_limit_messages: env_number("RATE_LIMIT_MESSAGES")?.unwrap_or(DEFAULT_RATE_LIMIT_MESSAGES),
+            rate_limit_window: env_number("RATE_LIMIT_WINDOW_SECS")?
+                .map_or(DEFAULT_RATE_LIMIT_WINDOW, |secs| Duration::from_secs(secs as u64)),
             phone_queues: Arc::new(DashMap::new()),
+            sent_replies: Arc::new(DashMap::new()),
             sms_send_url: env::var("SMS_SEND_URL").map_err(|_| AppError::MissingEnvironmentVariable("SMS_SEND_URL"))?,
             sms_send_auth: env::var("SMS_SEND_AUTH").ok(),
+            sms_ws_url: env::var("SMS_WS_URL").ok(),
+            webhook_secret: env::var("WEBHOOK_SECRET").map_err(|_| AppError::MissingEnvironmentVariable("WEBHOOK_SECRET"))?,
             openai_key: env::var("OPENAI_KEY").map_err(|_| AppError::MissingEnvironmentVariable("OPENAI_KEY"))?,
+            chatgpt_model: env_string("CHATGPT_MODEL").unwrap_or_else(|| DEFAULT_CHATGPT_MODEL.to_string()),
+            chatgpt_temperature: env_temperature()?.unwrap_or(DEFAULT_CHATGPT_TEMPERATURE),
+            chatgpt_system_prompt: env_string("CHATGPT_SYSTEM_PROMPT").unwrap_or_else(|| DEFAULT_CHATGPT_SYSTEM_PROMPT.to_string()),
             http_client
         };
         Ok(state)
@@ -111,12 +178,14 @@ impl AppState {
         let mut history_guard = self.message_history.lock().await;
         let messages = history_guard
             .entry(phone_number.to_string())
-            .or_insert_with(|| VecDeque::with_capacity(HISTORY_LIMIT));
+            .or_default();
 
         messages.push_back(message);
         Self::trim_history(messages);
 
-        messages.iter().cloned().collect()
+        let snapshot = messages.iter().cloned().collect();
+        self.save_history(&history_guard).await;
+        snapshot
     }
 
     /// Adds a message to existing conversation history.
@@ -126,6 +195,26 @@ impl AppState {
         if let Some(messages) = history_guard.get_mut(phone_number) {
             messages.push_back(message);
             Self::trim_history(messages);
+            self.save_history(&history_guard).await;
+        }
+    }
+
+    /// Write all history to `history_path` if set, via a temporary file so a crash mid-write
+    /// can't corrupt it. Called with the history lock held, so writes happen in order.
+    async fn save_history(&self, history: &MessageHistory) {
+        let Some(path) = &self.history_path else {
+            return;
+        };
+
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(".tmp");
+        let result = async {
+            tokio::fs::write(&temp_path, serde_json::to_vec(history)?).await?;
+            tokio::fs::rename(&temp_path, path).await?;
+            Ok::<_, AppError>(())
+        };
+        if let Err(e) = result.await {
+            error!("Failed to save message history to {}: {}", path.display(), e);
         }
     }
 
@@ -134,7 +223,7 @@ impl AppState {
     async fn get_reply(&self, messages: Vec<ChatMessage>) -> Result<String> {
         let system_message = ChatMessage {
             role: "system".to_string(),
-            content: CHATGPT_SYSTEM_PROMPT.to_string(),
+            content: self.chatgpt_system_prompt.clone(),
         };
 
         // Create new message set with system prompt.
@@ -144,63 +233,84 @@ impl AppState {
 
         // Create request payload.
         let request_body = ChatGPTCompletionRequest {
-            model: CHATGPT_MODEL,
-            temperature: CHATGPT_TEMPERATURE,
+            model: self.chatgpt_model.clone(),
+            temperature: self.chatgpt_temperature,
             messages: all_messages,
         };
 
-        // Send chat completion request with history (including optional authorization).
-        let mut builder = self
-            .http_client
-            .post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.openai_key))
-            .header("Content-Type", "application/json")
-            .json(&request_body);
-
-        if let Some(auth) = &self.sms_send_auth {
-            builder = builder.header("Authorization", auth);
-        }
+        // Rate limits and server errors are retried with backoff, anything else fails fast.
+        let mut attempt = 1;
+        loop {
+            // Send chat completion request with history (including optional authorization).
+            let mut builder = self
+                .http_client
+                .post("https://api.openai.com/v1/chat/completions")
+                .header("Authorization", format!("Bearer {}", self.openai_key))
+                .header("Content-Type", "application/json")
+                .json(&request_body);
+
+            if let Some(auth) = &self.sms_send_auth {
+                builder = builder.header("Authorization", auth);
+            }
 
-        // Send SMS message request with authorization header.
-        debug!("Sending request to ChatGPT API");
-        match builder.send().await {
-            Ok(response) => {
-                if response.status().is_success() {
-                    match response.json::<ChatGPTCompletionResponse>().await {
-                        Ok(chat_response) => {
-                            if let Some(choice) = chat_response.choices.first() {
-                                debug!("Successfully received ChatGPT response");
-                                Ok(choice.message.content.clone())
-                            } else {
-                                Err(AppError::OpenAI("No choices in response".to_string()))
-                            }
-                        }
-                        Err(e) => {
-                            error!("Failed to parse ChatGPT response: {}", e);
-                            Err(AppError::OpenAI(format!("Parse error: {}", e)))
+            debug!("Sending request to ChatGPT API (attempt {})", attempt);
+            let response = match builder.send().await {
+                Ok(response) => response,
+                Err(e) => {
+                    error!("Failed to call ChatGPT API: {}", e);
+                    return Err(AppError::Network(e));
+                }
+            };
+
+            let status = response.status();
+            if status.is_success() {
+                return match response.json::<ChatGPTCompletionResponse>().await {
+                    Ok(chat_response) => {
+                        if let Some(choice) = chat_response.choices.first() {
+                            debug!("Successfully received ChatGPT response");
+                            Ok(choice.message.content.clone())
+                        } else {
+                            Err(AppError::OpenAI("No choices in response".to_string()))
                         }
                     }
-                } else {
-                    let status = response.status();
-                    let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                    error!("ChatGPT API error: {} - {}", status, error_text);
-                    Err(AppError::OpenAI(format!("{}: {}", status, error_text)))
-                }
+                    Err(e) => {
+                        error!("Failed to parse ChatGPT response: {}", e);
+                        Err(AppError::OpenAI(format!("Parse error: {}", e)))
+                    }
+                };
             }
-            Err(e) => {
-                error!("Failed to call ChatGPT API: {}", e);
-                Err(AppError::Network(e))
+
+            // Prefer the server's Retry-After, otherwise double the delay each attempt.
+            let retryable = status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+            let delay = retry_after(response.headers())
+                .unwrap_or(OPENAI_RETRY_DELAY * 2u32.pow(attempt - 1))
+                .min(OPENAI_MAX_RETRY_DELAY);
+
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            if !retryable {
+                error!("ChatGPT API error: {} - {}", status, error_text);
+                return Err(AppError::OpenAI(format!("{}: {}", status, error_text)));
             }
+            if attempt >= OPENAI_MAX_ATTEMPTS {
+                error!("ChatGPT API still busy after {} attempts: {} - {}", attempt, status, error_text);
+                return Err(AppError::OpenAIBusy(format!("{}: {}", status, error_text)));
+            }
+
+            warn!("ChatGPT API busy ({}), retrying in {:?}", status, delay);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
         }
     }
 
     /// Send the ChatGPT reply back via SMS API.
-    #[instrument(skip(self), fields(phone_number = %phone_number, reply_length = reply.len()))]
     async fn send_reply(&self, phone_number: String, reply: String) -> Result<()> {
-        let request_body = SendReplyRequest {
-            to: phone_number.clone(),
-            content: reply.clone(),
-        };
+        self.send_reply_attempt(phone_number, reply, 1).await
+    }
+
+    /// Send a reply via SMS API, tracking it by message ID if delivery reports are enabled.
+    #[instrument(skip(self), fields(phone_number = %phone_number, reply_length = reply.len()))]
+    async fn send_reply_attempt(&self, phone_number: String, reply: String, attempts: u8) -> Result<()> {
+        let request_body = SendSmsRequest::new(phone_number.clone(), reply.clone());
 
         match self
             .http_client
@@ -211,7 +321,30 @@ impl AppState {
         {
             Ok(response) => {
                 if response.status().is_success() {
-                    debug!("Successfully sent reply to {}", phone_number);
+                    // Every route wraps its result, and reports failures with `success` unset.
+                    let sent = match response.json::<HttpResponse<SendSmsResponse>>().await {
+                        Ok(HttpResponse { success: true, response: Some(sent), .. }) => sent,
+                        Ok(HttpResponse { error, .. }) => {
+                            let error = error.unwrap_or_else(|| "Unknown error".to_string());
+                            error!("SMS API error: {}", error);
+                            return Err(AppError::Sms(error));
+                        }
+                        Err(e) => {
+                            // The reply was still sent, it just can't be tracked.
+                            warn!("Failed to parse SMS API send response, reply won't be tracked: {}", e);
+                            return Ok(());
+                        }
+                    };
+
+                    debug!("Successfully sent reply #{} to {}", sent.message_id, phone_number);
+                    if self.sms_ws_url.is_some() {
+                        self.sent_replies.insert(sent.message_id, SentReply {
+                            phone_number,
+                            content: reply,
+                            attempts,
+                            sent_at: Instant::now(),
+                        });
+                    }
                     Ok(())
                 } else {
                     let status = response.status();
@@ -227,25 +360,195 @@ impl AppState {
         }
     }
 
+    /// Connect to the SMS API WebSocket and handle delivery reports until the connection closes.
+    async fn listen_for_deliveries(&self, url: &str) -> Result<()> {
+        let mut request = url.into_client_request()?;
+        if let Some(auth) = &self.sms_send_auth {
+            let value = HeaderValue::from_str(auth)
+                .map_err(|e| AppError::Sms(format!("Invalid SMS_SEND_AUTH header value: {}", e)))?;
+            request.headers_mut().insert("Authorization", value);
+        }
+
+        let (mut stream, _) = tokio_tungstenite::connect_async(request).await?;
+        info!("Connected to SMS API WebSocket for delivery reports");
+
+        while let Some(message) = stream.next().await {
+            match message? {
+                Message::Text(text) => match serde_json::from_str::<Event>(text.as_str()) {
+                    Ok(Event::DeliveryReport { message_id, report }) => {
+                        self.handle_delivery_report(message_id, report);
+                    }
+                    Ok(event) => debug!("Ignoring WebSocket event: {:?}", event.to_event_type()),
+                    Err(e) => warn!("Received invalid WebSocket event: {}", e),
+                },
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Match a delivery report to a sent reply, retrying it once if it failed.
+    fn handle_delivery_report(&self, message_id: i64, report: IncomingDeliveryReport) {
+        // Replies whose report never arrives (eg: sent while disconnected) are dropped eventually.
+        self.sent_replies.retain(|_, reply| reply.sent_at.elapsed() < REPLY_TRACKING_TTL);
+
+        // The service centre is still trying (or the status is reserved), so wait for a later report.
+        if matches!(report.category, DeliveryStatusCategory::Pending | DeliveryStatusCategory::Unknown) {
+            debug!("Reply #{} is not final yet: {}", message_id, report.status_label);
+            return;
+        }
+
+        let Some((_, reply)) = self.sent_replies.remove(&message_id) else {
+            debug!("Ignoring delivery report for untracked message #{}", message_id);
+            return;
+        };
+
+        if report.category == DeliveryStatusCategory::Delivered {
+            info!("Reply #{} to {} was delivered", message_id, reply.phone_number);
+            return;
+        }
+
+        if reply.attempts >= MAX_REPLY_ATTEMPTS {
+            error!(
+                "Reply #{} to {} was not delivered after {} attempts: {}",
+                message_id, reply.phone_number, reply.attempts, report.status_label
+            );
+            return;
+        }
+
+        warn!("Reply #{} to {} failed ({}), retrying", message_id, reply.phone_number, report.status_label);
+        let state = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = state.send_reply_attempt(reply.phone_number, reply.content, reply.attempts + 1).await {
+                error!("Failed to retry reply #{}: {}", message_id, e);
+            }
+        });
+    }
+
+    /// Record a message against its sender's sliding window, deciding whether it should be processed.
+    /// Once over the limit only the first message gets a reply, until older messages leave the window.
+    async fn check_rate_limit(&self, phone_number: &str) -> RateLimitDecision {
+        if self.rate_limit_messages == 0 {
+            return RateLimitDecision::Allow;
+        }
+
+        let mut limits_guard = self.rate_limits.lock().await;
+        let now = Instant::now();
+
+        // Forget senders whose window has passed, so the map doesn't grow with every number seen.
+        limits_guard.retain(|_, limit| {
+            while limit.messages.front().is_some_and(|sent| now.duration_since(*sent) >= self.rate_limit_window) {
+                limit.messages.pop_front();
+            }
+            !limit.messages.is_empty()
+        });
+
+        let limit = limits_guard.entry(phone_number.to_string()).or_default();
+        if limit.messages.len() < self.rate_limit_messages {
+            limit.messages.push_back(now);
+            limit.notified = false;
+            RateLimitDecision::Allow
+        } else if !limit.notified {
+            limit.notified = true;
+            RateLimitDecision::Notify
+        } else {
+            RateLimitDecision::Drop
+        }
+    }
+
     /// Clears all message history for a phone number.
     #[instrument(skip(self), fields(phone_number = %phone_number))]
     async fn clear_history(&self, phone_number: &str) -> usize {
-        self.message_history
-            .lock()
-            .await
-            .remove(phone_number)
-            .map(|removed| removed.len())
-            .unwrap_or(0)
+        let mut history_guard = self.message_history.lock().await;
+        let Some(removed) = history_guard.remove(phone_number) else {
+            return 0;
+        };
+
+        self.save_history(&history_guard).await;
+        removed.len()
     }
 
-    /// Trims history to stay within limits.
+    /// Trims the oldest history to stay within the token budget. The most recent user
+    /// message (and anything after it) is always kept, even if it alone is over budget.
     fn trim_history(messages: &mut VecDeque<ChatMessage>) {
-        while messages.len() > HISTORY_LIMIT {
-            messages.pop_front();
+        let protected = messages
+            .iter()
+            .rposition(|message| message.role == "user")
+            .map_or(0, |idx| messages.len() - idx);
+
+        let mut tokens: usize = messages.iter().map(|message| estimate_tokens(&message.content)).sum();
+        while tokens > HISTORY_TOKEN_BUDGET && messages.len() > protected {
+            if let Some(removed) = messages.pop_front() {
+                tokens -= estimate_tokens(&removed.content);
+            }
         }
     }
 }
 
+/// Keep a delivery report WebSocket connected, reconnecting after a delay whenever it drops.
+/// Sent replies are tracked in the shared state rather than per connection, so none are lost.
+async fn track_deliveries(state: AppState, url: String) {
+    loop {
+        match state.listen_for_deliveries(&url).await {
+            Ok(()) => warn!("SMS API WebSocket closed, reconnecting"),
+            Err(e) => error!("SMS API WebSocket error: {}, reconnecting", e),
+        }
+        tokio::time::sleep(WS_RECONNECT_DELAY).await;
+    }
+}
+
+/// Load saved message history, starting empty if the file doesn't exist yet.
+/// An unreadable file is an error rather than being replaced, so history is never silently lost.
+fn load_history(path: &Path) -> Result<MessageHistory> {
+    match std::fs::read(path) {
+        Ok(data) => {
+            let history: MessageHistory = serde_json::from_slice(&data)?;
+            info!("Loaded message history for {} numbers from {}", history.len(), path.display());
+            Ok(history)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(AppError::Io(e)),
+    }
+}
+
+/// Read an optional environment variable, treating an empty value as unset.
+fn env_string(name: &'static str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+/// Read an optional numeric environment variable.
+fn env_number(name: &'static str) -> Result<Option<usize>> {
+    env::var(name)
+        .ok()
+        .map(|value| value.trim().parse().map_err(|_| AppError::InvalidEnvironmentVariable(name, "a whole number")))
+        .transpose()
+}
+
+/// Read the optional ChatGPT temperature, which the API only accepts from 0 to 2.
+fn env_temperature() -> Result<Option<f32>> {
+    env_string("CHATGPT_TEMPERATURE")
+        .map(|value| match value.trim().parse::<f32>() {
+            Ok(temperature) if (0.0..=2.0).contains(&temperature) => Ok(temperature),
+            _ => Err(AppError::InvalidEnvironmentVariable("CHATGPT_TEMPERATURE", "a number from 0 to 2")),
+        })
+        .transpose()
+}
+
+/// The delay requested by a `Retry-After` header, if it's given in seconds.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get("retry-after")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs)
+}
+
+/// Approximate token count, using the rough average of 4 characters per token.
+fn estimate_tokens(content: &str) -> usize {
+    content.chars().count().div_ceil(4)
+}
+
 #[instrument(skip(state))]
 async fn process_message(
     state: AppState,
@@ -253,6 +556,19 @@ async fn process_message(
     message_content: String,
 ) -> Result<()> {
 
+    // Check the rate limit first, so limited messages never reach OpenAI.
+    match state.check_rate_limit(&phone_number).await {
+        RateLimitDecision::Allow => {}
+        RateLimitDecision::Notify => {
+            warn!("Rate limiting messages from {}", phone_number);
+            return state.send_reply(phone_number, RATE_LIMIT_REPLY.to_string()).await;
+        }
+        RateLimitDecision::Drop => {
+            debug!("Dropping rate limited message from {}", phone_number);
+            return Ok(());
+        }
+    }
+
     // Check if this is a history clear command.
     if message_content.trim() == "#" {
         debug!("Received history clear command from {}", phone_number);
@@ -280,6 +596,7 @@ async fn process_message(
     let reply = state.get_reply(history_snapshot).await.unwrap_or_else(|e| {
         error!("Failed to get ChatGPT reply: {}", e);
         match e {
+            AppError::OpenAIBusy(_) => "Sorry, I'm a bit busy right now, try again in a minute!".to_string(),
             AppError::OpenAI(_) => "Sorry, the AI service is currently unavailable!".to_string(),
             AppError::Network(_) => "Sorry, I couldn't connect to the AI service!".to_string(),
             _ => "Sorry, there was an error processing your message!".to_string(),
@@ -302,29 +619,67 @@ async fn process_message(
     Ok(())
 }
 
-#[instrument(skip(state, payload))]
+/// Check the `X-Signature` header is the HMAC-SHA256 of the raw body with the shared secret.
+fn verify_signature(secret: &str, headers: &HeaderMap, body: &[u8]) -> bool {
+    let Some(signature) = headers
+        .get("x-signature")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("sha256="))
+        .and_then(|value| hex::decode(value).ok())
+    else {
+        return false;
+    };
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+
+    // Constant-time comparison.
+    mac.verify_slice(&signature).is_ok()
+}
+
+fn error_response(status: StatusCode, error: &str) -> (StatusCode, ResponseJson<ErrorResponse>) {
+    (status, ResponseJson(ErrorResponse { error: error.to_string() }))
+}
+
+#[instrument(skip(state, headers, body))]
 async fn http_webhook(
     State(state): State<AppState>,
-    Json(payload): Json<WebhookPayload>,
+    headers: HeaderMap,
+    body: Bytes,
 ) -> std::result::Result<StatusCode, (StatusCode, ResponseJson<ErrorResponse>)> {
-    if payload.webhook_type != "incoming" {
-        warn!("Received non-incoming webhook type: {}", payload.webhook_type);
-        return Err((
-            StatusCode::BAD_REQUEST,
-            ResponseJson(ErrorResponse {
-                error: "Invalid webhook type".to_string(),
-            }),
-        ));
+    // The signature covers the exact body bytes, so verify before parsing.
+    if !verify_signature(&state.webhook_secret, &headers, &body) {
+        warn!("Rejecting webhook with missing or invalid signature");
+        return Err(error_response(StatusCode::UNAUTHORIZED, "Invalid signature"));
     }
 
+    let payload: Event = serde_json::from_slice(&body).map_err(|e| {
+        warn!("Received invalid webhook payload: {}", e);
+        error_response(StatusCode::BAD_REQUEST, "Invalid payload")
+    })?;
+
+    let message = match payload {
+        Event::IncomingMessage(message) => message,
+        event => {
+            warn!("Received non-incoming webhook type: {:?}", event.to_event_type());
+            return Err((
+                StatusCode::BAD_REQUEST,
+                ResponseJson(ErrorResponse {
+                    error: "Invalid webhook type".to_string(),
+                }),
+            ));
+        }
+    };
+
     // Ignore non-international numbers such as carrier numbers.
-    let phone_number = payload.data.phone_number;
+    let phone_number = message.phone_number;
     if !phone_number.starts_with("+") {
         warn!("Discarding incoming non international number format: {}", phone_number);
         return Ok(StatusCode::OK);
     }
 
-    let message_content = payload.data.message_content.trim().to_string();
+    let message_content = message.message_content.trim().to_string();
     debug!("Received message from {}, queuing for processing", phone_number);
 
     // Send task to queue for this number.
@@ -357,6 +712,11 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
         .init();
 
     let state = AppState::from_env()?;
+    info!("Using ChatGPT model {} at temperature {}", state.chatgpt_model, state.chatgpt_temperature);
+    if let Some(url) = state.sms_ws_url.clone() {
+        tokio::spawn(track_deliveries(state.clone(), url));
+    }
+
     let app = Router::new()
         .route("/webhook", post(http_webhook))
         .with_state(state);
@@ -1,18 +1,7 @@
+use std::collections::VecDeque;
+use std::time::Instant;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize)]
-pub struct WebhookPayload {
-    #[serde(rename = "type")]
-    pub webhook_type: String,
-    pub data: WebhookMessage,
-}
-
-#[derive(Debug, Deserialize)]
-pub struct WebhookMessage {
-    pub phone_number: String,
-    pub message_content: String,
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub role: String,
@@ -21,7 +10,7 @@ pub struct ChatMessage {
 
 #[derive(Debug, Serialize)]
 pub struct ChatGPTCompletionRequest {
-    pub model: &'static str,
+    pub model: String,
     pub temperature: f32,
     pub messages: Vec<ChatMessage>,
 }
@@ -37,14 +26,38 @@ pub struct ChatGPTCompletionChoice {
 }
 
 #[derive(Serialize)]
-pub struct SendReplyRequest {
-    pub to: String,
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+/// A reply sent through the SMS API, kept until its delivery report arrives.
+#[derive(Debug, Clone)]
+pub struct SentReply {
+    pub phone_number: String,
     pub content: String,
+    pub attempts: u8,
+    pub sent_at: Instant,
 }
 
-#[derive(Serialize)]
-pub struct ErrorResponse {
-    pub error: String,
+/// Recent message times for a phone number, within the rate limit window.
+#[derive(Debug, Default)]
+pub struct RateLimit {
+    pub messages: VecDeque<Instant>,
+
+    /// Whether the slow down reply has been sent since the limit was reached.
+    pub notified: bool,
+}
+
+/// What to do with an incoming message, from its sender's rate limit.
+#[derive(Debug, PartialEq)]
+pub enum RateLimitDecision {
+    Allow,
+
+    /// Over the limit, reply with the slow down message.
+    Notify,
+
+    /// Over the limit and already told, ignore the message.
+    Drop,
 }
 
 #[derive(Debug)]
@@ -0,0 +1,120 @@
+use crate::http::{
+    GlobalFetchRequest, HttpResponse, MessageIdFetchRequest, PhoneNumberFetchRequest,
+    SendSmsRequest, SendSmsResponse,
+};
+use crate::modem::ModemResponse;
+use crate::sms::{SMSDeliveryReport, SMSMessage};
+use anyhow::{anyhow, bail, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// A thin client for the sms-server HTTP API, unwrapping the response envelope.
+#[derive(Clone)]
+pub struct SmsClient {
+    http: reqwest::Client,
+    base_url: String,
+    token: Option<String>,
+}
+impl SmsClient {
+    /// Create a client for the server at `base_url`, eg: `http://127.0.0.1:3000`.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self::with_http_client(reqwest::Client::new(), base_url)
+    }
+
+    /// Create a client reusing an existing reqwest client and its settings.
+    pub fn with_http_client(http: reqwest::Client, base_url: impl Into<String>) -> Self {
+        Self {
+            http,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            token: None,
+        }
+    }
+
+    /// Send a bearer token with every request.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub async fn send_sms(&self, request: &SendSmsRequest) -> Result<SendSmsResponse> {
+        self.post("/sms/send", Some(request)).await
+    }
+
+    /// Get stored messages for a phone number.
+    pub async fn get_messages(&self, request: &PhoneNumberFetchRequest) -> Result<Vec<SMSMessage>> {
+        self.post("/db/sms", Some(request)).await
+    }
+
+    pub async fn get_delivery_reports(
+        &self,
+        request: &MessageIdFetchRequest,
+    ) -> Result<Vec<SMSDeliveryReport>> {
+        self.post("/db/delivery-reports", Some(request)).await
+    }
+
+    /// Get the latest phone numbers messaged, with their friendly names.
+    pub async fn get_latest_numbers(
+        &self,
+        request: &GlobalFetchRequest,
+    ) -> Result<Vec<(String, Option<String>)>> {
+        self.post("/db/latest-numbers", Some(request)).await
+    }
+
+    pub async fn get_network_status(&self) -> Result<ModemResponse> {
+        self.get("/sms/network-status").await
+    }
+
+    pub async fn get_signal_strength(&self) -> Result<ModemResponse> {
+        self.get("/sms/signal-strength").await
+    }
+
+    pub async fn get_version(&self) -> Result<String> {
+        self.get("/sys/version").await
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        self.request(self.http.get(format!("{}{path}", self.base_url)))
+            .await
+    }
+
+    async fn post<B: Serialize, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: Option<&B>,
+    ) -> Result<T> {
+        let mut builder = self.http.post(format!("{}{path}", self.base_url));
+        if let Some(body) = body {
+            builder = builder.json(body);
+        }
+        self.request(builder).await
+    }
+
+    /// Send a request and unwrap the envelope, turning an unsuccessful response into an error.
+    async fn request<T: DeserializeOwned>(
+        &self,
+        mut builder: reqwest::RequestBuilder,
+    ) -> Result<T> {
+        if let Some(token) = &self.token {
+            builder = builder.bearer_auth(token);
+        }
+
+        let response = builder.send().await?;
+        let status = response.status();
+        let envelope: HttpResponse<T> = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Invalid response ({status}): {e}"))?;
+
+        match envelope {
+            HttpResponse {
+                success: true,
+                response: Some(response),
+                ..
+            } => Ok(response),
+            HttpResponse {
+                error: Some(error), ..
+            } => bail!("Request failed ({status}): {error}"),
+            _ => bail!("Request failed ({status}) without a response"),
+        }
+    }
+}
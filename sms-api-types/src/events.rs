@@ -0,0 +1,173 @@
+use crate::modem::{GNSSLocation, ModemStatus};
+use crate::sms::{IncomingDeliveryReport, SMSMessage};
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum EventType {
+    #[serde(rename = "incoming")]
+    IncomingMessage,
+
+    #[serde(rename = "outgoing")]
+    OutgoingMessage,
+
+    #[serde(rename = "delivery")]
+    DeliveryReport,
+
+    #[serde(rename = "modem_status_update")]
+    ModemStatusUpdate,
+
+    #[serde(rename = "gnss_position_report")]
+    GNSSPositionReport,
+
+    #[serde(rename = "raw_unsolicited")]
+    RawUnsolicited,
+
+    #[serde(rename = "incoming_call")]
+    IncomingCall,
+
+    #[serde(rename = "send_failure")]
+    SendFailure,
+
+    #[serde(rename = "opt_out")]
+    OptOut,
+
+    #[serde(rename = "opt_in")]
+    OptIn,
+}
+impl EventType {
+    pub const COUNT: usize = 10;
+
+    #[inline]
+    pub const fn to_bit(self) -> u16 {
+        match self {
+            EventType::IncomingMessage => 1 << 0,    // 0b0000000001
+            EventType::OutgoingMessage => 1 << 1,    // 0b0000000010
+            EventType::DeliveryReport => 1 << 2,     // 0b0000000100
+            EventType::ModemStatusUpdate => 1 << 3,  // 0b0000001000
+            EventType::GNSSPositionReport => 1 << 4, // 0b0000010000
+            EventType::RawUnsolicited => 1 << 5,     // 0b0000100000
+            EventType::IncomingCall => 1 << 6,       // 0b0001000000
+            EventType::SendFailure => 1 << 7,        // 0b0010000000
+            EventType::OptOut => 1 << 8,             // 0b0100000000
+            EventType::OptIn => 1 << 9,              // 0b1000000000
+        }
+    }
+
+    #[inline]
+    pub const fn all_bits() -> u16 {
+        (1 << 0)
+            | (1 << 1)
+            | (1 << 2)
+            | (1 << 3)
+            | (1 << 4)
+            | (1 << 5)
+            | (1 << 6)
+            | (1 << 7)
+            | (1 << 8)
+            | (1 << 9)
+        // 0b1111111111
+    }
+
+    #[inline]
+    pub fn events_to_mask(events: &[EventType]) -> u16 {
+        events.iter().fold(0, |acc, event| acc | event.to_bit())
+    }
+}
+impl TryFrom<&str> for EventType {
+    type Error = anyhow::Error;
+
+    #[inline]
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "incoming" => Ok(EventType::IncomingMessage),
+            "outgoing" => Ok(EventType::OutgoingMessage),
+            "delivery" => Ok(EventType::DeliveryReport),
+            "modem_status_update" => Ok(EventType::ModemStatusUpdate),
+            "gnss_position_report" => Ok(EventType::GNSSPositionReport),
+            "raw_unsolicited" => Ok(EventType::RawUnsolicited),
+            "incoming_call" => Ok(EventType::IncomingCall),
+            "send_failure" => Ok(EventType::SendFailure),
+            "opt_out" => Ok(EventType::OptOut),
+            "opt_in" => Ok(EventType::OptIn),
+            _ => Err(anyhow!("Unknown event type {}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum Event {
+    #[serde(rename = "incoming")]
+    IncomingMessage(SMSMessage),
+
+    #[serde(rename = "outgoing")]
+    OutgoingMessage(SMSMessage),
+
+    #[serde(rename = "delivery")]
+    DeliveryReport {
+        message_id: i64,
+        report: IncomingDeliveryReport,
+    },
+
+    #[serde(rename = "modem_status_update")]
+    ModemStatusUpdate {
+        previous: ModemStatus,
+        current: ModemStatus,
+
+        /// The configured modem whose status changed.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        modem_id: Option<String>,
+
+        /// Seconds until the next reconnection attempt, only while offline.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reconnect_backoff_secs: Option<u64>,
+    },
+
+    #[serde(rename = "gnss_position_report")]
+    GNSSPositionReport(GNSSLocation),
+
+    #[serde(rename = "raw_unsolicited")]
+    RawUnsolicited(String),
+
+    #[serde(rename = "incoming_call")]
+    IncomingCall { phone_number: Option<String> },
+
+    #[serde(rename = "send_failure")]
+    SendFailure {
+        message_id: Option<i64>,
+        phone_number: String,
+        error_message: String,
+    },
+
+    /// A sender replied with an opt-out keyword, and was added to the blocklist.
+    #[serde(rename = "opt_out")]
+    OptOut {
+        phone_number: String,
+        keyword: String,
+    },
+
+    /// A sender replied with a resubscribe keyword, and was removed from the blocklist.
+    #[serde(rename = "opt_in")]
+    OptIn {
+        phone_number: String,
+        keyword: String,
+    },
+}
+impl Event {
+    #[inline]
+    pub fn to_event_type(&self) -> EventType {
+        match self {
+            Event::IncomingMessage(_) => EventType::IncomingMessage,
+            Event::OutgoingMessage(_) => EventType::OutgoingMessage,
+            Event::DeliveryReport { .. } => EventType::DeliveryReport,
+            Event::ModemStatusUpdate { .. } => EventType::ModemStatusUpdate,
+            Event::GNSSPositionReport(_) => EventType::GNSSPositionReport,
+            Event::RawUnsolicited(_) => EventType::RawUnsolicited,
+            Event::IncomingCall { .. } => EventType::IncomingCall,
+            Event::SendFailure { .. } => EventType::SendFailure,
+            Event::OptOut { .. } => EventType::OptOut,
+            Event::OptIn { .. } => EventType::OptIn,
+        }
+    }
+}
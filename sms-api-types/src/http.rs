@@ -0,0 +1,259 @@
+use crate::sms::{SMSContentEncoding, SMSDataCoding, SMSEncoding, SMSStatus};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The envelope every JSON HTTP route responds with.
+#[derive(Serialize, Deserialize)]
+pub struct HttpResponse<T> {
+    pub success: bool,
+    pub response: Option<T>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PhoneNumberFetchRequest {
+    pub phone_number: String,
+
+    #[serde(default)]
+    pub limit: Option<u64>,
+
+    #[serde(default)]
+    pub offset: Option<u64>,
+
+    #[serde(default)]
+    pub reverse: bool,
+}
+impl PhoneNumberFetchRequest {
+    /// Fetch the most recent page of messages for a phone number.
+    pub fn new(phone_number: impl Into<String>) -> Self {
+        Self {
+            phone_number: phone_number.into(),
+            limit: None,
+            offset: None,
+            reverse: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MessageIdFetchRequest {
+    pub message_id: i64,
+
+    #[serde(default)]
+    pub limit: Option<u64>,
+
+    #[serde(default)]
+    pub offset: Option<u64>,
+
+    #[serde(default)]
+    pub reverse: bool,
+}
+impl MessageIdFetchRequest {
+    pub fn new(message_id: i64) -> Self {
+        Self {
+            message_id,
+            limit: None,
+            offset: None,
+            reverse: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct GlobalFetchRequest {
+    #[serde(default)]
+    pub limit: Option<u64>,
+
+    #[serde(default)]
+    pub offset: Option<u64>,
+
+    #[serde(default)]
+    pub reverse: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SendSmsRequest {
+    pub to: String,
+
+    /// The message body, unless sending a template.
+    #[serde(default)]
+    pub content: String,
+
+    /// A template configured on the server, rendered with `vars` instead of sending `content`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+
+    /// Values for the template's `{placeholder}` tokens.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub vars: HashMap<String, String>,
+
+    #[serde(default)]
+    pub flash: bool,
+
+    #[serde(default)]
+    pub validity_period: Option<u8>,
+
+    #[serde(default)]
+    pub timeout: Option<u32>,
+
+    #[serde(default)]
+    pub encoding: SMSEncoding,
+
+    /// Send `content` as 8-bit data rather than text, encoded as given by `content_encoding`.
+    #[serde(default, skip_serializing_if = "SMSDataCoding::is_text")]
+    pub data_coding: SMSDataCoding,
+
+    /// Whether 8-bit `content` is hex (default) or base64 encoded. Only for 8-bit data.
+    #[serde(default, skip_serializing_if = "SMSContentEncoding::is_hex")]
+    pub content_encoding: SMSContentEncoding,
+
+    /// A hex encoded user data header including its length octet, such as WAP push ports.
+    /// Only for 8-bit data.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub udh: Option<String>,
+
+    #[serde(default)]
+    pub priority: bool,
+
+    /// Hold the response until the final delivery report, or the wait timeout.
+    #[serde(default)]
+    pub wait_for_delivery: bool,
+
+    #[serde(default)]
+    pub wait_timeout_secs: Option<u64>,
+
+    /// Send with a specific configured modem, otherwise the least busy online modem is used.
+    #[serde(default)]
+    pub modem_id: Option<String>,
+
+    /// Validate, encode and store the message as if sent, without using the modem.
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// Send even if the phone number is on the blocklist.
+    #[serde(default)]
+    pub override_blocklist: bool,
+
+    /// Include the hex PDU of each part in the response, for auditing what was transmitted.
+    #[serde(default)]
+    pub include_pdus: bool,
+}
+impl SendSmsRequest {
+    /// A request to send content with the default options.
+    pub fn new(to: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            to: to.into(),
+            content: content.into(),
+            template: None,
+            vars: HashMap::new(),
+            flash: false,
+            validity_period: None,
+            timeout: None,
+            encoding: SMSEncoding::default(),
+            data_coding: SMSDataCoding::default(),
+            content_encoding: SMSContentEncoding::default(),
+            udh: None,
+            priority: false,
+            wait_for_delivery: false,
+            wait_timeout_secs: None,
+            modem_id: None,
+            dry_run: false,
+            override_blocklist: false,
+            include_pdus: false,
+        }
+    }
+
+    /// A request to send a server-side template, rendered with vars.
+    pub fn from_template(
+        to: impl Into<String>,
+        template: impl Into<String>,
+        vars: HashMap<String, String>,
+    ) -> Self {
+        Self {
+            template: Some(template.into()),
+            vars,
+            ..Self::new(to, String::new())
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SendSmsResponse {
+    pub message_id: i64,
+    pub reference_id: u8,
+    pub encoding: SMSEncoding,
+
+    #[serde(default, skip_serializing_if = "SMSDataCoding::is_text")]
+    pub data_coding: SMSDataCoding,
+
+    /// The final delivery status if waited for, otherwise sent.
+    pub status: SMSStatus,
+    pub delivery_timed_out: bool,
+
+    /// How the sending modem was chosen, unset by servers without modem routing.
+    #[serde(default)]
+    pub route: Option<SendRoute>,
+
+    /// The PDU of each part in the order sent, only if requested with `include_pdus`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pdus: Option<Vec<SendSmsPdu>>,
+}
+
+/// A message part exactly as written to the modem. The Ctrl-Z (`0x1A`) that submits
+/// it is appended when it's transmitted, so isn't included in `pdu`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SendSmsPdu {
+    /// The hex encoded PDU, without a service centre address.
+    pub pdu: String,
+
+    /// The TPDU length in octets given to `AT+CMGS=<len>`.
+    pub len: usize,
+}
+
+/// Why a modem was chosen to send a message.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteReason {
+    /// The request named the modem with `modem_id`.
+    Requested,
+
+    /// The destination matched a routing prefix.
+    Prefix,
+
+    /// The routing fallback, for a destination without an online prefix match.
+    Fallback,
+
+    /// The least busy online modem, without routing or when no routed modem was online.
+    LeastBusy,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SendRoute {
+    pub modem_id: String,
+    pub reason: RouteReason,
+
+    /// The routing prefix the destination matched, even if its modem was skipped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+
+    /// Routed modems passed over because they weren't online, in the order they were tried.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_send_sms_request_defaults() {
+        // Clients only need to set the target and content.
+        let request: SendSmsRequest =
+            serde_json::from_str(r#"{"to": "+441234567890", "content": "Hello"}"#).unwrap();
+        let defaults = SendSmsRequest::new("+441234567890", "Hello");
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::to_value(&defaults).unwrap()
+        );
+    }
+}
@@ -0,0 +1,10 @@
+//! Shared types for the sms-server HTTP API, webhooks and WebSocket events.
+//! These only depend on serde, so clients don't pull in any modem or serial dependencies.
+
+pub mod events;
+pub mod http;
+pub mod modem;
+pub mod sms;
+
+#[cfg(feature = "client")]
+pub mod client;
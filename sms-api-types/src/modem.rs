@@ -0,0 +1,288 @@
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum ModemResponse {
+    SendResult {
+        reference_id: u8,
+    },
+    NetworkStatus {
+        /// The registration status (`<stat>`) from AT+CREG.
+        registration: u8,
+
+        /// The second AT+CREG field as it has always been returned, which is also `<stat>`.
+        /// Kept for existing clients, the access technology is `access_technology`.
+        technology: u8,
+
+        /// The current operator access technology (AcT), if registered.
+        access_technology: Option<u8>,
+        registration_label: String,
+        technology_label: String,
+    },
+    SignalStrength {
+        rssi: i32,
+        ber: i32,
+    },
+    NetworkOperator {
+        status: u8,
+        format: u8,
+        operator: String,
+    },
+    ServiceProvider(String),
+    ServiceCentre(String),
+
+    /// The PDU of a message or status report read from modem storage.
+    StoredSMS(String),
+    Functionality(u8),
+
+    /// The SMS parameters set with AT+CSMP, used for sent messages without their own.
+    SmsParameters {
+        first_octet: u8,
+        validity_period: u8,
+        protocol_id: u8,
+        data_coding_scheme: u8,
+    },
+    PreferredMode(u8),
+    BatteryLevel {
+        status: u8,
+        charge: u8,
+        voltage: f32,
+    },
+    GNSSStatus(GNSSFixStatus),
+    GNSSLocation(GNSSLocation),
+
+    /// The modem clock from AT+CCLK, with `timestamp` as None if it hasn't been set from the network.
+    Clock {
+        time: String,
+        timestamp: Option<u64>,
+        utc_offset_minutes: i32,
+    },
+    Success,
+    Error {
+        /// The `+CME ERROR` or `+CMS ERROR` code, if the modem gave one.
+        code: Option<u16>,
+        message: String,
+    },
+}
+impl ModemResponse {
+    /// An error response without a modem error code.
+    pub fn error(message: impl Into<String>) -> Self {
+        ModemResponse::Error {
+            code: None,
+            message: message.into(),
+        }
+    }
+}
+impl Display for ModemResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModemResponse::SendResult { reference_id } => {
+                write!(f, "SMSResult: Ref {reference_id}")
+            }
+            ModemResponse::NetworkStatus {
+                registration,
+                technology,
+                access_technology,
+                registration_label,
+                technology_label,
+            } => write!(
+                f,
+                "NetworkStatus: Reg: {registration} ({registration_label}), Tech: {technology}, AcT: {access_technology:?} ({technology_label})"
+            ),
+            ModemResponse::SignalStrength { rssi, ber } => {
+                write!(f, "SignalStrength: {rssi} dBm ({ber})")
+            }
+            ModemResponse::NetworkOperator { operator, .. } => {
+                write!(f, "NetworkOperator: {operator}")
+            }
+            ModemResponse::ServiceProvider(operator) => write!(f, "ServiceProvider: {operator}"),
+            ModemResponse::ServiceCentre(number) => write!(f, "ServiceCentre: {number}"),
+            ModemResponse::StoredSMS(pdu) => write!(f, "StoredSMS: {pdu}"),
+            ModemResponse::Functionality(level) => write!(f, "Functionality: {level}"),
+            ModemResponse::SmsParameters {
+                first_octet,
+                validity_period,
+                protocol_id,
+                data_coding_scheme,
+            } => write!(
+                f,
+                "SmsParameters: FO: {first_octet}, VP: {validity_period}, PID: {protocol_id}, DCS: {data_coding_scheme}"
+            ),
+            ModemResponse::PreferredMode(mode) => write!(f, "PreferredMode: {mode}"),
+            ModemResponse::BatteryLevel {
+                status,
+                charge,
+                voltage,
+            } => write!(
+                f,
+                "BatteryLevel. Status: {status}, Charge: {charge}, Voltage: {voltage}"
+            ),
+            ModemResponse::GNSSStatus(status) => write!(f, "GNSS-Status: {status:?}"),
+            ModemResponse::GNSSLocation(location) => write!(f, "GNSS-Location: {location:?}"),
+            ModemResponse::Clock { time, .. } => write!(f, "Clock: {time}"),
+            ModemResponse::Success => write!(f, "Success"),
+            ModemResponse::Error {
+                code: Some(code),
+                message,
+            } => write!(f, "Error {code}: {message}"),
+            ModemResponse::Error { message, .. } => write!(f, "Error: {message}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ModemStatus {
+    Startup,
+    Online,
+
+    /// The serial connection is alive but the radio is disabled (AT+CFUN is not 1).
+    RadioOff,
+    ShuttingDown,
+    Offline,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GNSSFixStatus {
+    Unknown,
+    NotFix,
+    Fix2D,
+    Fix3D,
+}
+impl TryFrom<&str> for GNSSFixStatus {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.trim() {
+            "Location Unknown" | "Unknown" => Ok(GNSSFixStatus::Unknown),
+            "Location Not Fix" | "Not Fix" => Ok(GNSSFixStatus::NotFix),
+            "Location 2D Fix" | "2D Fix" => Ok(GNSSFixStatus::Fix2D),
+            "Location 3D Fix" | "3D Fix" => Ok(GNSSFixStatus::Fix3D),
+            _ => Err(anyhow!("Invalid GNSS fix status: '{}'", value)),
+        }
+    }
+}
+impl From<u8> for GNSSFixStatus {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => GNSSFixStatus::NotFix,
+            1 => GNSSFixStatus::Fix2D,
+            2 => GNSSFixStatus::Fix3D,
+            _ => GNSSFixStatus::Unknown,
+        }
+    }
+}
+
+/// Which registration a status is for, serialized as a lowercase string over the API.
+/// The u8 mapping is only used for the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistrationDomain {
+    /// Circuit switched (calls and SMS), from AT+CREG.
+    Circuit,
+
+    /// Packet switched (data), from AT+CGREG.
+    Packet,
+}
+impl From<RegistrationDomain> for u8 {
+    fn from(domain: RegistrationDomain) -> Self {
+        match domain {
+            RegistrationDomain::Circuit => 0,
+            RegistrationDomain::Packet => 1,
+        }
+    }
+}
+impl TryFrom<u8> for RegistrationDomain {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(RegistrationDomain::Circuit),
+            1 => Ok(RegistrationDomain::Packet),
+            _ => bail!("Invalid registration domain: {value}"),
+        }
+    }
+}
+
+/// A stored network registration change, used to correlate send failures with coverage gaps.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkEvent {
+    pub event_id: i64,
+    pub modem_id: String,
+
+    /// None for changes stored before the domain was recorded.
+    pub domain: Option<RegistrationDomain>,
+    pub registration: u8,
+    pub registration_label: String,
+    pub created_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GNSSLocation {
+    pub run_status: bool,
+    pub fix_status: bool,
+    pub utc_time: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub msl_altitude: Option<f64>,
+    pub ground_speed: Option<f32>,
+    pub ground_course: Option<f32>,
+    pub fix_mode: GNSSFixStatus,
+    pub hdop: Option<f32>,
+    pub pdop: Option<f32>,
+    pub vdop: Option<f32>,
+    pub gps_in_view: Option<u8>,
+    pub gnss_used: Option<u8>,
+    pub glonass_in_view: Option<u8>,
+}
+impl TryFrom<Vec<&str>> for GNSSLocation {
+    type Error = anyhow::Error;
+
+    fn try_from(fields: Vec<&str>) -> Result<Self, Self::Error> {
+        if fields.len() < 15 {
+            bail!("Insufficient GNSS data fields got {}", fields.len());
+        }
+
+        // Based on: https://simcom.ee/documents/SIM868/SIM868_GNSS_Application%20Note_V1.00.pdf (2.3)
+        Ok(Self {
+            run_status: fields[0] == "1",
+            fix_status: fields[1] == "1",
+            utc_time: fields[2].to_string(),
+            latitude: fields[3].parse().ok(),
+            longitude: fields[4].parse().ok(),
+            msl_altitude: fields[5].parse().ok(),
+            ground_speed: fields[6].parse().ok(),
+            ground_course: fields[7].parse().ok(),
+            fix_mode: GNSSFixStatus::from(fields[8].parse::<u8>().unwrap_or(0)),
+            // Reserved1
+            hdop: fields[10].parse().ok(),
+            pdop: fields[11].parse().ok(),
+            vdop: fields[12].parse().ok(),
+            // Reserved2
+            gps_in_view: fields[14].parse().ok(),
+            gnss_used: fields[15].parse().ok(),
+            glonass_in_view: fields[16].parse().ok(),
+        })
+    }
+}
+impl Display for GNSSLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fn convert_opt<T: Display>(opt: &Option<T>) -> String {
+            match opt {
+                Some(value) => value.to_string(),
+                None => "None".to_string(),
+            }
+        }
+
+        write!(
+            f,
+            "Lat: {}, Lon: {}, Alt: {}, Speed: {}, Course: {}",
+            convert_opt(&self.latitude),
+            convert_opt(&self.longitude),
+            convert_opt(&self.msl_altitude),
+            convert_opt(&self.ground_speed),
+            convert_opt(&self.ground_course)
+        )
+    }
+}
@@ -0,0 +1,360 @@
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SMSMessage {
+    pub message_id: Option<i64>,
+    pub phone_number: String,
+    pub message_content: String,
+    pub message_reference: Option<u8>,
+    pub is_outgoing: bool,
+    pub status: SMSStatus,
+    pub created_at: Option<u64>,
+    pub completed_at: Option<u64>,
+
+    /// The configured modem that sent or received the message, unset for messages stored
+    /// before modems had ids.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modem_id: Option<String>,
+
+    /// The hex PDU an incoming message was decoded from, one per line for multipart messages.
+    /// Only stored with `store_raw_pdu` enabled, and only returned when fetching a single message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_pdu: Option<String>,
+
+    /// How the content is coded, `message_content` is hex encoded user data for `8bit` messages.
+    #[serde(default, skip_serializing_if = "SMSDataCoding::is_text")]
+    pub data_coding: SMSDataCoding,
+
+    /// Whether an incoming message has been marked read, outgoing messages always are.
+    #[serde(default)]
+    pub is_read: bool,
+}
+impl SMSMessage {
+    /// Returns a clone of the message with the message_id option replaced.
+    pub fn with_message_id(&self, id: Option<i64>) -> Self {
+        SMSMessage {
+            message_id: id,
+            ..self.clone()
+        }
+    }
+}
+
+/// The encoding to send a message with, `Auto` uses GSM 7-bit only if every character fits.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SMSEncoding {
+    #[default]
+    Auto,
+    Gsm7,
+    Ucs2,
+}
+
+/// Whether a message is text, or 8-bit binary data such as OTA configuration or a WAP push.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum SMSDataCoding {
+    #[default]
+    #[serde(rename = "text")]
+    Text,
+
+    #[serde(rename = "8bit")]
+    EightBit,
+}
+impl SMSDataCoding {
+    pub fn is_text(&self) -> bool {
+        matches!(self, SMSDataCoding::Text)
+    }
+}
+/// How 8-bit data is encoded into the text of a send request's content.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SMSContentEncoding {
+    #[default]
+    Hex,
+    Base64,
+}
+impl SMSContentEncoding {
+    pub fn is_hex(&self) -> bool {
+        matches!(self, SMSContentEncoding::Hex)
+    }
+}
+
+impl From<SMSDataCoding> for u8 {
+    fn from(data_coding: SMSDataCoding) -> Self {
+        match data_coding {
+            SMSDataCoding::Text => 0,
+            SMSDataCoding::EightBit => 1,
+        }
+    }
+}
+impl TryFrom<u8> for SMSDataCoding {
+    type Error = Error;
+
+    fn try_from(value: u8) -> anyhow::Result<Self, Self::Error> {
+        match value {
+            0 => Ok(SMSDataCoding::Text),
+            1 => Ok(SMSDataCoding::EightBit),
+            _ => Err(anyhow!("Invalid SMS data coding value: {}", value)),
+        }
+    }
+}
+
+/// Serialized as a snake_case string over the API, the u8 mapping is only used for the database.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SMSStatus {
+    Sent,
+    Delivered,
+    Received,
+    TemporaryFailure,
+    PermanentFailure,
+    Expired,
+
+    /// Encoded and stored as if sent, without touching the modem.
+    DryRun,
+
+    /// Accepted and queued for the modem, but not sent yet.
+    Pending,
+}
+impl SMSStatus {
+    /// The serialized snake_case name, for non-JSON outputs.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SMSStatus::Sent => "sent",
+            SMSStatus::Delivered => "delivered",
+            SMSStatus::Received => "received",
+            SMSStatus::TemporaryFailure => "temporary_failure",
+            SMSStatus::PermanentFailure => "permanent_failure",
+            SMSStatus::Expired => "expired",
+            SMSStatus::DryRun => "dry_run",
+            SMSStatus::Pending => "pending",
+        }
+    }
+}
+impl From<&SMSStatus> for u8 {
+    fn from(status: &SMSStatus) -> Self {
+        match status {
+            SMSStatus::Sent => 0,
+            SMSStatus::Delivered => 1,
+            SMSStatus::Received => 2,
+            SMSStatus::TemporaryFailure => 3,
+            SMSStatus::PermanentFailure => 4,
+            SMSStatus::Expired => 5,
+            SMSStatus::DryRun => 6,
+            SMSStatus::Pending => 7,
+        }
+    }
+}
+impl TryFrom<u8> for SMSStatus {
+    type Error = Error;
+
+    fn try_from(value: u8) -> anyhow::Result<Self, Self::Error> {
+        match value {
+            0 => Ok(SMSStatus::Sent),
+            1 => Ok(SMSStatus::Delivered),
+            2 => Ok(SMSStatus::Received),
+            3 => Ok(SMSStatus::TemporaryFailure),
+            4 => Ok(SMSStatus::PermanentFailure),
+            5 => Ok(SMSStatus::Expired),
+            6 => Ok(SMSStatus::DryRun),
+            7 => Ok(SMSStatus::Pending),
+            _ => Err(anyhow!("Invalid SMS status value: {}", value)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SMSDeliveryReport {
+    pub report_id: Option<i64>,
+    pub status: SMSStatus,
+    pub is_final: bool,
+    pub created_at: Option<u64>,
+}
+
+/// A phone number that messages won't be sent to, after it opted out or was added manually.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlocklistEntry {
+    pub phone_number: String,
+
+    /// The opt-out keyword received, or the reason given when added manually.
+    pub reason: Option<String>,
+    pub created_at: Option<u64>,
+}
+
+/// A phone number messages were exchanged with, and its latest message for a conversation list.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SMSConversation {
+    pub phone_number: String,
+    pub friendly_name: Option<String>,
+
+    /// The total number of messages sent to and received from the number.
+    pub message_count: u64,
+
+    /// The number of incoming messages from the number that haven't been marked read.
+    pub unread_count: u64,
+
+    pub latest_message_id: i64,
+
+    /// The start of the latest message's content, or None if it couldn't be decrypted.
+    pub preview: Option<String>,
+    pub is_outgoing: bool,
+    pub created_at: Option<u64>,
+}
+
+/// A delivery report as received from the network, before it's matched to a message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncomingDeliveryReport {
+    pub phone_number: String,
+    pub reference_id: u8,
+
+    /// The raw TP-Status value, see 3GPP TS 23.040 (9.2.3.15).
+    pub status: u8,
+
+    /// A readable description of `status`, `unknown` for reserved or service centre specific values.
+    #[serde(default)]
+    pub status_label: String,
+
+    #[serde(default)]
+    pub category: DeliveryStatusCategory,
+}
+impl IncomingDeliveryReport {
+    /// A report for a raw TP-Status value, with its label and category.
+    pub fn new(phone_number: String, reference_id: u8, status: u8) -> Self {
+        Self {
+            phone_number,
+            reference_id,
+            status,
+            status_label: delivery_status_label(status).to_string(),
+            category: DeliveryStatusCategory::from(status),
+        }
+    }
+}
+
+/// What a delivery report TP-Status means for the message, from the range it's in.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeliveryStatusCategory {
+    /// The transaction completed, `0x00` to `0x1F`.
+    Delivered,
+
+    /// A temporary error the service centre is still retrying, `0x20` to `0x3F`.
+    Pending,
+
+    /// A temporary error the service centre has stopped retrying, `0x60` to `0x7F`.
+    FailedTemporary,
+
+    /// A permanent error, `0x40` to `0x5F`.
+    FailedPermanent,
+
+    /// A reserved value, or a category added by a newer server.
+    #[default]
+    #[serde(other)]
+    Unknown,
+}
+impl From<u8> for DeliveryStatusCategory {
+    fn from(status: u8) -> Self {
+        match status {
+            0x00..=0x1F => DeliveryStatusCategory::Delivered,
+            0x20..=0x3F => DeliveryStatusCategory::Pending,
+            0x40..=0x5F => DeliveryStatusCategory::FailedPermanent,
+            0x60..=0x7F => DeliveryStatusCategory::FailedTemporary,
+            _ => DeliveryStatusCategory::Unknown,
+        }
+    }
+}
+
+/// Human readable label for a delivery report TP-Status, see 3GPP TS 23.040 (9.2.3.15).
+pub fn delivery_status_label(status: u8) -> &'static str {
+    match status {
+        0x00 => "received by recipient",
+        0x01 => "forwarded, delivery unconfirmed",
+        0x02 => "replaced by service centre",
+        0x20 | 0x60 => "congestion",
+        0x21 | 0x61 => "recipient busy",
+        0x22 | 0x62 => "no response from recipient",
+        0x23 | 0x63 => "service rejected",
+        0x24 | 0x44 | 0x64 => "quality of service not available",
+        0x25 | 0x65 => "error in recipient",
+        0x40 => "remote procedure error",
+        0x41 => "incompatible destination",
+        0x42 => "connection rejected by recipient",
+        0x43 => "not obtainable",
+        0x45 => "no interworking available",
+        0x46 => "validity period expired",
+        0x47 => "deleted by sender",
+        0x48 => "deleted by service centre",
+        0x49 => "message does not exist",
+        _ => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sms_status_serialization() {
+        let cases = [
+            (SMSStatus::Sent, "\"sent\""),
+            (SMSStatus::Delivered, "\"delivered\""),
+            (SMSStatus::Received, "\"received\""),
+            (SMSStatus::TemporaryFailure, "\"temporary_failure\""),
+            (SMSStatus::PermanentFailure, "\"permanent_failure\""),
+            (SMSStatus::Expired, "\"expired\""),
+            (SMSStatus::DryRun, "\"dry_run\""),
+            (SMSStatus::Pending, "\"pending\""),
+        ];
+        for (status, expected) in cases {
+            assert_eq!(serde_json::to_string(&status).unwrap(), expected);
+            assert_eq!(format!("\"{}\"", status.as_str()), expected);
+
+            // The database mapping round trips.
+            let value = u8::from(&status);
+            assert_eq!(
+                SMSStatus::try_from(value).unwrap().as_str(),
+                status.as_str()
+            );
+        }
+        assert!(SMSStatus::try_from(8).is_err());
+    }
+
+    #[test]
+    fn test_delivery_status_category() {
+        let cases = [
+            (
+                0x00,
+                DeliveryStatusCategory::Delivered,
+                "received by recipient",
+            ),
+            (0x1F, DeliveryStatusCategory::Delivered, "unknown"),
+            (0x21, DeliveryStatusCategory::Pending, "recipient busy"),
+            (
+                0x46,
+                DeliveryStatusCategory::FailedPermanent,
+                "validity period expired",
+            ),
+            (
+                0x62,
+                DeliveryStatusCategory::FailedTemporary,
+                "no response from recipient",
+            ),
+            (0x80, DeliveryStatusCategory::Unknown, "unknown"),
+            (0xFF, DeliveryStatusCategory::Unknown, "unknown"),
+        ];
+        for (status, category, label) in cases {
+            let report = IncomingDeliveryReport::new("+44".to_string(), 1, status);
+            assert_eq!(report.category, category, "Category for {status:#04x}");
+            assert_eq!(report.status_label, label, "Label for {status:#04x}");
+        }
+
+        // The raw value is kept, and a category from a newer server doesn't fail to parse.
+        let report = IncomingDeliveryReport::new("+44".to_string(), 1, 0x62);
+        let json = serde_json::to_value(report).unwrap();
+        assert_eq!(json["status"], 0x62);
+        assert_eq!(json["category"], "failed-temporary");
+
+        let newer = r#"{"phone_number":"+44","reference_id":1,"status":0,"category":"queued"}"#;
+        let report: IncomingDeliveryReport = serde_json::from_str(newer).unwrap();
+        assert_eq!(report.category, DeliveryStatusCategory::Unknown);
+    }
+}
@@ -1,10 +1,12 @@
-use crate::config::AppConfig;
+use crate::config::{AppConfig, ModemConfig};
 use crate::events::{Event, EventBroadcaster};
+use crate::modem::pool::ModemPool;
 use crate::modem::types::ModemIncomingMessage;
 use crate::modem::ModemManager;
-use crate::sms::{SMSManager, SMSReceiver};
+use crate::sms::{MultipartOptions, SMSManager, SMSReceiver};
 use crate::TracingReloadHandle;
 use anyhow::{bail, Result};
+use std::path::PathBuf;
 use std::time::Duration;
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::task::JoinHandle;
@@ -17,6 +19,19 @@ use crate::{
     http::{create_app, websocket::WebSocketManager},
 };
 
+#[cfg(feature = "http-server")]
+use std::{collections::HashMap, net::SocketAddr};
+
+use crate::webhooks::WebhookSender;
+
+#[cfg(unix)]
+use crate::config::{
+    AutoReplyConfig, DatabaseConfig, OptOutConfig, RoutingConfig, WebhookQueueConfig,
+};
+
+/// How long queued webhook events are given to be delivered once the app is stopping.
+const WEBHOOK_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
 #[cfg(feature = "sentry")]
 pub type SentryGuard = Option<sentry::ClientInitGuard>;
 
@@ -25,44 +40,106 @@ pub type SentryGuard = Option<()>;
 
 pub struct AppHandles {
     tasks: Vec<(&'static str, JoinHandle<()>)>,
+    webhooks: Option<WebhookSender>,
     _sentry_guard: SentryGuard,
 }
 impl AppHandles {
     pub async fn new(
         config: AppConfig,
+        _config_path: Option<PathBuf>,
         _tracing_reload: TracingReloadHandle,
         _sentry_guard: SentryGuard,
     ) -> Result<AppHandles> {
         let mut tasks = Vec::new();
 
-        // Start modem manager
-        let (mut modem, main_rx) = ModemManager::new(&config);
-        let (modem_handle, modem_sender) = match modem.start().await {
-            Ok(handle) => (handle, modem.get_sender()?),
-            Err(e) => bail!("Failed to start ModemManager: {:?}", e),
-        };
-        tasks.push(("Modem Handler", modem_handle));
+        // Start a modem manager for every configured modem, each with its own channel.
+        let mut modem_senders = Vec::with_capacity(config.modems.len());
+        let mut modem_channels = Vec::with_capacity(config.modems.len());
+        for modem_config in &config.modems {
+            let (mut modem, main_rx) = ModemManager::new(modem_config);
+            let (modem_handle, modem_sender) = match modem.start().await {
+                Ok(handle) => (handle, modem.get_sender()?),
+                Err(e) => bail!(
+                    "Failed to start ModemManager '{}': {:?}",
+                    modem_config.id,
+                    e
+                ),
+            };
+            tasks.push(("Modem Handler", modem_handle));
+            modem_senders.push((modem_config.id.clone(), modem_sender));
+            modem_channels.push((modem_config, main_rx));
+        }
 
         // Create event broadcaster (and webhook worker handle).
         let (broadcaster, webhooks_handle) = EventBroadcaster::new(&config);
         if let Some(webhooks_worker) = webhooks_handle {
             tasks.push(("Webhooks Worker", webhooks_worker));
         }
+        let webhook_sender = broadcaster.as_ref().and_then(|b| b.webhooks.clone());
+
+        // Apply reloadable config sections on SIGHUP.
+        #[cfg(unix)]
+        tasks.push((
+            "Config Reload",
+            Self::start_config_reload(
+                _config_path,
+                RestartRequiredConfig::new(&config),
+                webhook_sender.clone(),
+                _tracing_reload.clone(),
+            )?,
+        ));
 
         // Setup SMS manager and receivers.
-        let sms_manager =
-            SMSManager::connect(config.database, modem_sender, broadcaster.clone()).await?;
+        let sms_manager = SMSManager::connect(
+            config.database.clone(),
+            ModemPool::new(modem_senders, config.routing.clone())?,
+            broadcaster.clone(),
+            config.opt_out.clone(),
+            &config.auto_replies,
+        )
+        .await?;
 
-        let (cleanup_handle, channel_handle) =
-            Self::start_sms_receiver(main_rx, sms_manager.clone(), broadcaster.clone());
-        tasks.push(("Modem Cleanup", cleanup_handle));
-        tasks.push(("Modem Channel", channel_handle));
+        // Fetched before anything new can be queued, so only messages left queued when the server
+        // last stopped are recovered. It's a one-off task, so isn't one of the tasks waited on.
+        match sms_manager.queued_messages().await {
+            Ok(queued) if !queued.is_empty() => {
+                let sms_manager = sms_manager.clone();
+                tokio::spawn(async move { sms_manager.recover_queued_messages(queued).await });
+            }
+            Ok(_) => {}
+            Err(e) => error!("Failed to get the messages queued before stopping: {e:?}"),
+        }
+
+        if config.database.retain_days > 0 {
+            tasks.push((
+                "Message Pruning",
+                Self::start_message_pruning(sms_manager.clone()),
+            ));
+        }
+
+        for (modem_config, main_rx) in modem_channels {
+            let (cleanup_handle, channel_handle) = Self::start_sms_receiver(
+                main_rx,
+                sms_manager.clone(),
+                broadcaster.clone(),
+                modem_config,
+            );
+            tasks.push(("Modem Cleanup", cleanup_handle));
+            tasks.push(("Modem Channel", channel_handle));
+        }
 
         // Setup HTTP server if enabled.
+        #[cfg(feature = "http-server")]
+        let (websocket, webhooks) = broadcaster.map_or((None, None), |broadcaster| {
+            (broadcaster.websocket, broadcaster.webhooks)
+        });
+
         #[cfg(feature = "http-server")]
         if let Some(http_handle) = Self::start_http_server(
             config.http,
-            broadcaster.and_then(|broadcaster| broadcaster.websocket),
+            websocket,
+            webhooks,
+            config.templates,
             sms_manager,
             _sentry_guard.is_some(),
             _tracing_reload,
@@ -72,6 +149,7 @@ impl AppHandles {
 
         Ok(AppHandles {
             tasks,
+            webhooks: webhook_sender,
             _sentry_guard,
         })
     }
@@ -94,14 +172,37 @@ impl AppHandles {
         // Wait for any task to complete. All handles are boxed, so when dropped they are cancelled.
         let (_, _, remaining) = futures::future::select_all(futures).await;
         drop(remaining);
+
+        // Queued webhook events, including those for ordered webhooks, are sent before exiting.
+        if let Some(webhooks) = self.webhooks {
+            webhooks.shutdown(WEBHOOK_SHUTDOWN_TIMEOUT).await;
+        }
+    }
+
+    /// Prune old messages on startup, then every 10 minutes.
+    fn start_message_pruning(sms_manager: SMSManager) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = interval(Duration::from_secs(600)); // 10 minutes
+
+            loop {
+                interval.tick().await;
+                sms_manager.prune_messages().await;
+            }
+        })
     }
 
     fn start_sms_receiver(
         mut main_rx: UnboundedReceiver<ModemIncomingMessage>,
         sms_manager: SMSManager,
         broadcaster: Option<EventBroadcaster>,
+        modem_config: &ModemConfig,
     ) -> (JoinHandle<()>, JoinHandle<()>) {
-        let receiver = SMSReceiver::new(sms_manager);
+        let receiver = SMSReceiver::new(
+            sms_manager,
+            modem_config.id.clone(),
+            modem_config.auto_reject_calls,
+            MultipartOptions::from(modem_config),
+        );
 
         // Cleanup task
         let mut cleanup_receiver = receiver.clone();
@@ -111,6 +212,7 @@ impl AppHandles {
             loop {
                 interval.tick().await;
                 cleanup_receiver.cleanup_stalled_multipart().await;
+                cleanup_receiver.expire_pending_messages().await;
             }
         });
 
@@ -144,10 +246,20 @@ impl AppHandles {
                     Err(e) => warn!("Failed to update delivery report: {e:?}"),
                 }
             }
-            ModemIncomingMessage::ModemStatusUpdate { previous, current } => {
+            ModemIncomingMessage::ModemStatusUpdate {
+                previous,
+                current,
+                reconnect_backoff_secs,
+            } => {
+                receiver.handle_status_update(current.clone());
                 if let Some(broadcaster) = broadcaster {
                     broadcaster
-                        .broadcast(Event::ModemStatusUpdate { previous, current })
+                        .broadcast(Event::ModemStatusUpdate {
+                            previous,
+                            current,
+                            modem_id: Some(receiver.modem_id().to_string()),
+                            reconnect_backoff_secs,
+                        })
                         .await;
                 }
             }
@@ -158,14 +270,105 @@ impl AppHandles {
                         .await;
                 }
             }
-            _ => warn!("Unhandled message type: {message:?}"),
+            ModemIncomingMessage::Ring => receiver.handle_ring(),
+            ModemIncomingMessage::IncomingCall(phone_number) => {
+                match receiver.handle_incoming_call(phone_number).await {
+                    Some(Ok(call_id)) => debug!("Stored incoming call #{call_id}"),
+                    Some(Err(e)) => error!("Failed to store incoming call: {e:?}"),
+                    None => debug!("Incoming call is already stored"),
+                }
+            }
+            ModemIncomingMessage::CallEnded => receiver.handle_call_ended(),
+            ModemIncomingMessage::LateSendResult(reference_id) => {
+                match receiver.handle_late_send_result(reference_id).await {
+                    Ok(Some(message_id)) => debug!("Recovered timed out message #{message_id}"),
+                    Ok(None) => {
+                        warn!("No timed out message matches late send reference {reference_id}")
+                    }
+                    Err(e) => error!("Failed to recover timed out message: {e:?}"),
+                }
+            }
+            ModemIncomingMessage::RawUnsolicited(content) => {
+                if let Some(broadcaster) = broadcaster {
+                    broadcaster.broadcast(Event::RawUnsolicited(content)).await;
+                }
+            }
+            ModemIncomingMessage::NetworkStatusChange {
+                domain,
+                registration,
+            } => match receiver
+                .handle_network_status_change(domain, registration)
+                .await
+            {
+                Ok(Some(event_id)) => {
+                    debug!("Network status changed: {domain:?} {registration} (event #{event_id})")
+                }
+                Ok(None) => {
+                    debug!("Network status not stored as a new change: {domain:?} {registration}")
+                }
+                Err(e) => error!("Failed to store network status change: {e:?}"),
+            },
         }
     }
 
+    #[cfg(unix)]
+    fn start_config_reload(
+        config_path: Option<PathBuf>,
+        current: RestartRequiredConfig,
+        webhooks: Option<WebhookSender>,
+        tracing_reload: TracingReloadHandle,
+    ) -> Result<JoinHandle<()>> {
+        use tokio::signal::unix::{signal, SignalKind};
+        use tracing_subscriber::EnvFilter;
+
+        let mut hangup = signal(SignalKind::hangup())?;
+        let handle = tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                info!("Received SIGHUP, reloading config");
+
+                // Reject the whole reload if anything is invalid, leaving the current config running.
+                let config = match AppConfig::load(config_path.clone())
+                    .and_then(|config| config.validate().map(|_| config))
+                {
+                    Ok(config) => config,
+                    Err(e) => {
+                        error!("Rejected config reload, keeping current config: {e:#}");
+                        continue;
+                    }
+                };
+
+                for section in current.changed_sections(&config) {
+                    warn!("Config section [{section}] has changed and requires restart");
+                }
+
+                let filter = match &config.log_level {
+                    Some(log_level) => EnvFilter::new(log_level),
+                    None => EnvFilter::from_default_env(),
+                };
+                info!("Setting log level to {filter} from config");
+                if let Err(e) = tracing_reload.reload(filter) {
+                    error!("Failed to reload log level: {e}");
+                }
+
+                match (&webhooks, config.webhooks) {
+                    (Some(sender), webhooks) => sender.reload(webhooks.unwrap_or_default()),
+                    (None, Some(webhooks)) if !webhooks.is_empty() => {
+                        warn!("Webhooks were not enabled at startup, adding them requires restart")
+                    }
+                    (None, _) => {}
+                }
+            }
+        });
+
+        Ok(handle)
+    }
+
     #[cfg(feature = "http-server")]
     fn start_http_server(
         config: HTTPConfig,
         websocket: Option<WebSocketManager>,
+        webhooks: Option<WebhookSender>,
+        templates: HashMap<String, String>,
         sms_manager: SMSManager,
         _sentry_enabled: bool,
         _tracing_reload: TracingReloadHandle,
@@ -181,6 +384,8 @@ impl AppHandles {
         let app = create_app(
             config,
             websocket,
+            webhooks,
+            templates,
             sms_manager,
             _sentry_enabled,
             _tracing_reload,
@@ -204,7 +409,7 @@ impl AppHandles {
                             .await
                             .expect("Failed to load rustls TLS certificates!");
                             axum_server::bind_rustls(address, tls)
-                                .serve(app.into_make_service())
+                                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
                                 .await
                                 .map_err(anyhow::Error::from)
                         }
@@ -217,7 +422,7 @@ impl AppHandles {
                             )
                             .expect("Failed to load openssl TLS certificates!");
                             axum_server::bind_openssl(address, tls)
-                                .serve(app.into_make_service())
+                                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
                                 .await
                                 .map_err(anyhow::Error::from)
                         }
@@ -231,7 +436,7 @@ impl AppHandles {
                 None => {
                     info!("Starting HTTP (insecure) server on {address}");
                     axum_server::bind(address)
-                        .serve(app.into_make_service())
+                        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
                         .await
                         .map_err(anyhow::Error::from)
                 }
@@ -245,3 +450,73 @@ impl AppHandles {
         Ok(Some(handle))
     }
 }
+
+/// Config sections that are only read at startup, kept to report changes on reload.
+#[cfg(unix)]
+struct RestartRequiredConfig {
+    modems: Vec<ModemConfig>,
+    routing: Option<RoutingConfig>,
+    opt_out: Option<OptOutConfig>,
+    auto_replies: Vec<AutoReplyConfig>,
+    database: DatabaseConfig,
+    webhook_queue: WebhookQueueConfig,
+
+    #[cfg(feature = "http-server")]
+    http: HTTPConfig,
+
+    #[cfg(feature = "http-server")]
+    templates: HashMap<String, String>,
+}
+#[cfg(unix)]
+impl RestartRequiredConfig {
+    fn new(config: &AppConfig) -> Self {
+        Self {
+            modems: config.modems.clone(),
+            routing: config.routing.clone(),
+            opt_out: config.opt_out.clone(),
+            auto_replies: config.auto_replies.clone(),
+            database: config.database.clone(),
+            webhook_queue: config.webhook_queue.clone(),
+
+            #[cfg(feature = "http-server")]
+            http: config.http.clone(),
+
+            #[cfg(feature = "http-server")]
+            templates: config.templates.clone(),
+        }
+    }
+
+    fn changed_sections(&self, config: &AppConfig) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.modems != config.modems {
+            changed.push("modem");
+        }
+        if self.routing != config.routing {
+            changed.push("routing");
+        }
+        if self.opt_out != config.opt_out {
+            changed.push("opt_out");
+        }
+        if self.auto_replies != config.auto_replies {
+            changed.push("auto_reply");
+        }
+        if self.database != config.database {
+            changed.push("database");
+        }
+        if self.webhook_queue != config.webhook_queue {
+            changed.push("webhook_queue");
+        }
+
+        #[cfg(feature = "http-server")]
+        if self.http != config.http {
+            changed.push("http");
+        }
+
+        #[cfg(feature = "http-server")]
+        if self.templates != config.templates {
+            changed.push("templates");
+        }
+
+        changed
+    }
+}
@@ -1,5 +1,7 @@
 use crate::events::EventType;
-use anyhow::{Context, Result};
+use crate::modem::types::PreferredRat;
+use crate::types::{validity_period_to_duration, DEFAULT_VALIDITY_PERIOD};
+use anyhow::{bail, Context, Result};
 use base64::engine::general_purpose;
 use base64::Engine;
 use reqwest::header::HeaderMap;
@@ -19,15 +21,44 @@ pub struct AppConfig {
     #[cfg(feature = "sentry")]
     pub sentry: Option<SentryConfig>,
 
+    /// Either a single `[modem]` table or several `[[modem]]` tables, each with a unique `id`.
+    #[serde(
+        default = "default_modems",
+        rename = "modem",
+        deserialize_with = "deserialize_one_or_many"
+    )]
+    pub modems: Vec<ModemConfig>,
+
+    #[serde(default)]
+    pub routing: Option<RoutingConfig>,
+
+    /// Blocklist senders that reply with an opt-out keyword, disabled without the section.
     #[serde(default)]
-    pub modem: ModemConfig,
+    pub opt_out: Option<OptOutConfig>,
+
+    /// Rules replying to incoming messages, each an `[[auto_reply]]` table. The first match is used.
+    #[serde(default, rename = "auto_reply")]
+    pub auto_replies: Vec<AutoReplyConfig>,
 
     #[cfg(feature = "http-server")]
     #[serde(default)]
     pub http: HTTPConfig,
 
+    /// Named message bodies with `{placeholder}` tokens, sent by name over HTTP.
+    #[cfg(feature = "http-server")]
+    #[serde(default)]
+    pub templates: HashMap<String, String>,
+
     #[serde(default)]
     pub webhooks: Option<Vec<ConfiguredWebhook>>,
+
+    /// The webhook queue capacity, and how many webhooks are sent to at once and for how long.
+    #[serde(default)]
+    pub webhook_queue: WebhookQueueConfig,
+
+    /// Tracing filter directive, overriding RUST_LOG. eg: "info,sms_server=debug"
+    #[serde(default)]
+    pub log_level: Option<String>,
 }
 impl AppConfig {
     pub fn load(config_filepath: Option<PathBuf>) -> Result<Self> {
@@ -41,12 +72,127 @@ impl AppConfig {
 
         Ok(config)
     }
+
+    /// Check the loaded config for problems that would otherwise only surface at runtime.
+    /// All problems are reported together rather than failing on the first.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+
+        if let Some(log_level) = &self.log_level {
+            if let Err(e) = tracing_subscriber::EnvFilter::from_str(log_level) {
+                problems.push(format!("Invalid log_level '{log_level}': {e}"));
+            }
+        }
+
+        if self.modems.is_empty() {
+            problems.push("At least one modem must be configured".to_string());
+        }
+        for (idx, modem) in self.modems.iter().enumerate() {
+            if self.modems[..idx].iter().any(|other| other.id == modem.id) {
+                problems.push(format!("Modem id '{}' is used more than once", modem.id));
+            }
+            problems.extend(modem.problems());
+        }
+        if let Some(routing) = &self.routing {
+            problems.extend(routing.problems(&self.modems));
+        }
+        if let Some(opt_out) = &self.opt_out {
+            problems.extend(opt_out.problems());
+        }
+        for (idx, auto_reply) in self.auto_replies.iter().enumerate() {
+            problems.extend(auto_reply.problems(idx));
+        }
+
+        if self.database.max_connections == 0 {
+            problems.push("Database max_connections must be at least 1".to_string());
+        }
+        if self.database.min_connections > self.database.max_connections {
+            problems.push(format!(
+                "Database min_connections ({}) is greater than max_connections ({})",
+                self.database.min_connections, self.database.max_connections
+            ));
+        }
+
+        #[cfg(feature = "http-server")]
+        for (name, template) in &self.templates {
+            if let Err(e) = crate::templates::validate(template) {
+                problems.push(format!("Template '{name}' is invalid: {e}"));
+            }
+        }
+
+        #[cfg(feature = "http-server")]
+        if self.http.enabled {
+            if let Some(tls) = &self.http.tls {
+                for path in [&tls.certificate_path, &tls.key_path] {
+                    if !path.is_file() {
+                        problems.push(format!("TLS file does not exist: {}", path.display()));
+                    }
+                }
+            }
+            if let Some(cors) = &self.http.cors {
+                problems.extend(cors.problems());
+            }
+            if self.http.max_request_bytes == 0 {
+                problems.push("HTTP max_request_bytes must be at least 1".to_string());
+            }
+            if self.http.max_bulk_recipients == 0 {
+                problems.push("HTTP max_bulk_recipients must be at least 1".to_string());
+            }
+            if self.http.require_authentication {
+                if let Err(e) = crate::http::load_auth_tokens() {
+                    problems.push(e.to_string());
+                }
+            }
+        }
+
+        if self.webhook_queue.capacity == Some(0) {
+            problems.push("Webhook queue capacity must be at least 1".to_string());
+        }
+        if self.webhook_queue.concurrency == 0 {
+            problems.push("Webhook concurrency must be at least 1".to_string());
+        }
+        if self.webhook_queue.timeout_secs == 0 {
+            problems.push("Webhook timeout must be at least 1 second".to_string());
+        }
+        for (idx, webhook) in self.webhooks.iter().flatten().enumerate() {
+            if let Err(e) = reqwest::Url::parse(&webhook.url) {
+                problems.push(format!(
+                    "Webhook #{idx} has an invalid URL '{}': {e}",
+                    webhook.url
+                ));
+            }
+            if webhook.events.is_empty() {
+                problems.push(format!("Webhook #{idx} has no events configured"));
+            }
+            if let Err(e) = webhook.get_header_map() {
+                problems.push(format!("Webhook #{idx} has an invalid header: {e}"));
+            }
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+        bail!(
+            "Found {} config problem(s):\n  - {}",
+            problems.len(),
+            problems.join("\n  - ")
+        )
+    }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct ModemConfig {
-    #[serde(default = "default_modem_device")]
-    pub device: String,
+    /// Identifies the modem when routing sends and in stored messages.
+    #[serde(default = "default_modem_id")]
+    pub id: String,
+
+    /// Candidate device paths or glob patterns (eg: `/dev/serial/by-id/usb-SimTech*`), tried in order.
+    /// A single path is also accepted.
+    #[serde(
+        default = "default_modem_device",
+        deserialize_with = "deserialize_one_or_many"
+    )]
+    pub device: Vec<String>,
 
     #[serde(default = "default_modem_baud")]
     pub baud_rate: u32,
@@ -67,6 +213,74 @@ pub struct ModemConfig {
     #[serde(default = "default_modem_read_buffer_size")]
     pub line_buffer_size: usize,
 
+    /// Broadcast any unhandled idle line as a RawUnsolicited event.
+    #[serde(default = "default_false")]
+    pub raw_unsolicited_events: bool,
+
+    /// Enable caller identification (AT+CLIP) to log and broadcast incoming calls.
+    #[serde(default = "default_false")]
+    pub incoming_calls_enabled: bool,
+
+    /// Hang up incoming calls on their first RING, whether or not they're logged.
+    #[serde(default = "default_false")]
+    pub auto_reject_calls: bool,
+
+    /// Strip a trailing `@` from every incoming multipart message part, for modems that
+    /// report an incorrect user data length. Legitimate trailing `@` characters are lost.
+    #[serde(default = "default_false")]
+    pub strip_multipart_padding: bool,
+
+    /// The most parts an incoming multipart message may claim, larger messages are rejected.
+    #[serde(default = "default_modem_max_multipart_parts")]
+    pub max_multipart_parts: u8,
+
+    /// The most content bytes an incoming multipart message may assemble before it's dropped.
+    #[serde(default = "default_modem_max_total_content_bytes")]
+    pub max_total_content_bytes: usize,
+
+    /// New message indication settings (AT+CNMI). A `<mt>` of 1 stores incoming messages
+    /// and notifies with +CMTI, which are then read rather than routed directly.
+    #[serde(default = "default_modem_cnmi")]
+    pub cnmi: String,
+
+    /// Relative validity period (TP-VP) for sent messages without their own, also set with AT+CSMP.
+    #[serde(default = "default_modem_validity_period")]
+    pub default_validity_period: u8,
+
+    /// Delete stored messages and status reports (AT+CMGD) once they have been read and decoded.
+    #[serde(default = "default_true")]
+    pub delete_stored_messages: bool,
+
+    /// SMS service centre number to set on the modem, otherwise the SIM default is used.
+    #[serde(default)]
+    pub smsc: Option<String>,
+
+    /// Numeric operator code (MCC+MNC) to manually register with on startup,
+    /// falling back to automatic selection if it isn't available.
+    #[serde(default)]
+    pub preferred_operator: Option<String>,
+
+    /// Preferred radio access technology set at startup (AT+CNMP), where supported.
+    #[serde(default)]
+    pub preferred_rat: Option<PreferredRat>,
+
+    /// LTE CAT-M bands to restrict the modem to at startup (AT+CBANDCFG), where supported.
+    #[serde(default)]
+    pub lte_bands: Option<Vec<u16>>,
+
+    /// Let the network set the modem clock at startup (AT+CTZU=1), where supported.
+    #[serde(default = "default_false")]
+    pub network_time_sync: bool,
+
+    /// Send `AT` after this many seconds without a command, to detect a modem that stopped responding.
+    /// A value of 0 disables the heartbeat.
+    #[serde(default = "default_modem_heartbeat_interval_secs")]
+    pub heartbeat_interval_secs: u64,
+
+    /// Consecutive heartbeats without an OK before the modem is considered offline and reconnected.
+    #[serde(default = "default_modem_heartbeat_failures_before_offline")]
+    pub heartbeat_failures_before_offline: u32,
+
     #[serde(default = "default_false")]
     #[cfg(feature = "gpio")]
     pub gpio_enabled: bool,
@@ -79,9 +293,226 @@ pub struct ModemConfig {
     #[cfg(feature = "gpio")]
     pub gpio_repower: bool,
 }
+
+/// Least-cost routing, sending each message with the modem whose SIM suits its destination.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RoutingConfig {
+    /// Destination number prefixes (eg: `+44`) to the id of the modem that sends to them.
+    /// The longest matching prefix is used.
+    #[serde(default)]
+    pub prefixes: HashMap<String, String>,
+
+    /// The modem for destinations without a matching prefix, or whose modem isn't online.
+    /// Defaults to the first configured modem.
+    #[serde(default)]
+    pub fallback: Option<String>,
+}
+impl RoutingConfig {
+    fn problems(&self, modems: &[ModemConfig]) -> Vec<String> {
+        let mut problems = Vec::new();
+        let is_modem = |id: &String| modems.iter().any(|modem| &modem.id == id);
+
+        let mut prefixes: Vec<_> = self.prefixes.iter().collect();
+        prefixes.sort_unstable();
+        for (prefix, modem_id) in prefixes {
+            let digits = prefix.strip_prefix('+').unwrap_or_default();
+            if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+                problems.push(format!(
+                    "Routing prefix '{prefix}' must be a + followed by digits, eg: +44"
+                ));
+            }
+            if !is_modem(modem_id) {
+                problems.push(format!(
+                    "Routing prefix '{prefix}' uses unknown modem '{modem_id}'"
+                ));
+            }
+        }
+        if let Some(fallback) = self.fallback.as_ref().filter(|id| !is_modem(id)) {
+            problems.push(format!("Routing fallback uses unknown modem '{fallback}'"));
+        }
+        problems
+    }
+}
+
+/// Keywords that add or remove the sender of an incoming message from the blocklist.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct OptOutConfig {
+    #[serde(default = "default_opt_out_keywords")]
+    pub keywords: Vec<String>,
+
+    #[serde(default = "default_opt_out_resubscribe_keywords")]
+    pub resubscribe_keywords: Vec<String>,
+}
+impl OptOutConfig {
+    /// Get the opt-out keyword an incoming message consists of, ignoring case and whitespace.
+    pub fn opt_out_keyword(&self, content: &str) -> Option<&str> {
+        find_keyword(&self.keywords, content)
+    }
+
+    /// Get the resubscribe keyword an incoming message consists of, ignoring case and whitespace.
+    pub fn resubscribe_keyword(&self, content: &str) -> Option<&str> {
+        find_keyword(&self.resubscribe_keywords, content)
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self
+            .keywords
+            .iter()
+            .all(|keyword| keyword.trim().is_empty())
+        {
+            problems.push("Opt-out keywords must not be empty".to_string());
+        }
+        for keyword in &self.resubscribe_keywords {
+            if self.opt_out_keyword(keyword).is_some() {
+                problems.push(format!(
+                    "Opt-out keyword '{keyword}' is also a resubscribe keyword"
+                ));
+            }
+        }
+        problems
+    }
+}
+
+fn find_keyword<'a>(keywords: &'a [String], content: &str) -> Option<&'a str> {
+    keywords
+        .iter()
+        .map(|keyword| keyword.trim())
+        .find(|keyword| is_keyword(keyword, content))
+}
+
+/// Check if a message consists of a keyword, ignoring case and surrounding whitespace.
+pub fn is_keyword(keyword: &str, content: &str) -> bool {
+    let keyword = keyword.trim();
+    !keyword.is_empty() && keyword.to_lowercase() == content.trim().to_lowercase()
+}
+
+/// Reply to incoming messages that match a keyword or regex, from the modem that received them.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AutoReplyConfig {
+    /// Match messages consisting of this keyword, ignoring case and surrounding whitespace.
+    #[serde(default)]
+    pub keyword: Option<String>,
+
+    /// Match messages containing this regex. Named groups can be used in the response.
+    #[serde(default)]
+    pub regex: Option<String>,
+
+    /// The reply, with `{phone_number}`, `{message}` and named regex group placeholders.
+    pub response: String,
+
+    /// Seconds before the same sender can get another reply from this rule, so that two
+    /// auto-responders can't reply to each other in a loop.
+    #[serde(default = "default_auto_reply_cooldown_secs")]
+    pub cooldown_secs: u64,
+}
+impl AutoReplyConfig {
+    /// Vars always available to a response, alongside named regex groups.
+    pub const VARS: [&'static str; 2] = ["phone_number", "message"];
+
+    fn problems(&self, idx: usize) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut vars = Self::VARS.map(str::to_string).to_vec();
+        match (&self.keyword, &self.regex) {
+            (Some(keyword), None) if keyword.trim().is_empty() => {
+                problems.push(format!("Auto-reply #{idx} keyword must not be empty"));
+            }
+            (Some(_), None) => {}
+            (None, Some(regex)) => match regex::Regex::new(regex) {
+                Ok(regex) => vars.extend(regex.capture_names().flatten().map(str::to_string)),
+                Err(e) => problems.push(format!("Auto-reply #{idx} has an invalid regex: {e}")),
+            },
+            _ => problems.push(format!(
+                "Auto-reply #{idx} must have either a keyword or a regex"
+            )),
+        }
+
+        match crate::templates::placeholders(&self.response) {
+            Ok(placeholders) => {
+                for placeholder in placeholders {
+                    if !vars.iter().any(|var| var == placeholder) {
+                        problems.push(format!(
+                            "Auto-reply #{idx} response uses unknown placeholder '{{{placeholder}}}'"
+                        ));
+                    }
+                }
+            }
+            Err(e) => problems.push(format!("Auto-reply #{idx} response is invalid: {e}")),
+        }
+        if self.cooldown_secs == 0 {
+            problems.push(format!(
+                "Auto-reply #{idx} cooldown_secs must be at least 1"
+            ));
+        }
+        problems
+    }
+}
+
+impl ModemConfig {
+    /// Get all problems with this modem's config, labelled with its id.
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let id = &self.id;
+        if id.is_empty() {
+            problems.push("Modem id must not be empty".to_string());
+        }
+        if let Some(smsc) = &self.smsc {
+            let digits = smsc.strip_prefix('+').unwrap_or(smsc);
+            if digits.is_empty() || digits.len() > 20 || !digits.chars().all(|c| c.is_ascii_digit())
+            {
+                problems.push(format!(
+                    "Modem '{id}' smsc '{smsc}' is not a valid phone number"
+                ));
+            }
+        }
+        if let Some(operator) = &self.preferred_operator {
+            if !is_valid_operator_code(operator) {
+                problems.push(format!(
+                    "Modem '{id}' preferred_operator '{operator}' must be a numeric MCC+MNC code"
+                ));
+            }
+        }
+        if !is_valid_cnmi(&self.cnmi) {
+            problems.push(format!(
+                "Modem '{id}' cnmi '{}' must be up to 5 comma separated numbers, eg: 2,1,0,1,0",
+                self.cnmi
+            ));
+        }
+        if self.max_multipart_parts == 0 {
+            problems.push(format!(
+                "Modem '{id}' max_multipart_parts must be at least 1"
+            ));
+        }
+        if self.max_total_content_bytes == 0 {
+            problems.push(format!(
+                "Modem '{id}' max_total_content_bytes must be at least 1"
+            ));
+        }
+        if self.device.is_empty() {
+            problems.push(format!("Modem '{id}' device must have at least one path"));
+        }
+        for pattern in &self.device {
+            if let Err(e) = glob::Pattern::new(pattern) {
+                problems.push(format!(
+                    "Modem '{id}' device '{pattern}' is not a valid pattern: {e}"
+                ));
+            }
+        }
+        if self.lte_bands.as_ref().is_some_and(Vec::is_empty) {
+            problems.push(format!("Modem '{id}' lte_bands must not be empty if set"));
+        }
+        problems
+    }
+
+    /// Check if incoming messages are stored and indicated with +CMTI, instead of routed directly.
+    pub fn stores_incoming_sms(&self) -> bool {
+        self.cnmi.split(',').nth(1).map(str::trim) == Some("1")
+    }
+}
 impl Default for ModemConfig {
     fn default() -> Self {
         Self {
+            id: default_modem_id(),
             device: default_modem_device(),
             baud_rate: default_modem_baud(),
             gnss_enabled: default_false(),
@@ -89,6 +520,22 @@ impl Default for ModemConfig {
             cmd_channel_buffer_size: default_modem_cmd_buffer_size(),
             read_buffer_size: default_modem_read_buffer_size(),
             line_buffer_size: default_modem_read_buffer_size(),
+            raw_unsolicited_events: default_false(),
+            incoming_calls_enabled: default_false(),
+            auto_reject_calls: default_false(),
+            strip_multipart_padding: default_false(),
+            max_multipart_parts: default_modem_max_multipart_parts(),
+            max_total_content_bytes: default_modem_max_total_content_bytes(),
+            cnmi: default_modem_cnmi(),
+            default_validity_period: default_modem_validity_period(),
+            delete_stored_messages: default_true(),
+            smsc: None,
+            preferred_operator: None,
+            preferred_rat: None,
+            lte_bands: None,
+            network_time_sync: default_false(),
+            heartbeat_interval_secs: default_modem_heartbeat_interval_secs(),
+            heartbeat_failures_before_offline: default_modem_heartbeat_failures_before_offline(),
 
             #[cfg(feature = "gpio")]
             gpio_enabled: default_false(),
@@ -102,12 +549,145 @@ impl Default for ModemConfig {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct DatabaseConfig {
     pub database_url: String,
 
     #[serde(deserialize_with = "deserialize_encryption_key")]
     pub encryption_key: [u8; 32],
+
+    /// Seconds after which an outgoing message without a final delivery report is marked as expired.
+    #[serde(default = "default_delivery_report_expiry")]
+    pub delivery_report_expiry: u64,
+
+    /// Writes are serialized by the modem anyway, so only a few connections are useful.
+    #[serde(default = "default_database_max_connections")]
+    pub max_connections: u32,
+
+    #[serde(default = "default_database_min_connections")]
+    pub min_connections: u32,
+
+    #[serde(default = "default_database_busy_timeout_secs")]
+    pub busy_timeout_secs: u64,
+
+    /// SQLite page cache size per connection, in KiB.
+    #[serde(default = "default_database_cache_size_kb")]
+    pub cache_size_kb: u32,
+
+    /// SQLite journal mode, WAL for a database file unless set.
+    #[serde(default)]
+    pub journal_mode: Option<DatabaseJournalMode>,
+
+    /// How often SQLite syncs to disk, trading durability for write speed and wear.
+    #[serde(default)]
+    pub synchronous: DatabaseSynchronous,
+
+    /// Store the hex PDU of incoming messages, encrypted like their content. Off by default, as
+    /// it holds the full message and is only useful for debugging how a message was decoded.
+    #[serde(default)]
+    pub store_raw_pdu: bool,
+
+    /// Encrypt stored phone numbers deterministically, so they can still be looked up.
+    /// Existing numbers are converted on startup whenever this is changed.
+    #[serde(default)]
+    pub encrypt_phone_numbers: bool,
+
+    /// Delete messages created more than this many days ago, 0 keeps them forever.
+    #[serde(default)]
+    pub retain_days: u32,
+
+    /// Only prune this kind of message, otherwise every message past `retain_days` is pruned.
+    #[serde(default)]
+    pub prune_only: Option<PruneOnly>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DatabaseJournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    Wal,
+    Off,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DatabaseSynchronous {
+    Off,
+    #[default]
+    Normal,
+    Full,
+    Extra,
+}
+
+/// The messages that are pruned once older than `retain_days`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PruneOnly {
+    Received,
+    Delivered,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WebhookQueueConfig {
+    /// The most events waiting for webhook delivery before the `overflow` policy drops one.
+    #[serde(default)]
+    pub capacity: Option<usize>,
+
+    #[serde(default)]
+    pub overflow: QueueOverflow,
+
+    /// The most webhooks an event is sent to at once, ordered webhooks aside.
+    #[serde(default = "default_webhook_concurrency")]
+    pub concurrency: usize,
+
+    /// How long to wait for each webhook request before it fails.
+    #[serde(default = "default_webhook_timeout_secs")]
+    pub timeout_secs: u64,
+}
+impl Default for WebhookQueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: None,
+            overflow: QueueOverflow::default(),
+            concurrency: default_webhook_concurrency(),
+            timeout_secs: default_webhook_timeout_secs(),
+        }
+    }
+}
+
+/// Which event is dropped when a bounded queue is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueOverflow {
+    #[default]
+    DropOldest,
+    DropNewest,
+}
+
+/// The HTTP method used to deliver webhook events.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookMethod {
+    #[default]
+    Post,
+    Put,
+
+    /// Sends the event as query parameters without a body.
+    Get,
+}
+
+/// How the event is encoded in a webhook request body.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookContentType {
+    #[default]
+    Json,
+
+    /// URL-encoded form fields, with nested values flattened into dotted keys.
+    Form,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -125,6 +705,28 @@ pub struct ConfiguredWebhook {
     #[serde(deserialize_with = "deserialize_optional_existing_file")]
     #[serde(default)]
     pub certificate_path: Option<PathBuf>,
+
+    /// Shared secret used to sign request bodies, sent as the `X-Signature` header.
+    #[serde(default)]
+    pub secret: Option<String>,
+
+    /// Deliver events to this webhook one at a time in the order they happened.
+    #[serde(default)]
+    pub ordered: bool,
+
+    #[serde(default)]
+    pub method: WebhookMethod,
+
+    /// Ignored for GET requests, which always use query parameters.
+    #[serde(default)]
+    pub content_type: WebhookContentType,
+
+    /// Consecutive failures before events are skipped for `circuit_cooldown_secs`, 0 to never skip.
+    #[serde(default = "default_webhook_circuit_threshold")]
+    pub circuit_threshold: u32,
+
+    #[serde(default = "default_webhook_circuit_cooldown_secs")]
+    pub circuit_cooldown_secs: u64,
 }
 impl ConfiguredWebhook {
     pub fn get_header_map(&self) -> Result<Option<HeaderMap>> {
@@ -165,7 +767,7 @@ pub struct SentryConfig {
 }
 
 #[cfg(feature = "http-server")]
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct HTTPConfig {
     #[serde(default)]
     pub enabled: bool,
@@ -187,6 +789,22 @@ pub struct HTTPConfig {
 
     #[serde(default)]
     pub tls: Option<TLSConfig>,
+
+    /// Cross-origin policy for browser clients, any origin is allowed if unset.
+    #[serde(default)]
+    pub cors: Option<CorsConfig>,
+
+    /// Source IP addresses or CIDR ranges allowed to use the API, empty allows all.
+    #[serde(default, deserialize_with = "deserialize_allowed_ips")]
+    pub allowed_ips: Vec<ipnet::IpNet>,
+
+    /// Largest request body accepted by any route, larger requests get a 413 response.
+    #[serde(default = "default_http_max_request_bytes")]
+    pub max_request_bytes: usize,
+
+    /// Most recipients accepted by a single bulk send, larger requests get a 400 response.
+    #[serde(default = "default_http_max_bulk_recipients")]
+    pub max_bulk_recipients: usize,
 }
 #[cfg(feature = "http-server")]
 impl Default for HTTPConfig {
@@ -199,15 +817,59 @@ impl Default for HTTPConfig {
             websocket_enabled: default_true(),
             phone_number: None,
             tls: None,
+            cors: None,
+            allowed_ips: Vec::new(),
+            max_request_bytes: default_http_max_request_bytes(),
+            max_bulk_recipients: default_http_max_bulk_recipients(),
         }
     }
 }
+#[cfg(feature = "http-server")]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CorsConfig {
+    /// Origins allowed to make requests, eg: `https://dashboard.example.com`, or `*` for any.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+
+    #[serde(default = "default_cors_allowed_methods")]
+    pub allowed_methods: Vec<String>,
+
+    /// Allow credentials (cookies and Authorization) in cross-origin requests.
+    #[serde(default)]
+    pub allow_credentials: bool,
+}
+#[cfg(feature = "http-server")]
+impl CorsConfig {
+    /// Get all problems with the policy, as they'd otherwise only show as browser errors.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let wildcard = self.allowed_origins.iter().any(|origin| origin == "*");
+        if wildcard && self.allow_credentials {
+            problems.push(
+                "HTTP cors cannot allow credentials with a wildcard origin, browsers reject it"
+                    .to_string(),
+            );
+        }
+        for origin in self.allowed_origins.iter().filter(|origin| *origin != "*") {
+            if reqwest::header::HeaderValue::from_str(origin).is_err() || !origin.contains("://") {
+                problems.push(format!("HTTP cors origin '{origin}' is not a valid origin"));
+            }
+        }
+        for method in &self.allowed_methods {
+            if reqwest::Method::from_str(method).is_err() {
+                problems.push(format!("HTTP cors method '{method}' is not a valid method"));
+            }
+        }
+        problems
+    }
+}
+
 #[cfg_attr(
     not(any(feature = "tls-rustls", feature = "tls-native")),
     allow(dead_code)
 )]
 #[cfg(feature = "http-server")]
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct TLSConfig {
     #[serde(deserialize_with = "deserialize_existing_file")]
     pub certificate_path: PathBuf,
@@ -216,8 +878,52 @@ pub struct TLSConfig {
     pub key_path: PathBuf,
 }
 
-fn default_modem_device() -> String {
-    "/dev/ttyS0".to_string()
+/// Numeric operator codes are a 3 digit MCC followed by a 2 or 3 digit MNC.
+pub fn is_valid_operator_code(code: &str) -> bool {
+    matches!(code.len(), 5 | 6) && code.chars().all(|c| c.is_ascii_digit())
+}
+
+/// AT+CNMI takes up to 5 numeric parameters, `<mode>,<mt>,<bm>,<ds>,<bfr>`.
+fn is_valid_cnmi(cnmi: &str) -> bool {
+    let fields = cnmi.split(',').collect::<Vec<_>>();
+    fields.len() <= 5
+        && fields
+            .iter()
+            .all(|field| !field.is_empty() && field.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn default_modems() -> Vec<ModemConfig> {
+    vec![ModemConfig::default()]
+}
+fn default_modem_id() -> String {
+    "default".to_string()
+}
+fn default_modem_device() -> Vec<String> {
+    vec!["/dev/ttyS0".to_string()]
+}
+fn default_auto_reply_cooldown_secs() -> u64 {
+    300
+}
+
+fn default_opt_out_keywords() -> Vec<String> {
+    vec!["STOP".to_string(), "UNSUBSCRIBE".to_string()]
+}
+
+fn default_opt_out_resubscribe_keywords() -> Vec<String> {
+    vec!["START".to_string()]
+}
+
+fn default_modem_max_multipart_parts() -> u8 {
+    32
+}
+fn default_modem_max_total_content_bytes() -> usize {
+    16384
+}
+fn default_modem_cnmi() -> String {
+    "2,2,0,1,0".to_string()
+}
+fn default_modem_validity_period() -> u8 {
+    DEFAULT_VALIDITY_PERIOD
 }
 fn default_modem_baud() -> u32 {
     115200
@@ -228,9 +934,42 @@ fn default_modem_cmd_buffer_size() -> usize {
 fn default_modem_read_buffer_size() -> usize {
     4096
 }
+fn default_modem_heartbeat_interval_secs() -> u64 {
+    60
+}
+fn default_modem_heartbeat_failures_before_offline() -> u32 {
+    3
+}
+fn default_database_max_connections() -> u32 {
+    4
+}
+fn default_database_min_connections() -> u32 {
+    1
+}
+fn default_database_busy_timeout_secs() -> u64 {
+    30
+}
+fn default_database_cache_size_kb() -> u32 {
+    2048
+}
+fn default_delivery_report_expiry() -> u64 {
+    validity_period_to_duration(DEFAULT_VALIDITY_PERIOD).as_secs()
+}
 fn default_webhook_events() -> Vec<EventType> {
     vec![EventType::IncomingMessage]
 }
+fn default_webhook_concurrency() -> usize {
+    10
+}
+fn default_webhook_timeout_secs() -> u64 {
+    10
+}
+fn default_webhook_circuit_threshold() -> u32 {
+    5
+}
+fn default_webhook_circuit_cooldown_secs() -> u64 {
+    60
+}
 fn default_gnss_report_interval() -> u32 {
     0
 }
@@ -247,10 +986,22 @@ fn default_gpio_power_pin() -> u8 {
     4
 }
 
+#[cfg(feature = "http-server")]
+fn default_cors_allowed_methods() -> Vec<String> {
+    vec!["GET".to_string(), "POST".to_string()]
+}
 #[cfg(feature = "http-server")]
 fn default_http_address() -> SocketAddr {
     SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 3000)
 }
+#[cfg(feature = "http-server")]
+fn default_http_max_request_bytes() -> usize {
+    2 * 1024 * 1024
+}
+#[cfg(feature = "http-server")]
+fn default_http_max_bulk_recipients() -> usize {
+    100
+}
 
 fn deserialize_encryption_key<'de, D>(deserializer: D) -> Result<[u8; 32], D::Error>
 where
@@ -273,6 +1024,60 @@ where
     Ok(key)
 }
 
+/// Parse an IP address or CIDR range, a single address is treated as a full length prefix.
+#[cfg(feature = "http-server")]
+pub fn parse_allowed_ip(value: &str) -> Result<ipnet::IpNet> {
+    let value = value.trim();
+    value
+        .parse::<ipnet::IpNet>()
+        .or_else(|_| value.parse::<IpAddr>().map(ipnet::IpNet::from))
+        .with_context(|| format!("'{value}' is not a valid IP address or CIDR range"))
+}
+
+/// Accept either a single value (a string or table) or a list of them. This is a visitor
+/// rather than an untagged enum, so errors within a value are still reported.
+fn deserialize_one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer, StrDeserializer};
+
+    struct OneOrMany<T>(std::marker::PhantomData<T>);
+    impl<'de, T: Deserialize<'de>> serde::de::Visitor<'de> for OneOrMany<T> {
+        type Value = Vec<T>;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a value or a list of values")
+        }
+
+        fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+            T::deserialize(StrDeserializer::new(value)).map(|value| vec![value])
+        }
+
+        fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            T::deserialize(MapAccessDeserializer::new(map)).map(|value| vec![value])
+        }
+
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+            Vec::deserialize(SeqAccessDeserializer::new(seq))
+        }
+    }
+
+    deserializer.deserialize_any(OneOrMany(std::marker::PhantomData))
+}
+
+#[cfg(feature = "http-server")]
+fn deserialize_allowed_ips<'de, D>(deserializer: D) -> Result<Vec<ipnet::IpNet>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|value| parse_allowed_ip(value).map_err(serde::de::Error::custom))
+        .collect()
+}
+
 fn deserialize_existing_file<'de, D>(deserializer: D) -> Result<PathBuf, D::Error>
 where
     D: serde::Deserializer<'de>,
@@ -306,3 +1111,358 @@ where
         None => Ok(None),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATABASE_CONFIG: &str = r#"
+        [database]
+        database_url = "test.db"
+        encryption_key = "SGVsbG8gV29ybGQhIFRoaXMgaXMgMzIgYnl0ZSBrZXk="
+    "#;
+
+    #[test]
+    fn test_validate_valid_config() {
+        let config: AppConfig = toml::from_str(&format!(
+            r#"{DATABASE_CONFIG}
+            [[webhooks]]
+            url = "https://example.com/webhook"
+            "#
+        ))
+        .unwrap();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_aggregates_problems() {
+        let config: AppConfig = toml::from_str(&format!(
+            r#"{DATABASE_CONFIG}
+            [[webhooks]]
+            url = "not a url"
+            events = []
+
+            [[webhooks]]
+            url = "https://example.com/webhook"
+            events = []
+            "#
+        ))
+        .unwrap();
+
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("Found 3 config problem(s)"), "Got: {err}");
+        assert!(err.contains("Webhook #0 has an invalid URL"));
+        assert!(err.contains("Webhook #0 has no events configured"));
+        assert!(err.contains("Webhook #1 has no events configured"));
+    }
+
+    #[test]
+    fn test_webhook_queue_config() {
+        let config: AppConfig = toml::from_str(DATABASE_CONFIG).unwrap();
+        assert_eq!(config.webhook_queue.concurrency, 10);
+        assert_eq!(config.webhook_queue.timeout_secs, 10);
+        assert_eq!(config.webhook_queue, WebhookQueueConfig::default());
+
+        let config: AppConfig = toml::from_str(&format!(
+            "{DATABASE_CONFIG}\n[webhook_queue]\nconcurrency = 0\ntimeout_secs = 0"
+        ))
+        .unwrap();
+        let err = config.validate().unwrap_err().to_string();
+        assert!(
+            err.contains("Webhook concurrency must be at least 1"),
+            "Got: {err}"
+        );
+        assert!(err.contains("Webhook timeout must be at least 1 second"));
+    }
+
+    #[test]
+    fn test_validate_log_level() {
+        let config: AppConfig = toml::from_str(&format!(
+            "log_level = \"info,sms_server=debug\"\n{DATABASE_CONFIG}"
+        ))
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        let config: AppConfig =
+            toml::from_str(&format!("log_level = \"sms_server=[\"\n{DATABASE_CONFIG}")).unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_database_encryption_key() {
+        let config: AppConfig = toml::from_str(DATABASE_CONFIG).unwrap();
+        assert_eq!(config.database.database_url, "test.db");
+        assert_eq!(
+            &config.database.encryption_key,
+            b"Hello World! This is 32 byte key"
+        );
+
+        let short_key =
+            DATABASE_CONFIG.replace("SGVsbG8gV29ybGQhIFRoaXMgaXMgMzIgYnl0ZSBrZXk=", "c2hvcnQ=");
+        let err = toml::from_str::<AppConfig>(&short_key).unwrap_err();
+        assert!(err.to_string().contains("Encryption key must be 32 bytes"));
+
+        let invalid_key = DATABASE_CONFIG.replace("SGVsbG8", "!!!");
+        assert!(toml::from_str::<AppConfig>(&invalid_key).is_err());
+    }
+
+    #[test]
+    fn test_modem_device_one_or_many() {
+        let config: AppConfig = toml::from_str(DATABASE_CONFIG).unwrap();
+        assert_eq!(config.modems[0].device, ["/dev/ttyS0"]);
+
+        let config: AppConfig = toml::from_str(&format!(
+            "{DATABASE_CONFIG}\n[modem]\ndevice = \"/dev/ttyUSB2\""
+        ))
+        .unwrap();
+        assert_eq!(config.modems[0].device, ["/dev/ttyUSB2"]);
+
+        let config: AppConfig = toml::from_str(&format!(
+            "{DATABASE_CONFIG}\n[modem]\ndevice = [\"/dev/serial/by-id/usb-SimTech*\", \"/dev/ttyUSB2\"]"
+        ))
+        .unwrap();
+        assert_eq!(
+            config.modems[0].device,
+            ["/dev/serial/by-id/usb-SimTech*", "/dev/ttyUSB2"]
+        );
+        assert!(config.validate().is_ok());
+
+        let config: AppConfig =
+            toml::from_str(&format!("{DATABASE_CONFIG}\n[modem]\ndevice = []")).unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_modem_table_or_array() {
+        let config: AppConfig = toml::from_str(&format!(
+            "{DATABASE_CONFIG}\n[modem]\ndevice = \"/dev/ttyUSB2\""
+        ))
+        .unwrap();
+        assert_eq!(config.modems.len(), 1);
+        assert_eq!(config.modems[0].id, "default");
+
+        let config: AppConfig = toml::from_str(&format!(
+            r#"{DATABASE_CONFIG}
+            [[modem]]
+            id = "uk"
+            device = "/dev/ttyUSB0"
+
+            [[modem]]
+            id = "us"
+            device = "/dev/ttyUSB4"
+            auto_reject_calls = true
+            "#
+        ))
+        .unwrap();
+        let ids: Vec<&str> = config
+            .modems
+            .iter()
+            .map(|modem| modem.id.as_str())
+            .collect();
+        assert_eq!(ids, ["uk", "us"]);
+        assert!(config.modems[1].auto_reject_calls);
+        assert!(config.validate().is_ok());
+
+        // Several modems need their own ids.
+        let config: AppConfig = toml::from_str(&format!(
+            "{DATABASE_CONFIG}\n[[modem]]\ndevice = \"/dev/ttyUSB0\"\n[[modem]]\ndevice = \"/dev/ttyUSB4\""
+        ))
+        .unwrap();
+        let err = config.validate().unwrap_err().to_string();
+        assert!(
+            err.contains("Modem id 'default' is used more than once"),
+            "Got: {err}"
+        );
+
+        // Invalid fields are still reported from within a table.
+        let err = toml::from_str::<AppConfig>(&format!(
+            "{DATABASE_CONFIG}\n[[modem]]\nbaud_rate = \"fast\""
+        ))
+        .unwrap_err();
+        assert!(err.to_string().contains("invalid type"), "Got: {err}");
+    }
+
+    #[test]
+    fn test_validate_routing() {
+        let config: AppConfig = toml::from_str(&format!(
+            r#"{DATABASE_CONFIG}
+            [[modem]]
+            id = "uk"
+
+            [[modem]]
+            id = "us"
+
+            [routing]
+            prefixes = {{ "+44" = "uk", "+1" = "us" }}
+            "#
+        ))
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        let config: AppConfig = toml::from_str(&format!(
+            r#"{DATABASE_CONFIG}
+            [routing]
+            prefixes = {{ "44" = "default", "+33" = "fr" }}
+            fallback = "us"
+            "#
+        ))
+        .unwrap();
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("Found 3 config problem(s)"), "Got: {err}");
+        assert!(err.contains("Routing prefix '44' must be a + followed by digits"));
+        assert!(err.contains("Routing prefix '+33' uses unknown modem 'fr'"));
+        assert!(err.contains("Routing fallback uses unknown modem 'us'"));
+    }
+
+    #[test]
+    fn test_opt_out_keywords() {
+        let config: AppConfig = toml::from_str(&format!("{DATABASE_CONFIG}\n[opt_out]")).unwrap();
+        let opt_out = config.opt_out.as_ref().unwrap();
+        assert!(config.validate().is_ok());
+
+        assert_eq!(opt_out.opt_out_keyword("  stop \n"), Some("STOP"));
+        assert_eq!(opt_out.opt_out_keyword("Unsubscribe"), Some("UNSUBSCRIBE"));
+        assert_eq!(opt_out.opt_out_keyword("please stop"), None);
+        assert_eq!(opt_out.resubscribe_keyword(" Start"), Some("START"));
+        assert_eq!(opt_out.resubscribe_keyword("stop"), None);
+
+        let config: AppConfig = toml::from_str(&format!(
+            r#"{DATABASE_CONFIG}
+            [opt_out]
+            keywords = [" "]
+            resubscribe_keywords = ["start", "Stop"]
+            "#
+        ))
+        .unwrap();
+        let err = config.validate().unwrap_err().to_string();
+        assert!(
+            err.contains("Opt-out keywords must not be empty"),
+            "Got: {err}"
+        );
+
+        let config: AppConfig = toml::from_str(&format!(
+            r#"{DATABASE_CONFIG}
+            [opt_out]
+            resubscribe_keywords = ["start", "Stop"]
+            "#
+        ))
+        .unwrap();
+        let err = config.validate().unwrap_err().to_string();
+        assert!(
+            err.contains("Opt-out keyword 'Stop' is also a resubscribe keyword"),
+            "Got: {err}"
+        );
+    }
+
+    #[test]
+    fn test_validate_auto_replies() {
+        let config: AppConfig = toml::from_str(&format!(
+            r#"{DATABASE_CONFIG}
+            [[auto_reply]]
+            keyword = "HOURS"
+            response = "We're open 9-5, {{phone_number}}"
+
+            [[auto_reply]]
+            regex = "(?i)^track (?P<order>\\d+)$"
+            response = "Order {{order}} is on its way"
+            cooldown_secs = 60
+            "#
+        ))
+        .unwrap();
+        assert_eq!(config.auto_replies.len(), 2);
+        assert_eq!(config.auto_replies[0].cooldown_secs, 300);
+        assert!(config.validate().is_ok());
+
+        let config: AppConfig = toml::from_str(&format!(
+            r#"{DATABASE_CONFIG}
+            [[auto_reply]]
+            keyword = "HOURS"
+            regex = "hours"
+            response = "Hi"
+
+            [[auto_reply]]
+            regex = "(unclosed"
+            response = "Order {{order}}"
+            cooldown_secs = 0
+            "#
+        ))
+        .unwrap();
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("Found 4 config problem(s)"), "Got: {err}");
+        assert!(err.contains("Auto-reply #0 must have either a keyword or a regex"));
+        assert!(err.contains("Auto-reply #1 has an invalid regex"));
+        assert!(err.contains("Auto-reply #1 response uses unknown placeholder '{order}'"));
+        assert!(err.contains("Auto-reply #1 cooldown_secs must be at least 1"));
+    }
+
+    #[test]
+    #[cfg(feature = "http-server")]
+    fn test_validate_templates() {
+        let config: AppConfig = toml::from_str(&format!(
+            r#"{DATABASE_CONFIG}
+            [templates]
+            alert = "{{host}} is down"
+            broken = "{{host is down"
+            "#
+        ))
+        .unwrap();
+        assert_eq!(config.templates["alert"], "{host} is down");
+
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("Found 1 config problem(s)"), "Got: {err}");
+        assert!(err.contains("Template 'broken' is invalid"));
+    }
+
+    #[test]
+    fn test_cnmi() {
+        assert!(is_valid_cnmi("2,2,0,1,0"));
+        assert!(is_valid_cnmi("2,1"));
+        assert!(!is_valid_cnmi(""));
+        assert!(!is_valid_cnmi("2,,0"));
+        assert!(!is_valid_cnmi("2,1,0,1,0,0"));
+        assert!(!is_valid_cnmi("AT+CNMI=2,1"));
+
+        let mut config = ModemConfig::default();
+        assert!(!config.stores_incoming_sms());
+
+        config.cnmi = "2,1,0,1,0".to_string();
+        assert!(config.stores_incoming_sms());
+    }
+
+    #[test]
+    fn test_is_valid_operator_code() {
+        assert!(is_valid_operator_code("23410"));
+        assert!(is_valid_operator_code("310260"));
+
+        assert!(!is_valid_operator_code("2341"));
+        assert!(!is_valid_operator_code("2341000"));
+        assert!(!is_valid_operator_code("O2-UK"));
+        assert!(!is_valid_operator_code(""));
+    }
+
+    #[cfg(feature = "http-server")]
+    #[test]
+    fn test_cors_config() {
+        let cors = |origins: &[&str], methods: &[&str], allow_credentials: bool| CorsConfig {
+            allowed_origins: origins.iter().map(|s| s.to_string()).collect(),
+            allowed_methods: methods.iter().map(|s| s.to_string()).collect(),
+            allow_credentials,
+        };
+
+        assert!(cors(&["https://app.example.com"], &["GET", "POST"], true)
+            .problems()
+            .is_empty());
+        assert!(cors(&["*"], &["GET"], false).problems().is_empty());
+
+        // Browsers won't honour credentials with a wildcard origin.
+        assert_eq!(cors(&["*"], &["GET"], true).problems().len(), 1);
+        assert_eq!(
+            cors(&["app.example.com"], &["GET"], false).problems().len(),
+            1
+        );
+        assert_eq!(
+            cors(&["*"], &["GET", "BAD METHOD"], false).problems().len(),
+            1
+        );
+    }
+}
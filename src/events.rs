@@ -1,111 +1,13 @@
 use crate::config::AppConfig;
-use crate::modem::types::{GNSSLocation, ModemStatus};
-use crate::sms::types::SMSIncomingDeliveryReport;
-use crate::types::SMSMessage;
 use crate::webhooks::WebhookSender;
-use anyhow::anyhow;
-use serde::{Deserialize, Serialize};
 use tokio::task::JoinHandle;
 use tracing::log::debug;
 
+pub use sms_api_types::events::{Event, EventType};
+
 #[cfg(feature = "http-server")]
 use crate::http::websocket::WebSocketManager;
 
-#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy, Deserialize)]
-pub enum EventType {
-    #[serde(rename = "incoming")]
-    IncomingMessage,
-
-    #[serde(rename = "outgoing")]
-    OutgoingMessage,
-
-    #[serde(rename = "delivery")]
-    DeliveryReport,
-
-    #[serde(rename = "modem_status_update")]
-    ModemStatusUpdate,
-
-    #[serde(rename = "gnss_position_report")]
-    GNSSPositionReport,
-}
-#[cfg_attr(not(feature = "http-server"), allow(dead_code))]
-impl EventType {
-    pub const COUNT: usize = 5;
-
-    #[inline]
-    pub const fn to_bit(self) -> u8 {
-        match self {
-            EventType::IncomingMessage => 1 << 0,    // 0b00001
-            EventType::OutgoingMessage => 1 << 1,    // 0b00010
-            EventType::DeliveryReport => 1 << 2,     // 0b00100
-            EventType::ModemStatusUpdate => 1 << 3,  // 0b01000
-            EventType::GNSSPositionReport => 1 << 4, // 0b10000
-        }
-    }
-
-    #[inline]
-    pub const fn all_bits() -> u8 {
-        (1 << 0) | (1 << 1) | (1 << 2) | (1 << 3) | (1 << 4) // 0b11111
-    }
-
-    #[inline]
-    pub fn events_to_mask(events: &[EventType]) -> u8 {
-        events.iter().fold(0, |acc, event| acc | event.to_bit())
-    }
-}
-impl TryFrom<&str> for EventType {
-    type Error = anyhow::Error;
-
-    #[inline]
-    fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value {
-            "incoming" => Ok(EventType::IncomingMessage),
-            "outgoing" => Ok(EventType::OutgoingMessage),
-            "delivery" => Ok(EventType::DeliveryReport),
-            "modem_status_update" => Ok(EventType::ModemStatusUpdate),
-            "gnss_position_report" => Ok(EventType::GNSSPositionReport),
-            _ => Err(anyhow!("Unknown event type {}", value)),
-        }
-    }
-}
-
-#[derive(Debug, Clone, Serialize)]
-#[serde(tag = "type", content = "data")]
-pub enum Event {
-    #[serde(rename = "incoming")]
-    IncomingMessage(SMSMessage),
-
-    #[serde(rename = "outgoing")]
-    OutgoingMessage(SMSMessage),
-
-    #[serde(rename = "delivery")]
-    DeliveryReport {
-        message_id: i64,
-        report: SMSIncomingDeliveryReport,
-    },
-
-    #[serde(rename = "modem_status_update")]
-    ModemStatusUpdate {
-        previous: ModemStatus,
-        current: ModemStatus,
-    },
-
-    #[serde(rename = "gnss_position_report")]
-    GNSSPositionReport(GNSSLocation),
-}
-impl Event {
-    #[inline]
-    pub fn to_event_type(&self) -> EventType {
-        match self {
-            Event::IncomingMessage(_) => EventType::IncomingMessage,
-            Event::OutgoingMessage(_) => EventType::OutgoingMessage,
-            Event::DeliveryReport { .. } => EventType::DeliveryReport,
-            Event::ModemStatusUpdate { .. } => EventType::ModemStatusUpdate,
-            Event::GNSSPositionReport(_) => EventType::GNSSPositionReport,
-        }
-    }
-}
-
 #[derive(Clone)]
 pub struct EventBroadcaster {
     pub webhooks: Option<WebhookSender>,
@@ -118,13 +20,16 @@ impl EventBroadcaster {
         let (webhook_sender, webhook_handle) = config
             .webhooks
             .clone()
-            .map(WebhookSender::new)
+            .map(|webhooks| WebhookSender::new(webhooks, &config.webhook_queue))
             .map_or((None, None), |(sender, handle)| {
                 (Some(sender), Some(handle))
             });
 
         #[cfg(feature = "http-server")]
-        let websocket = config.http.websocket_enabled.then(WebSocketManager::new);
+        let websocket = config
+            .http
+            .websocket_enabled
+            .then(|| WebSocketManager::new(&config.webhook_queue));
 
         #[cfg(feature = "http-server")]
         let is_enabled = webhook_sender.is_some() || websocket.is_some();
@@ -0,0 +1,186 @@
+use crate::sms::SMSManager;
+use crate::types::{SMSMessage, SMSStatus};
+use anyhow::Result;
+use futures::{stream, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+/// Messages are fetched in pages so a long conversation is never held in memory at once.
+const EXPORT_PAGE_SIZE: u64 = 500;
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+
+    #[default]
+    Json,
+}
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Json => "application/json",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    fn header(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "timestamp,direction,status,content\n",
+            ExportFormat::Json => "[",
+        }
+    }
+
+    fn footer(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "",
+            ExportFormat::Json => "]",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ExportedMessage<'a> {
+    timestamp: Option<u64>,
+    direction: &'static str,
+    status: &'a SMSStatus,
+    content: &'a str,
+}
+impl<'a> From<&'a SMSMessage> for ExportedMessage<'a> {
+    fn from(message: &'a SMSMessage) -> Self {
+        Self {
+            timestamp: message.created_at,
+            direction: if message.is_outgoing {
+                "outgoing"
+            } else {
+                "incoming"
+            },
+            status: &message.status,
+            content: &message.message_content,
+        }
+    }
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn format_page(messages: &[SMSMessage], format: ExportFormat, first_page: bool) -> Result<String> {
+    let mut out = String::new();
+    for (idx, message) in messages.iter().enumerate() {
+        let exported = ExportedMessage::from(message);
+        match format {
+            ExportFormat::Csv => {
+                let timestamp = exported
+                    .timestamp
+                    .map(|t| t.to_string())
+                    .unwrap_or_default();
+                out.push_str(&format!(
+                    "{timestamp},{},{},{}\n",
+                    exported.direction,
+                    exported.status.as_str(),
+                    csv_escape(exported.content)
+                ));
+            }
+            ExportFormat::Json => {
+                if !(first_page && idx == 0) {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::to_string(&exported)?);
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Stream every message for a phone number, oldest first, in the requested format.
+pub fn export_messages(
+    sms_manager: SMSManager,
+    phone_number: String,
+    format: ExportFormat,
+) -> impl Stream<Item = Result<String>> {
+    let pages = stream::try_unfold(Some(0u64), move |offset| {
+        let sms_manager = sms_manager.clone();
+        let phone_number = phone_number.clone();
+        async move {
+            let Some(offset) = offset else {
+                return Ok(None);
+            };
+
+            let messages = sms_manager
+                .borrow_database()
+                .get_messages(&phone_number, Some(EXPORT_PAGE_SIZE), Some(offset), true)
+                .await?;
+
+            let next_offset =
+                (messages.len() as u64 == EXPORT_PAGE_SIZE).then_some(offset + EXPORT_PAGE_SIZE);
+            let page = format_page(&messages, format, offset == 0)?;
+            Ok(Some((page, next_offset)))
+        }
+    });
+
+    stream::once(async move { Ok(format.header().to_string()) })
+        .chain(pages)
+        .chain(stream::once(async move { Ok(format.footer().to_string()) }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::SMSDataCoding;
+
+    fn message(content: &str, is_outgoing: bool) -> SMSMessage {
+        SMSMessage {
+            message_id: Some(1),
+            phone_number: "+441234567890".to_string(),
+            message_content: content.to_string(),
+            message_reference: None,
+            is_outgoing,
+            status: SMSStatus::Delivered,
+            created_at: Some(1700000000),
+            completed_at: None,
+            modem_id: None,
+            raw_pdu: None,
+            data_coding: SMSDataCoding::Text,
+            is_read: false,
+        }
+    }
+
+    #[test]
+    fn test_csv_escape() {
+        assert_eq!(csv_escape("hello"), "hello");
+        assert_eq!(csv_escape("hello, world"), "\"hello, world\"");
+        assert_eq!(csv_escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_escape("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn test_format_page() {
+        let messages = [message("hi, there", true), message("reply", false)];
+
+        let csv = format_page(&messages, ExportFormat::Csv, true).unwrap();
+        assert_eq!(
+            csv,
+            "1700000000,outgoing,delivered,\"hi, there\"\n1700000000,incoming,delivered,reply\n"
+        );
+
+        // JSON pages are joined into one array, so only the very first entry has no leading comma.
+        let first = format_page(&messages, ExportFormat::Json, true).unwrap();
+        let second = format_page(&messages[..1], ExportFormat::Json, false).unwrap();
+        let json = format!("[{first}{second}]");
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.as_array().unwrap().len(), 3);
+        assert_eq!(parsed[0]["content"], "hi, there");
+        assert_eq!(parsed[1]["direction"], "incoming");
+    }
+}
@@ -1,21 +1,33 @@
+mod export;
+mod openapi;
 mod routes;
+mod timestamps;
 mod types;
 pub mod websocket;
 
-use crate::config::HTTPConfig;
+use crate::config::{CorsConfig, HTTPConfig};
 use crate::http::routes::*;
 use crate::http::types::{HttpResponse, JsonResult};
 use crate::http::websocket::WebSocketManager;
 use crate::modem::types::{ModemRequest, ModemResponse};
 use crate::sms::SMSManager;
+use crate::webhooks::WebhookSender;
 use crate::TracingReloadHandle;
 use anyhow::{bail, Result};
-use axum::http::{HeaderName, HeaderValue};
+use axum::extract::DefaultBodyLimit;
+use axum::http::{HeaderName, HeaderValue, Method};
+use axum::response::IntoResponse;
 use axum::routing::{get, post};
+use ipnet::IpNet;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
 use tower::ServiceBuilder;
-use tower_http::cors::CorsLayer;
+use tower_http::cors::{AllowHeaders, AllowOrigin, CorsLayer};
 use tower_http::set_header::SetResponseHeaderLayer;
-use tracing::log::{info, warn};
+use tracing::log::{debug, info, warn};
+use tracing::Instrument;
 
 #[cfg(feature = "sentry")]
 use sentry::integrations::tower::{NewSentryLayer, SentryHttpLayer};
@@ -26,13 +38,16 @@ pub struct HttpState {
     pub config: HTTPConfig,
     pub tracing_reload: TracingReloadHandle,
     pub websocket: Option<WebSocketManager>,
+    pub webhooks: Option<WebhookSender>,
+    pub templates: Arc<HashMap<String, String>>,
 }
 
 async fn get_modem_json_result(
     state: HttpState,
+    modem_id: Option<&str>,
     request: ModemRequest,
 ) -> JsonResult<ModemResponse> {
-    let response = match state.sms_manager.send_command(request).await {
+    let response = match state.sms_manager.send_command(modem_id, request).await {
         Ok(response) => response,
         Err(e) => {
             return Ok(axum::response::Json(HttpResponse {
@@ -50,59 +65,359 @@ async fn get_modem_json_result(
     }))
 }
 
-async fn auth_middleware(
-    axum::extract::State(expected_token): axum::extract::State<String>,
-    headers: axum::http::HeaderMap,
-    request: axum::http::Request<axum::body::Body>,
-    next: axum::middleware::Next,
-) -> Result<axum::response::Response, axum::http::StatusCode> {
-    let auth_header = headers
-        .get("authorization")
-        .ok_or(axum::http::StatusCode::UNAUTHORIZED)?;
+/// The subprotocol selected for WebSocket connections that send their token as a subprotocol.
+pub const WEBSOCKET_AUTH_PROTOCOL: &str = "sms-api";
+
+#[derive(serde::Deserialize)]
+struct WebSocketAuthQuery {
+    token: Option<String>,
+}
+
+/// The environment variable holding the single legacy authentication token.
+pub const AUTH_TOKEN_ENV: &str = "SMS_HTTP_AUTH_TOKEN";
+
+/// The environment variable holding comma separated `label:token` or plain tokens.
+pub const AUTH_TOKENS_ENV: &str = "SMS_HTTP_AUTH_TOKENS";
+
+/// An accepted authentication token, with a label to identify its user in logs.
+#[derive(Clone)]
+pub struct AuthToken {
+    label: Arc<str>,
+    token: String,
+}
+
+/// The label of the token a request was authenticated with, added to its extensions.
+#[derive(Debug, Clone)]
+pub struct AuthTokenLabel(pub Arc<str>);
+
+/// Parse tokens from the single and comma separated token variables. Entries in the list
+/// may be labelled as `label:token`, otherwise they're labelled by their position.
+fn parse_auth_tokens(single: Option<String>, multiple: Option<String>) -> Vec<AuthToken> {
+    let mut tokens = Vec::new();
+    if let Some(token) = single.filter(|token| !token.is_empty()) {
+        tokens.push(AuthToken {
+            label: "default".into(),
+            token,
+        });
+    }
+
+    let entries = multiple.unwrap_or_default();
+    for (idx, entry) in entries
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .enumerate()
+    {
+        let (label, token) = match entry.split_once(':') {
+            Some((label, token)) => (label.trim().to_string(), token.trim()),
+            None => (format!("token-{idx}"), entry),
+        };
+        if !token.is_empty() {
+            tokens.push(AuthToken {
+                label: label.into(),
+                token: token.to_string(),
+            });
+        }
+    }
+
+    tokens
+}
+
+/// Load the accepted authentication tokens from the environment, requiring at least one.
+pub fn load_auth_tokens() -> Result<Arc<[AuthToken]>> {
+    let tokens = parse_auth_tokens(
+        std::env::var(AUTH_TOKEN_ENV).ok(),
+        std::env::var(AUTH_TOKENS_ENV).ok(),
+    );
+    if tokens.is_empty() {
+        bail!("Missing required {AUTH_TOKEN_ENV} or {AUTH_TOKENS_ENV} environment variable, and require_authentication is enabled");
+    }
+
+    Ok(tokens.into())
+}
+
+/// Compare in time depending only on the longest input, not where the first difference is.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let mut diff = a.len() ^ b.len();
+    for idx in 0..a.len().max(b.len()) {
+        let x = a.get(idx).copied().unwrap_or(0);
+        let y = b.get(idx).copied().unwrap_or(0);
+        diff |= (x ^ y) as usize;
+    }
+    diff == 0
+}
+
+/// Find the token matching the presented one. Every token is compared, so the time
+/// taken doesn't reveal which one matched.
+fn find_auth_token<'a>(tokens: &'a [AuthToken], presented: &str) -> Option<&'a AuthToken> {
+    let mut found = None;
+    for token in tokens {
+        if constant_time_eq(token.token.as_bytes(), presented.as_bytes()) && found.is_none() {
+            found = Some(token);
+        }
+    }
+    found
+}
+
+/// Find a valid token in the `token` query parameter or `Sec-WebSocket-Protocol` header.
+fn websocket_token_matches<'a>(
+    headers: &axum::http::HeaderMap,
+    uri: &axum::http::Uri,
+    tokens: &'a [AuthToken],
+) -> Option<&'a AuthToken> {
+    let query_match = axum::extract::Query::<WebSocketAuthQuery>::try_from_uri(uri)
+        .ok()
+        .and_then(|query| query.0.token)
+        .and_then(|token| find_auth_token(tokens, &token));
+
+    query_match.or_else(|| {
+        headers
+            .get(axum::http::header::SEC_WEBSOCKET_PROTOCOL)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|protocols| {
+                protocols
+                    .split(',')
+                    .find_map(|protocol| find_auth_token(tokens, protocol.trim()))
+            })
+    })
+}
+
+/// Check the `Authorization` header holds an accepted token, with or without a `Bearer` prefix.
+/// Errors are kept generic so they don't reveal anything about the accepted tokens.
+fn check_authorization_header<'a>(
+    headers: &axum::http::HeaderMap,
+    tokens: &'a [AuthToken],
+) -> Result<&'a AuthToken, (axum::http::StatusCode, &'static str)> {
+    let auth_header = headers.get("authorization").ok_or((
+        axum::http::StatusCode::UNAUTHORIZED,
+        "missing authorization header",
+    ))?;
 
     let auth_str = auth_header
         .to_str()
-        .map_err(|_| axum::http::StatusCode::BAD_REQUEST)?
+        .map_err(|_| {
+            (
+                axum::http::StatusCode::BAD_REQUEST,
+                "malformed authorization header",
+            )
+        })?
         .trim();
 
     let token = auth_str.strip_prefix("Bearer ").unwrap_or(auth_str).trim();
-    if token != expected_token {
-        return Err(axum::http::StatusCode::UNAUTHORIZED);
+    find_auth_token(tokens, token).ok_or((axum::http::StatusCode::UNAUTHORIZED, "invalid token"))
+}
+
+async fn auth_middleware(
+    axum::extract::State(tokens): axum::extract::State<Arc<[AuthToken]>>,
+    headers: axum::http::HeaderMap,
+    mut request: axum::http::Request<axum::body::Body>,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    // Browsers can't set headers when connecting a WebSocket, so the upgrade route also
+    // accepts the token from the query or subprotocol. Other routes only accept the header.
+    let websocket_match = if request.uri().path() == "/ws" {
+        websocket_token_matches(&headers, request.uri(), &tokens)
+    } else {
+        None
+    };
+
+    let result = websocket_match
+        .map(Ok)
+        .unwrap_or_else(|| check_authorization_header(&headers, &tokens));
+    match result {
+        Ok(token) => {
+            debug!(
+                "Authenticated {} with token '{}'",
+                request.uri().path(),
+                token.label
+            );
+            request
+                .extensions_mut()
+                .insert(AuthTokenLabel(token.label.clone()));
+            next.run(request).await
+        }
+        Err((status, error)) => json_error_response(status, error),
+    }
+}
+
+/// Check if a peer address is in the allowlist. IPv4-mapped IPv6 addresses, from a dual stack
+/// listener, are compared as IPv4.
+fn ip_allowed(allowed_ips: &[IpNet], address: IpAddr) -> bool {
+    let address = address.to_canonical();
+    allowed_ips.iter().any(|net| net.contains(&address))
+}
+
+async fn ip_allowlist_middleware(
+    axum::extract::State(allowed_ips): axum::extract::State<Arc<[IpNet]>>,
+    axum::extract::ConnectInfo(peer): axum::extract::ConnectInfo<SocketAddr>,
+    request: axum::http::Request<axum::body::Body>,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    if ip_allowed(&allowed_ips, peer.ip()) {
+        return next.run(request).await;
+    }
+
+    warn!(
+        "Rejected {} request from disallowed address {}",
+        request.uri().path(),
+        peer.ip()
+    );
+    json_error_response(axum::http::StatusCode::FORBIDDEN, "address not allowed")
+}
+
+/// The response header echoing the id generated for each request.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Log each request's method, path, status and duration with a generated request id, which is
+/// also returned in the `X-Request-Id` header. Any logs made while handling the request are
+/// within its span. Only the path is logged, as the query and headers can hold tokens.
+async fn access_log_middleware(
+    request: axum::http::Request<axum::body::Body>,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let request_id = uuid::Uuid::new_v4().to_string();
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let started = std::time::Instant::now();
+
+    let span = tracing::info_span!("request", request_id = %request_id);
+    let mut response = next.run(request).instrument(span).await;
+
+    tracing::info!(
+        request_id = %request_id,
+        method = %method,
+        path = %path,
+        status = response.status().as_u16(),
+        duration_ms = started.elapsed().as_millis() as u64,
+        "{method} {path} {}",
+        response.status()
+    );
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
     }
+    response
+}
 
-    Ok(next.run(request).await)
+/// An error response in the usual HttpResponse envelope, for middleware rejections.
+fn json_error_response(status: axum::http::StatusCode, error: &str) -> axum::response::Response {
+    let body = HttpResponse::<()> {
+        success: false,
+        response: None,
+        error: Some(error.to_string()),
+    };
+    (status, axum::Json(body)).into_response()
+}
+
+/// Build the CORS layer from the configured policy, allowing everything if there isn't one.
+fn build_cors_layer(config: Option<&CorsConfig>) -> Result<CorsLayer> {
+    let Some(config) = config else {
+        return Ok(CorsLayer::permissive());
+    };
+
+    // An invalid policy (eg: wildcard origin with credentials) would panic when building the layer.
+    if let Some(problem) = config.problems().into_iter().next() {
+        bail!("{problem}");
+    }
+
+    let origins = if config.allowed_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        let origins = config
+            .allowed_origins
+            .iter()
+            .map(|origin| HeaderValue::from_str(origin))
+            .collect::<Result<Vec<_>, _>>()?;
+        AllowOrigin::list(origins)
+    };
+    let methods = config
+        .allowed_methods
+        .iter()
+        .map(|method| Method::from_str(method))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .allow_headers(AllowHeaders::mirror_request())
+        .allow_credentials(config.allow_credentials)
+        .expose_headers([
+            HeaderName::from_static(REQUEST_ID_HEADER),
+            HeaderName::from_static("x-version"),
+        ]))
 }
 
 pub fn create_app(
     config: HTTPConfig,
     websocket: Option<WebSocketManager>,
+    webhooks: Option<WebhookSender>,
+    templates: HashMap<String, String>,
     sms_manager: SMSManager,
     _sentry: bool,
     _tracing_reload: TracingReloadHandle,
 ) -> Result<axum::Router> {
     let mut router = axum::Router::new()
         .route("/db/sms", post(db_sms))
+        .route("/db/message", post(db_message))
         .route("/db/latest-numbers", post(db_latest_numbers))
+        .route("/db/conversations", post(db_conversations))
         .route("/db/delivery-reports", post(db_delivery_reports))
+        .route("/db/mark-read", post(db_mark_read))
         .route("/db/friendly-names/set", post(friendly_names_set))
         .route("/db/friendly-names/get", post(friendly_names_get))
+        .route("/db/blocklist/add", post(blocklist_add))
+        .route("/db/blocklist/remove", post(blocklist_remove))
+        .route("/db/blocklist/list", post(blocklist_list))
+        .route("/db/export", get(db_export))
+        .route("/db/reconcile-delivery", post(db_reconcile_delivery))
+        .route("/db/stats", get(db_stats))
         .route("/sms/send", post(sms_send))
+        .route("/sms/send-bulk", post(sms_send_bulk))
+        .route("/sms/resend", post(sms_resend))
+        .route("/sms/estimate", post(sms_estimate))
         .route("/sms/network-status", get(sms_get_network_status))
         .route("/sms/signal-strength", get(sms_get_signal_strength))
-        .route("/sms/network-operator", get(sms_get_network_operator))
+        .route(
+            "/sms/network-operator",
+            get(sms_get_network_operator).post(sms_set_network_operator),
+        )
         .route("/sms/service-provider", get(sms_get_service_provider))
         .route("/sms/battery-level", get(sms_get_battery_level))
+        .route("/sms/smsc", get(sms_get_service_centre))
+        .route(
+            "/sms/preferred-mode",
+            get(sms_get_preferred_mode).post(sms_set_preferred_mode),
+        )
+        .route("/sms/bands", post(sms_set_bands))
+        .route(
+            "/sms/functionality",
+            get(sms_get_functionality).post(sms_set_functionality),
+        )
+        .route("/sms/parameters", get(sms_get_parameters))
         .route("/sms/device-info", get(sms_get_device_info))
         .route("/gnss/status", get(gnss_get_status))
         .route("/gnss/location", get(gnss_get_location))
         .route("/sys/phone-number", get(sys_phone_number))
         .route("/sys/version", get(sys_version))
+        .route("/sys/time", get(sys_time))
         .route("/sys/set-log-level", post(sys_set_log_level))
+        .route("/sys/modem/queue", get(sys_modem_queue))
+        .route("/sys/modem/reset", post(sys_modem_reset))
+        .route("/sys/modem/flush-queue", post(sys_modem_flush_queue))
+        .route("/sys/db/maintenance", post(sys_db_maintenance))
+        .route("/sys/webhooks", get(sys_webhooks))
+        .route("/sys/webhooks/test", post(sys_test_webhooks))
+        .route("/sys/webhooks/queue", get(sys_webhooks_queue))
+        .route("/sys/webhooks/replay", post(sys_replay_webhooks))
+        .route("/sys/network-history", get(sys_network_history))
+        .route("/sys/websocket/stats", get(sys_websocket_stats))
+        .route("/openapi.json", get(openapi_json))
         .layer(SetResponseHeaderLayer::overriding(
             HeaderName::from_static("x-version"),
             HeaderValue::from_static(crate::VERSION),
-        ))
-        .layer(ServiceBuilder::new().layer(CorsLayer::permissive()));
+        ));
 
     // Add optional websocket route if there is a manager.
     if websocket.is_some() {
@@ -112,19 +427,42 @@ pub fn create_app(
 
     // Add optional authentication middleware.
     if config.require_authentication {
-        match std::env::var("SMS_HTTP_AUTH_TOKEN") {
-            Ok(token) => {
-                info!("Adding HTTP authentication middleware!");
-                router = router.layer(
-                    axum::middleware::from_fn_with_state(token, auth_middleware)
-                );
-            },
-            Err(_) => bail!("Missing required SMS_HTTP_AUTH_TOKEN environment variable, and require_authentication is enabled!")
-        }
+        let tokens = load_auth_tokens()?;
+        info!(
+            "Adding HTTP authentication middleware with {} token(s)!",
+            tokens.len()
+        );
+        router = router.layer(axum::middleware::from_fn_with_state(
+            tokens,
+            auth_middleware,
+        ));
     } else {
         warn!("Serving HTTP without authentication middleware, as require_authentication is disabled!");
     }
 
+    // Add optional IP allowlist middleware, added last so it runs before authentication.
+    if !config.allowed_ips.is_empty() {
+        info!(
+            "Adding HTTP IP allowlist middleware with {} range(s)!",
+            config.allowed_ips.len()
+        );
+        let allowed_ips: Arc<[IpNet]> = config.allowed_ips.clone().into();
+        router = router.layer(axum::middleware::from_fn_with_state(
+            allowed_ips,
+            ip_allowlist_middleware,
+        ));
+    }
+
+    // CORS wraps authentication and the IP allowlist, so preflight requests are answered without
+    // credentials and rejected responses still carry the headers a browser needs to read them.
+    router = router.layer(ServiceBuilder::new().layer(build_cors_layer(config.cors.as_ref())?));
+
+    // Applies to every route added above, so any body extractor rejects oversized requests with a 413.
+    router = router.layer(DefaultBodyLimit::max(config.max_request_bytes));
+
+    // Access logging is outermost, so rejected requests are logged too.
+    router = router.layer(axum::middleware::from_fn(access_log_middleware));
+
     // If Sentry is enabled, include axum integration layers.
     #[cfg(feature = "sentry")]
     if _sentry {
@@ -143,6 +481,222 @@ pub fn create_app(
         config,
         tracing_reload: _tracing_reload,
         websocket,
+        webhooks,
+        templates: Arc::new(templates),
     };
     Ok(router.with_state(state))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{header, HeaderMap, Uri};
+
+    fn tokens(entries: &str) -> Vec<AuthToken> {
+        parse_auth_tokens(None, Some(entries.to_string()))
+    }
+
+    fn label(token: Option<&AuthToken>) -> Option<&str> {
+        token.map(|token| token.label.as_ref())
+    }
+
+    #[test]
+    fn test_parse_auth_tokens() {
+        let parsed = parse_auth_tokens(
+            Some("legacy".to_string()),
+            Some(" billing:abc, , def ,alerts:".to_string()),
+        );
+        let pairs: Vec<(&str, &str)> = parsed
+            .iter()
+            .map(|token| (token.label.as_ref(), token.token.as_str()))
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("default", "legacy"),
+                ("billing", "abc"),
+                ("token-1", "def")
+            ]
+        );
+
+        assert!(parse_auth_tokens(Some(String::new()), None).is_empty());
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret1"));
+        assert!(!constant_time_eq(b"secret", b"secre"));
+        assert!(!constant_time_eq(b"", b"secret"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn test_websocket_token_matches() {
+        let tokens = tokens("app:secret,other:second");
+        let empty = HeaderMap::new();
+        let uri: Uri = "/ws?events=incoming&token=secret".parse().unwrap();
+        assert_eq!(
+            label(websocket_token_matches(&empty, &uri, &tokens)),
+            Some("app")
+        );
+
+        let uri: Uri = "/ws?events=incoming&token=wrong".parse().unwrap();
+        assert!(websocket_token_matches(&empty, &uri, &tokens).is_none());
+
+        let uri: Uri = "/ws?events=incoming".parse().unwrap();
+        assert!(websocket_token_matches(&empty, &uri, &tokens).is_none());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static("sms-api, second"),
+        );
+        assert_eq!(
+            label(websocket_token_matches(&headers, &uri, &tokens)),
+            Some("other")
+        );
+
+        headers.insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static("sms-api, sms"),
+        );
+        assert!(websocket_token_matches(&headers, &uri, &tokens).is_none());
+    }
+
+    #[test]
+    fn test_check_authorization_header() {
+        let tokens = tokens("app:secret,other:second");
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            check_authorization_header(&headers, &tokens).err(),
+            Some((
+                axum::http::StatusCode::UNAUTHORIZED,
+                "missing authorization header"
+            ))
+        );
+
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer secret"),
+        );
+        assert_eq!(
+            label(check_authorization_header(&headers, &tokens).ok()),
+            Some("app")
+        );
+
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("second"));
+        assert_eq!(
+            label(check_authorization_header(&headers, &tokens).ok()),
+            Some("other")
+        );
+
+        // Wrong tokens get the same error, whatever their length.
+        for token in ["Bearer secre", "Bearer secret1", "Bearer other"] {
+            headers.insert(header::AUTHORIZATION, HeaderValue::from_static(token));
+            assert_eq!(
+                check_authorization_header(&headers, &tokens).err(),
+                Some((axum::http::StatusCode::UNAUTHORIZED, "invalid token"))
+            );
+        }
+
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_bytes(b"Bearer \xff").unwrap(),
+        );
+        assert_eq!(
+            check_authorization_header(&headers, &tokens).err(),
+            Some((
+                axum::http::StatusCode::BAD_REQUEST,
+                "malformed authorization header"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_ip_allowed() {
+        let allowed: Vec<IpNet> = ["10.0.20.0/24", "192.168.1.5", "fd00::/8"]
+            .into_iter()
+            .map(|value| crate::config::parse_allowed_ip(value).unwrap())
+            .collect();
+
+        assert!(ip_allowed(&allowed, "10.0.20.17".parse().unwrap()));
+        assert!(ip_allowed(&allowed, "192.168.1.5".parse().unwrap()));
+        assert!(ip_allowed(&allowed, "fd00::1".parse().unwrap()));
+        assert!(ip_allowed(&allowed, "::ffff:10.0.20.1".parse().unwrap()));
+
+        assert!(!ip_allowed(&allowed, "10.0.21.1".parse().unwrap()));
+        assert!(!ip_allowed(&allowed, "192.168.1.6".parse().unwrap()));
+        assert!(!ip_allowed(&allowed, "::1".parse().unwrap()));
+
+        assert!(crate::config::parse_allowed_ip("10.0.0.0/33").is_err());
+        assert!(crate::config::parse_allowed_ip("gateway").is_err());
+    }
+
+    #[test]
+    fn test_access_log_request_id() {
+        use tower::ServiceExt;
+
+        let router = axum::Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn(access_log_middleware));
+
+        let request = || {
+            axum::http::Request::builder()
+                .uri("/")
+                .body(axum::body::Body::empty())
+                .unwrap()
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let first = runtime.block_on(router.clone().oneshot(request())).unwrap();
+        let second = runtime.block_on(router.oneshot(request())).unwrap();
+
+        let request_id = |response: &axum::response::Response| {
+            let value = response.headers().get(REQUEST_ID_HEADER).unwrap();
+            uuid::Uuid::parse_str(value.to_str().unwrap()).unwrap()
+        };
+        assert_eq!(first.status(), axum::http::StatusCode::OK);
+        assert_ne!(request_id(&first), request_id(&second));
+    }
+
+    #[test]
+    fn test_request_body_limit() {
+        use axum::Json;
+        use serde_json::Value;
+        use tower::ServiceExt;
+
+        // Both the required and optional JSON extractors used by the route handlers.
+        let router = axum::Router::new()
+            .route("/required", post(|Json(_): Json<Value>| async { "ok" }))
+            .route("/optional", post(|_: Option<Json<Value>>| async { "ok" }))
+            .layer(DefaultBodyLimit::max(16));
+
+        let request = |uri: &str, body: &str| {
+            axum::http::Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(axum::body::Body::from(body.to_string()))
+                .unwrap()
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        for uri in ["/required", "/optional"] {
+            let status = |body| {
+                runtime
+                    .block_on(router.clone().oneshot(request(uri, body)))
+                    .unwrap()
+                    .status()
+            };
+            assert_eq!(status(r#"{"a":1}"#), axum::http::StatusCode::OK);
+            assert_eq!(
+                status(r#"{"content":"too long"}"#),
+                axum::http::StatusCode::PAYLOAD_TOO_LARGE
+            );
+        }
+    }
+}
@@ -0,0 +1,680 @@
+use serde_json::{json, Map, Value};
+
+/// Reference a schema in the document components.
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{name}") })
+}
+
+/// Make a schema nullable. OpenAPI 3.0 ignores siblings of `$ref`, so references are wrapped.
+fn nullable(schema: Value) -> Value {
+    match schema {
+        Value::Object(mut object) if !object.contains_key("$ref") => {
+            object.insert("nullable".to_string(), Value::Bool(true));
+            Value::Object(object)
+        }
+        schema => json!({ "allOf": [schema], "nullable": true }),
+    }
+}
+
+/// An object schema, where every property not listed as optional is required.
+fn object(properties: &[(&str, Value)], optional: &[&str]) -> Value {
+    let required: Vec<&str> = properties
+        .iter()
+        .map(|(name, _)| *name)
+        .filter(|name| !optional.contains(name))
+        .collect();
+    let properties: Map<String, Value> = properties
+        .iter()
+        .map(|(name, schema)| (name.to_string(), schema.clone()))
+        .collect();
+
+    json!({ "type": "object", "required": required, "properties": properties })
+}
+
+fn array(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+fn string_enum(values: &[&str]) -> Value {
+    json!({ "type": "string", "enum": values })
+}
+
+fn integer(format: &str) -> Value {
+    json!({ "type": "integer", "format": format })
+}
+
+/// A stored timestamp, in Unix seconds (UTC) unless requested as RFC 3339 with `?timestamps=iso8601`.
+fn timestamp() -> Value {
+    json!({
+        "oneOf": [integer("uint64"), { "type": "string", "format": "date-time" }],
+        "description": "Unix seconds (UTC), or an RFC 3339 string with `?timestamps=iso8601`."
+    })
+}
+
+fn number(format: &str) -> Value {
+    json!({ "type": "number", "format": format })
+}
+
+/// The `HttpResponse` envelope every JSON route responds with.
+fn envelope(response: Value) -> Value {
+    object(
+        &[
+            ("success", json!({ "type": "boolean" })),
+            ("response", nullable(response)),
+            ("error", nullable(json!({ "type": "string" }))),
+        ],
+        &[],
+    )
+}
+
+/// A `ModemResponse` variant, serialized adjacently tagged as `{ "type": ..., "data": ... }`.
+fn modem_response_variant(name: &str, data: Option<Value>) -> Value {
+    let mut properties = vec![("type", string_enum(&[name]))];
+    if let Some(data) = data {
+        properties.push(("data", data));
+    }
+    object(&properties, &[])
+}
+
+fn modem_response_schema() -> Value {
+    let string = || json!({ "type": "string" });
+    let variants = vec![
+        modem_response_variant(
+            "SendResult",
+            Some(object(&[("reference_id", integer("uint8"))], &[])),
+        ),
+        modem_response_variant(
+            "NetworkStatus",
+            Some(object(
+                &[
+                    ("registration", integer("uint8")),
+                    ("technology", integer("uint8")),
+                    ("access_technology", nullable(integer("uint8"))),
+                    ("registration_label", string()),
+                    ("technology_label", string()),
+                ],
+                &[],
+            )),
+        ),
+        modem_response_variant(
+            "SignalStrength",
+            Some(object(
+                &[("rssi", integer("int32")), ("ber", integer("int32"))],
+                &[],
+            )),
+        ),
+        modem_response_variant(
+            "NetworkOperator",
+            Some(object(
+                &[
+                    ("status", integer("uint8")),
+                    ("format", integer("uint8")),
+                    ("operator", string()),
+                ],
+                &[],
+            )),
+        ),
+        modem_response_variant("ServiceProvider", Some(string())),
+        modem_response_variant("ServiceCentre", Some(string())),
+        modem_response_variant("StoredSMS", Some(string())),
+        modem_response_variant("Functionality", Some(integer("uint8"))),
+        modem_response_variant(
+            "SmsParameters",
+            Some(object(
+                &[
+                    ("first_octet", integer("uint8")),
+                    ("validity_period", integer("uint8")),
+                    ("protocol_id", integer("uint8")),
+                    ("data_coding_scheme", integer("uint8")),
+                ],
+                &[],
+            )),
+        ),
+        modem_response_variant("PreferredMode", Some(integer("uint8"))),
+        modem_response_variant(
+            "BatteryLevel",
+            Some(object(
+                &[
+                    ("status", integer("uint8")),
+                    ("charge", integer("uint8")),
+                    ("voltage", number("float")),
+                ],
+                &[],
+            )),
+        ),
+        modem_response_variant("GNSSStatus", Some(schema_ref("GNSSFixStatus"))),
+        modem_response_variant("GNSSLocation", Some(schema_ref("GNSSLocation"))),
+        modem_response_variant(
+            "Clock",
+            Some(object(
+                &[
+                    ("time", string()),
+                    ("timestamp", nullable(integer("uint64"))),
+                    ("utc_offset_minutes", integer("int32")),
+                ],
+                &[],
+            )),
+        ),
+        modem_response_variant("Success", None),
+        modem_response_variant(
+            "Error",
+            Some(object(
+                &[("code", nullable(integer("uint16"))), ("message", string())],
+                &[],
+            )),
+        ),
+    ];
+
+    json!({
+        "oneOf": variants,
+        "discriminator": { "propertyName": "type" }
+    })
+}
+
+fn components() -> Value {
+    let string = || json!({ "type": "string" });
+    let boolean = || json!({ "type": "boolean" });
+    let fetch_options: [(&str, Value); 3] = [
+        ("limit", integer("uint64")),
+        ("offset", integer("uint64")),
+        ("reverse", boolean()),
+    ];
+    let fetch_optional = ["limit", "offset", "reverse"];
+
+    json!({
+        "SMSStatus": string_enum(&[
+            "sent",
+            "delivered",
+            "received",
+            "temporary_failure",
+            "permanent_failure",
+            "expired",
+            "dry_run",
+            "pending",
+        ]),
+        "SMSEncoding": string_enum(&["auto", "gsm7", "ucs2"]),
+        "SMSDataCoding": string_enum(&["text", "8bit"]),
+        "SMSContentEncoding": string_enum(&["hex", "base64"]),
+        "GNSSFixStatus": string_enum(&["Unknown", "NotFix", "Fix2D", "Fix3D"]),
+        "StateMachineStatus": string_enum(&["idle", "command", "unsolicited_message"]),
+        "ModemResponse": modem_response_schema(),
+        "GNSSLocation": object(
+            &[
+                ("run_status", boolean()),
+                ("fix_status", boolean()),
+                ("utc_time", string()),
+                ("latitude", nullable(number("double"))),
+                ("longitude", nullable(number("double"))),
+                ("msl_altitude", nullable(number("double"))),
+                ("ground_speed", nullable(number("float"))),
+                ("ground_course", nullable(number("float"))),
+                ("fix_mode", schema_ref("GNSSFixStatus")),
+                ("hdop", nullable(number("float"))),
+                ("pdop", nullable(number("float"))),
+                ("vdop", nullable(number("float"))),
+                ("gps_in_view", nullable(integer("uint8"))),
+                ("gnss_used", nullable(integer("uint8"))),
+                ("glonass_in_view", nullable(integer("uint8"))),
+            ],
+            &[],
+        ),
+        "SMSMessage": object(
+            &[
+                ("message_id", nullable(integer("int64"))),
+                ("phone_number", string()),
+                ("message_content", string()),
+                ("message_reference", nullable(integer("uint8"))),
+                ("is_outgoing", boolean()),
+                ("status", schema_ref("SMSStatus")),
+                ("created_at", nullable(timestamp())),
+                ("completed_at", nullable(timestamp())),
+                ("modem_id", string()),
+                ("raw_pdu", string()),
+                ("data_coding", schema_ref("SMSDataCoding")),
+                ("is_read", boolean()),
+            ],
+            &["modem_id", "raw_pdu", "data_coding"],
+        ),
+        "SMSDeliveryReport": object(
+            &[
+                ("report_id", nullable(integer("int64"))),
+                ("status", schema_ref("SMSStatus")),
+                ("is_final", boolean()),
+                ("created_at", nullable(timestamp())),
+            ],
+            &[],
+        ),
+        "SendSmsRequest": object(
+            &[
+                ("to", string()),
+                ("content", string()),
+                ("template", string()),
+                (
+                    "vars",
+                    json!({ "type": "object", "additionalProperties": string() }),
+                ),
+                ("flash", boolean()),
+                ("validity_period", nullable(integer("uint8"))),
+                ("timeout", nullable(integer("uint32"))),
+                ("encoding", schema_ref("SMSEncoding")),
+                ("data_coding", schema_ref("SMSDataCoding")),
+                ("content_encoding", schema_ref("SMSContentEncoding")),
+                ("udh", string()),
+                ("priority", boolean()),
+                ("wait_for_delivery", boolean()),
+                ("wait_timeout_secs", nullable(integer("uint64"))),
+                ("modem_id", nullable(string())),
+                ("dry_run", boolean()),
+                ("override_blocklist", boolean()),
+                ("include_pdus", boolean()),
+            ],
+            &[
+                "content",
+                "template",
+                "vars",
+                "flash",
+                "validity_period",
+                "timeout",
+                "encoding",
+                "data_coding",
+                "content_encoding",
+                "udh",
+                "priority",
+                "wait_for_delivery",
+                "wait_timeout_secs",
+                "modem_id",
+                "dry_run",
+                "override_blocklist",
+                "include_pdus",
+            ],
+        ),
+        "SendSmsResponse": object(
+            &[
+                ("message_id", integer("int64")),
+                ("reference_id", integer("uint8")),
+                ("encoding", schema_ref("SMSEncoding")),
+                ("data_coding", schema_ref("SMSDataCoding")),
+                ("status", schema_ref("SMSStatus")),
+                ("delivery_timed_out", boolean()),
+                ("route", schema_ref("SendRoute")),
+                ("pdus", array(schema_ref("SendSmsPdu"))),
+            ],
+            &["data_coding", "route", "pdus"],
+        ),
+        "SendSmsPdu": object(
+            &[("pdu", string()), ("len", integer("uint64"))],
+            &[],
+        ),
+        "SendRoute": object(
+            &[
+                ("modem_id", string()),
+                (
+                    "reason",
+                    string_enum(&["requested", "prefix", "fallback", "least_busy"]),
+                ),
+                ("prefix", string()),
+                ("skipped", array(string())),
+            ],
+            &["prefix", "skipped"],
+        ),
+        "PhoneNumberFetchRequest": object(
+            &[&[("phone_number", string())], &fetch_options[..]].concat(),
+            &fetch_optional,
+        ),
+        "MessageIdFetchRequest": object(
+            &[&[("message_id", integer("int64"))], &fetch_options[..]].concat(),
+            &fetch_optional,
+        ),
+        "GlobalFetchRequest": object(&fetch_options, &fetch_optional),
+        "SetFriendlyNameRequest": object(
+            &[
+                ("phone_number", string()),
+                ("friendly_name", nullable(string())),
+            ],
+            &["friendly_name"],
+        ),
+        "GetFriendlyNameRequest": object(&[("phone_number", string())], &[]),
+        "GetMessageRequest": object(&[("message_id", integer("int64"))], &[]),
+        "SMSConversation": object(
+            &[
+                ("phone_number", string()),
+                ("friendly_name", nullable(string())),
+                ("message_count", integer("uint64")),
+                ("unread_count", integer("uint64")),
+                ("latest_message_id", integer("int64")),
+                ("preview", nullable(string())),
+                ("is_outgoing", boolean()),
+                ("created_at", nullable(timestamp())),
+            ],
+            &[],
+        ),
+        "BlocklistEntry": object(
+            &[
+                ("phone_number", string()),
+                ("reason", nullable(string())),
+                ("created_at", nullable(timestamp())),
+            ],
+            &[],
+        ),
+        "BlocklistAddRequest": object(
+            &[
+                ("phone_number", string()),
+                ("reason", nullable(string())),
+            ],
+            &["reason"],
+        ),
+        "BlocklistRemoveRequest": object(&[("phone_number", string())], &[]),
+        "ModemQueueStatus": object(
+            &[
+                ("queue_length", integer("uint64")),
+                ("priority_queue_length", integer("uint64")),
+                ("queue_capacity", integer("uint64")),
+                ("state", schema_ref("StateMachineStatus")),
+                ("command_sequence", nullable(integer("uint32"))),
+                ("command_running_ms", nullable(integer("uint64"))),
+            ],
+            &[],
+        ),
+    })
+}
+
+/// A JSON route, responding with the `HttpResponse` envelope around `response`.
+fn json_operation(summary: &str, request: Option<(Value, bool)>, response: Value) -> Value {
+    let mut operation = json!({
+        "summary": summary,
+        "responses": {
+            "200": {
+                "description": "The response envelope, with `success` false and an `error` if it failed.",
+                "content": { "application/json": { "schema": envelope(response) } }
+            },
+            "500": {
+                "description": "The request failed, with `error` set.",
+                "content": { "application/json": { "schema": envelope(json!({})) } }
+            }
+        }
+    });
+    if let Some((schema, required)) = request {
+        operation["requestBody"] = json!({
+            "required": required,
+            "content": { "application/json": { "schema": schema } }
+        });
+    }
+    operation
+}
+
+/// Add the optional `timestamps` query parameter, for routes responding with stored timestamps.
+fn with_timestamps_query(mut operation: Value) -> Value {
+    operation["parameters"] = json!([{
+        "name": "timestamps",
+        "in": "query",
+        "required": false,
+        "schema": { "type": "string", "enum": ["unix", "iso8601"], "default": "unix" }
+    }]);
+    operation
+}
+
+/// Add the optional `modem_id` query parameter, for routes sent to one modem.
+fn with_modem_query(mut operation: Value) -> Value {
+    operation["parameters"] = json!([{
+        "name": "modem_id",
+        "in": "query",
+        "required": false,
+        "description": "The modem to use, the first configured modem if missing.",
+        "schema": { "type": "string" }
+    }]);
+    operation
+}
+
+fn modem_operation(summary: &str) -> Value {
+    json!({ "get": with_modem_query(json_operation(summary, None, schema_ref("ModemResponse"))) })
+}
+
+fn paths() -> Value {
+    let string = || json!({ "type": "string" });
+
+    json!({
+        "/sms/send": {
+            "post": json_operation(
+                "Send an SMS message.",
+                Some((schema_ref("SendSmsRequest"), true)),
+                schema_ref("SendSmsResponse"),
+            )
+        },
+        "/db/sms": {
+            "post": with_timestamps_query(json_operation(
+                "Get stored messages for a phone number.",
+                Some((schema_ref("PhoneNumberFetchRequest"), true)),
+                array(schema_ref("SMSMessage")),
+            ))
+        },
+        "/db/message": {
+            "post": with_timestamps_query(json_operation(
+                "Get a stored message by id, with its raw PDU if stored, or null if not found.",
+                Some((schema_ref("GetMessageRequest"), true)),
+                nullable(schema_ref("SMSMessage")),
+            ))
+        },
+        "/db/latest-numbers": {
+            "post": json_operation(
+                "Get the latest phone numbers messaged, with their friendly names.",
+                Some((schema_ref("GlobalFetchRequest"), false)),
+                array(json!({
+                    "type": "array",
+                    "minItems": 2,
+                    "maxItems": 2,
+                    "items": { "type": "string", "nullable": true },
+                    "description": "The phone number, and its friendly name if set."
+                })),
+            )
+        },
+        "/db/conversations": {
+            "post": with_timestamps_query(json_operation(
+                "Get each phone number messaged with its latest message preview, most recent first.",
+                Some((schema_ref("GlobalFetchRequest"), false)),
+                array(schema_ref("SMSConversation")),
+            ))
+        },
+        "/db/delivery-reports": {
+            "post": with_timestamps_query(json_operation(
+                "Get the delivery reports for an outgoing message.",
+                Some((schema_ref("MessageIdFetchRequest"), true)),
+                array(schema_ref("SMSDeliveryReport")),
+            ))
+        },
+        "/db/friendly-names/set": {
+            "post": json_operation(
+                "Set or clear the friendly name for a phone number.",
+                Some((schema_ref("SetFriendlyNameRequest"), true)),
+                json!({ "type": "boolean" }),
+            )
+        },
+        "/db/friendly-names/get": {
+            "post": json_operation(
+                "Get the friendly name for a phone number.",
+                Some((schema_ref("GetFriendlyNameRequest"), true)),
+                nullable(string()),
+            )
+        },
+        "/db/blocklist/add": {
+            "post": json_operation(
+                "Add a phone number to the blocklist, returning false if it was already there.",
+                Some((schema_ref("BlocklistAddRequest"), true)),
+                json!({ "type": "boolean" }),
+            )
+        },
+        "/db/blocklist/remove": {
+            "post": json_operation(
+                "Remove a phone number from the blocklist, returning false if it wasn't there.",
+                Some((schema_ref("BlocklistRemoveRequest"), true)),
+                json!({ "type": "boolean" }),
+            )
+        },
+        "/db/blocklist/list": {
+            "post": with_timestamps_query(json_operation(
+                "Get the blocklisted phone numbers, most recently added first.",
+                Some((schema_ref("GlobalFetchRequest"), false)),
+                array(schema_ref("BlocklistEntry")),
+            ))
+        },
+        "/db/reconcile-delivery": {
+            "post": json_operation(
+                "Recompute message statuses from stored delivery reports, forbidden without authentication.",
+                None,
+                json!({ "type": "integer", "format": "int64", "description": "The number of messages changed." }),
+            )
+        },
+        "/db/export": {
+            "get": {
+                "summary": "Export the conversation with a phone number.",
+                "parameters": [
+                    {
+                        "name": "phone_number",
+                        "in": "query",
+                        "required": true,
+                        "schema": string()
+                    },
+                    {
+                        "name": "format",
+                        "in": "query",
+                        "required": false,
+                        "schema": { "type": "string", "enum": ["csv", "json"], "default": "json" }
+                    }
+                ],
+                "responses": {
+                    "200": {
+                        "description": "The exported messages as a file download.",
+                        "content": {
+                            "application/json": { "schema": array(schema_ref("SMSMessage")) },
+                            "text/csv": { "schema": string() }
+                        }
+                    }
+                }
+            }
+        },
+        "/sms/network-status": modem_operation("Get the network registration status."),
+        "/sms/signal-strength": modem_operation("Get the signal strength."),
+        "/sms/network-operator": modem_operation("Get the network operator."),
+        "/sms/service-provider": modem_operation("Get the SIM service provider name."),
+        "/sms/battery-level": modem_operation("Get the battery level."),
+        "/sms/smsc": modem_operation("Get the SMS service centre number."),
+        "/sms/functionality": modem_operation("Get the modem functionality level."),
+        "/sms/parameters": modem_operation("Get the SMS parameters, including the default validity period."),
+        "/sms/preferred-mode": modem_operation("Get the preferred radio mode."),
+        "/gnss/status": modem_operation("Get the GNSS fix status."),
+        "/gnss/location": modem_operation("Get the GNSS location."),
+        "/sys/modem/queue": {
+            "get": with_modem_query(json_operation(
+                "Get the modem command queue status.",
+                None,
+                schema_ref("ModemQueueStatus"),
+            ))
+        }
+    })
+}
+
+/// Build the OpenAPI document describing the HTTP API.
+pub fn openapi_document() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "SMS Server",
+            "version": crate::VERSION
+        },
+        "paths": paths(),
+        "components": {
+            "schemas": components(),
+            "securitySchemes": {
+                "bearer": { "type": "http", "scheme": "bearer" }
+            }
+        },
+        "security": [{ "bearer": [] }]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modem::types::ModemResponse;
+    use crate::types::SMSStatus;
+    use std::collections::HashSet;
+
+    /// Exhaustive, so adding a ModemResponse variant fails to compile until it's documented here.
+    fn variant_name(response: &ModemResponse) -> &'static str {
+        match response {
+            ModemResponse::SendResult { .. } => "SendResult",
+            ModemResponse::NetworkStatus { .. } => "NetworkStatus",
+            ModemResponse::SignalStrength { .. } => "SignalStrength",
+            ModemResponse::NetworkOperator { .. } => "NetworkOperator",
+            ModemResponse::ServiceProvider(_) => "ServiceProvider",
+            ModemResponse::ServiceCentre(_) => "ServiceCentre",
+            ModemResponse::StoredSMS(_) => "StoredSMS",
+            ModemResponse::Functionality(_) => "Functionality",
+            ModemResponse::SmsParameters { .. } => "SmsParameters",
+            ModemResponse::PreferredMode(_) => "PreferredMode",
+            ModemResponse::BatteryLevel { .. } => "BatteryLevel",
+            ModemResponse::GNSSStatus(_) => "GNSSStatus",
+            ModemResponse::GNSSLocation(_) => "GNSSLocation",
+            ModemResponse::Clock { .. } => "Clock",
+            ModemResponse::Success => "Success",
+            ModemResponse::Error { .. } => "Error",
+        }
+    }
+
+    #[test]
+    fn test_modem_response_schema() {
+        let document = openapi_document();
+        let variants = document["components"]["schemas"]["ModemResponse"]["oneOf"]
+            .as_array()
+            .unwrap();
+        let documented: HashSet<&str> = variants
+            .iter()
+            .map(|variant| variant["properties"]["type"]["enum"][0].as_str().unwrap())
+            .collect();
+        assert_eq!(documented.len(), 16);
+
+        let samples = [
+            ModemResponse::SendResult { reference_id: 4 },
+            ModemResponse::ServiceCentre("+447802000332".to_string()),
+            ModemResponse::Success,
+            ModemResponse::error("ERROR"),
+        ];
+        for sample in &samples {
+            let name = variant_name(sample);
+            assert!(documented.contains(name), "{name} is not documented");
+
+            // Serialized adjacently tagged, with data only for variants that hold some.
+            let value = serde_json::to_value(sample).unwrap();
+            assert_eq!(value["type"], name);
+            let variant = variants
+                .iter()
+                .find(|variant| variant["properties"]["type"]["enum"][0] == name)
+                .unwrap();
+            assert_eq!(
+                value.get("data").is_some(),
+                variant["properties"].get("data").is_some()
+            );
+        }
+    }
+
+    #[test]
+    fn test_sms_status_schema() {
+        let document = openapi_document();
+        let documented = &document["components"]["schemas"]["SMSStatus"]["enum"];
+
+        let statuses = [
+            SMSStatus::Sent,
+            SMSStatus::Delivered,
+            SMSStatus::Received,
+            SMSStatus::TemporaryFailure,
+            SMSStatus::PermanentFailure,
+            SMSStatus::Expired,
+            SMSStatus::DryRun,
+            SMSStatus::Pending,
+        ];
+        let serialized: Vec<Value> = statuses
+            .iter()
+            .map(|status| serde_json::to_value(status).unwrap())
+            .collect();
+        assert_eq!(documented, &Value::Array(serialized));
+    }
+}
@@ -1,18 +1,40 @@
+use crate::events::EventType;
+use crate::http::export::export_messages;
+use crate::http::timestamps::{timestamp_format, TimestampQuery, Timestamped};
 use crate::http::types::{
-    GetFriendlyNameRequest, GlobalFetchRequest, HttpResponse, MessageIdFetchRequest,
-    PhoneNumberFetchRequest, SendSmsRequest, SendSmsResponse, SetFriendlyNameRequest,
-    SetLogLevelRequest, SmsDeviceInfo, WebSocketQuery,
+    BadRequest, BlocklistAddRequest, BlocklistRemoveRequest, DatabaseMaintenanceRequest,
+    EstimateSmsRequest, ExportQuery, FlushQueueRequest, Forbidden, GetFriendlyNameRequest,
+    GetMessageRequest, GlobalFetchRequest, HttpResponse, JsonResult, MarkReadRequest,
+    MessageIdFetchRequest, ModemTarget, PhoneNumberFetchRequest, ReplayWebhookRequest,
+    ResendSmsRequest, SendBulkSmsRequest, SendBulkSmsResult, SendSmsRequest, SendSmsResponse,
+    SetBandRequest, SetFriendlyNameRequest, SetFunctionalityRequest, SetLogLevelRequest,
+    SetOperatorRequest, SetPreferredModeRequest, SmsDeviceInfo, StatsQuery, SystemTimeStatus,
+    TestWebhookRequest, WebSocketQuery,
 };
-use crate::http::websocket::{handle_websocket, WebSocketConnection};
-use crate::http::{get_modem_json_result, HttpState};
-use crate::modem::types::{ModemRequest, ModemResponse};
-use crate::types::{SMSDeliveryReport, SMSMessage, SMSOutgoingMessage};
+use crate::http::websocket::{handle_websocket, WebSocketConnection, WebSocketStats};
+use crate::http::{get_modem_json_result, AuthTokenLabel, HttpState, WEBSOCKET_AUTH_PROTOCOL};
+use crate::modem::types::{
+    ModemQueueStatus, ModemRequest, ModemResponse, ModemStatus, NetworkEvent,
+};
+use crate::sms::types::{DatabaseMaintenanceResult, MarkReadTarget, SMSMessageStats};
+use crate::sms::SMSManager;
+use crate::templates;
+use crate::types::{
+    decode_data_content, BlocklistEntry, SMSContentEncoding, SMSConversation, SMSDataCoding,
+    SMSDeliveryReport, SMSEncoding, SMSEncodingEstimate, SMSEncodingExt, SMSMessage,
+    SMSOutgoingMessage, SMSStatus,
+};
+use crate::webhooks::{WebhookQueueStatus, WebhookReplayStarted, WebhookStatus, WebhookTestResult};
 use anyhow::{anyhow, bail};
-use axum::extract::{Query, State, WebSocketUpgrade};
-use axum::http::StatusCode;
+use axum::body::Body;
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{Extension, Query, State, WebSocketUpgrade};
+use axum::http::{header, StatusCode};
 use axum::response::Response;
 use sms_pdu::pdu::{PduAddress, TypeOfNumber};
+use std::collections::HashMap;
 use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tracing_subscriber::EnvFilter;
 
 macro_rules! http_response_handler {
@@ -24,7 +46,15 @@ macro_rules! http_response_handler {
                 error: None,
             })),
             Err(e) => Err((
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                if e.is::<crate::http::types::BadRequest>() {
+                    axum::http::StatusCode::BAD_REQUEST
+                } else if e.is::<crate::http::types::Forbidden>()
+                    || e.is::<crate::sms::types::BlocklistedNumber>()
+                {
+                    axum::http::StatusCode::FORBIDDEN
+                } else {
+                    axum::http::StatusCode::INTERNAL_SERVER_ERROR
+                },
                 axum::Json(HttpResponse {
                     success: false,
                     response: None,
@@ -36,6 +66,27 @@ macro_rules! http_response_handler {
 }
 
 macro_rules! http_get_handler {
+    (
+        $fn_name:ident,
+        Query<$query_type:ty>,
+        $response_type:ty,
+        |$state:ident, $query:ident| $callback:block
+    ) => {
+        pub async fn $fn_name(
+            axum::extract::State($state): axum::extract::State<crate::http::HttpState>,
+            axum::extract::Query($query): axum::extract::Query<$query_type>,
+        ) -> crate::http::types::JsonResult<$response_type> {
+            async fn inner(
+                $state: crate::http::HttpState,
+                $query: $query_type,
+            ) -> anyhow::Result<$response_type> {
+                $callback
+            }
+
+            let result = inner($state, $query).await;
+            http_response_handler!(result)
+        }
+    };
     (
         $fn_name:ident,
         $response_type:ty,
@@ -55,6 +106,76 @@ macro_rules! http_get_handler {
 }
 
 macro_rules! http_post_handler {
+    // A POST responding with stored timestamps, formatted by the `timestamps` query parameter.
+    (
+        $fn_name:ident,
+        Option<$request_type:ty>,
+        Timestamped<$response_type:ty>,
+        |$state:ident, $payload:ident| $callback:block
+    ) => {
+        pub async fn $fn_name(
+            axum::extract::State($state): axum::extract::State<crate::http::HttpState>,
+            timestamps: Result<
+                axum::extract::Query<crate::http::timestamps::TimestampQuery>,
+                axum::extract::rejection::QueryRejection,
+            >,
+            payload: Option<axum::Json<$request_type>>,
+        ) -> crate::http::types::JsonResult<crate::http::timestamps::Timestamped<$response_type>> {
+            async fn inner(
+                $state: crate::http::HttpState,
+                $payload: Option<$request_type>,
+            ) -> anyhow::Result<$response_type> {
+                $callback
+            }
+
+            let $payload = payload.map(|json| json.0);
+            let result = match crate::http::timestamps::timestamp_format(timestamps) {
+                Ok(format) => inner($state, $payload)
+                    .await
+                    .map(|data| crate::http::timestamps::Timestamped::new(data, format)),
+                Err(e) => Err(e),
+            };
+            http_response_handler!(result)
+        }
+    };
+    (
+        $fn_name:ident,
+        $request_type:ty,
+        Timestamped<$response_type:ty>,
+        |$state:ident, $payload:ident| $callback:block
+    ) => {
+        pub async fn $fn_name(
+            axum::extract::State($state): axum::extract::State<crate::http::HttpState>,
+            timestamps: Result<
+                axum::extract::Query<crate::http::timestamps::TimestampQuery>,
+                axum::extract::rejection::QueryRejection,
+            >,
+            axum::Json($payload): axum::Json<$request_type>,
+        ) -> crate::http::types::JsonResult<crate::http::timestamps::Timestamped<$response_type>> {
+            async fn inner(
+                $state: crate::http::HttpState,
+                $payload: $request_type,
+            ) -> anyhow::Result<$response_type> {
+                $callback
+            }
+
+            let result = match crate::http::timestamps::timestamp_format(timestamps) {
+                Ok(format) => inner($state, $payload)
+                    .await
+                    .map(|data| crate::http::timestamps::Timestamped::new(data, format)),
+                Err(e) => Err(e),
+            };
+            http_response_handler!(result)
+        }
+    };
+    // A POST without a request body.
+    (
+        $fn_name:ident,
+        $response_type:ty,
+        |$state:ident| $callback:block
+    ) => {
+        http_get_handler!($fn_name, $response_type, |$state| $callback);
+    };
     (
         $fn_name:ident,
         Option<$request_type:ty>,
@@ -104,22 +225,23 @@ macro_rules! http_modem_handler {
     ($fn_name:ident, $modem_req:expr) => {
         pub async fn $fn_name(
             State(state): State<crate::http::HttpState>,
+            Query(target): Query<ModemTarget>,
         ) -> crate::http::types::JsonResult<crate::modem::types::ModemResponse> {
-            get_modem_json_result(state, $modem_req).await
+            get_modem_json_result(state, target.modem_id.as_deref(), $modem_req).await
         }
     };
 }
 
 macro_rules! modem_extract {
-    ($sms_manager:expr, $request:expr => $variant:ident) => {
-        match $sms_manager.send_command($request).await {
+    ($sms_manager:expr, $modem_id:expr, $request:expr => $variant:ident) => {
+        match $sms_manager.send_command($modem_id, $request).await {
             Ok(ModemResponse::$variant(data)) => Some(data),
             _ => None
         }
     };
-    ($sms_manager:expr, $request:expr => $variant:ident { $($field:ident),+ }) => {
-        match $sms_manager.send_command($request).await {
-            Ok(ModemResponse::$variant { $($field),+ }) => Some(($($field,)+)),
+    ($sms_manager:expr, $modem_id:expr, $request:expr => $variant:ident { $($field:ident),+ }) => {
+        match $sms_manager.send_command($modem_id, $request).await {
+            Ok(ModemResponse::$variant { $($field),+, .. }) => Some(($($field,)+)),
             _ => None
         }
     };
@@ -128,7 +250,7 @@ macro_rules! modem_extract {
 http_post_handler!(
     db_sms,
     PhoneNumberFetchRequest,
-    Vec<SMSMessage>,
+    Timestamped<Vec<SMSMessage>>,
     |state, payload| {
         state
             .sms_manager
@@ -143,10 +265,23 @@ http_post_handler!(
     }
 );
 
+http_post_handler!(
+    db_message,
+    GetMessageRequest,
+    Timestamped<Option<SMSMessage>>,
+    |state, payload| {
+        state
+            .sms_manager
+            .borrow_database()
+            .get_message(payload.message_id)
+            .await
+    }
+);
+
 http_post_handler!(
     db_delivery_reports,
     MessageIdFetchRequest,
-    Vec<SMSDeliveryReport>,
+    Timestamped<Vec<SMSDeliveryReport>>,
     |state, payload| {
         state
             .sms_manager
@@ -179,6 +314,24 @@ http_post_handler!(
     }
 );
 
+http_post_handler!(
+    db_conversations,
+    Option<GlobalFetchRequest>,
+    Timestamped<Vec<SMSConversation>>,
+    |state, payload| {
+        let (limit, offset, reverse) = match payload {
+            Some(req) => (req.limit, req.offset, req.reverse),
+            None => (None, None, false),
+        };
+
+        state
+            .sms_manager
+            .borrow_database()
+            .get_conversations(limit, offset, reverse)
+            .await
+    }
+);
+
 http_post_handler!(
     friendly_names_set,
     SetFriendlyNameRequest,
@@ -206,70 +359,530 @@ http_post_handler!(
     }
 );
 
+/// Parse a phone number the way sent and received numbers are stored, so blocklist entries match.
+fn normalize_phone_number(phone_number: &str) -> anyhow::Result<String> {
+    match PduAddress::from_str(phone_number)?.to_string().as_str() {
+        "+" | "" => bail!("Invalid phone number!"),
+        normalized => Ok(normalized.to_string()),
+    }
+}
+
+http_post_handler!(db_mark_read, MarkReadRequest, u64, |state, payload| {
+    let target = match (payload.message_id, payload.phone_number) {
+        (Some(message_id), None) => MarkReadTarget::Message(message_id),
+        (None, Some(phone_number)) => {
+            MarkReadTarget::PhoneNumber(normalize_phone_number(&phone_number)?)
+        }
+        _ => bail!(BadRequest(
+            "Either message_id or phone_number must be given".to_string()
+        )),
+    };
+
+    state.sms_manager.borrow_database().mark_read(&target).await
+});
+
+http_post_handler!(
+    blocklist_add,
+    BlocklistAddRequest,
+    bool,
+    |state, payload| {
+        state
+            .sms_manager
+            .borrow_database()
+            .add_to_blocklist(
+                &normalize_phone_number(&payload.phone_number)?,
+                payload.reason.as_deref(),
+            )
+            .await
+    }
+);
+
+http_post_handler!(
+    blocklist_remove,
+    BlocklistRemoveRequest,
+    bool,
+    |state, payload| {
+        state
+            .sms_manager
+            .borrow_database()
+            .remove_from_blocklist(&normalize_phone_number(&payload.phone_number)?)
+            .await
+    }
+);
+
+http_post_handler!(
+    blocklist_list,
+    Option<GlobalFetchRequest>,
+    Timestamped<Vec<BlocklistEntry>>,
+    |state, payload| {
+        let (limit, offset, reverse) = match payload {
+            Some(req) => (req.limit, req.offset, req.reverse),
+            None => (None, None, false),
+        };
+
+        state
+            .sms_manager
+            .borrow_database()
+            .get_blocklist(limit, offset, reverse)
+            .await
+    }
+);
+
+http_post_handler!(db_reconcile_delivery, u64, |state| {
+    // It mutates stored data, so isn't allowed without authentication.
+    if !state.config.require_authentication {
+        bail!(Forbidden(
+            "Reconciling delivery reports requires authentication to be enabled".to_string()
+        ));
+    }
+
+    let changed = state
+        .sms_manager
+        .borrow_database()
+        .reconcile_delivery_reports()
+        .await?;
+
+    tracing::log::info!("Reconciled delivery reports via API, {changed} message(s) changed");
+    Ok(changed)
+});
+
+http_post_handler!(
+    sys_db_maintenance,
+    Option<DatabaseMaintenanceRequest>,
+    DatabaseMaintenanceResult,
+    |state, payload| {
+        // A vacuum blocks writes until it completes, so isn't allowed without authentication.
+        if !state.config.require_authentication {
+            bail!(Forbidden(
+                "Database maintenance requires authentication to be enabled".to_string()
+            ));
+        }
+
+        let vacuum = payload.is_some_and(|request| request.vacuum);
+        let result = state
+            .sms_manager
+            .borrow_database()
+            .run_maintenance(vacuum)
+            .await?;
+
+        tracing::log::info!(
+            "Ran database maintenance via API, database {} -> {} bytes, WAL {} -> {} bytes",
+            result.before.database_bytes,
+            result.after.database_bytes,
+            result.before.wal_bytes,
+            result.after.wal_bytes
+        );
+        Ok(result)
+    }
+);
+
+pub async fn db_stats(
+    State(state): State<HttpState>,
+    Query(query): Query<StatsQuery>,
+) -> JsonResult<SMSMessageStats> {
+    async fn inner(state: HttpState, query: StatsQuery) -> anyhow::Result<SMSMessageStats> {
+        state
+            .sms_manager
+            .borrow_database()
+            .get_stats(query.get_window()?)
+            .await
+    }
+
+    let result = inner(state, query).await;
+    http_response_handler!(result)
+}
+
+pub async fn db_export(
+    State(state): State<HttpState>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, StatusCode> {
+    let format = query.format;
+    let stream = export_messages(state.sms_manager, query.phone_number, format);
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, format.content_type())
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"messages.{}\"", format.extension()),
+        )
+        .body(Body::from_stream(stream))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// The default and maximum time to hold a send response waiting for delivery.
+const DEFAULT_DELIVERY_WAIT: Duration = Duration::from_secs(60);
+const MAX_DELIVERY_WAIT: Duration = Duration::from_secs(600);
+
+/// Validate and send a single message, shared by the single and bulk send routes.
+async fn send_sms_request(
+    state: &HttpState,
+    payload: SendSmsRequest,
+) -> anyhow::Result<SendSmsResponse> {
+    let phone_number = PduAddress::from_str(&payload.to)?;
+    if state.config.send_international_format_only
+        && !matches!(
+            phone_number.type_addr.type_of_number,
+            TypeOfNumber::International
+        )
+    {
+        bail!("Sending phone number must be in international format!");
+    }
+
+    // Quick-fix to make sure the number is valid before attempting.
+    match phone_number.to_string().as_str() {
+        "+" | "" => bail!("Invalid phone number!"),
+        _ => {}
+    }
+
+    let delivery_wait = match payload.wait_timeout_secs.map(Duration::from_secs) {
+        Some(wait) if wait > MAX_DELIVERY_WAIT => bail!(
+            "The wait_timeout_secs must be at most {}",
+            MAX_DELIVERY_WAIT.as_secs()
+        ),
+        Some(wait) => wait,
+        None => DEFAULT_DELIVERY_WAIT,
+    };
+
+    // Templates are rendered first, so the result is encoded and validated like literal content.
+    let content = match &payload.template {
+        Some(_) if !payload.content.is_empty() => {
+            bail!(BadRequest(
+                "Send either content or a template, not both".to_string()
+            ))
+        }
+        Some(name) => templates::render(&state.templates, name, &payload.vars)
+            .map_err(|e| BadRequest(e.to_string()))?,
+        None if payload.content.is_empty() => {
+            bail!(BadRequest(
+                "Message content or a template is required".to_string()
+            ))
+        }
+        None => payload.content,
+    };
+
+    // 8-bit data is normalised to hex for storage, and is never text encoded.
+    let (content, user_data_header) = match payload.data_coding {
+        SMSDataCoding::Text if payload.udh.is_some() => bail!(BadRequest(
+            "A user data header can only be sent with 8-bit data".to_string()
+        )),
+        SMSDataCoding::Text if !payload.content_encoding.is_hex() => bail!(BadRequest(
+            "A content encoding can only be set for 8-bit data".to_string()
+        )),
+        SMSDataCoding::Text => (content, None),
+        SMSDataCoding::EightBit if payload.encoding != SMSEncoding::Auto => bail!(BadRequest(
+            "An encoding can't be set for 8-bit data".to_string()
+        )),
+        SMSDataCoding::EightBit => {
+            let data = decode_data_content(&content, payload.content_encoding)
+                .map_err(|e| BadRequest(e.to_string()))?;
+            let udh = payload
+                .udh
+                .as_deref()
+                .map(hex::decode)
+                .transpose()
+                .map_err(|_| BadRequest("The user data header must be hex encoded".to_string()))?;
+            (hex::encode(data), udh)
+        }
+    };
+
+    // Resolved early so that content which can't be forced into GSM 7-bit is rejected before sending.
+    let encoding = match payload.data_coding {
+        SMSDataCoding::Text => payload.encoding.resolve(&content)?,
+        SMSDataCoding::EightBit => SMSEncoding::Auto,
+    };
+    let outgoing = SMSOutgoingMessage {
+        phone_number,
+        content,
+        flash: payload.flash,
+        validity_period: payload.validity_period,
+        timeout: payload.timeout,
+        encoding: payload.encoding,
+        data_coding: payload.data_coding,
+        user_data_header,
+        priority: payload.priority,
+        modem_id: payload.modem_id,
+        dry_run: payload.dry_run,
+        override_blocklist: payload.override_blocklist,
+    };
+
+    let (message_id, response, route, pdus) = state.sms_manager.send_sms(outgoing).await?;
+    match response {
+        ModemResponse::SendResult { reference_id } => {
+            let message_id =
+                message_id.ok_or_else(|| anyhow!("Message sent but no message ID returned"))?;
+
+            // A dry run is already final, as it's never sent to the network.
+            let delivery_status = if payload.wait_for_delivery && !payload.dry_run {
+                state
+                    .sms_manager
+                    .wait_for_delivery(message_id, delivery_wait)
+                    .await?
+            } else {
+                None
+            };
+            Ok(SendSmsResponse {
+                message_id,
+                reference_id,
+                encoding,
+                data_coding: payload.data_coding,
+                delivery_timed_out: payload.wait_for_delivery && delivery_status.is_none(),
+                status: delivery_status.unwrap_or(if payload.dry_run {
+                    SMSStatus::DryRun
+                } else {
+                    SMSStatus::Sent
+                }),
+                route: Some(route),
+                pdus: payload.include_pdus.then_some(pdus),
+            })
+        }
+        ModemResponse::Error { message, .. } => Err(anyhow!(message)),
+        _ => Err(anyhow!("Unexpected response type for SMS send request")),
+    }
+}
+
 http_post_handler!(
     sms_send,
     SendSmsRequest,
     SendSmsResponse,
+    |state, payload| { send_sms_request(&state, payload).await }
+);
+
+http_post_handler!(
+    sms_resend,
+    ResendSmsRequest,
+    SendSmsResponse,
     |state, payload| {
-        let phone_number = PduAddress::from_str(&payload.to)?;
-        if state.config.send_international_format_only
-            && !matches!(
-                phone_number.type_addr.type_of_number,
-                TypeOfNumber::International
-            )
+        let database = state.sms_manager.borrow_database().clone();
+        let original = database
+            .get_message(payload.message_id)
+            .await?
+            .ok_or_else(|| anyhow!("Message #{} not found", payload.message_id))?;
+        if !original.is_outgoing {
+            bail!(
+                "Message #{} is an incoming message and can't be resent",
+                payload.message_id
+            );
+        }
+        if !matches!(
+            original.status,
+            SMSStatus::Sent
+                | SMSStatus::Delivered
+                | SMSStatus::TemporaryFailure
+                | SMSStatus::PermanentFailure
+        ) {
+            bail!(
+                "Message #{} is {} and can't be resent",
+                payload.message_id,
+                original.status.as_str()
+            );
+        }
+
+        // Messages sent before their options were kept use the defaults. A user data header isn't
+        // resent, and it isn't tied to the original modem, which may be the reason it needs resending.
+        let send_options = database.get_send_options(payload.message_id).await?;
+        let request = SendSmsRequest {
+            to: original.phone_number,
+            content: original.message_content,
+            template: None,
+            vars: HashMap::new(),
+            flash: send_options.as_ref().is_some_and(|options| options.flash),
+            validity_period: send_options.and_then(|options| options.validity_period),
+            timeout: None,
+            encoding: SMSEncoding::Auto,
+            data_coding: original.data_coding,
+            content_encoding: SMSContentEncoding::Hex,
+            udh: None,
+            priority: false,
+            wait_for_delivery: false,
+            wait_timeout_secs: None,
+            modem_id: None,
+            dry_run: false,
+            override_blocklist: false,
+            include_pdus: false,
+        };
+        let response = send_sms_request(&state, request).await?;
+        if let Err(e) = database
+            .set_resend_of(response.message_id, payload.message_id)
+            .await
         {
-            bail!("Sending phone number must be in international format!");
+            tracing::log::error!(
+                "Failed to link resent message #{}: {e:?}",
+                response.message_id
+            );
         }
 
-        // Quick-fix to make sure the number is valid before attempting.
-        match phone_number.to_string().as_str() {
-            "+" | "" => bail!("Invalid phone number!"),
-            _ => {}
+        Ok(response)
+    }
+);
+
+http_post_handler!(
+    sms_send_bulk,
+    SendBulkSmsRequest,
+    Vec<SendBulkSmsResult>,
+    |state, payload| {
+        // Each recipient is sent before responding, so the count is capped to bound the request.
+        let max_recipients = state.config.max_bulk_recipients;
+        if payload.to.len() > max_recipients {
+            bail!(BadRequest(format!(
+                "A bulk send can have at most {max_recipients} recipients, got {}",
+                payload.to.len()
+            )));
         }
 
-        let outgoing = SMSOutgoingMessage {
-            phone_number,
-            content: payload.content,
-            flash: payload.flash,
-            validity_period: payload.validity_period,
-            timeout: payload.timeout,
-        };
+        // Messages are sent one at a time to avoid flooding the modem command queue.
+        let mut results = Vec::with_capacity(payload.to.len());
+        for to in payload.to {
+            let request = SendSmsRequest {
+                to: to.clone(),
+                content: payload.content.clone(),
+                template: None,
+                vars: HashMap::new(),
+                flash: payload.flash,
+                validity_period: payload.validity_period,
+                timeout: payload.timeout,
+                encoding: payload.encoding,
+                data_coding: SMSDataCoding::Text,
+                content_encoding: SMSContentEncoding::Hex,
+                udh: None,
+                priority: payload.priority,
+                wait_for_delivery: false,
+                wait_timeout_secs: None,
+                modem_id: payload.modem_id.clone(),
+                dry_run: payload.dry_run,
+                override_blocklist: payload.override_blocklist,
+                include_pdus: false,
+            };
 
-        let (message_id, response) = state.sms_manager.send_sms(outgoing).await?;
-        match response {
-            ModemResponse::SendResult(reference_id) => {
-                let message_id =
-                    message_id.ok_or_else(|| anyhow!("Message sent but no message ID returned"))?;
-                Ok(SendSmsResponse {
-                    message_id,
-                    reference_id,
-                })
-            }
-            ModemResponse::Error(message) => Err(anyhow!(message)),
-            _ => Err(anyhow!("Unexpected response type for SMS send request")),
+            results.push(match send_sms_request(&state, request).await {
+                Ok(response) => SendBulkSmsResult {
+                    to,
+                    message_id: Some(response.message_id),
+                    reference_id: Some(response.reference_id),
+                    error: None,
+                },
+                Err(e) => SendBulkSmsResult {
+                    to,
+                    message_id: None,
+                    reference_id: None,
+                    error: Some(e.to_string()),
+                },
+            });
         }
+
+        Ok(results)
     }
 );
 
+http_post_handler!(
+    sms_estimate,
+    EstimateSmsRequest,
+    SMSEncodingEstimate,
+    |_state, payload| { Ok(SMSEncodingEstimate::from_content(&payload.content)) }
+);
+
 http_modem_handler!(sms_get_network_status, ModemRequest::GetNetworkStatus);
 http_modem_handler!(sms_get_signal_strength, ModemRequest::GetSignalStrength);
 http_modem_handler!(sms_get_network_operator, ModemRequest::GetNetworkOperator);
 http_modem_handler!(sms_get_service_provider, ModemRequest::GetServiceProvider);
 http_modem_handler!(sms_get_battery_level, ModemRequest::GetBatteryLevel);
+http_modem_handler!(sms_get_service_centre, ModemRequest::GetServiceCentre);
+http_modem_handler!(sms_get_functionality, ModemRequest::GetFunctionality);
+http_modem_handler!(sms_get_parameters, ModemRequest::GetSmsParameters);
+http_modem_handler!(sms_get_preferred_mode, ModemRequest::GetPreferredMode);
 http_modem_handler!(gnss_get_status, ModemRequest::GetGNSSStatus);
 http_modem_handler!(gnss_get_location, ModemRequest::GetGNSSLocation);
 
-http_get_handler!(sms_get_device_info, SmsDeviceInfo, |state| {
-    Ok(SmsDeviceInfo {
-        version: crate::VERSION.to_string(),
-        phone_number: state.config.phone_number,
-        service_provider: modem_extract!(state.sms_manager, ModemRequest::GetServiceProvider => ServiceProvider),
-        network_operator: modem_extract!(state.sms_manager, ModemRequest::GetNetworkOperator => NetworkOperator { status, format, operator }),
-        network_status: modem_extract!(state.sms_manager, ModemRequest::GetNetworkStatus => NetworkStatus { registration, technology }),
-        battery: modem_extract!(state.sms_manager, ModemRequest::GetBatteryLevel => BatteryLevel { status, charge, voltage }),
-        signal: modem_extract!(state.sms_manager, ModemRequest::GetSignalStrength => SignalStrength { rssi, ber }),
-    })
-});
+http_post_handler!(
+    sms_set_network_operator,
+    SetOperatorRequest,
+    ModemResponse,
+    |state, payload| {
+        state
+            .sms_manager
+            .set_operator(payload.modem_id.as_deref(), payload.operator)
+            .await
+    }
+);
+
+http_post_handler!(
+    sms_set_preferred_mode,
+    SetPreferredModeRequest,
+    ModemResponse,
+    |state, payload| {
+        state
+            .sms_manager
+            .send_command(
+                payload.modem_id.as_deref(),
+                ModemRequest::SetPreferredMode {
+                    mode: payload.rat.cnmp_mode(),
+                },
+            )
+            .await
+    }
+);
+
+http_post_handler!(
+    sms_set_bands,
+    SetBandRequest,
+    ModemResponse,
+    |state, payload| {
+        if payload.bands.is_empty() {
+            bail!("At least one band is required");
+        }
+        if !matches!(payload.network.as_str(), "CAT-M" | "NB-IOT") {
+            bail!("Invalid band network, expected CAT-M or NB-IOT");
+        }
+        state
+            .sms_manager
+            .send_command(
+                payload.modem_id.as_deref(),
+                ModemRequest::SetBand {
+                    network: payload.network,
+                    bands: payload.bands,
+                },
+            )
+            .await
+    }
+);
+
+http_post_handler!(
+    sms_set_functionality,
+    SetFunctionalityRequest,
+    ModemResponse,
+    |state, payload| {
+        // Only allow minimum (0), full (1) and flight mode (4), others are vendor specific.
+        if !matches!(payload.level, 0 | 1 | 4) {
+            bail!("Invalid functionality level, expected 0 (minimum), 1 (full) or 4 (flight mode)");
+        }
+        state
+            .sms_manager
+            .send_command(
+                payload.modem_id.as_deref(),
+                ModemRequest::SetFunctionality {
+                    level: payload.level,
+                },
+            )
+            .await
+    }
+);
+
+http_get_handler!(
+    sms_get_device_info,
+    Query<ModemTarget>,
+    SmsDeviceInfo,
+    |state, target| {
+        let modem_id = target.modem_id.as_deref();
+        Ok(SmsDeviceInfo {
+            version: crate::VERSION.to_string(),
+            phone_number: state.config.phone_number,
+            service_provider: modem_extract!(state.sms_manager, modem_id, ModemRequest::GetServiceProvider => ServiceProvider),
+            network_operator: modem_extract!(state.sms_manager, modem_id, ModemRequest::GetNetworkOperator => NetworkOperator { status, format, operator }),
+            network_status: modem_extract!(state.sms_manager, modem_id, ModemRequest::GetNetworkStatus => NetworkStatus { registration, technology }),
+            battery: modem_extract!(state.sms_manager, modem_id, ModemRequest::GetBatteryLevel => BatteryLevel { status, charge, voltage }),
+            signal: modem_extract!(state.sms_manager, modem_id, ModemRequest::GetSignalStrength => SignalStrength { rssi, ber }),
+        })
+    }
+);
 
 http_get_handler!(sys_version, &'static str, |_state| { Ok(crate::VERSION) });
 
@@ -277,6 +890,158 @@ http_get_handler!(sys_phone_number, Option<String>, |state| {
     Ok(state.config.phone_number)
 });
 
+http_get_handler!(
+    sys_time,
+    Query<ModemTarget>,
+    SystemTimeStatus,
+    |state, target| {
+        let (modem_time, timestamp, utc_offset_minutes) = match state
+            .sms_manager
+            .send_command(target.modem_id.as_deref(), ModemRequest::GetClock)
+            .await?
+        {
+            ModemResponse::Clock {
+                time,
+                timestamp,
+                utc_offset_minutes,
+            } => (time, timestamp, utc_offset_minutes),
+            ModemResponse::Error { message, .. } => bail!(message),
+            _ => bail!("Unexpected response type for clock request"),
+        };
+
+        let system_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        Ok(SystemTimeStatus {
+            system_time,
+            modem_time,
+            timestamp,
+            utc_offset_minutes,
+            synced: timestamp.is_some(),
+            drift_secs: timestamp.map(|timestamp| timestamp as i64 - system_time as i64),
+        })
+    }
+);
+
+http_get_handler!(
+    sys_modem_queue,
+    Query<ModemTarget>,
+    ModemQueueStatus,
+    |state, target| {
+        state
+            .sms_manager
+            .modem_queue_status(target.modem_id.as_deref())
+    }
+);
+
+http_post_handler!(
+    sys_modem_reset,
+    Option<ModemTarget>,
+    ModemStatus,
+    |state, payload| {
+        let modem_id = payload.and_then(|target| target.modem_id);
+        tracing::log::warn!("Resetting modem via API");
+        state.sms_manager.reset_modem(modem_id.as_deref()).await
+    }
+);
+
+http_post_handler!(
+    sys_modem_flush_queue,
+    Option<FlushQueueRequest>,
+    usize,
+    |state, payload| {
+        let modem_id = payload.and_then(|req| req.modem_id);
+        tracing::log::warn!("Flushing modem command queue via API");
+        state
+            .sms_manager
+            .flush_modem_queue(modem_id.as_deref())
+            .await
+    }
+);
+
+http_get_handler!(sys_webhooks, Vec<WebhookStatus>, |state| {
+    match state.webhooks {
+        Some(webhooks) => Ok(webhooks.status().await),
+        None => Ok(Vec::new()),
+    }
+});
+
+http_get_handler!(sys_webhooks_queue, WebhookQueueStatus, |state| {
+    match state.webhooks {
+        Some(webhooks) => Ok(webhooks.queue_status()),
+        None => bail!("No webhooks are configured!"),
+    }
+});
+
+pub async fn sys_network_history(
+    State(state): State<HttpState>,
+    timestamps: Result<Query<TimestampQuery>, QueryRejection>,
+    Query(query): Query<GlobalFetchRequest>,
+) -> JsonResult<Timestamped<Vec<NetworkEvent>>> {
+    let result = match timestamp_format(timestamps) {
+        Ok(format) => state
+            .sms_manager
+            .borrow_database()
+            .get_network_events(query.limit, query.offset, query.reverse)
+            .await
+            .map(|events| Timestamped::new(events, format)),
+        Err(e) => Err(e),
+    };
+    http_response_handler!(result)
+}
+
+http_get_handler!(sys_websocket_stats, WebSocketStats, |state| {
+    match state.websocket {
+        Some(websocket) => Ok(websocket.stats().await),
+        None => bail!("Websocket functionality is disabled!"),
+    }
+});
+
+http_post_handler!(
+    sys_test_webhooks,
+    Option<TestWebhookRequest>,
+    Vec<WebhookTestResult>,
+    |state, payload| {
+        let webhooks = state
+            .webhooks
+            .ok_or_else(|| anyhow!("No webhooks are configured!"))?;
+
+        let (index, url) = payload.map_or((None, None), |req| (req.index, req.url));
+        webhooks.test(index, url.as_deref()).await
+    }
+);
+
+http_post_handler!(
+    sys_replay_webhooks,
+    ReplayWebhookRequest,
+    WebhookReplayStarted,
+    |state, payload| {
+        let webhooks = state
+            .webhooks
+            .ok_or_else(|| anyhow!("No webhooks are configured!"))?;
+
+        let to = match payload.to {
+            Some(to) => to,
+            None => SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        };
+        let event_types = payload.events.unwrap_or_else(|| {
+            vec![
+                EventType::IncomingMessage,
+                EventType::OutgoingMessage,
+                EventType::DeliveryReport,
+            ]
+        });
+
+        SMSManager::check_replayable(&event_types)?;
+
+        // Sending can take a while, so it continues after responding.
+        let sms_manager = state.sms_manager.clone();
+        let from = payload.from;
+        let events = async move { sms_manager.replay_events(from, to, &event_types).await };
+        webhooks
+            .replay(payload.index, payload.url.as_deref(), events)
+            .await
+    }
+);
+
 http_post_handler!(
     sys_set_log_level,
     SetLogLevelRequest,
@@ -293,18 +1058,31 @@ http_post_handler!(
     }
 );
 
+/// The OpenAPI document, served directly rather than in the response envelope.
+pub async fn openapi_json() -> axum::Json<serde_json::Value> {
+    axum::Json(crate::http::openapi::openapi_document())
+}
+
 pub async fn websocket_upgrade(
     ws: WebSocketUpgrade,
     State(state): State<HttpState>,
     Query(query_params): Query<WebSocketQuery>,
+    token_label: Option<Extension<AuthTokenLabel>>,
 ) -> Result<Response, StatusCode> {
+    if let Some(Extension(AuthTokenLabel(label))) = token_label {
+        tracing::log::info!("Upgrading WebSocket connection authenticated with token '{label}'");
+    }
+
     // Read all target events from query string for filtering.
     let events = query_params.get_event_types();
     let response = match state.websocket {
-        Some(manager) => ws.on_upgrade(|socket| {
-            let connection: WebSocketConnection = (socket, events);
-            handle_websocket(connection, manager)
-        }),
+        // Only selected if the client offered it, for clients authenticating with a subprotocol.
+        Some(manager) => ws
+            .protocols([WEBSOCKET_AUTH_PROTOCOL])
+            .on_upgrade(|socket| {
+                let connection: WebSocketConnection = (socket, events);
+                handle_websocket(connection, manager)
+            }),
         None => Response::builder()
             .status(StatusCode::NOT_FOUND)
             .body("Websocket functionality is disabled!".into())
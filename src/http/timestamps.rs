@@ -0,0 +1,140 @@
+use crate::http::types::BadRequest;
+use crate::sms::types::civil_from_days;
+use axum::extract::rejection::QueryRejection;
+use axum::extract::Query;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+
+/// Response fields holding a stored timestamp, in Unix seconds (UTC) by default.
+const TIMESTAMP_FIELDS: [&str; 2] = ["created_at", "completed_at"];
+
+/// How timestamps are serialized in JSON responses, selected with the `timestamps` query parameter.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampFormat {
+    #[default]
+    Unix,
+    Iso8601,
+}
+
+#[derive(Deserialize)]
+pub struct TimestampQuery {
+    #[serde(default)]
+    timestamps: TimestampFormat,
+}
+
+/// Get the requested timestamp format, rejecting anything other than `unix` or `iso8601`.
+pub fn timestamp_format(
+    query: Result<Query<TimestampQuery>, QueryRejection>,
+) -> anyhow::Result<TimestampFormat> {
+    match query {
+        Ok(Query(query)) => Ok(query.timestamps),
+        Err(_) => Err(BadRequest(
+            "invalid timestamps format, expected unix or iso8601".to_string(),
+        )
+        .into()),
+    }
+}
+
+/// A response whose stored timestamps are serialized in the requested format. Unix seconds are
+/// serialized as is, so existing clients are untouched.
+pub struct Timestamped<T> {
+    data: T,
+    format: TimestampFormat,
+}
+impl<T> Timestamped<T> {
+    pub fn new(data: T, format: TimestampFormat) -> Self {
+        Self { data, format }
+    }
+}
+impl<T: Serialize> Serialize for Timestamped<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.format == TimestampFormat::Unix {
+            return self.data.serialize(serializer);
+        }
+
+        let mut value = serde_json::to_value(&self.data).map_err(serde::ser::Error::custom)?;
+        rewrite_timestamps(&mut value);
+        value.serialize(serializer)
+    }
+}
+
+/// Format Unix seconds as an RFC 3339 UTC timestamp, eg: `2023-11-14T22:13:20Z`.
+pub fn format_rfc3339(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let seconds = timestamp % 86_400;
+
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3_600,
+        seconds % 3_600 / 60,
+        seconds % 60
+    )
+}
+
+/// Replace integer timestamp fields anywhere in a JSON value with RFC 3339 strings.
+fn rewrite_timestamps(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            for (key, field) in object.iter_mut() {
+                match field.as_u64() {
+                    Some(timestamp) if TIMESTAMP_FIELDS.contains(&key.as_str()) => {
+                        *field = Value::String(format_rfc3339(timestamp));
+                    }
+                    _ => rewrite_timestamps(field),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(rewrite_timestamps),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_format_rfc3339() {
+        assert_eq!(format_rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_rfc3339(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_rfc3339(1_700_000_000), "2023-11-14T22:13:20Z");
+        assert_eq!(format_rfc3339(4_102_444_799), "2099-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn test_rewrite_timestamps() {
+        let mut value = json!({
+            "success": true,
+            "response": [
+                { "message_id": 1, "created_at": 1_700_000_000, "completed_at": null },
+                { "message_id": 2, "created_at": 0, "completed_at": 60 }
+            ],
+            "error": null
+        });
+        rewrite_timestamps(&mut value);
+
+        assert_eq!(
+            value,
+            json!({
+                "success": true,
+                "response": [
+                    { "message_id": 1, "created_at": "2023-11-14T22:13:20Z", "completed_at": null },
+                    { "message_id": 2, "created_at": "1970-01-01T00:00:00Z", "completed_at": "1970-01-01T00:01:00Z" }
+                ],
+                "error": null
+            })
+        );
+
+        let message = json!({ "created_at": 60 });
+        let serialized = |format| serde_json::to_value(Timestamped::new(&message, format)).unwrap();
+        assert_eq!(serialized(TimestampFormat::Unix), message);
+        assert_eq!(
+            serialized(TimestampFormat::Iso8601),
+            json!({ "created_at": "1970-01-01T00:01:00Z" })
+        );
+    }
+}
@@ -1,71 +1,173 @@
 use crate::events::EventType;
+use crate::http::export::ExportFormat;
+use crate::modem::types::PreferredRat;
+use crate::types::SMSEncoding;
 use axum::http::StatusCode;
 use axum::Json;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::time::Duration;
+
+pub use sms_api_types::http::{
+    GlobalFetchRequest, HttpResponse, MessageIdFetchRequest, PhoneNumberFetchRequest,
+    SendSmsRequest, SendSmsResponse,
+};
 
 pub type JsonResult<T> = Result<Json<HttpResponse<T>>, (StatusCode, Json<HttpResponse<T>>)>;
 
-#[derive(Serialize)]
-pub struct HttpResponse<T> {
-    pub success: bool,
-    pub response: Option<T>,
-    pub error: Option<String>,
+/// An error caused by the request itself, responded to with 400 rather than 500.
+#[derive(Debug)]
+pub struct BadRequest(pub String);
+impl std::fmt::Display for BadRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
 }
+impl std::error::Error for BadRequest {}
+
+/// An error for a request that isn't allowed, responded to with 403 rather than 500.
+#[derive(Debug)]
+pub struct Forbidden(pub String);
+impl std::fmt::Display for Forbidden {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+impl std::error::Error for Forbidden {}
 
 #[derive(Deserialize)]
-pub struct PhoneNumberFetchRequest {
+pub struct ExportQuery {
     pub phone_number: String,
 
     #[serde(default)]
-    pub limit: Option<u64>,
+    pub format: ExportFormat,
+}
+
+#[derive(Deserialize)]
+pub struct SendBulkSmsRequest {
+    pub to: Vec<String>,
+    pub content: String,
+
+    #[serde(default)]
+    pub flash: bool,
+
+    #[serde(default)]
+    pub validity_period: Option<u8>,
+
+    #[serde(default)]
+    pub timeout: Option<u32>,
+
+    #[serde(default)]
+    pub encoding: SMSEncoding,
 
     #[serde(default)]
-    pub offset: Option<u64>,
+    pub priority: bool,
 
     #[serde(default)]
-    pub reverse: bool,
+    pub modem_id: Option<String>,
+
+    #[serde(default)]
+    pub dry_run: bool,
+
+    #[serde(default)]
+    pub override_blocklist: bool,
 }
 
 #[derive(Deserialize)]
-pub struct MessageIdFetchRequest {
+pub struct GetMessageRequest {
     pub message_id: i64,
+}
 
-    #[serde(default)]
-    pub limit: Option<u64>,
+#[derive(Deserialize)]
+pub struct ResendSmsRequest {
+    pub message_id: i64,
+}
 
+#[derive(Deserialize)]
+pub struct TestWebhookRequest {
     #[serde(default)]
-    pub offset: Option<u64>,
+    pub index: Option<usize>,
 
     #[serde(default)]
-    pub reverse: bool,
+    pub url: Option<String>,
 }
 
 #[derive(Deserialize)]
-pub struct GlobalFetchRequest {
+pub struct ReplayWebhookRequest {
+    pub from: u64,
+
     #[serde(default)]
-    pub limit: Option<u64>,
+    pub to: Option<u64>,
 
     #[serde(default)]
-    pub offset: Option<u64>,
+    pub events: Option<Vec<EventType>>,
 
     #[serde(default)]
-    pub reverse: bool,
+    pub index: Option<usize>,
+
+    #[serde(default)]
+    pub url: Option<String>,
 }
 
 #[derive(Deserialize)]
-pub struct SendSmsRequest {
-    pub to: String,
+pub struct DatabaseMaintenanceRequest {
+    #[serde(default)]
+    pub vacuum: bool,
+}
+
+#[derive(Deserialize)]
+pub struct FlushQueueRequest {
+    #[serde(default)]
+    pub modem_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct EstimateSmsRequest {
     pub content: String,
+}
 
+/// The modem a request is sent to, the default (first configured) modem if missing.
+#[derive(Deserialize)]
+pub struct ModemTarget {
     #[serde(default)]
-    pub flash: bool,
+    pub modem_id: Option<String>,
+}
 
+#[derive(Deserialize)]
+pub struct SetOperatorRequest {
+    /// Numeric operator code (MCC+MNC), or automatic selection if missing.
     #[serde(default)]
-    pub validity_period: Option<u8>,
+    pub operator: Option<String>,
 
     #[serde(default)]
-    pub timeout: Option<u32>,
+    pub modem_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SetPreferredModeRequest {
+    pub rat: PreferredRat,
+
+    #[serde(default)]
+    pub modem_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SetBandRequest {
+    /// The network type bands are set for, `CAT-M` or `NB-IOT`.
+    #[serde(default = "default_band_network")]
+    pub network: String,
+    pub bands: Vec<u16>,
+
+    #[serde(default)]
+    pub modem_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SetFunctionalityRequest {
+    pub level: u8,
+
+    #[serde(default)]
+    pub modem_id: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -74,9 +176,11 @@ pub struct SetLogLevelRequest {
 }
 
 #[derive(Serialize)]
-pub struct SendSmsResponse {
-    pub message_id: i64,
-    pub reference_id: u8,
+pub struct SendBulkSmsResult {
+    pub to: String,
+    pub message_id: Option<i64>,
+    pub reference_id: Option<u8>,
+    pub error: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -90,6 +194,17 @@ pub struct SmsDeviceInfo {
     pub signal: Option<(i32, i32)>,
 }
 
+/// The modem clock compared to the system clock, `drift_secs` is positive if the modem is ahead.
+#[derive(Serialize)]
+pub struct SystemTimeStatus {
+    pub system_time: u64,
+    pub modem_time: String,
+    pub timestamp: Option<u64>,
+    pub utc_offset_minutes: i32,
+    pub synced: bool,
+    pub drift_secs: Option<i64>,
+}
+
 #[derive(Deserialize)]
 pub struct SetFriendlyNameRequest {
     pub phone_number: String,
@@ -101,6 +216,29 @@ pub struct GetFriendlyNameRequest {
     pub phone_number: String,
 }
 
+#[derive(Deserialize)]
+pub struct BlocklistAddRequest {
+    pub phone_number: String,
+
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct BlocklistRemoveRequest {
+    pub phone_number: String,
+}
+
+/// Mark one incoming message read by `message_id`, or every message from `phone_number`.
+#[derive(Deserialize)]
+pub struct MarkReadRequest {
+    #[serde(default)]
+    pub message_id: Option<i64>,
+
+    #[serde(default)]
+    pub phone_number: Option<String>,
+}
+
 #[derive(Deserialize)]
 pub struct WebSocketQuery {
     pub events: Option<String>,
@@ -129,6 +267,42 @@ impl WebSocketQuery {
     }
 }
 
+#[derive(Deserialize)]
+pub struct StatsQuery {
+    #[serde(default = "default_stats_window")]
+    pub window: String,
+}
+impl StatsQuery {
+    /// Parse the window as a number of seconds, minutes, hours or days, e.g. `30m` or `24h`.
+    pub fn get_window(&self) -> Result<Duration, BadRequest> {
+        let invalid = || BadRequest(format!("Invalid stats window {:?}", self.window));
+        let window = self.window.trim();
+        let unit_secs = match window.chars().last().ok_or_else(invalid)? {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+
+        match window[..window.len() - 1].parse::<u64>() {
+            Ok(value) if value > 0 => value
+                .checked_mul(unit_secs)
+                .map(Duration::from_secs)
+                .ok_or_else(invalid),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+fn default_stats_window() -> String {
+    "24h".to_string()
+}
+
+fn default_band_network() -> String {
+    "CAT-M".to_string()
+}
+
 #[cfg(test)]
 mod websocket_query_tests {
     use super::*;
@@ -161,7 +335,8 @@ mod websocket_query_tests {
         // All valid event types
         let query = WebSocketQuery {
             events: Some(
-                "incoming,outgoing,delivery,modem_status_update,gnss_position_report".to_string(),
+                "incoming,outgoing,delivery,modem_status_update,gnss_position_report,raw_unsolicited,incoming_call,send_failure,opt_out,opt_in"
+                    .to_string(),
             ),
         };
         assert_eq!(query.get_event_types(), None);
@@ -206,3 +381,27 @@ mod websocket_query_tests {
         assert!(result.contains(&EventType::OutgoingMessage));
     }
 }
+
+#[cfg(test)]
+mod stats_query_tests {
+    use super::*;
+
+    fn window(window: &str) -> Option<u64> {
+        let query = StatsQuery {
+            window: window.to_string(),
+        };
+        query.get_window().ok().map(|window| window.as_secs())
+    }
+
+    #[test]
+    fn test_window_parsing() {
+        assert_eq!(window("90s"), Some(90));
+        assert_eq!(window("30m"), Some(1_800));
+        assert_eq!(window(&default_stats_window()), Some(86_400));
+        assert_eq!(window("7d"), Some(604_800));
+
+        for invalid in ["", "h", "0h", "24", "-1h", "1.5h", "24x", "1w"] {
+            assert_eq!(window(invalid), None, "{invalid:?}");
+        }
+    }
+}
@@ -1,23 +1,124 @@
+use crate::config::{QueueOverflow, WebhookQueueConfig};
 use crate::events::{Event, EventType};
+use axum::extract::ws::Utf8Bytes;
 use futures::{SinkExt, StreamExt};
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::mpsc::UnboundedSender;
-use tokio::sync::{mpsc, RwLock};
-use tracing::log::{debug, error};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, Notify, RwLock};
+use tracing::log::{debug, error, warn};
 use uuid::Uuid;
 
 pub type WebSocketConnection = (axum::extract::ws::WebSocket, Option<Vec<EventType>>);
-type StoredConnection = (UnboundedSender<axum::extract::ws::Utf8Bytes>, u8); // sender + event mask
+type StoredConnection = (Arc<ClientQueue>, u16); // queue + event mask
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WebSocketStats {
+    pub connections: usize,
+
+    /// Connections accepted since start, including closed ones.
+    pub total_connections: u64,
+
+    /// Messages dropped since start because a client's queue was full.
+    pub dropped: u64,
+}
+
+/// Messages waiting to be written to a client. Unbounded unless the webhook queue has a capacity,
+/// in which case each client's queue is bounded by it and its overflow policy, so a client that
+/// stops reading can't grow it without limit.
+struct ClientQueue {
+    messages: Mutex<VecDeque<Utf8Bytes>>,
+    notify: Notify,
+    closed: AtomicBool,
+}
+impl ClientQueue {
+    fn new() -> Self {
+        Self {
+            messages: Mutex::new(VecDeque::new()),
+            notify: Notify::new(),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    /// Stop waiting for messages, dropping any still queued.
+    fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+        self.notify.notify_one();
+    }
+
+    /// Queue a message, returning false if it or an older one was dropped to make room.
+    fn push(&self, message: Utf8Bytes, capacity: Option<usize>, overflow: QueueOverflow) -> bool {
+        let Ok(mut messages) = self.messages.lock() else {
+            error!("Failed to queue WebSocket message, the queue lock is poisoned!");
+            return false;
+        };
+
+        let is_full = capacity.is_some_and(|capacity| messages.len() >= capacity);
+        if is_full {
+            match overflow {
+                QueueOverflow::DropOldest => {
+                    messages.pop_front();
+                }
+                QueueOverflow::DropNewest => return false,
+            }
+        }
+
+        messages.push_back(message);
+        drop(messages);
+        self.notify.notify_one();
+        !is_full
+    }
+
+    /// Wait for the next message, returning None once the queue is closed.
+    async fn pop(&self) -> Option<Utf8Bytes> {
+        loop {
+            if self.closed.load(Ordering::Relaxed) {
+                return None;
+            }
+            if let Some(message) = self
+                .messages
+                .lock()
+                .ok()
+                .and_then(|mut messages| messages.pop_front())
+            {
+                return Some(message);
+            }
+            self.notify.notified().await;
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct WebSocketManager {
     connections: Arc<RwLock<HashMap<String, StoredConnection>>>,
+    total_connections: Arc<AtomicU64>,
+    capacity: Option<usize>,
+    overflow: QueueOverflow,
+    dropped: Arc<AtomicU64>,
 }
 impl WebSocketManager {
-    pub fn new() -> Self {
+    pub fn new(queue_config: &WebhookQueueConfig) -> Self {
         Self {
             connections: Arc::new(RwLock::new(HashMap::new())),
+            total_connections: Arc::new(AtomicU64::new(0)),
+            capacity: queue_config.capacity,
+            overflow: queue_config.overflow,
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// The number of currently connected clients. Connections are removed as soon
+    /// as either side closes, so this doesn't include zombie connections.
+    pub async fn connection_count(&self) -> usize {
+        self.connections.read().await.len()
+    }
+
+    pub async fn stats(&self) -> WebSocketStats {
+        WebSocketStats {
+            connections: self.connection_count().await,
+            total_connections: self.total_connections.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
         }
     }
 
@@ -33,33 +134,31 @@ impl WebSocketManager {
         let event_bit = event.to_event_type().to_bit();
         let connections = self.connections.read().await;
         let mut successful_sends = 0;
-        let mut failed_connections = Vec::new();
 
         // Send events to all with matching events.
-        for (id, (sender, event_mask)) in connections.iter() {
+        for (queue, event_mask) in connections.values() {
             if event_mask & event_bit != 0 {
-                if sender.send(message.clone()).is_ok() {
+                if queue.push(message.clone(), self.capacity, self.overflow) {
                     successful_sends += 1;
                 } else {
-                    failed_connections.push(id.clone());
+                    self.record_drop();
                 }
             }
         }
-        drop(connections);
+        successful_sends
+    }
 
-        // Cleanup failed connections (read lock dropped before acquiring write).
-        if !failed_connections.is_empty() {
-            let mut connections = self.connections.write().await;
-            for id in failed_connections {
-                connections.remove(&id);
-            }
+    fn record_drop(&self) {
+        // Logged at increasing intervals, so a client that stops reading doesn't flood the logs too.
+        let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+        if dropped.is_power_of_two() {
+            warn!("WebSocket client queue is full, {dropped} message(s) have been dropped");
         }
-        successful_sends
     }
 
-    pub async fn add_connection(
+    async fn add_connection(
         &self,
-        tx: UnboundedSender<axum::extract::ws::Utf8Bytes>,
+        queue: Arc<ClientQueue>,
         events: Option<Vec<EventType>>,
     ) -> String {
         let event_mask = match events {
@@ -72,25 +171,31 @@ impl WebSocketManager {
             let mut connections = self.connections.write().await;
 
             if !connections.contains_key(&id) {
-                connections.insert(id.clone(), (tx, event_mask));
+                connections.insert(id.clone(), (queue, event_mask));
+                self.total_connections.fetch_add(1, Ordering::Relaxed);
                 return id;
             }
             drop(connections);
         }
     }
 
+    /// Remove a connection, closing its queue so the writer stops.
     pub async fn remove_connection(&self, id: &str) {
-        self.connections.write().await.remove(id);
+        if let Some((queue, _)) = self.connections.write().await.remove(id) {
+            queue.close();
+        }
     }
 }
 
 // Called after the connection is upgraded.
 pub async fn handle_websocket(connection: WebSocketConnection, manager: WebSocketManager) {
     let (mut sender, mut receiver) = connection.0.split();
-    let (tx, mut rx) = mpsc::unbounded_channel::<axum::extract::ws::Utf8Bytes>();
+    let queue = Arc::new(ClientQueue::new());
 
     // Add connection.
-    let connection_id = manager.add_connection(tx, connection.1).await;
+    let connection_id = manager
+        .add_connection(Arc::clone(&queue), connection.1)
+        .await;
     debug!("WebSocket connection established: {connection_id}");
 
     // Writer task.
@@ -100,14 +205,14 @@ pub async fn handle_websocket(connection: WebSocketConnection, manager: WebSocke
         loop {
             tokio::select! {
                 // Outgoing messages.
-                msg = rx.recv() => {
+                msg = queue.pop() => {
                     match msg {
                         Some(msg) => {
                             if sender.send(axum::extract::ws::Message::Text(msg)).await.is_err() {
                                 break;
                             }
                         }
-                        None => break // Channel closed
+                        None => break // Queue closed
                     }
                 },
                 // Handle ping responses (pong messages).
@@ -132,6 +237,7 @@ pub async fn handle_websocket(connection: WebSocketConnection, manager: WebSocke
                 Ok(axum::extract::ws::Message::Text(text)) => {
                     debug!("Received WebSocket message from {connection_id}: {text:?}")
                 }
+                #[allow(clippy::collapsible_match)]
                 Ok(axum::extract::ws::Message::Ping(ping)) => {
                     if ping_tx.send(ping).is_err() {
                         break;
@@ -159,3 +265,65 @@ pub async fn handle_websocket(connection: WebSocketConnection, manager: WebSocke
     manager.remove_connection(&connection_id_for_tx).await;
     debug!("WebSocket connection cleaned up: {connection_id_for_tx}");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Push each message, returning how many were dropped.
+    fn push_messages(
+        queue: &ClientQueue,
+        capacity: Option<usize>,
+        overflow: QueueOverflow,
+        messages: &[&str],
+    ) -> usize {
+        messages
+            .iter()
+            .filter(|message| !queue.push(Utf8Bytes::from(message.to_string()), capacity, overflow))
+            .count()
+    }
+
+    fn queued(queue: &ClientQueue) -> Vec<String> {
+        let messages = queue.messages.lock().unwrap();
+        messages.iter().map(|message| message.to_string()).collect()
+    }
+
+    #[test]
+    fn test_client_queue_overflow() {
+        let unbounded = ClientQueue::new();
+        let messages = vec!["message"; 1000];
+        assert_eq!(
+            push_messages(&unbounded, None, QueueOverflow::DropOldest, &messages),
+            0
+        );
+        assert_eq!(queued(&unbounded).len(), 1000);
+
+        let drop_oldest = ClientQueue::new();
+        let dropped = push_messages(
+            &drop_oldest,
+            Some(2),
+            QueueOverflow::DropOldest,
+            &["a", "b", "c", "d"],
+        );
+        assert_eq!(dropped, 2);
+        assert_eq!(queued(&drop_oldest), ["c", "d"]);
+
+        let drop_newest = ClientQueue::new();
+        let dropped = push_messages(
+            &drop_newest,
+            Some(2),
+            QueueOverflow::DropNewest,
+            &["a", "b", "c", "d"],
+        );
+        assert_eq!(dropped, 2);
+        assert_eq!(queued(&drop_newest), ["a", "b"]);
+
+        // A closed queue stops the writer, rather than giving its remaining messages.
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        assert_eq!(runtime.block_on(drop_newest.pop()).as_deref(), Some("a"));
+        drop_newest.close();
+        assert!(runtime.block_on(drop_newest.pop()).is_none());
+    }
+}
@@ -3,6 +3,7 @@ mod config;
 mod events;
 mod modem;
 mod sms;
+mod templates;
 mod types;
 mod webhooks;
 
@@ -14,6 +15,7 @@ use anyhow::Result;
 use clap::Parser;
 use dotenv::dotenv;
 use std::path::PathBuf;
+use std::str::FromStr;
 use tracing::log::info;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
@@ -93,7 +95,13 @@ fn main() -> Result<()> {
 
     let tracing_reload = init_tracing();
     let args = CliArguments::parse();
-    let config = config::AppConfig::load(args.config)?;
+    let config = config::AppConfig::load(args.config.clone())?;
+    config.validate()?;
+
+    if let Some(log_level) = &config.log_level {
+        info!("Setting log level to {log_level} from config");
+        tracing_reload.reload(EnvFilter::from_str(log_level)?)?;
+    }
 
     #[cfg(feature = "sentry")]
     let _sentry_guard = config.sentry.as_ref().map(init_sentry).transpose()?;
@@ -105,7 +113,8 @@ fn main() -> Result<()> {
         .enable_all()
         .build()?
         .block_on(async move {
-            let handles = AppHandles::new(config, tracing_reload, _sentry_guard).await?;
+            let handles =
+                AppHandles::new(config, args.config, tracing_reload, _sentry_guard).await?;
             handles.run().await;
 
             #[cfg(feature = "sentry")]
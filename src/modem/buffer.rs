@@ -1,3 +1,5 @@
+use crate::modem::types::UnsolicitedMessageType;
+
 #[derive(Debug)]
 pub enum LineEvent {
     Line(String),
@@ -7,17 +9,27 @@ pub enum LineEvent {
 pub struct LineBuffer {
     buffer: Vec<u8>,
     max_buffer_size: usize,
+
+    /// Set after a `+CMT:` or `+CDS:` header, as the next line is its hex PDU.
+    expecting_pdu: bool,
+
+    /// Whether the retained data starts straight after a line ending.
+    at_line_start: bool,
 }
 impl LineBuffer {
     pub fn with_max_size(size: usize) -> Self {
         Self {
             buffer: Vec::with_capacity(512),
             max_buffer_size: size,
+            expecting_pdu: false,
+            at_line_start: false,
         }
     }
 
     pub fn clear(&mut self) {
         self.buffer.clear();
+        self.expecting_pdu = false;
+        self.at_line_start = false;
     }
 
     pub fn process_data(&mut self, data: &[u8]) -> Vec<LineEvent> {
@@ -35,12 +47,45 @@ impl LineBuffer {
         let mut i = 0;
 
         while i < self.buffer.len() {
+            if self.expecting_pdu && i == start {
+                // The header's line ending may arrive in a later read.
+                if matches!(self.buffer[i], b'\r' | b'\n') {
+                    i += 1;
+                    start = i;
+                    continue;
+                }
+
+                match self.take_pdu(start) {
+                    // Wait for the rest of the PDU, rather than splitting it across two lines.
+                    None => break,
+                    Some(end) if end > start => {
+                        let pdu = String::from_utf8_lossy(&self.buffer[start..end]).into_owned();
+                        events.push(LineEvent::Line(pdu));
+                        start = end;
+                        i = end;
+                        self.at_line_start = false;
+                    }
+                    Some(_) => {}
+                }
+                self.expecting_pdu = false;
+                continue;
+            }
+
             match self.buffer[i] {
                 b'\r' | b'\n' => {
                     if i > start {
                         if let Some(line_event) =
                             self.try_create_event(&self.buffer[start..i], LineEvent::Line)
                         {
+                            if let LineEvent::Line(line) = &line_event {
+                                self.expecting_pdu = matches!(
+                                    UnsolicitedMessageType::from_header(line),
+                                    Some(
+                                        UnsolicitedMessageType::IncomingSMS
+                                            | UnsolicitedMessageType::DeliveryReport
+                                    )
+                                );
+                            }
                             events.push(line_event);
                         }
                     }
@@ -52,25 +97,20 @@ impl LineBuffer {
                         i += 1;
                     }
                     start = i;
+                    self.at_line_start = true;
                 }
-                b'>' => {
-                    // Only treat as prompt if it's at start of line or after whitespace.
-                    let is_prompt = start == i
-                        || (i > 0 && (self.buffer[i - 1] == b'\n' || self.buffer[i - 1] == b'\r'));
-
-                    if is_prompt {
-                        if let Some(prompt_event) =
-                            self.try_create_event(&self.buffer[start..=i], LineEvent::Prompt)
-                        {
-                            events.push(prompt_event);
-                        }
-                        i += 1;
+                b'>' if i == start && self.at_line_start => match self.is_prompt(i) {
+                    Some(true) => {
+                        events.push(LineEvent::Prompt(">".to_string()));
+                        i += 2;
                         start = i;
-                        continue;
-                    } else {
-                        i += 1;
+                        self.at_line_start = false;
                     }
-                }
+                    Some(false) => i += 1,
+
+                    // Wait for the bytes that decide whether this is a prompt.
+                    None => break,
+                },
                 _ => i += 1,
             }
         }
@@ -83,6 +123,31 @@ impl LineBuffer {
         events
     }
 
+    /// Check if the `>` at `index` is the `\r\n> ` prompt, which the modem sends on its own while
+    /// it waits for input. It's only followed by a line ending if input was refused, so a
+    /// message line that happens to start with `> ` isn't mistaken for it.
+    /// Returns None if the data so far could still be either.
+    fn is_prompt(&self, index: usize) -> Option<bool> {
+        match self.buffer.get(index + 1) {
+            None => None,
+            Some(b' ') => Some(matches!(
+                self.buffer.get(index + 2),
+                None | Some(b'\r' | b'\n')
+            )),
+            Some(_) => Some(false),
+        }
+    }
+
+    /// Find the end of a PDU starting at `start`, which is the first byte that isn't a hex
+    /// digit. This is usually the CRLF, but also splits off a following line merged onto it.
+    /// Returns None if the PDU may continue in data that hasn't been read yet.
+    fn take_pdu(&self, start: usize) -> Option<usize> {
+        self.buffer[start..]
+            .iter()
+            .position(|byte| !byte.is_ascii_hexdigit())
+            .map(|length| start + length)
+    }
+
     fn try_create_event<F>(&self, data: &[u8], constructor: F) -> Option<LineEvent>
     where
         F: FnOnce(String) -> LineEvent,
@@ -134,15 +199,21 @@ mod tests {
     fn test_prompt_detection() {
         let mut buffer = LineBuffer::with_max_size(1024);
 
-        let events = buffer.process_data(b">");
+        let events = buffer.process_data(b"\r\n> ");
         assert_eq!(events.len(), 1);
         assert!(matches!(&events[0], LineEvent::Prompt(s) if s == ">"));
 
-        let events = buffer.process_data(b"output\n>");
+        let events = buffer.process_data(b"output\n> ");
         assert_eq!(events.len(), 2);
         assert!(matches!(&events[0], LineEvent::Line(s) if s == "output"));
         assert!(matches!(&events[1], LineEvent::Prompt(s) if s == ">"));
 
+        // A refused prompt is followed by the error.
+        let events = buffer.process_data(b"\r\n> \r\nERROR\r\n");
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[0], LineEvent::Prompt(s) if s == ">"));
+        assert!(matches!(&events[1], LineEvent::Line(s) if s == "ERROR"));
+
         let events = buffer.process_data(b"test>data\n");
         assert_eq!(events.len(), 1);
         assert!(matches!(&events[0], LineEvent::Line(s) if s == "test>data"));
@@ -152,7 +223,7 @@ mod tests {
     fn test_mixed_events_sequence() {
         let mut buffer = LineBuffer::with_max_size(1024);
 
-        let events = buffer.process_data(b"command output\n>user input\n>");
+        let events = buffer.process_data(b"command output\r\n> \r\nuser input\r\n> ");
         assert_eq!(events.len(), 4);
         assert!(matches!(&events[0], LineEvent::Line(s) if s == "command output"));
         assert!(matches!(&events[1], LineEvent::Prompt(s) if s == ">"));
@@ -160,6 +231,28 @@ mod tests {
         assert!(matches!(&events[3], LineEvent::Prompt(s) if s == ">"));
     }
 
+    #[test]
+    fn test_content_line_starting_with_prompt() {
+        let mut buffer = LineBuffer::with_max_size(1024);
+
+        let events = buffer.process_data(b"\r\n>quoted\r\n> quoted reply\r\n>\r\n");
+        assert_eq!(events.len(), 3);
+        assert!(matches!(&events[0], LineEvent::Line(s) if s == ">quoted"));
+        assert!(matches!(&events[1], LineEvent::Line(s) if s == "> quoted reply"));
+        assert!(matches!(&events[2], LineEvent::Line(s) if s == ">"));
+
+        // Undecided until the byte after the `>` arrives.
+        assert_eq!(buffer.process_data(b">").len(), 0);
+        let events = buffer.process_data(b"> not a prompt\r\n");
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], LineEvent::Line(s) if s == ">> not a prompt"));
+
+        assert_eq!(buffer.process_data(b">").len(), 0);
+        let events = buffer.process_data(b" ");
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], LineEvent::Prompt(s) if s == ">"));
+    }
+
     #[test]
     fn test_incremental_processing() {
         let mut buffer = LineBuffer::with_max_size(1024);
@@ -173,7 +266,7 @@ mod tests {
 
         assert_eq!(buffer.process_data(b"line").len(), 0);
         assert_eq!(buffer.process_data(b" two").len(), 0);
-        let events = buffer.process_data(b"\n>");
+        let events = buffer.process_data(b"\r\n> ");
         assert_eq!(events.len(), 2);
         assert!(matches!(&events[0], LineEvent::Line(s) if s == "line two"));
         assert!(matches!(&events[1], LineEvent::Prompt(s) if s == ">"));
@@ -194,7 +287,7 @@ mod tests {
         assert!(matches!(&events[2], LineEvent::Line(s) if s == "mac"));
         assert!(matches!(&events[3], LineEvent::Line(s) if s == "end"));
 
-        let events = buffer.process_data(b"output\r>");
+        let events = buffer.process_data(b"output\r> ");
         assert_eq!(events.len(), 2);
         assert!(matches!(&events[0], LineEvent::Line(s) if s == "output"));
         assert!(matches!(&events[1], LineEvent::Prompt(s) if s == ">"));
@@ -241,6 +334,39 @@ mod tests {
         assert!(matches!(&events[0], LineEvent::Line(_)));
     }
 
+    #[test]
+    fn test_fragmented_pdu() {
+        let mut buffer = LineBuffer::with_max_size(1024);
+
+        let events = buffer.process_data(b"\r\n+CMT: ,24\r");
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], LineEvent::Line(s) if s == "+CMT: ,24"));
+
+        assert_eq!(buffer.process_data(b"\n0791447728").len(), 0);
+        assert_eq!(buffer.process_data(b"008000040C91").len(), 0);
+
+        let events = buffer.process_data(b"4477\r\nOK\r\n");
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[0], LineEvent::Line(s) if s == "0791447728008000040C914477"));
+        assert!(matches!(&events[1], LineEvent::Line(s) if s == "OK"));
+    }
+
+    #[test]
+    fn test_pdu_merged_with_next_line() {
+        let mut buffer = LineBuffer::with_max_size(1024);
+
+        let events = buffer.process_data(b"+CDS: 25\r\n07914477AB+CMTI: \"SM\",3\r\n");
+        assert_eq!(events.len(), 3);
+        assert!(matches!(&events[0], LineEvent::Line(s) if s == "+CDS: 25"));
+        assert!(matches!(&events[1], LineEvent::Line(s) if s == "07914477AB"));
+        assert!(matches!(&events[2], LineEvent::Line(s) if s == "+CMTI: \"SM\",3"));
+
+        // Only the line after a PDU header is framed as a PDU.
+        let events = buffer.process_data(b"+CMTI: \"SM\",4\r\n0A+CGREG: 1\r\n");
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[1], LineEvent::Line(s) if s == "0A+CGREG: 1"));
+    }
+
     #[test]
     fn test_clear_buffer() {
         let mut buffer = LineBuffer::with_max_size(1024);
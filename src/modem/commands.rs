@@ -19,6 +19,7 @@ pub struct CommandContext {
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum CommandState {
     WaitingForOk,
     WaitingForPrompt,
@@ -103,3 +104,25 @@ impl OutgoingCommand {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_timeout_override() {
+        let request = ModemRequest::SendSMS {
+            len: 0,
+            pdu: String::new(),
+        };
+        let default_timeout = request.get_default_timeout();
+
+        let (tx, _rx) = oneshot::channel();
+        let command = OutgoingCommand::new(1, tx, request.clone(), None);
+        assert_eq!(command.get_request_timeout(), default_timeout);
+
+        let (tx, _rx) = oneshot::channel();
+        let command = OutgoingCommand::new(2, tx, request, Some(120));
+        assert_eq!(command.get_request_timeout(), Duration::from_secs(120));
+    }
+}
@@ -4,8 +4,8 @@ use crate::modem::types::{
     ModemIncomingMessage, ModemRequest, ModemResponse, ModemStatus, UnsolicitedMessageType,
 };
 use crate::modem::worker::WorkerEvent;
-use crate::sms::types::{SMSIncomingDeliveryReport, SMSIncomingMessage};
-use anyhow::{anyhow, bail, Result};
+use crate::sms::types::{smsc_timestamp_to_unix, SMSIncomingDeliveryReport, SMSIncomingMessage};
+use anyhow::{anyhow, Result};
 use sms_pdu::pdu::{DeliverPdu, StatusReportPdu};
 use tokio::sync::mpsc;
 use tracing::log::{debug, warn};
@@ -19,6 +19,15 @@ fn get_real_number(phone_number: String) -> String {
     }
 }
 
+/// Prefix a read or delete with `+CPMS` so the index refers to the storage it was reported in,
+/// as an index from +CMTI or +CDSI is only meaningful in that storage.
+fn select_storage(storage: &Option<String>) -> String {
+    match storage {
+        Some(storage) => format!("+CPMS=\"{storage}\";"),
+        None => String::new(),
+    }
+}
+
 pub struct ModemEventHandlers {
     worker_event_tx: mpsc::UnboundedSender<WorkerEvent>,
 }
@@ -34,11 +43,50 @@ impl ModemEventHandlers {
                 self.write(command.as_bytes()).await?;
                 return Ok(CommandState::WaitingForPrompt);
             }
-            ModemRequest::GetNetworkStatus => self.write(b"AT+CREG?\r\n").await?,
+            ModemRequest::GetNetworkStatus => self.write(b"AT+CREG?;+COPS?\r\n").await?,
             ModemRequest::GetSignalStrength => self.write(b"AT+CSQ\r\n").await?,
             ModemRequest::GetNetworkOperator => self.write(b"AT+COPS?\r\n").await?,
             ModemRequest::GetServiceProvider => self.write(b"AT+CSPN?\r\n").await?,
             ModemRequest::GetBatteryLevel => self.write(b"AT+CBC\r\n").await?,
+            ModemRequest::GetServiceCentre => self.write(b"AT+CSCA?\r\n").await?,
+            ModemRequest::GetFunctionality => self.write(b"AT+CFUN?\r\n").await?,
+            ModemRequest::GetSmsParameters => self.write(b"AT+CSMP?\r\n").await?,
+            ModemRequest::SetFunctionality { level } => {
+                let command = format!("AT+CFUN={level}\r\n");
+                self.write(command.as_bytes()).await?;
+            }
+            ModemRequest::GetPreferredMode => self.write(b"AT+CNMP?\r\n").await?,
+            ModemRequest::SetPreferredMode { mode } => {
+                let command = format!("AT+CNMP={mode}\r\n");
+                self.write(command.as_bytes()).await?;
+            }
+            ModemRequest::SetBand { network, bands } => {
+                let bands = bands
+                    .iter()
+                    .map(|band| band.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let command = format!("AT+CBANDCFG=\"{network}\",{bands}\r\n");
+                self.write(command.as_bytes()).await?;
+            }
+            ModemRequest::SetOperator { mode, operator } => {
+                let command = match operator {
+                    Some(operator) => format!("AT+COPS={mode},2,\"{operator}\"\r\n"),
+                    None => format!("AT+COPS={mode}\r\n"),
+                };
+                self.write(command.as_bytes()).await?;
+            }
+            ModemRequest::ReadSMS { storage, index } => {
+                let command = format!("AT{}+CMGR={index}\r\n", select_storage(storage));
+                self.write(command.as_bytes()).await?;
+            }
+            ModemRequest::DeleteSMS { storage, index } => {
+                let command = format!("AT{}+CMGD={index}\r\n", select_storage(storage));
+                self.write(command.as_bytes()).await?;
+            }
+            ModemRequest::GetClock => self.write(b"AT+CCLK?\r\n").await?,
+            ModemRequest::HangUp => self.write(b"ATH\r\n").await?,
+            ModemRequest::Heartbeat => self.write(b"AT\r\n").await?,
             ModemRequest::GetGNSSStatus => self.write(b"AT+CGPSSTATUS?\r\n").await?,
             ModemRequest::GetGNSSLocation => self.write(b"AT+CGNSINF\r\n").await?,
         }
@@ -76,17 +124,33 @@ impl ModemEventHandlers {
                     DeliverPdu::try_from(content_hex.as_slice()).map_err(|e| anyhow!(e))?;
 
                 // Decode incoming message data to get user data header which is required for multipart messages.
-                let incoming = match deliver_pdu.get_message_data().decode_message() {
-                    Ok(msg) => SMSIncomingMessage {
-                        phone_number: get_real_number(deliver_pdu.originating_address.to_string()),
-                        user_data_header: msg.udh,
-                        content: msg.text,
-                    },
-                    Err(e) => bail!("Failed to parse incoming SMS data: {:?}", e),
-                };
+                let mut incoming = SMSIncomingMessage::decode(
+                    get_real_number(deliver_pdu.originating_address.to_string()),
+                    &deliver_pdu.get_message_data(),
+                )?;
+
+                // Kept with the decoded text, it's only stored if `store_raw_pdu` is enabled.
+                incoming.raw_pdu = Some(content.to_string());
 
                 Ok(Some(ModemIncomingMessage::IncomingSMS(incoming)))
             }
+            UnsolicitedMessageType::StoredSMS => {
+                // The worker reads the PDU, which is then decoded the same as a directly routed one.
+                let message_type = if content.starts_with("+CDSI:") {
+                    UnsolicitedMessageType::DeliveryReport
+                } else {
+                    UnsolicitedMessageType::IncomingSMS
+                };
+                let (storage, index) = parse_cmti_index(content)?;
+                self.worker_event_tx
+                    .send(WorkerEvent::ReadStoredSMS {
+                        storage,
+                        index,
+                        message_type,
+                    })
+                    .map_err(|_| anyhow!("Failed to send read stored message event"))?;
+                Ok(None)
+            }
             UnsolicitedMessageType::DeliveryReport => {
                 let content_hex = hex::decode(content).map_err(|e| anyhow!(e))?;
                 let status_report_pdu =
@@ -96,11 +160,16 @@ impl ModemEventHandlers {
                     status: status_report_pdu.status,
                     phone_number: get_real_number(status_report_pdu.recipient_address.to_string()),
                     reference_id: status_report_pdu.message_reference,
+                    submitted_at: smsc_timestamp_to_unix(&status_report_pdu.scts),
                 };
                 Ok(Some(ModemIncomingMessage::DeliveryReport(report)))
             }
             UnsolicitedMessageType::NetworkStatusChange => {
-                Ok(Some(ModemIncomingMessage::NetworkStatusChange(0)))
+                let (domain, registration) = parse_creg_unsolicited(content)?;
+                Ok(Some(ModemIncomingMessage::NetworkStatusChange {
+                    domain,
+                    registration,
+                }))
             }
             UnsolicitedMessageType::ShuttingDown => {
                 warn!("The modem is shutting down!");
@@ -110,6 +179,19 @@ impl ModemEventHandlers {
             UnsolicitedMessageType::GNSSPositionReport => Ok(Some(
                 ModemIncomingMessage::GNSSPositionReport(parse_cgnsinf_response(content, true)?),
             )),
+            UnsolicitedMessageType::IncomingCall => {
+                // The caller is only known from the +CLIP line that follows when enabled.
+                debug!("Incoming call is ringing");
+                Ok(Some(ModemIncomingMessage::Ring))
+            }
+            UnsolicitedMessageType::CallerIdentification => {
+                let phone_number = parse_clip_response(content)?.map(get_real_number);
+                Ok(Some(ModemIncomingMessage::IncomingCall(phone_number)))
+            }
+            UnsolicitedMessageType::CallEnded => {
+                debug!("Call ended");
+                Ok(Some(ModemIncomingMessage::CallEnded))
+            }
         }
     }
 
@@ -124,14 +206,20 @@ impl ModemEventHandlers {
         }
 
         match request {
-            ModemRequest::SendSMS { .. } => {
-                Ok(ModemResponse::SendResult(parse_cmgs_result(response)?))
-            }
+            ModemRequest::SendSMS { .. } => Ok(ModemResponse::SendResult {
+                reference_id: parse_cmgs_result(response)?,
+            }),
             ModemRequest::GetNetworkStatus => {
-                let (registration, technology) = parse_creg_response(response)?;
+                let registration = parse_creg_response(response)?;
+                let access_technology = parse_cops_access_technology(response);
                 Ok(ModemResponse::NetworkStatus {
                     registration,
-                    technology,
+                    technology: registration,
+                    access_technology,
+                    registration_label: registration_label(registration).to_string(),
+                    technology_label: access_technology
+                        .map_or("unknown", technology_label)
+                        .to_string(),
                 })
             }
             ModemRequest::GetSignalStrength => {
@@ -157,6 +245,52 @@ impl ModemEventHandlers {
                     voltage,
                 })
             }
+            ModemRequest::GetServiceCentre => {
+                Ok(ModemResponse::ServiceCentre(parse_csca_response(response)?))
+            }
+            ModemRequest::GetFunctionality => {
+                Ok(ModemResponse::Functionality(parse_cfun_response(response)?))
+            }
+            ModemRequest::SetFunctionality { level } => {
+                // Only full functionality has a usable radio.
+                let status = if *level == 1 {
+                    ModemStatus::Online
+                } else {
+                    ModemStatus::RadioOff
+                };
+                self.set_status(status).await?;
+                Ok(ModemResponse::Success)
+            }
+            ModemRequest::GetSmsParameters => {
+                let (first_octet, validity_period, protocol_id, data_coding_scheme) =
+                    parse_csmp_response(response)?;
+                Ok(ModemResponse::SmsParameters {
+                    first_octet,
+                    validity_period,
+                    protocol_id,
+                    data_coding_scheme,
+                })
+            }
+            ModemRequest::GetPreferredMode => {
+                Ok(ModemResponse::PreferredMode(parse_cnmp_response(response)?))
+            }
+            ModemRequest::SetPreferredMode { .. } => Ok(ModemResponse::Success),
+            ModemRequest::SetBand { .. } => Ok(ModemResponse::Success),
+            ModemRequest::SetOperator { .. } => Ok(ModemResponse::Success),
+            ModemRequest::ReadSMS { .. } => {
+                Ok(ModemResponse::StoredSMS(parse_cmgr_response(response)?))
+            }
+            ModemRequest::DeleteSMS { .. } => Ok(ModemResponse::Success),
+            ModemRequest::GetClock => {
+                let (time, timestamp, utc_offset_minutes) = parse_cclk_response(response)?;
+                Ok(ModemResponse::Clock {
+                    time,
+                    timestamp,
+                    utc_offset_minutes,
+                })
+            }
+            ModemRequest::HangUp => Ok(ModemResponse::Success),
+            ModemRequest::Heartbeat => Ok(ModemResponse::Success),
             ModemRequest::GetGNSSStatus => Ok(ModemResponse::GNSSStatus(
                 parse_cgpsstatus_response(response)?,
             )),
@@ -166,6 +300,11 @@ impl ModemEventHandlers {
         }
     }
 
+    /// Send ESC to abort any SMS input the modem may be waiting for.
+    pub async fn cancel_sms_entry(&self) -> Result<()> {
+        self.write(b"\x1b").await
+    }
+
     async fn write(&self, data: &[u8]) -> Result<()> {
         self.worker_event_tx
             .send(WorkerEvent::WriteCommand(data.to_vec()))
@@ -1,17 +1,21 @@
-use crate::config::{AppConfig, ModemConfig};
+use crate::config::ModemConfig;
 use crate::modem::commands::OutgoingCommand;
+use crate::modem::port::{closed_port, SerialPortOpener};
 use crate::modem::sender::ModemSender;
-use crate::modem::types::ModemIncomingMessage;
+use crate::modem::state_machine::SharedStateMachineSnapshot;
+use crate::modem::types::{ModemControl, ModemIncomingMessage};
 use crate::modem::worker::ModemWorker;
 use anyhow::{anyhow, Result};
 use tokio::sync::mpsc;
-use tokio_serial::SerialPortBuilderExt;
-use tracing::log::error;
+use tracing::log::{error, warn};
+use tracing::Instrument;
 
 mod buffer;
 mod commands;
 mod handlers;
-mod parsers;
+pub mod parsers;
+pub mod pool;
+mod port;
 pub mod sender;
 mod state_machine;
 pub mod types;
@@ -21,14 +25,20 @@ pub struct ModemManager {
     config: ModemConfig,
     main_tx: mpsc::UnboundedSender<ModemIncomingMessage>,
     command_tx: Option<mpsc::Sender<OutgoingCommand>>,
+    priority_command_tx: Option<mpsc::Sender<OutgoingCommand>>,
+    control_tx: Option<mpsc::Sender<ModemControl>>,
+    snapshot: SharedStateMachineSnapshot,
 }
 impl ModemManager {
-    pub fn new(config: &AppConfig) -> (Self, mpsc::UnboundedReceiver<ModemIncomingMessage>) {
+    pub fn new(config: &ModemConfig) -> (Self, mpsc::UnboundedReceiver<ModemIncomingMessage>) {
         let (main_tx, main_rx) = mpsc::unbounded_channel();
         let manager = Self {
-            config: config.modem.clone(),
+            config: config.clone(),
             main_tx,
             command_tx: None,
+            priority_command_tx: None,
+            control_tx: None,
+            snapshot: SharedStateMachineSnapshot::default(),
         };
 
         (manager, main_rx)
@@ -36,27 +46,56 @@ impl ModemManager {
 
     pub async fn start(&mut self) -> Result<tokio::task::JoinHandle<()>> {
         let (command_tx, command_rx) = mpsc::channel(self.config.cmd_channel_buffer_size);
-        self.command_tx = Some(command_tx);
+        self.command_tx = Some(command_tx.clone());
 
-        let port = tokio_serial::new(&self.config.device, self.config.baud_rate)
-            .open_native_async()
-            .map_err(|e| anyhow!("Failed to open serial port {}: {}", self.config.device, e))?;
+        // Priority commands are taken before normal ones, but still one at a time.
+        let (priority_command_tx, priority_command_rx) =
+            mpsc::channel(self.config.cmd_channel_buffer_size);
+        self.priority_command_tx = Some(priority_command_tx);
 
-        let worker = ModemWorker::new(port, self.main_tx.clone(), self.config.clone())?;
-        let handle = tokio::spawn(async move {
-            if let Err(e) = worker.initialize_and_run(command_rx).await {
-                error!("ModemWorker error: {e}");
-            }
+        let (control_tx, control_rx) = mpsc::channel(1);
+        self.control_tx = Some(control_tx);
+
+        // A modem that can't be opened yet starts offline, and is reconnected like any other.
+        let mut port_opener = SerialPortOpener::new(&self.config);
+        let port = port_opener.open_first().unwrap_or_else(|e| {
+            warn!(
+                "Modem '{}' is unavailable, starting offline: {e}",
+                self.config.id
+            );
+            closed_port()
         });
 
+        let worker = ModemWorker::new(
+            port,
+            self.main_tx.clone(),
+            command_tx,
+            self.snapshot.clone(),
+            self.config.clone(),
+        )?
+        .with_port_opener(port_opener);
+
+        // Worker logs are tagged with the modem id, to tell several modems apart.
+        let span = tracing::info_span!("modem", id = %self.config.id);
+        let handle = tokio::spawn(
+            async move {
+                if let Err(e) = worker
+                    .initialize_and_run(command_rx, priority_command_rx, control_rx)
+                    .await
+                {
+                    error!("ModemWorker error: {e}");
+                }
+            }
+            .instrument(span),
+        );
+
         Ok(handle)
     }
 
     pub fn get_sender(&mut self) -> Result<ModemSender> {
-        if let Some(command_tx) = self.command_tx.take() {
-            Ok(ModemSender::new(command_tx))
-        } else {
-            Err(anyhow!("Could not get ModemSender, command_tx channel has already been taken or the modem hasn't been started!"))
+        match (self.command_tx.take(), self.priority_command_tx.take(), self.control_tx.take()) {
+            (Some(command_tx), Some(priority_command_tx), Some(control_tx)) => Ok(ModemSender::new(command_tx, priority_command_tx, control_tx, self.snapshot.clone()).with_default_validity_period(self.config.default_validity_period)),
+            _ => Err(anyhow!("Could not get ModemSender, the channels have already been taken or the modem hasn't been started!"))
         }
     }
 }
@@ -1,5 +1,7 @@
-use crate::modem::types::{GNSSFixStatus, GNSSLocation};
-use anyhow::{anyhow, Result};
+use crate::modem::types::{GNSSFixStatus, GNSSLocation, RegistrationDomain};
+use crate::sms::types::smsc_timestamp_to_unix;
+use anyhow::{anyhow, bail, Result};
+use sms_pdu::pdu::SmscTimestamp;
 
 pub fn parse_cmgs_result(response: &str) -> Result<u8> {
     let cmgs_line = response
@@ -16,7 +18,46 @@ pub fn parse_cmgs_result(response: &str) -> Result<u8> {
         .map_err(|_| anyhow!("Invalid CMGS message reference number"))
 }
 
-pub fn parse_creg_response(response: &str) -> Result<(u8, u8)> {
+/// Get the storage and index from a `+CMTI: "ME",<index>` or `+CDSI: "SR",<index>` indication.
+/// The storage is unquoted, and `None` if the modem didn't include one.
+pub fn parse_cmti_index(response: &str) -> Result<(Option<String>, u32)> {
+    let response = response.trim();
+    let fields = response
+        .strip_prefix("+CMTI:")
+        .or_else(|| response.strip_prefix("+CDSI:"))
+        .ok_or(anyhow!("Malformed storage indication"))?;
+
+    let (storage, index) = match fields.rsplit_once(',') {
+        Some((storage, index)) => (storage.trim().trim_matches('"'), index),
+        None => ("", fields),
+    };
+    let index = index
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid storage index"))?;
+
+    let storage = (!storage.is_empty()).then(|| storage.to_string());
+    Ok((storage, index))
+}
+
+/// Get the PDU from an `AT+CMGR` response, which is on the line after `+CMGR:`.
+pub fn parse_cmgr_response(response: &str) -> Result<String> {
+    let mut lines = response.lines().map(str::trim);
+    lines
+        .find(|line| line.starts_with("+CMGR:"))
+        .ok_or(anyhow!("No CMGR response found in buffer"))?;
+
+    match lines.next() {
+        Some(pdu) if !pdu.is_empty() && pdu != "OK" => Ok(pdu.to_string()),
+        _ => Err(anyhow!(
+            "Missing CMGR message PDU, the storage index may be empty"
+        )),
+    }
+}
+
+/// Get the registration status from a `+CREG: <n>,<stat>[,<lac>,<ci>[,<AcT>]]` query response.
+/// The leading `<n>` is only the unsolicited result code mode, so it's skipped.
+pub fn parse_creg_response(response: &str) -> Result<u8> {
     let creg_line = response
         .lines()
         .find(|line| line.trim().starts_with("+CREG:"))
@@ -29,21 +70,71 @@ pub fn parse_creg_response(response: &str) -> Result<(u8, u8)> {
         .trim();
 
     let mut parts = data.split(',');
-    let registration: u8 = parts
+    parts
+        .next()
+        .ok_or(anyhow!("Missing unsolicited result mode"))?
+        .trim()
+        .parse::<u8>()
+        .map_err(|_| anyhow!("Invalid unsolicited result mode"))?;
+
+    parts
         .next()
         .ok_or(anyhow!("Missing registration status"))?
         .trim()
         .parse()
-        .map_err(|_| anyhow!("Invalid registration status"))?;
+        .map_err(|_| anyhow!("Invalid registration status"))
+}
 
-    let technology: u8 = parts
+/// Get the domain and registration status from a `+CREG: <stat>[,<lac>,<ci>[,<AcT>]]` unsolicited
+/// change, or its `+CGREG:` packet domain equivalent which has the same layout. Unlike the query
+/// response, the unsolicited form has no leading `<n>` mode field.
+pub fn parse_creg_unsolicited(content: &str) -> Result<(RegistrationDomain, u8)> {
+    let content = content.trim();
+    let (domain, fields) = if let Some(fields) = content.strip_prefix("+CREG:") {
+        (RegistrationDomain::Circuit, fields)
+    } else if let Some(fields) = content.strip_prefix("+CGREG:") {
+        (RegistrationDomain::Packet, fields)
+    } else {
+        bail!("Malformed CREG unsolicited message");
+    };
+
+    let registration = fields
+        .split(',')
         .next()
-        .ok_or(anyhow!("Missing technology status"))?
+        .unwrap_or_default()
         .trim()
         .parse()
-        .map_err(|_| anyhow!("Invalid technology status"))?;
+        .map_err(|_| anyhow!("Invalid registration status"))?;
+    Ok((domain, registration))
+}
 
-    Ok((registration, technology))
+/// Human readable label for a 3GPP network registration status.
+pub fn registration_label(registration: u8) -> &'static str {
+    match registration {
+        0 => "not registered",
+        1 => "registered-home",
+        2 => "searching",
+        3 => "denied",
+        5 => "roaming",
+        _ => "unknown",
+    }
+}
+
+/// Human readable label for a 3GPP access technology (AcT).
+pub fn technology_label(technology: u8) -> &'static str {
+    match technology {
+        0 => "GSM",
+        1 => "GSM Compact",
+        2 => "UMTS",
+        3 => "EDGE",
+        4 => "HSDPA",
+        5 => "HSUPA",
+        6 => "HSPA",
+        7 => "LTE",
+        8 => "LTE-M",
+        9 => "NB-IoT",
+        _ => "unknown",
+    }
 }
 
 pub fn parse_csq_response(response: &str) -> Result<(i32, i32)> {
@@ -115,6 +206,18 @@ pub fn parse_cops_response(response: &str) -> Result<(u8, u8, String)> {
     Ok((status, format, operator))
 }
 
+/// Get the access technology (4th field) from a COPS response, which is
+/// only present when registered and on modems that report it.
+pub fn parse_cops_access_technology(response: &str) -> Option<u8> {
+    let cops_line = response
+        .lines()
+        .find(|line| line.trim().starts_with("+COPS:"))?;
+
+    // The operator name is quoted and may contain commas, so start after it.
+    let (_, after_operator) = cops_line.trim().rsplit_once('"')?;
+    after_operator.strip_prefix(',')?.trim().parse().ok()
+}
+
 pub fn parse_cspn_response(response: &str) -> Result<String> {
     let cspn_line = response
         .lines()
@@ -141,6 +244,157 @@ pub fn parse_cspn_response(response: &str) -> Result<String> {
     Ok(data[quote_start + 1..quote_end].to_string())
 }
 
+pub fn parse_csca_response(response: &str) -> Result<String> {
+    let csca_line = response
+        .lines()
+        .find(|line| line.trim().starts_with("+CSCA:"))
+        .ok_or(anyhow!("No CSCA response found in buffer"))?;
+
+    let data = csca_line
+        .trim()
+        .strip_prefix("+CSCA:")
+        .ok_or(anyhow!("Malformed CSCA response"))?
+        .trim();
+
+    // The service centre number is the first quoted field, followed by its type of address.
+    let number = data
+        .split(',')
+        .next()
+        .ok_or(anyhow!("Missing service centre number"))?
+        .trim()
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or(anyhow!("Service centre number not properly quoted"))?;
+
+    Ok(number.to_string())
+}
+
+/// Parse the modem clock (`+CCLK: "yy/MM/dd,HH:mm:ss±zz"`) into its text, unix timestamp and
+/// UTC offset in minutes. The timestamp is None while the clock is still at the modem's default.
+pub fn parse_cclk_response(response: &str) -> Result<(String, Option<u64>, i32)> {
+    let cclk_line = response
+        .lines()
+        .find(|line| line.trim().starts_with("+CCLK:"))
+        .ok_or(anyhow!("No CCLK response found in buffer"))?;
+
+    let time = cclk_line
+        .trim()
+        .strip_prefix("+CCLK:")
+        .ok_or(anyhow!("Malformed CCLK response"))?
+        .trim()
+        .trim_matches('"');
+
+    let (date, clock) = time
+        .split_once(',')
+        .ok_or(anyhow!("Malformed CCLK time: {time}"))?;
+
+    // The timezone is a signed number of quarter hours, which some modems leave off.
+    let (clock, quarter_hours) = match clock.find(['+', '-']) {
+        Some(index) => {
+            let (clock, offset) = clock.split_at(index);
+            let quarter_hours: i32 = offset
+                .parse()
+                .map_err(|_| anyhow!("Invalid CCLK timezone: {offset}"))?;
+            (clock, quarter_hours)
+        }
+        None => (clock, 0),
+    };
+
+    let fields = |value: &str, separator| -> Result<[u8; 3]> {
+        value
+            .split(separator)
+            .map(|field| field.parse::<u8>())
+            .collect::<Result<Vec<_>, _>>()
+            .ok()
+            .and_then(|fields| fields.try_into().ok())
+            .ok_or(anyhow!("Malformed CCLK time: {time}"))
+    };
+    let [year, month, day] = fields(date, '/')?;
+    let [hour, minute, second] = fields(clock, ':')?;
+    if quarter_hours.abs() > 79 {
+        return Err(anyhow!("Invalid CCLK timezone: {quarter_hours}"));
+    }
+
+    // Until the network sets it, the clock counts up from a default such as 80/01/06. Two digit
+    // years are 2000 based, so those from 80 would be decades ahead and are treated as unsynced.
+    let timestamp = if year < 80 {
+        let timestamp = SmscTimestamp {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            timezone: match quarter_hours {
+                offset if offset < 0 => (80 - offset) as u8,
+                offset => offset as u8,
+            },
+        };
+        Some(smsc_timestamp_to_unix(&timestamp).ok_or(anyhow!("Invalid CCLK time: {time}"))?)
+    } else {
+        None
+    };
+
+    Ok((time.to_string(), timestamp, quarter_hours * 15))
+}
+
+pub fn parse_cfun_response(response: &str) -> Result<u8> {
+    let cfun_line = response
+        .lines()
+        .find(|line| line.trim().starts_with("+CFUN:"))
+        .ok_or(anyhow!("No CFUN response found in buffer"))?;
+
+    cfun_line
+        .trim()
+        .strip_prefix("+CFUN:")
+        .ok_or(anyhow!("Malformed CFUN response"))?
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid CFUN functionality level"))
+}
+
+pub fn parse_cnmp_response(response: &str) -> Result<u8> {
+    let cnmp_line = response
+        .lines()
+        .find(|line| line.trim().starts_with("+CNMP:"))
+        .ok_or(anyhow!("No CNMP response found in buffer"))?;
+
+    cnmp_line
+        .trim()
+        .strip_prefix("+CNMP:")
+        .ok_or(anyhow!("Malformed CNMP response"))?
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid CNMP preferred mode"))
+}
+
+/// Parse the text mode parameters (fo, vp, pid, dcs) from an AT+CSMP? response.
+pub fn parse_csmp_response(response: &str) -> Result<(u8, u8, u8, u8)> {
+    let csmp_line = response
+        .lines()
+        .find(|line| line.trim().starts_with("+CSMP:"))
+        .ok_or(anyhow!("No CSMP response found in buffer"))?;
+
+    let values = csmp_line
+        .trim()
+        .strip_prefix("+CSMP:")
+        .ok_or(anyhow!("Malformed CSMP response"))?
+        .split(',')
+        .map(|value| value.trim().parse::<u8>())
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| anyhow!("Invalid CSMP parameter"))?;
+
+    match values[..] {
+        [first_octet, validity_period, protocol_id, data_coding_scheme] => Ok((
+            first_octet,
+            validity_period,
+            protocol_id,
+            data_coding_scheme,
+        )),
+        _ => Err(anyhow!("Expected 4 CSMP parameters, got {}", values.len())),
+    }
+}
+
 pub fn parse_cbc_response(response: &str) -> Result<(u8, u8, f32)> {
     let cbc_line = response
         .lines()
@@ -179,6 +433,35 @@ pub fn parse_cbc_response(response: &str) -> Result<(u8, u8, f32)> {
     Ok((status, charge, voltage))
 }
 
+pub fn parse_clip_response(response: &str) -> Result<Option<String>> {
+    let clip_line = response
+        .lines()
+        .find(|line| line.trim().starts_with("+CLIP:"))
+        .ok_or(anyhow!("No CLIP response found in buffer"))?;
+
+    let data = clip_line
+        .trim()
+        .strip_prefix("+CLIP:")
+        .ok_or(anyhow!("Malformed CLIP response"))?
+        .trim();
+
+    // The caller number is the first quoted field, which is empty if withheld.
+    let phone_number = data
+        .split(',')
+        .next()
+        .ok_or(anyhow!("Missing caller number"))?
+        .trim()
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or(anyhow!("Caller number not properly quoted"))?;
+
+    if phone_number.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(phone_number.to_string()))
+    }
+}
+
 pub fn parse_cgpsstatus_response(response: &str) -> Result<GNSSFixStatus> {
     let cgps_line = response
         .lines()
@@ -209,6 +492,132 @@ pub fn parse_cgnsinf_response(response: &str, unsolicited: bool) -> Result<GNSSL
     GNSSLocation::try_from(fields)
 }
 
+/// Get the numeric code from a `+CME ERROR` or `+CMS ERROR` line, if any.
+/// Modems in verbose error mode (AT+CMEE=2) send text instead, which has no code.
+pub fn parse_error_code(response: &str) -> Option<u16> {
+    response.lines().find_map(|line| {
+        let line = line.trim();
+        line.strip_prefix("+CME ERROR:")
+            .or_else(|| line.strip_prefix("+CMS ERROR:"))?
+            .trim()
+            .parse()
+            .ok()
+    })
+}
+
+/// Describe the first `+CME ERROR` or `+CMS ERROR` line of a response, labelling its code for
+/// logs and error messages, eg: `CME ERROR 10 (SIM not inserted)`. Verbose errors (AT+CMEE=2)
+/// are already text, so are kept as they are.
+pub fn describe_error(response: &str) -> Option<String> {
+    response.lines().find_map(|line| {
+        let line = line.trim();
+        let (kind, detail, label): (_, _, fn(u16) -> &'static str) =
+            if let Some(detail) = line.strip_prefix("+CME ERROR:") {
+                ("CME", detail.trim(), cme_error_label)
+            } else if let Some(detail) = line.strip_prefix("+CMS ERROR:") {
+                ("CMS", detail.trim(), cms_error_label)
+            } else {
+                return None;
+            };
+        Some(match detail.parse() {
+            Ok(code) => format!("{kind} ERROR {code} ({})", label(code)),
+            Err(_) => format!("{kind} ERROR: {detail}"),
+        })
+    })
+}
+
+/// Describe a response for an error message, by its error code if it has one.
+pub fn describe_response(response: &str) -> String {
+    describe_error(response).unwrap_or_else(|| response.trim().to_string())
+}
+
+/// Human readable label for a `+CME ERROR` code, from 3GPP TS 27.007 (9.2).
+pub fn cme_error_label(code: u16) -> &'static str {
+    match code {
+        0 => "phone failure",
+        1 => "no connection to phone",
+        2 => "phone adaptor link reserved",
+        3 => "operation not allowed",
+        4 => "operation not supported",
+        5 => "PH-SIM PIN required",
+        6 => "PH-FSIM PIN required",
+        7 => "PH-FSIM PUK required",
+        10 => "SIM not inserted",
+        11 => "SIM PIN required",
+        12 => "SIM PUK required",
+        13 => "SIM failure",
+        14 => "SIM busy",
+        15 => "SIM wrong",
+        16 => "incorrect password",
+        17 => "SIM PIN2 required",
+        18 => "SIM PUK2 required",
+        20 => "memory full",
+        21 => "invalid index",
+        22 => "not found",
+        23 => "memory failure",
+        24 => "text string too long",
+        25 => "invalid characters in text string",
+        26 => "dial string too long",
+        27 => "invalid characters in dial string",
+        30 => "no network service",
+        31 => "network timeout",
+        32 => "network not allowed, emergency calls only",
+        40 => "network personalization PIN required",
+        41 => "network personalization PUK required",
+        42 => "network subset personalization PIN required",
+        43 => "network subset personalization PUK required",
+        44 => "service provider personalization PIN required",
+        45 => "service provider personalization PUK required",
+        46 => "corporate personalization PIN required",
+        47 => "corporate personalization PUK required",
+        50 => "incorrect parameters",
+        100 => "unknown error",
+        103 => "illegal MS",
+        106 => "illegal ME",
+        107 => "GPRS services not allowed",
+        111 => "PLMN not allowed",
+        112 => "location area not allowed",
+        113 => "roaming not allowed in this location area",
+        132 => "service option not supported",
+        133 => "requested service option not subscribed",
+        134 => "service option temporarily out of order",
+        148 => "unspecified GPRS error",
+        149 => "PDP authentication failure",
+        150 => "invalid mobile class",
+        _ => "unknown",
+    }
+}
+
+/// Human readable label for a `+CMS ERROR` code, from 3GPP TS 27.005 (3.2.5).
+pub fn cms_error_label(code: u16) -> &'static str {
+    match code {
+        300 => "ME failure",
+        301 => "SMS service of ME reserved",
+        302 => "operation not allowed",
+        303 => "operation not supported",
+        304 => "invalid PDU mode parameter",
+        305 => "invalid text mode parameter",
+        310 => "SIM not inserted",
+        311 => "SIM PIN required",
+        312 => "PH-SIM PIN required",
+        313 => "SIM failure",
+        314 => "SIM busy",
+        315 => "SIM wrong",
+        316 => "SIM PUK required",
+        317 => "SIM PIN2 required",
+        318 => "SIM PUK2 required",
+        320 => "memory failure",
+        321 => "invalid memory index",
+        322 => "memory full",
+        330 => "SMSC address unknown",
+        331 => "no network service",
+        332 => "network timeout",
+        340 => "no +CNMA acknowledgement expected",
+        500 => "unknown error",
+        _ => "unknown",
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -285,63 +694,90 @@ mod tests {
 
     #[test]
     fn test_parse_creg_response() {
-        // Success cases - test both values
-        let response = "+CREG: 1,7\r\nOK\r\n";
-        let (reg, tech) = parse_creg_response(response).unwrap();
-        assert_eq!(reg, 1, "Expected registration status 1");
-        assert_eq!(tech, 7, "Expected technology status 7");
-
-        let response = "  +CREG:  2 , 4  \r\nOK\r\n";
-        let (reg, tech) = parse_creg_response(response).unwrap();
-        assert_eq!(reg, 2, "Expected registration status 2 with whitespace");
-        assert_eq!(tech, 4, "Expected technology status 4 with whitespace");
-
-        // Test various valid combinations
-        let response = "+CREG: 0,0\r\n";
-        let (reg, tech) = parse_creg_response(response).unwrap();
-        assert_eq!(reg, 0, "Expected minimum registration status");
-        assert_eq!(tech, 0, "Expected minimum technology status");
-
-        let response = "+CREG: 5,9\r\n";
-        let (reg, tech) = parse_creg_response(response).unwrap();
-        assert_eq!(reg, 5, "Expected registration status 5");
-        assert_eq!(tech, 9, "Expected technology status 9");
+        // The registration status is the second field, after the unsolicited result mode.
+        assert_eq!(parse_creg_response("+CREG: 0,5\r\n\r\nOK\r\n").unwrap(), 5);
+        assert_eq!(
+            parse_creg_response("  +CREG:  2 , 1  \r\nOK\r\n").unwrap(),
+            1
+        );
+        assert_eq!(
+            parse_creg_response("+CREG: 2,1,\"1A2B\",\"01C2D3E4\",7\r\nOK\r\n").unwrap(),
+            1,
+            "Expected the location fields to be ignored"
+        );
+        assert_eq!(parse_creg_response("+CREG: 1,0\r\n").unwrap(), 0);
 
         // Failure cases
-        let response = "OK\r\n";
-        let err = parse_creg_response(response).unwrap_err();
+        let err = parse_creg_response("OK\r\n").unwrap_err();
         assert!(
             err.to_string().contains("No CREG response found"),
             "Expected 'No CREG response found' error"
         );
 
-        let response = "+CREG: 1\r\n";
-        let err = parse_creg_response(response).unwrap_err();
+        let err = parse_creg_response("+CREG: 1\r\n").unwrap_err();
+        assert!(
+            err.to_string().contains("Missing registration status"),
+            "Expected missing registration status error"
+        );
+
+        let err = parse_creg_response("+CREG: abc,1\r\n").unwrap_err();
         assert!(
-            err.to_string().contains("Missing technology status"),
-            "Expected missing technology status error"
+            err.to_string().contains("Invalid unsolicited result mode"),
+            "Expected invalid mode error"
         );
 
-        let response = "+CREG: abc,7\r\n";
-        let err = parse_creg_response(response).unwrap_err();
+        let err = parse_creg_response("+CREG: 0,xyz\r\n").unwrap_err();
         assert!(
             err.to_string().contains("Invalid registration status"),
             "Expected invalid registration status error"
         );
 
-        let response = "+CREG: 1,xyz\r\n";
-        let err = parse_creg_response(response).unwrap_err();
+        let err = parse_creg_response("+CREG: 0,\r\n").unwrap_err();
         assert!(
-            err.to_string().contains("Invalid technology status"),
-            "Expected invalid technology status error"
+            err.to_string().contains("Invalid registration status"),
+            "Expected error for empty registration field"
         );
+    }
 
-        let response = "+CREG: 1,\r\n"; // Empty technology field
-        let err = parse_creg_response(response).unwrap_err();
-        assert!(
-            err.to_string().contains("Invalid technology status"),
-            "Expected error for empty technology field"
+    #[test]
+    fn test_parse_creg_unsolicited() {
+        assert_eq!(
+            parse_creg_unsolicited("+CREG: 1").unwrap(),
+            (RegistrationDomain::Circuit, 1)
         );
+        assert_eq!(
+            parse_creg_unsolicited("  +CREG: 5 \r\n").unwrap(),
+            (RegistrationDomain::Circuit, 5)
+        );
+        assert_eq!(
+            parse_creg_unsolicited("+CREG: 2,\"1A2B\",\"0123ABCD\",7").unwrap(),
+            (RegistrationDomain::Circuit, 2),
+            "Expected the location fields to be ignored"
+        );
+
+        assert_eq!(
+            parse_creg_unsolicited("+CGREG: 1,\"1A2B\",\"0123ABCD\"").unwrap(),
+            (RegistrationDomain::Packet, 1)
+        );
+
+        assert!(parse_creg_unsolicited("+CSQ: 1").is_err());
+        assert!(parse_creg_unsolicited("+CREG: ").is_err());
+        assert!(parse_creg_unsolicited("+CREG: abc").is_err());
+    }
+
+    #[test]
+    fn test_network_status_labels() {
+        assert_eq!(registration_label(0), "not registered");
+        assert_eq!(registration_label(1), "registered-home");
+        assert_eq!(registration_label(4), "unknown");
+        assert_eq!(registration_label(5), "roaming");
+        assert_eq!(registration_label(42), "unknown");
+
+        assert_eq!(technology_label(0), "GSM");
+        assert_eq!(technology_label(3), "EDGE");
+        assert_eq!(technology_label(7), "LTE");
+        assert_eq!(technology_label(9), "NB-IoT");
+        assert_eq!(technology_label(42), "unknown");
     }
 
     #[test]
@@ -636,6 +1072,171 @@ mod tests {
         assert_eq!(charge, 150, "Parser accepts values > 100 as valid u8");
     }
 
+    #[test]
+    fn test_parse_cclk_response() {
+        // 12:34:56 at +01:00 and -05:00, the same instants as the SMSC timestamp test.
+        let (time, timestamp, offset) =
+            parse_cclk_response("+CCLK: \"24/03/05,12:34:56+04\"\r\n\r\nOK\r\n").unwrap();
+        assert_eq!(time, "24/03/05,12:34:56+04");
+        assert_eq!(timestamp, Some(1709638496));
+        assert_eq!(offset, 60);
+
+        let (_, timestamp, offset) =
+            parse_cclk_response("+CCLK: \"24/03/05,12:34:56-20\"\r\nOK\r\n").unwrap();
+        assert_eq!(timestamp, Some(1709660096));
+        assert_eq!(offset, -300);
+
+        // A clock that hasn't been set by the network yet, and one without a timezone.
+        let (_, timestamp, _) =
+            parse_cclk_response("+CCLK: \"80/01/06,00:01:23+00\"\r\nOK\r\n").unwrap();
+        assert_eq!(timestamp, None);
+        let (_, timestamp, offset) =
+            parse_cclk_response("+CCLK: \"24/03/05,12:34:56\"\r\nOK\r\n").unwrap();
+        assert_eq!((timestamp, offset), (Some(1709642096), 0));
+
+        assert!(parse_cclk_response("+CCLK: \"24/13/05,12:34:56+00\"\r\n").is_err());
+        assert!(parse_cclk_response("+CCLK: \"24/03/05\"\r\n").is_err());
+        assert!(parse_cclk_response("OK\r\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_csca_response() {
+        // Success cases
+        let response = "AT+CSCA?\r\n+CSCA: \"+447802000332\",145\r\nOK\r\n";
+        let number = parse_csca_response(response).unwrap();
+        assert_eq!(number, "+447802000332", "Expected international SMSC");
+
+        let response = "  +CSCA:  \"07802000332\" , 129  \r\n";
+        let number = parse_csca_response(response).unwrap();
+        assert_eq!(
+            number, "07802000332",
+            "Expected national SMSC with whitespace"
+        );
+
+        // Failure cases
+        let response = "AT+CSCA?\r\nOK\r\n";
+        let err = parse_csca_response(response).unwrap_err();
+        assert!(
+            err.to_string().contains("No CSCA response found"),
+            "Expected 'No CSCA response found' error"
+        );
+
+        let response = "+CSCA: +447802000332,145\r\n";
+        let err = parse_csca_response(response).unwrap_err();
+        assert!(
+            err.to_string().contains("not properly quoted"),
+            "Expected unquoted number error"
+        );
+    }
+
+    #[test]
+    fn test_parse_cops_access_technology() {
+        let response = "+CREG: 0,1\r\n+COPS: 0,0,\"EE\",7\r\nOK\r\n";
+        assert_eq!(parse_cops_access_technology(response), Some(7));
+
+        let response = "+COPS: 1,0,\"Foo, Bar\", 9\r\n";
+        assert_eq!(
+            parse_cops_access_technology(response),
+            Some(9),
+            "Expected commas in operator name to be skipped"
+        );
+
+        // Not registered, or no access technology reported.
+        assert_eq!(parse_cops_access_technology("+COPS: 0\r\nOK\r\n"), None);
+        assert_eq!(parse_cops_access_technology("+COPS: 0,0,\"EE\"\r\n"), None);
+        assert_eq!(parse_cops_access_technology("OK\r\n"), None);
+    }
+
+    #[test]
+    fn test_parse_cnmp_response() {
+        let response = "+CNMP: 38\r\nOK\r\n";
+        assert_eq!(parse_cnmp_response(response).unwrap(), 38);
+
+        let err = parse_cnmp_response("OK\r\n").unwrap_err();
+        assert!(
+            err.to_string().contains("No CNMP response found"),
+            "Expected 'No CNMP response found' error"
+        );
+    }
+
+    #[test]
+    fn test_parse_csmp_response() {
+        let response = "+CSMP: 49,167,0,0\r\nOK\r\n";
+        assert_eq!(parse_csmp_response(response).unwrap(), (49, 167, 0, 0));
+
+        assert!(parse_csmp_response("+CSMP: 17,167,0\r\nOK\r\n").is_err());
+        assert!(parse_csmp_response("+CSMP: 17,,0,0\r\nOK\r\n").is_err());
+        assert!(parse_csmp_response("OK\r\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_cfun_response() {
+        // Success cases
+        let response = "AT+CFUN?\r\n+CFUN: 1\r\nOK\r\n";
+        let level = parse_cfun_response(response).unwrap();
+        assert_eq!(level, 1, "Expected full functionality");
+
+        let response = "  +CFUN:  4  \r\n";
+        let level = parse_cfun_response(response).unwrap();
+        assert_eq!(level, 4, "Expected flight mode with whitespace");
+
+        // Failure cases
+        let response = "OK\r\n";
+        let err = parse_cfun_response(response).unwrap_err();
+        assert!(
+            err.to_string().contains("No CFUN response found"),
+            "Expected 'No CFUN response found' error"
+        );
+
+        let response = "+CFUN: on\r\n";
+        let err = parse_cfun_response(response).unwrap_err();
+        assert!(
+            err.to_string().contains("Invalid CFUN functionality level"),
+            "Expected invalid level error"
+        );
+    }
+
+    #[test]
+    fn test_parse_clip_response() {
+        // Success cases
+        let response = "+CLIP: \"+447771115678\",145,\"\",0,\"\",0\r\n";
+        let number = parse_clip_response(response).unwrap();
+        assert_eq!(
+            number.as_deref(),
+            Some("+447771115678"),
+            "Expected international caller number"
+        );
+
+        let response = "  +CLIP:  \"07771115678\" , 129  \r\n";
+        let number = parse_clip_response(response).unwrap();
+        assert_eq!(
+            number.as_deref(),
+            Some("07771115678"),
+            "Expected national caller number with whitespace"
+        );
+
+        // Withheld number
+        let response = "+CLIP: \"\",128,\"\",0,\"\",2\r\n";
+        let number = parse_clip_response(response).unwrap();
+        assert_eq!(number, None, "Expected no number for withheld caller");
+
+        // Failure cases
+        let response = "RING\r\n";
+        let err = parse_clip_response(response).unwrap_err();
+        assert!(
+            err.to_string().contains("No CLIP response found"),
+            "Expected 'No CLIP response found' error"
+        );
+
+        let response = "+CLIP: +447771115678,145\r\n";
+        let err = parse_clip_response(response).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Caller number not properly quoted"),
+            "Expected unquoted caller number error"
+        );
+    }
+
     #[test]
     fn test_parse_cgpsstatus_response() {
         // Success cases - test various fix statuses
@@ -768,4 +1369,68 @@ mod tests {
             "Expected error for insufficient CGNSINF fields"
         );
     }
+
+    #[test]
+    fn test_parse_error_code() {
+        assert_eq!(parse_error_code("+CMS ERROR: 500\r\n"), Some(500));
+        assert_eq!(parse_error_code("> \n+CME ERROR: 10\n"), Some(10));
+        assert_eq!(parse_error_code("+CME ERROR: SIM not inserted\r\n"), None);
+        assert_eq!(parse_error_code("ERROR\r\n"), None);
+        assert_eq!(parse_error_code("OK\r\n"), None);
+    }
+
+    #[test]
+    fn test_describe_error() {
+        assert_eq!(
+            describe_error("+CME ERROR: 10\r\n").as_deref(),
+            Some("CME ERROR 10 (SIM not inserted)")
+        );
+        assert_eq!(
+            describe_error("> \n+CMS ERROR: 330\n").as_deref(),
+            Some("CMS ERROR 330 (SMSC address unknown)")
+        );
+
+        // Unknown codes keep their number, and verbose errors their text.
+        assert_eq!(
+            describe_error("+CME ERROR: 999").as_deref(),
+            Some("CME ERROR 999 (unknown)")
+        );
+        assert_eq!(
+            describe_error("+CME ERROR: SIM not inserted").as_deref(),
+            Some("CME ERROR: SIM not inserted")
+        );
+        assert_eq!(describe_error("ERROR\r\n"), None);
+        assert_eq!(describe_response(" ERROR\r\n"), "ERROR");
+    }
+
+    #[test]
+    fn test_parse_storage_index() {
+        assert_eq!(
+            parse_cmti_index("+CMTI: \"ME\",3").unwrap(),
+            (Some("ME".to_string()), 3)
+        );
+        assert_eq!(
+            parse_cmti_index("+CMTI: \"SM\", 12\r\n").unwrap(),
+            (Some("SM".to_string()), 12)
+        );
+        assert_eq!(
+            parse_cmti_index("+CDSI: \"SR\",7").unwrap(),
+            (Some("SR".to_string()), 7)
+        );
+        assert_eq!(parse_cmti_index("+CMTI: 5").unwrap(), (None, 5));
+        assert!(parse_cmti_index("+CMTI: \"ME\",").is_err());
+        assert!(parse_cmti_index("+CMT: ,24").is_err());
+    }
+
+    #[test]
+    fn test_parse_cmgr_response() {
+        let response = "+CMGR: 0,,24\n07914477790706520408D0E474D81C0E\nOK\n";
+        assert_eq!(
+            parse_cmgr_response(response).unwrap(),
+            "07914477790706520408D0E474D81C0E"
+        );
+
+        assert!(parse_cmgr_response("+CMGR: 0,,0\nOK\n").is_err());
+        assert!(parse_cmgr_response("OK\n").is_err());
+    }
 }
@@ -0,0 +1,333 @@
+use crate::config::RoutingConfig;
+use crate::modem::sender::ModemSender;
+use crate::modem::types::ModemStatus;
+use anyhow::{anyhow, Result};
+use sms_api_types::http::{RouteReason, SendRoute};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// A configured modem's sender, with the last status it published.
+struct PooledModem {
+    id: String,
+    sender: ModemSender,
+    status: Mutex<ModemStatus>,
+}
+impl PooledModem {
+    fn is_online(&self) -> bool {
+        self.status
+            .lock()
+            .is_ok_and(|status| *status == ModemStatus::Online)
+    }
+
+    /// Queued commands plus the in-flight one, used to pick the least busy modem.
+    fn load(&self) -> usize {
+        let status = self.sender.queue_status();
+        status.queue_length
+            + status.priority_queue_length
+            + usize::from(status.command_sequence.is_some())
+    }
+
+    fn route(
+        &self,
+        reason: RouteReason,
+        prefix: Option<String>,
+        skipped: Vec<String>,
+    ) -> SendRoute {
+        SendRoute {
+            modem_id: self.id.clone(),
+            reason,
+            prefix,
+            skipped,
+        }
+    }
+}
+
+/// Every configured modem, each with its own worker and command queue so a modem
+/// that's down never holds up sends to the others.
+#[derive(Clone)]
+pub struct ModemPool {
+    modems: Arc<[PooledModem]>,
+    routing: Option<Arc<RoutingConfig>>,
+    next: Arc<AtomicUsize>,
+}
+impl ModemPool {
+    /// The first modem is the default for commands that aren't routed to a specific one.
+    pub fn new(modems: Vec<(String, ModemSender)>, routing: Option<RoutingConfig>) -> Result<Self> {
+        if modems.is_empty() {
+            return Err(anyhow!("At least one modem is required"));
+        }
+
+        let modems = modems
+            .into_iter()
+            .map(|(id, sender)| PooledModem {
+                id,
+                sender,
+                status: Mutex::new(ModemStatus::Startup),
+            })
+            .collect();
+        Ok(Self {
+            modems,
+            routing: routing.map(Arc::new),
+            next: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    pub fn default_modem(&self) -> (&str, &ModemSender) {
+        let modem = &self.modems[0];
+        (&modem.id, &modem.sender)
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = (&str, &ModemSender)> {
+        self.modems
+            .iter()
+            .map(|modem| (modem.id.as_str(), &modem.sender))
+    }
+
+    pub fn get(&self, id: &str) -> Result<&ModemSender> {
+        self.find(id).map(|modem| &modem.sender)
+    }
+
+    /// Get a modem by id, or the default modem if no id is given.
+    pub fn get_or_default(&self, id: Option<&str>) -> Result<&ModemSender> {
+        match id {
+            Some(id) => self.get(id),
+            None => Ok(self.default_modem().1),
+        }
+    }
+
+    fn find(&self, id: &str) -> Result<&PooledModem> {
+        self.modems
+            .iter()
+            .find(|modem| modem.id == id)
+            .ok_or_else(|| anyhow!("Unknown modem '{id}'"))
+    }
+
+    /// Record a modem's status, so sends are only routed to it while it's online.
+    pub fn set_status(&self, id: &str, status: ModemStatus) {
+        if let Ok(modem) = self.find(id) {
+            if let Ok(mut current) = modem.status.lock() {
+                *current = status;
+            }
+        }
+    }
+
+    /// Get the modem to send to a phone number with, and why it was chosen. A requested modem
+    /// is always used. With routing, the modem for the longest matching prefix is used if it's
+    /// online, otherwise the fallback. Without routing, or if neither is online, the least busy
+    /// online modem is used.
+    pub fn route(
+        &self,
+        modem_id: Option<&str>,
+        phone_number: &str,
+    ) -> Result<(SendRoute, &ModemSender)> {
+        if let Some(id) = modem_id {
+            let modem = self.find(id)?;
+            return Ok((
+                modem.route(RouteReason::Requested, None, Vec::new()),
+                &modem.sender,
+            ));
+        }
+
+        let mut prefix = None;
+        let mut skipped = Vec::new();
+        if let Some(routing) = &self.routing {
+            let matched = routing
+                .prefixes
+                .iter()
+                .filter(|(prefix, _)| phone_number.starts_with(prefix.as_str()))
+                .max_by_key(|(prefix, _)| prefix.len());
+            let fallback = routing.fallback.as_deref().unwrap_or(&self.modems[0].id);
+            prefix = matched.map(|(prefix, _)| prefix.clone());
+
+            let candidates = matched
+                .map(|(_, id)| (id.as_str(), RouteReason::Prefix))
+                .into_iter()
+                .chain([(fallback, RouteReason::Fallback)]);
+            for (id, reason) in candidates {
+                let modem = self.find(id)?;
+                if modem.is_online() {
+                    return Ok((modem.route(reason, prefix, skipped), &modem.sender));
+                }
+                if !skipped.contains(&modem.id) {
+                    skipped.push(modem.id.clone());
+                }
+            }
+        }
+
+        let modem = self.least_busy();
+        Ok((
+            modem.route(RouteReason::LeastBusy, prefix, skipped),
+            &modem.sender,
+        ))
+    }
+
+    /// The least busy online modem, taking turns between modems that are equally busy.
+    /// If no modem is online they're all considered, so a send still reaches one starting up.
+    fn least_busy(&self) -> &PooledModem {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let rotated = || {
+            (0..self.modems.len())
+                .map(move |offset| &self.modems[(start + offset) % self.modems.len()])
+        };
+
+        let any_online = rotated().any(PooledModem::is_online);
+        rotated()
+            .filter(|modem| !any_online || modem.is_online())
+            .min_by_key(|modem| modem.load())
+            .unwrap_or(&self.modems[0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modem::commands::OutgoingCommand;
+    use crate::modem::types::{ModemControl, ModemRequest};
+    use tokio::sync::{mpsc, oneshot};
+
+    /// The channel receivers are returned to keep the sender open.
+    struct TestModem {
+        sender: ModemSender,
+        command_tx: mpsc::Sender<OutgoingCommand>,
+        _receivers: (
+            mpsc::Receiver<OutgoingCommand>,
+            mpsc::Receiver<OutgoingCommand>,
+            mpsc::Receiver<ModemControl>,
+        ),
+    }
+    impl TestModem {
+        fn new() -> Self {
+            let (command_tx, command_rx) = mpsc::channel(8);
+            let (priority_command_tx, priority_command_rx) = mpsc::channel(8);
+            let (control_tx, control_rx) = mpsc::channel(1);
+            Self {
+                sender: ModemSender::new(
+                    command_tx.clone(),
+                    priority_command_tx,
+                    control_tx,
+                    Default::default(),
+                ),
+                command_tx,
+                _receivers: (command_rx, priority_command_rx, control_rx),
+            }
+        }
+
+        fn queue_command(&self) {
+            let (response_tx, _) = oneshot::channel();
+            let command = OutgoingCommand::new(0, response_tx, ModemRequest::Heartbeat, None);
+            self.command_tx.try_send(command).unwrap();
+        }
+    }
+
+    fn pool(modems: &[(&str, &TestModem)], routing: Option<RoutingConfig>) -> ModemPool {
+        let modems = modems
+            .iter()
+            .map(|(id, modem)| (id.to_string(), modem.sender.clone()))
+            .collect();
+        ModemPool::new(modems, routing).unwrap()
+    }
+
+    #[test]
+    fn test_least_busy_modem() {
+        let (uk, us) = (TestModem::new(), TestModem::new());
+        let pool = pool(&[("uk", &uk), ("us", &us)], None);
+        let selected = |modem_id| {
+            let (route, _) = pool.route(modem_id, "+15550100").unwrap();
+            assert_eq!(
+                route.reason,
+                match modem_id {
+                    Some(_) => RouteReason::Requested,
+                    None => RouteReason::LeastBusy,
+                }
+            );
+            route.modem_id
+        };
+
+        // Before any status is published, every modem is considered in turn.
+        assert_ne!(selected(None), selected(None));
+
+        // Only online modems are picked, unless requested by id.
+        pool.set_status("uk", ModemStatus::Online);
+        pool.set_status("us", ModemStatus::Offline);
+        assert_eq!([selected(None), selected(None)], ["uk", "uk"]);
+        assert_eq!(selected(Some("us")), "us");
+        assert!(pool.route(Some("fr"), "+15550100").is_err());
+
+        // Equally busy modems take turns, otherwise the least busy is used.
+        pool.set_status("us", ModemStatus::Online);
+        assert_ne!(selected(None), selected(None));
+        uk.queue_command();
+        assert_eq!([selected(None), selected(None)], ["us", "us"]);
+
+        assert_eq!(pool.default_modem().0, "uk");
+    }
+
+    #[test]
+    fn test_route_by_prefix() {
+        let modems = [TestModem::new(), TestModem::new(), TestModem::new()];
+        let routing = RoutingConfig {
+            prefixes: [("+44", "uk"), ("+447", "uk-mobile"), ("+1", "us")]
+                .into_iter()
+                .map(|(prefix, id)| (prefix.to_string(), id.to_string()))
+                .collect(),
+            fallback: Some("us".to_string()),
+        };
+        let pool = pool(
+            &[
+                ("uk", &modems[0]),
+                ("uk-mobile", &modems[1]),
+                ("us", &modems[2]),
+            ],
+            Some(routing),
+        );
+        for id in ["uk", "uk-mobile", "us"] {
+            pool.set_status(id, ModemStatus::Online);
+        }
+        let route = |phone_number| pool.route(None, phone_number).unwrap().0;
+        let expected = |modem_id: &str, reason, prefix: Option<&str>, skipped: &[&str]| SendRoute {
+            modem_id: modem_id.to_string(),
+            reason,
+            prefix: prefix.map(str::to_string),
+            skipped: skipped.iter().map(|id| id.to_string()).collect(),
+        };
+
+        // The longest prefix wins, and numbers without one always use the fallback.
+        assert_eq!(
+            route("+447700900123"),
+            expected("uk-mobile", RouteReason::Prefix, Some("+447"), &[])
+        );
+        assert_eq!(
+            route("+441632960000"),
+            expected("uk", RouteReason::Prefix, Some("+44"), &[])
+        );
+        for _ in 0..3 {
+            assert_eq!(
+                route("+33612345678"),
+                expected("us", RouteReason::Fallback, None, &[])
+            );
+        }
+
+        // Modems that aren't online are skipped, down to the least busy online modem.
+        pool.set_status("uk-mobile", ModemStatus::Offline);
+        assert_eq!(
+            route("+447700900123"),
+            expected("us", RouteReason::Fallback, Some("+447"), &["uk-mobile"])
+        );
+        pool.set_status("us", ModemStatus::Offline);
+        assert_eq!(
+            route("+447700900123"),
+            expected(
+                "uk",
+                RouteReason::LeastBusy,
+                Some("+447"),
+                &["uk-mobile", "us"]
+            )
+        );
+
+        // A requested modem overrides routing.
+        assert_eq!(
+            pool.route(Some("us"), "+447700900123").unwrap().0,
+            expected("us", RouteReason::Requested, None, &[])
+        );
+    }
+}
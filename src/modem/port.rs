@@ -0,0 +1,222 @@
+use crate::config::ModemConfig;
+use crate::modem::worker::ModemTransport;
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use tokio_serial::{SerialPortBuilderExt, SerialPortInfo, SerialPortType};
+use tracing::log::{debug, info};
+
+/// Identifies a USB serial device, so it can be found again if it's re-enumerated under another path.
+#[derive(Debug, Clone, PartialEq)]
+struct UsbIdentity {
+    vid: u16,
+    pid: u16,
+    serial_number: Option<String>,
+}
+
+/// Opens the modem serial port, resolving the configured devices again on every open. Each
+/// device may be a path or glob pattern, tried in order. A replugged USB modem may come back
+/// under a different path (eg: ttyUSB0 to ttyUSB1), so if nothing configured exists the port
+/// with the same USB identity as the last one opened is used instead.
+pub struct SerialPortOpener {
+    devices: Vec<String>,
+    baud_rate: u32,
+    usb_identity: Option<UsbIdentity>,
+}
+impl SerialPortOpener {
+    pub fn new(config: &ModemConfig) -> Self {
+        Self {
+            devices: config.device.clone(),
+            baud_rate: config.baud_rate,
+            usb_identity: None,
+        }
+    }
+
+    /// The concrete device paths to try, in order.
+    pub fn candidates(&self) -> Vec<String> {
+        let candidates = expand_devices(&self.devices);
+        if !candidates.is_empty() {
+            return candidates;
+        }
+
+        self.usb_identity
+            .as_ref()
+            .and_then(|identity| {
+                let ports = tokio_serial::available_ports().ok()?;
+                find_usb_port(&ports, identity)
+            })
+            .map(|device| {
+                info!("No configured modem device exists, using the same USB device at {device}");
+                vec![device]
+            })
+            .unwrap_or_default()
+    }
+
+    /// Open the first candidate that can be opened, without checking the modem responds.
+    pub fn open_first(&mut self) -> Result<Box<dyn ModemTransport>> {
+        let mut last_error = None;
+        for device in self.candidates() {
+            match self.open(&device) {
+                Ok(port) => return Ok(port),
+                Err(e) => {
+                    debug!("{e}");
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            anyhow!("No modem device found matching {}", self.devices.join(", "))
+        }))
+    }
+
+    /// Open a new handle to a device, the previous one is unusable once the device was unplugged.
+    pub fn open(&mut self, device: &str) -> Result<Box<dyn ModemTransport>> {
+        let port = tokio_serial::new(device, self.baud_rate)
+            .open_native_async()
+            .map_err(|e| anyhow!("Failed to open serial port {device}: {e}"))?;
+
+        // Remember which USB device this is, so it can be found after being re-enumerated.
+        if let Some(identity) = usb_identity(device) {
+            debug!("Modem serial port {device} is USB device {identity:?}");
+            self.usb_identity = Some(identity);
+        }
+        Ok(Box::new(port))
+    }
+}
+
+/// A stand-in for a closed port, reads end immediately and writes are discarded.
+pub fn closed_port() -> Box<dyn ModemTransport> {
+    Box::new(tokio::io::join(tokio::io::empty(), tokio::io::sink()))
+}
+
+/// Expand glob patterns into the existing paths they match, sorted within each pattern.
+/// Plain paths are kept if they exist, and duplicates are only tried once.
+fn expand_devices(patterns: &[String]) -> Vec<String> {
+    let mut devices: Vec<String> = Vec::new();
+    for pattern in patterns {
+        let Ok(paths) = glob::glob(pattern) else {
+            continue;
+        };
+
+        let mut matched: Vec<String> = paths
+            .filter_map(|path| path.ok())
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        matched.sort_unstable();
+
+        for device in matched {
+            if !devices.contains(&device) {
+                devices.push(device);
+            }
+        }
+    }
+    devices
+}
+
+fn identity_of(port: &SerialPortInfo) -> Option<UsbIdentity> {
+    match &port.port_type {
+        SerialPortType::UsbPort(info) => Some(UsbIdentity {
+            vid: info.vid,
+            pid: info.pid,
+            serial_number: info.serial_number.clone(),
+        }),
+        _ => None,
+    }
+}
+
+/// Get the USB identity of an open device, following symlinks such as `/dev/serial/by-id`.
+fn usb_identity(device: &str) -> Option<UsbIdentity> {
+    let device = Path::new(device).canonicalize().ok()?;
+    tokio_serial::available_ports()
+        .ok()?
+        .iter()
+        .find(|port| Path::new(&port.port_name).canonicalize().ok().as_ref() == Some(&device))
+        .and_then(identity_of)
+}
+
+/// Find the first port belonging to the USB device, in path order.
+fn find_usb_port(ports: &[SerialPortInfo], identity: &UsbIdentity) -> Option<String> {
+    let mut matching: Vec<&str> = ports
+        .iter()
+        .filter(|port| identity_of(port).as_ref() == Some(identity))
+        .map(|port| port.port_name.as_str())
+        .collect();
+
+    matching.sort_unstable();
+    matching.first().map(|name| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_serial::UsbPortInfo;
+
+    fn usb_port(port_name: &str, pid: u16, serial_number: Option<&str>) -> SerialPortInfo {
+        SerialPortInfo {
+            port_name: port_name.to_string(),
+            port_type: SerialPortType::UsbPort(UsbPortInfo {
+                vid: 0x1e0e,
+                pid,
+                serial_number: serial_number.map(str::to_string),
+                manufacturer: None,
+                product: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_expand_devices() {
+        let dir = std::env::temp_dir().join(format!("sms-api-devices-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["usb-SimTech_B-if03", "usb-SimTech_A-if02", "usb-Other-if00"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+        let devices = expand_devices(&[
+            path("missing"),
+            path("usb-Other-if00"),
+            path("usb-SimTech*"),
+            path("usb-*-if00"),
+        ]);
+        assert_eq!(
+            devices,
+            [
+                path("usb-Other-if00"),
+                path("usb-SimTech_A-if02"),
+                path("usb-SimTech_B-if03"),
+            ]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_find_usb_port() {
+        let identity = UsbIdentity {
+            vid: 0x1e0e,
+            pid: 0x9001,
+            serial_number: Some("ABC123".to_string()),
+        };
+        let ports = [
+            SerialPortInfo {
+                port_name: "/dev/ttyS0".to_string(),
+                port_type: SerialPortType::Unknown,
+            },
+            usb_port("/dev/ttyUSB3", 0x9001, Some("ABC123")),
+            usb_port("/dev/ttyUSB0", 0x9001, Some("OTHER")),
+            usb_port("/dev/ttyUSB1", 0x9002, Some("ABC123")),
+            usb_port("/dev/ttyUSB2", 0x9001, Some("ABC123")),
+        ];
+
+        assert_eq!(
+            find_usb_port(&ports, &identity).as_deref(),
+            Some("/dev/ttyUSB2")
+        );
+        assert_eq!(
+            find_usb_port(&ports[..3], &identity).as_deref(),
+            Some("/dev/ttyUSB3")
+        );
+        assert_eq!(find_usb_port(&ports[..1], &identity), None);
+    }
+}
@@ -1,19 +1,94 @@
 #![cfg_attr(not(feature = "http-server"), allow(dead_code))]
 
+use crate::config::is_valid_operator_code;
 use crate::modem::commands::{next_command_sequence, OutgoingCommand};
-use crate::modem::types::{ModemRequest, ModemResponse};
-use crate::types::SMSOutgoingMessage;
+use crate::modem::state_machine::SharedStateMachineSnapshot;
+use crate::modem::types::{
+    ModemControl, ModemQueueStatus, ModemRequest, ModemResponse, ModemStatus,
+};
+use crate::types::{
+    set_concatenation_reference, validity_period_to_duration, SMSOutgoingMessage,
+    DEFAULT_VALIDITY_PERIOD,
+};
 use anyhow::Result;
 use anyhow::{anyhow, bail};
-use sms_pdu::{gsm_encoding, pdu};
-use std::time::Duration;
-use tokio::sync::{mpsc, oneshot};
+use sms_api_types::http::SendSmsPdu;
+use sms_pdu::pdu;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, Mutex};
 use tracing::log::{debug, error, warn};
 
 const SEND_TIMEOUT: Duration = Duration::from_secs(90);
+const RESET_TIMEOUT: Duration = Duration::from_secs(120);
 
-fn create_sms_requests(message: &SMSOutgoingMessage) -> Result<Vec<ModemRequest>> {
-    let requests = gsm_encoding::GsmMessageData::encode_message(&message.content)
+/// A flush is handled between commands, so only waits on a reset that's in progress.
+const FLUSH_TIMEOUT: Duration = RESET_TIMEOUT;
+
+/// Fabricated message references for dry runs, which are never sent to the network.
+static DRY_RUN_REFERENCE: AtomicU8 = AtomicU8::new(0);
+
+/// A destination's concatenation reference is forgotten once unused for this long, by when its
+/// messages have long been reassembled, so it starts from a random one again like after a restart.
+const CONCATENATION_REFERENCE_IDLE: Duration = Duration::from_secs(60 * 60);
+
+/// The most destinations a concatenation reference is kept for, past which the least recently
+/// used is forgotten, so sending to many numbers can't grow them without limit.
+const MAX_CONCATENATION_DESTINATIONS: usize = 10_000;
+
+/// The next concatenation reference for each destination, so overlapping multipart messages to
+/// the same number don't share one and get their parts mixed up when reassembled.
+#[derive(Clone, Default)]
+struct ConcatenationReferences(Arc<std::sync::Mutex<HashMap<String, (u8, Instant)>>>);
+impl ConcatenationReferences {
+    /// Get the reference for a destination's next multipart message, wrapping after 255.
+    /// A destination starts at a random reference, so it's unlikely to repeat after a restart.
+    fn next(&self, destination: &str) -> u8 {
+        self.next_at(destination, Instant::now())
+    }
+
+    fn next_at(&self, destination: &str, now: Instant) -> u8 {
+        let mut references = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if !references.contains_key(destination) {
+            // Idle destinations are only pruned when adding another, as that's all that grows it.
+            references
+                .retain(|_, (_, used)| now.duration_since(*used) < CONCATENATION_REFERENCE_IDLE);
+            if references.len() >= MAX_CONCATENATION_DESTINATIONS {
+                let least_recent = references
+                    .iter()
+                    .min_by_key(|(_, (_, used))| *used)
+                    .map(|(destination, _)| destination.clone());
+                if let Some(least_recent) = least_recent {
+                    references.remove(&least_recent);
+                }
+            }
+        }
+
+        let (next, used) = references
+            .entry(destination.to_string())
+            .or_insert_with(|| (rand::random(), now));
+        let reference = *next;
+        *next = reference.wrapping_add(1);
+        *used = now;
+        reference
+    }
+}
+
+fn create_sms_requests(
+    message: &SMSOutgoingMessage,
+    default_validity_period: u8,
+    references: &ConcatenationReferences,
+) -> Result<Vec<ModemRequest>> {
+    let mut parts = message.encode()?;
+    if parts.len() > 1 {
+        let reference = references.next(&message.phone_number.to_string());
+        set_concatenation_reference(&mut parts, reference);
+    }
+
+    let requests = parts
         .into_iter()
         .map(|data| {
             let pdu = pdu::SubmitPdu {
@@ -36,7 +111,7 @@ fn create_sms_requests(message: &SMSOutgoingMessage) -> Result<Vec<ModemRequest>
                 validity_period: if message.flash {
                     0
                 } else {
-                    message.get_validity_period()
+                    message.get_validity_period(default_validity_period)
                 },
                 user_data: data.bytes,
                 user_data_len: data.user_data_len,
@@ -56,33 +131,186 @@ fn create_sms_requests(message: &SMSOutgoingMessage) -> Result<Vec<ModemRequest>
 #[derive(Clone)]
 pub struct ModemSender {
     command_tx: mpsc::Sender<OutgoingCommand>,
+    priority_command_tx: mpsc::Sender<OutgoingCommand>,
+    control_tx: mpsc::Sender<ModemControl>,
+    reset_lock: Arc<Mutex<()>>,
+    snapshot: SharedStateMachineSnapshot,
+    default_validity_period: u8,
+    references: ConcatenationReferences,
 }
 impl ModemSender {
-    pub fn new(command_tx: mpsc::Sender<OutgoingCommand>) -> Self {
-        Self { command_tx }
+    pub fn new(
+        command_tx: mpsc::Sender<OutgoingCommand>,
+        priority_command_tx: mpsc::Sender<OutgoingCommand>,
+        control_tx: mpsc::Sender<ModemControl>,
+        snapshot: SharedStateMachineSnapshot,
+    ) -> Self {
+        Self {
+            command_tx,
+            priority_command_tx,
+            control_tx,
+            reset_lock: Arc::new(Mutex::new(())),
+            snapshot,
+            default_validity_period: DEFAULT_VALIDITY_PERIOD,
+            references: ConcatenationReferences::default(),
+        }
+    }
+
+    /// Use this validity period for messages that don't request their own.
+    pub fn with_default_validity_period(mut self, validity_period: u8) -> Self {
+        self.default_validity_period = validity_period;
+        self
+    }
+
+    /// How long the service centre keeps trying to deliver a message sent by this modem.
+    pub fn validity(&self, message: &SMSOutgoingMessage) -> Duration {
+        validity_period_to_duration(message.get_validity_period(self.default_validity_period))
+    }
+
+    /// Get the command queue depth and what the worker state machine is doing.
+    pub fn queue_status(&self) -> ModemQueueStatus {
+        let snapshot = self
+            .snapshot
+            .lock()
+            .map(|snapshot| *snapshot)
+            .unwrap_or_default();
+
+        let queue_capacity = self.command_tx.max_capacity();
+        ModemQueueStatus {
+            queue_length: queue_capacity - self.command_tx.capacity(),
+            priority_queue_length: self.priority_command_tx.max_capacity()
+                - self.priority_command_tx.capacity(),
+            queue_capacity,
+            state: snapshot.status,
+            command_sequence: snapshot.command.map(|(sequence, _)| sequence),
+            command_running_ms: snapshot
+                .command
+                .map(|(_, started_at)| started_at.elapsed().as_millis() as u64),
+        }
+    }
+
+    /// Reset and reinitialize the modem, returning its new status.
+    /// Any in-flight command is failed, and only one reset can run at a time.
+    pub async fn reset(&self) -> Result<ModemStatus> {
+        let _guard = self
+            .reset_lock
+            .try_lock()
+            .map_err(|_| anyhow!("A modem reset is already in progress"))?;
+
+        let (tx, rx) = oneshot::channel();
+        self.control_tx
+            .send(ModemControl::Reset(tx))
+            .await
+            .map_err(|_| anyhow!("Modem control channel is closed"))?;
+
+        match tokio::time::timeout(RESET_TIMEOUT, rx).await {
+            Ok(Ok(status)) => Ok(status),
+            Ok(Err(_)) => Err(anyhow!("Modem reset response channel closed")),
+            Err(_) => Err(anyhow!("Timed out waiting for modem reset")),
+        }
+    }
+
+    /// Cancel every queued command that hasn't started yet, returning how many were cancelled.
+    /// The in-flight command is left to complete.
+    pub async fn flush_queue(&self) -> Result<usize> {
+        let (tx, rx) = oneshot::channel();
+        self.control_tx
+            .send(ModemControl::FlushQueue(tx))
+            .await
+            .map_err(|_| anyhow!("Modem control channel is closed"))?;
+
+        match tokio::time::timeout(FLUSH_TIMEOUT, rx).await {
+            Ok(Ok(flushed)) => Ok(flushed),
+            Ok(Err(_)) => Err(anyhow!("Modem flush response channel closed")),
+            Err(_) => Err(anyhow!("Timed out waiting for modem queue flush")),
+        }
     }
 
     /// Send an SMSOutgoingMessage, and get a resulting ModemResponse.
-    /// Returns: Result<(sent_all, Option<last_response>)>
-    pub async fn send_sms(
+    /// The PDU of every part is returned as queued, whether or not it was sent, and
+    /// `on_part_sent` is awaited with each part's reference as soon as the modem sends it.
+    /// Returns: Result<(sent_all, Option<last_response>, pdus)>
+    pub async fn send_sms<F: Future<Output = ()>>(
         &self,
         message: &SMSOutgoingMessage,
-    ) -> Result<(bool, Option<ModemResponse>)> {
+        on_part_sent: impl Fn(u8) -> F,
+    ) -> Result<(bool, Option<ModemResponse>, Vec<SendSmsPdu>)> {
+        // A dry run is encoded exactly like a real send, so it fails for the same reasons.
+        let requests =
+            create_sms_requests(message, self.default_validity_period, &self.references)?;
+        let pdus = requests
+            .iter()
+            .filter_map(|request| match request {
+                ModemRequest::SendSMS { len, pdu } => Some(SendSmsPdu {
+                    pdu: pdu.clone(),
+                    len: *len,
+                }),
+                _ => None,
+            })
+            .collect();
+
+        if message.dry_run {
+            debug!("Dry run, not sending {} SMS part(s)", requests.len());
+            let reference_id = DRY_RUN_REFERENCE.fetch_add(1, Ordering::Relaxed);
+            return Ok((true, Some(ModemResponse::SendResult { reference_id }), pdus));
+        }
+
         // Send each send request for message, returning the last message.
         let mut last_response_opt = None;
-        for request in create_sms_requests(message)? {
-            let response = self.send_request(request, message.timeout).await?;
+        for request in requests {
+            let response = self
+                .queue_request(request, message.timeout, message.priority)
+                .await?;
 
             // If one of the message parts return an error response, then return immediately
             // as there's no use in continuing to send message parts for a broken concatenation.
-            if matches!(response, ModemResponse::Error(_)) {
-                return Ok((false, Some(response)));
+            if matches!(response, ModemResponse::Error { .. }) {
+                return Ok((false, Some(response), pdus));
+            }
+            if let ModemResponse::SendResult { reference_id } = &response {
+                on_part_sent(*reference_id).await;
             }
             last_response_opt.replace(response);
         }
 
         // Sent all requests, last response
-        Ok((true, last_response_opt))
+        Ok((true, last_response_opt, pdus))
+    }
+
+    /// Register with a numeric operator code, or use automatic selection if None.
+    /// A failed manual selection falls back to automatic so the modem isn't left
+    /// unregistered. Returns the resulting network status.
+    pub async fn set_operator(&self, operator: Option<String>) -> Result<ModemResponse> {
+        if let Some(operator) = &operator {
+            if !is_valid_operator_code(operator) {
+                bail!("Operator '{operator}' must be a numeric MCC+MNC code");
+            }
+        }
+
+        let manual = operator.is_some();
+        let request = ModemRequest::SetOperator {
+            mode: if manual { 1 } else { 0 },
+            operator,
+        };
+        if let ModemResponse::Error { message: e, .. } = self.send_request(request, None).await? {
+            if !manual {
+                bail!("Failed to enable automatic operator selection: {e}");
+            }
+
+            warn!("Manual operator selection failed, falling back to automatic: {e}");
+            let request = ModemRequest::SetOperator {
+                mode: 0,
+                operator: None,
+            };
+            if let ModemResponse::Error { message: e, .. } =
+                self.send_request(request, None).await?
+            {
+                bail!("Manual operator selection failed, and automatic fallback failed: {e}");
+            }
+        }
+
+        self.send_request(ModemRequest::GetNetworkStatus, None)
+            .await
     }
 
     /// Send a modem request and get some result.
@@ -90,15 +318,30 @@ impl ModemSender {
         &self,
         request: ModemRequest,
         timeout: Option<u32>,
+    ) -> Result<ModemResponse> {
+        self.queue_request(request, timeout, false).await
+    }
+
+    /// Queue a modem request, priority requests are started before any normal queued requests.
+    async fn queue_request(
+        &self,
+        request: ModemRequest,
+        timeout: Option<u32>,
+        priority: bool,
     ) -> Result<ModemResponse> {
         let sequence = next_command_sequence();
         let (tx, rx) = oneshot::channel();
 
-        debug!("Queuing command sequence {sequence}: {request:?}");
+        debug!("Queuing command sequence {sequence} (priority: {priority}): {request:?}");
         let cmd = OutgoingCommand::new(sequence, tx, request, timeout);
 
         // Try to queue without blocking.
-        match self.command_tx.try_send(cmd) {
+        let command_tx = if priority {
+            &self.priority_command_tx
+        } else {
+            &self.command_tx
+        };
+        match command_tx.try_send(cmd) {
             Ok(_) => debug!("Command sequence {sequence} successfully queued"),
             Err(mpsc::error::TrySendError::Full(_)) => {
                 bail!("Command queue is full! The modem may be overwhelmed")
@@ -132,3 +375,264 @@ impl ModemSender {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{SMSDataCoding, SMSEncoding};
+    use sms_pdu::pdu::PduAddress;
+    use std::str::FromStr;
+
+    fn message(content: &str, encoding: SMSEncoding) -> SMSOutgoingMessage {
+        SMSOutgoingMessage {
+            phone_number: PduAddress::from_str("+441234567890").unwrap(),
+            content: content.to_string(),
+            flash: false,
+            validity_period: None,
+            timeout: None,
+            encoding,
+            data_coding: SMSDataCoding::Text,
+            user_data_header: None,
+            priority: false,
+            modem_id: None,
+            dry_run: false,
+            override_blocklist: false,
+        }
+    }
+
+    /// Get the (DCS, validity period) bytes from the first SendSMS request for a message.
+    fn dcs_and_validity(message: &SMSOutgoingMessage, default_validity_period: u8) -> (u8, u8) {
+        let requests = create_sms_requests(
+            message,
+            default_validity_period,
+            &ConcatenationReferences::default(),
+        )
+        .unwrap();
+        let ModemRequest::SendSMS { pdu, .. } = &requests[0] else {
+            panic!("Expected a SendSMS request");
+        };
+
+        // SCA length, first octet, message ref, 8 address bytes and the protocol ID come first.
+        let bytes = hex::decode(pdu).unwrap();
+        (bytes[12], bytes[13])
+    }
+
+    #[test]
+    fn test_flash_message_pdu() {
+        let normal = message("Your code is 123456", SMSEncoding::Auto);
+        let (normal_dcs, normal_validity) = dcs_and_validity(&normal, DEFAULT_VALIDITY_PERIOD);
+        assert_eq!(normal_dcs, 0x00);
+        assert_ne!(normal_validity, 0);
+
+        // Class 0 with the message class bit set, and the shortest validity period (0, 5 minutes).
+        let flash = SMSOutgoingMessage {
+            flash: true,
+            ..message("Your code is 123456", SMSEncoding::Auto)
+        };
+        let (flash_dcs, flash_validity) = dcs_and_validity(&flash, DEFAULT_VALIDITY_PERIOD);
+        assert_eq!(flash_dcs, 0x10);
+        assert_eq!(flash_validity, 0);
+    }
+
+    #[test]
+    fn test_default_validity_period() {
+        let default = message("Hello", SMSEncoding::Gsm7);
+        assert_eq!(dcs_and_validity(&default, 11).1, 11);
+
+        // A message's own validity period overrides the configured default.
+        let requested = SMSOutgoingMessage {
+            validity_period: Some(173),
+            ..message("Hello", SMSEncoding::Gsm7)
+        };
+        assert_eq!(dcs_and_validity(&requested, 11).1, 173);
+    }
+
+    #[test]
+    fn test_8bit_message_pdu() {
+        // A WAP push header with destination port 2948 and source port 9200.
+        let wap_push = SMSOutgoingMessage {
+            data_coding: SMSDataCoding::EightBit,
+            user_data_header: Some(hex::decode("0605040b8423f0").unwrap()),
+            ..message("01060403ae81ea", SMSEncoding::Auto)
+        };
+        let requests = create_sms_requests(
+            &wap_push,
+            DEFAULT_VALIDITY_PERIOD,
+            &ConcatenationReferences::default(),
+        )
+        .unwrap();
+        assert_eq!(requests.len(), 1);
+        let ModemRequest::SendSMS { pdu, .. } = &requests[0] else {
+            panic!("Expected a SendSMS request");
+        };
+
+        // The UDHI bit is set, and the 8-bit user data is the header followed by the raw data.
+        let bytes = hex::decode(pdu).unwrap();
+        assert_eq!(bytes[1] & 0x40, 0x40);
+        assert_eq!(bytes[12], 0x04);
+        assert_eq!(bytes[14] as usize, bytes.len() - 15);
+        assert_eq!(hex::encode(&bytes[15..]), "0605040b8423f001060403ae81ea");
+    }
+
+    #[test]
+    fn test_concatenation_references() {
+        /// Get the concatenation reference of every part, the first element's data in its UDH.
+        fn part_references(requests: &[ModemRequest]) -> Vec<u8> {
+            requests
+                .iter()
+                .map(|request| match request {
+                    ModemRequest::SendSMS { pdu, .. } => hex::decode(pdu).unwrap()[18],
+                    request => panic!("Unexpected request {request:?}"),
+                })
+                .collect()
+        }
+
+        let references = ConcatenationReferences::default();
+        let long = message(&"a".repeat(200), SMSEncoding::Gsm7);
+        let first = create_sms_requests(&long, DEFAULT_VALIDITY_PERIOD, &references).unwrap();
+        let second = create_sms_requests(&long, DEFAULT_VALIDITY_PERIOD, &references).unwrap();
+
+        // Each part of a message shares a reference, the next message to the number gets another.
+        let first = part_references(&first);
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[0], first[1]);
+        assert_eq!(part_references(&second), [first[0].wrapping_add(1); 2]);
+
+        // Single part messages don't use up a reference.
+        create_sms_requests(
+            &message("Hello", SMSEncoding::Gsm7),
+            DEFAULT_VALIDITY_PERIOD,
+            &references,
+        )
+        .unwrap();
+        assert_eq!(references.next("+441234567890"), first[0].wrapping_add(2));
+
+        // Other numbers have their own counter, which wraps after 255.
+        let other = references.next("+447700900000");
+        for _ in 0..255 {
+            references.next("+447700900000");
+        }
+        assert_eq!(references.next("+447700900000"), other);
+    }
+
+    #[test]
+    fn test_concatenation_references_bounded() {
+        let references = ConcatenationReferences::default();
+        let start = Instant::now();
+        let len = || references.0.lock().unwrap().len();
+
+        // Destinations unused for long enough are forgotten once another is added.
+        references.next_at("+441234567890", start);
+        let later = start + CONCATENATION_REFERENCE_IDLE;
+        references.next_at("+447700900000", later);
+        assert_eq!(len(), 1);
+
+        // Past the limit, the least recently used destination is forgotten.
+        for index in 1..MAX_CONCATENATION_DESTINATIONS {
+            references.next_at(&format!("+44{index:09}"), later);
+        }
+        references.next_at("+447700900000", later + Duration::from_secs(1));
+        assert_eq!(len(), MAX_CONCATENATION_DESTINATIONS);
+        references.next_at("+441234567890", later + Duration::from_secs(1));
+        assert_eq!(len(), MAX_CONCATENATION_DESTINATIONS);
+        assert!(references.0.lock().unwrap().contains_key("+447700900000"));
+    }
+
+    #[test]
+    fn test_dry_run_skips_modem() {
+        let (command_tx, mut command_rx) = mpsc::channel(8);
+        let (priority_command_tx, _priority_command_rx) = mpsc::channel(8);
+        let (control_tx, _control_rx) = mpsc::channel(1);
+        let sender = ModemSender::new(
+            command_tx,
+            priority_command_tx,
+            control_tx,
+            Default::default(),
+        );
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let dry_run = |content: &str, encoding| SMSOutgoingMessage {
+            dry_run: true,
+            ..message(content, encoding)
+        };
+        let (sent_all, response, pdus) = runtime
+            .block_on(sender.send_sms(&dry_run(&"a".repeat(200), SMSEncoding::Gsm7), |_| async {}))
+            .unwrap();
+        assert!(sent_all);
+        assert_eq!(pdus.len(), 2);
+        assert!(matches!(response, Some(ModemResponse::SendResult { .. })));
+        assert!(command_rx.try_recv().is_err());
+
+        // Content that can't be encoded fails the same as a real send.
+        for dry_run_enabled in [true, false] {
+            let message = SMSOutgoingMessage {
+                dry_run: dry_run_enabled,
+                ..message("Caf\u{e9} \u{1F600}", SMSEncoding::Gsm7)
+            };
+            assert!(runtime
+                .block_on(sender.send_sms(&message, |_| async {}))
+                .is_err());
+        }
+        assert!(command_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_send_reports_queued_pdus() {
+        let (command_tx, mut command_rx) = mpsc::channel::<OutgoingCommand>(8);
+        let (priority_command_tx, _priority_command_rx) = mpsc::channel(8);
+        let (control_tx, _control_rx) = mpsc::channel(1);
+        let sender = ModemSender::new(
+            command_tx,
+            priority_command_tx,
+            control_tx,
+            Default::default(),
+        );
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        // Concatenated parts get a random reference, so only the queued requests can be compared.
+        let modem = runtime.spawn(async move {
+            let mut queued = Vec::new();
+            while let Some(mut command) = command_rx.recv().await {
+                queued.push(command.request.clone());
+                let reference_id = queued.len() as u8;
+                command
+                    .respond(ModemResponse::SendResult { reference_id })
+                    .await
+                    .unwrap();
+            }
+            queued
+        });
+
+        // Each part's reference is reported as it's sent.
+        let sent_parts = std::sync::Mutex::new(Vec::new());
+        let multipart = message(&"a".repeat(200), SMSEncoding::Gsm7);
+        let (sent_all, _, pdus) = runtime
+            .block_on(sender.send_sms(&multipart, |reference_id| {
+                sent_parts.lock().unwrap().push(reference_id);
+                async {}
+            }))
+            .unwrap();
+        assert!(sent_all);
+        assert_eq!(*sent_parts.lock().unwrap(), [1, 2]);
+
+        drop(sender);
+        let queued = runtime.block_on(modem).unwrap();
+        assert_eq!(pdus.len(), 2);
+        assert_eq!(
+            pdus,
+            queued
+                .into_iter()
+                .map(|request| match request {
+                    ModemRequest::SendSMS { len, pdu } => SendSmsPdu { pdu, len },
+                    request => panic!("Unexpected request {request:?}"),
+                })
+                .collect::<Vec<_>>()
+        );
+    }
+}
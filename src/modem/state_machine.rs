@@ -1,21 +1,39 @@
 use crate::modem::buffer::LineEvent;
 use crate::modem::commands::{CommandContext, CommandState, OutgoingCommand};
 use crate::modem::handlers::ModemEventHandlers;
+use crate::modem::parsers::{describe_error, parse_cmgs_result, parse_error_code};
 use crate::modem::types::{
-    ModemEvent, ModemIncomingMessage, ModemResponse, UnsolicitedMessageType,
+    ModemEvent, ModemIncomingMessage, ModemRequest, ModemResponse, StateMachineStatus,
+    UnsolicitedMessageType, COMMAND_TIMEOUT_ERROR, LATE_SEND_RESULT_WINDOW,
 };
 use crate::modem::worker::WorkerEvent;
 use anyhow::{bail, Result};
 use std::mem::take;
-use std::time::Instant;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use tracing::log::{debug, error, warn};
 
+/// A snapshot of the state machine, published by the worker after each event.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StateMachineSnapshot {
+    pub status: StateMachineStatus,
+    pub command: Option<(u32, Instant)>,
+}
+
+pub type SharedStateMachineSnapshot = Arc<Mutex<StateMachineSnapshot>>;
+
+/// How long to wait for a `>` prompt before failing, this is usually near instant
+/// but is generous so that a slow modem isn't cancelled while about to prompt.
+const PROMPT_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[derive(Debug)]
 struct CommandExecution {
     context: CommandContext,
     command: OutgoingCommand,
+    started_at: Instant,
     timeout_at: Instant,
+    prompt_timeout_at: Instant,
 }
 impl CommandExecution {
     fn new(command: OutgoingCommand, command_state: CommandState) -> Self {
@@ -31,15 +49,30 @@ impl CommandExecution {
             command.sequence, timeout
         );
 
+        let started_at = Instant::now();
         Self {
             context,
             command,
-            timeout_at: Instant::now() + timeout,
+            started_at,
+            timeout_at: started_at + timeout,
+            prompt_timeout_at: started_at + PROMPT_TIMEOUT.min(timeout),
         }
     }
 
-    fn is_timed_out(&self) -> bool {
-        Instant::now() >= self.timeout_at
+    fn is_waiting_for_prompt(&self) -> bool {
+        matches!(self.context.state, CommandState::WaitingForPrompt)
+    }
+
+    /// Get the timeout error message if the command has timed out.
+    fn timeout_error(&self) -> Option<&'static str> {
+        let now = Instant::now();
+        if self.is_waiting_for_prompt() && now >= self.prompt_timeout_at {
+            Some("Command timed out waiting for prompt!")
+        } else if now >= self.timeout_at {
+            Some(COMMAND_TIMEOUT_ERROR)
+        } else {
+            None
+        }
     }
 }
 
@@ -57,12 +90,21 @@ enum StateMachineState {
 pub struct ModemStateMachine {
     state: StateMachineState,
     handlers: ModemEventHandlers,
+    raw_unsolicited_events: bool,
+
+    /// When a send last timed out after writing its PDU, so a late `+CMGS` can be matched to it.
+    send_timed_out_at: Option<Instant>,
 }
 impl ModemStateMachine {
-    pub fn new(worker_event_tx: mpsc::UnboundedSender<WorkerEvent>) -> Self {
+    pub fn new(
+        worker_event_tx: mpsc::UnboundedSender<WorkerEvent>,
+        raw_unsolicited_events: bool,
+    ) -> Self {
         Self {
             state: StateMachineState::Idle,
             handlers: ModemEventHandlers::new(worker_event_tx),
+            raw_unsolicited_events,
+            send_timed_out_at: None,
         }
     }
 
@@ -70,10 +112,52 @@ impl ModemStateMachine {
         matches!(self.state, StateMachineState::Idle)
     }
 
+    pub fn snapshot(&self) -> StateMachineSnapshot {
+        let (status, execution) = match &self.state {
+            StateMachineState::Idle => (StateMachineStatus::Idle, None),
+            StateMachineState::Command(execution) => (StateMachineStatus::Command, Some(execution)),
+            StateMachineState::UnsolicitedMessage {
+                interrupted_command,
+                ..
+            } => (
+                StateMachineStatus::UnsolicitedMessage,
+                interrupted_command.as_ref(),
+            ),
+        };
+
+        StateMachineSnapshot {
+            status,
+            command: execution.map(|execution| (execution.command.sequence, execution.started_at)),
+        }
+    }
+
     pub fn reset_to_idle(&mut self) {
         self.state = StateMachineState::Idle;
     }
 
+    /// Respond to any in-flight command with an error and return to idle.
+    pub async fn fail_current_command(&mut self, message: &str) {
+        let execution = match take(&mut self.state) {
+            StateMachineState::Command(execution) => Some(execution),
+            StateMachineState::UnsolicitedMessage {
+                interrupted_command,
+                ..
+            } => interrupted_command,
+            StateMachineState::Idle => None,
+        };
+
+        if let Some(mut execution) = execution {
+            warn!("Failing command #{}: {message}", execution.command.sequence);
+            if let Err(e) = execution
+                .command
+                .respond(ModemResponse::error(message))
+                .await
+            {
+                error!("Failed to respond to failed command: {e}");
+            }
+        }
+    }
+
     pub async fn start_command(&mut self, cmd: OutgoingCommand) -> Result<()> {
         debug!("Starting command: {cmd:?}");
 
@@ -90,9 +174,9 @@ impl ModemStateMachine {
             _ => return Ok(false),
         };
 
-        if !execution.is_timed_out() {
+        let Some(message) = execution.timeout_error() else {
             return Ok(false);
-        }
+        };
 
         // Remove the CommandExecution from state to get OutgoingCommand.
         let mut command = match take(&mut self.state) {
@@ -103,11 +187,25 @@ impl ModemStateMachine {
             _ => unreachable!(),
         };
 
-        warn!("Command {} timed out!", command.sequence);
-        command
-            .respond(ModemResponse::Error("Command timed out!".to_string()))
-            .await
-            .map(|_| true)
+        warn!("Command {} timed out! {message}", command.sequence);
+
+        // The modem may still be waiting for PDU input, or be about to send a prompt. Abort the
+        // SMS entry so neither the prompt or a late +CMGS are taken as the next command's response.
+        if matches!(command.request, ModemRequest::SendSMS { .. }) {
+            if let Err(e) = self.handlers.cancel_sms_entry().await {
+                error!("Failed to abort SMS entry for timed out command: {e}");
+            }
+            if message == COMMAND_TIMEOUT_ERROR {
+                self.send_timed_out_at = Some(Instant::now());
+            }
+        }
+
+        // Always report the timeout, so the worker flushes the line buffer even if
+        // the caller has already given up waiting for this response.
+        if let Err(e) = command.respond(ModemResponse::error(message)).await {
+            warn!("Failed to respond to timed out command: {e}");
+        }
+        Ok(true)
     }
 
     pub async fn transition_state(
@@ -217,7 +315,19 @@ impl ModemStateMachine {
                 StateMachineState::Idle,
                 ModemEvent::CommandResponse(content) | ModemEvent::Data(content),
             ) => {
-                warn!("Received unexpected response when idle: {content:?}");
+                if let Some(reference_id) = self.late_send_result(&content) {
+                    warn!("Received +CMGS with reference {reference_id} after the send timed out");
+                    let _ = main_tx.send(ModemIncomingMessage::LateSendResult(reference_id));
+                    return Ok(StateMachineState::Idle);
+                }
+
+                // Only lines outside a command are forwarded, so command responses are never affected.
+                if self.raw_unsolicited_events {
+                    debug!("Forwarding raw unsolicited line: {content:?}");
+                    let _ = main_tx.send(ModemIncomingMessage::RawUnsolicited(content));
+                } else {
+                    warn!("Received unexpected response when idle: {content:?}");
+                }
                 Ok(StateMachineState::Idle)
             }
             (read_state, modem_event) => {
@@ -231,6 +341,19 @@ impl ModemStateMachine {
         }
     }
 
+    /// Get the reference from a `+CMGS` received while idle, if a send recently timed out.
+    /// Each timeout is only matched once, so repeated lines can't update another message.
+    fn late_send_result(&mut self, content: &str) -> Option<u8> {
+        if !content.starts_with("+CMGS:") {
+            return None;
+        }
+        let timed_out_at = self.send_timed_out_at.take()?;
+        if timed_out_at.elapsed() > LATE_SEND_RESULT_WINDOW {
+            return None;
+        }
+        parse_cmgs_result(content).ok()
+    }
+
     async fn process_command(
         &mut self,
         mut execution: CommandExecution,
@@ -252,8 +375,8 @@ impl ModemStateMachine {
                     Ok(None) => {
                         execution
                             .command
-                            .respond(ModemResponse::Error(
-                                "Command completed during prompt handling".to_string(),
+                            .respond(ModemResponse::error(
+                                "Command completed during prompt handling",
                             ))
                             .await?;
                         Ok(StateMachineState::Idle)
@@ -262,7 +385,7 @@ impl ModemStateMachine {
                         error!("Prompt handler error: {e}");
                         execution
                             .command
-                            .respond(ModemResponse::Error(format!("Prompt handler error: {e}")))
+                            .respond(ModemResponse::error(format!("Prompt handler error: {e}")))
                             .await?;
                         Ok(StateMachineState::Idle)
                     }
@@ -288,9 +411,12 @@ impl ModemStateMachine {
                             Ok(StateMachineState::Idle)
                         }
                         Err(e) => {
+                            let buffer = &execution.context.response_buffer;
+                            let code = parse_error_code(buffer);
+                            let message = describe_error(buffer).unwrap_or_else(|| e.to_string());
                             execution
                                 .command
-                                .respond(ModemResponse::Error(e.to_string()))
+                                .respond(ModemResponse::Error { code, message })
                                 .await?;
                             Ok(StateMachineState::Idle)
                         }
@@ -307,12 +433,13 @@ impl ModemStateMachine {
         }
     }
 
-    async fn handle_unsolicited(
+    /// Decode an unsolicited message and pass it on, returning false if it couldn't be handled.
+    pub async fn handle_unsolicited(
         &self,
         main_tx: &mpsc::UnboundedSender<ModemIncomingMessage>,
         message_type: &UnsolicitedMessageType,
         content: &str,
-    ) {
+    ) -> bool {
         match self
             .handlers
             .handle_unsolicited_message(message_type, content)
@@ -322,20 +449,35 @@ impl ModemStateMachine {
                 if let Some(message) = message {
                     let _ = main_tx.send(message);
                 }
+                true
+            }
+            Err(e) => {
+                error!("Couldn't handle incoming SMS message with error: {e:?}");
+                false
             }
-            Err(e) => error!("Couldn't handle incoming SMS message with error: {e:?}"),
         }
     }
 
+    fn is_querying_registration(&self) -> bool {
+        matches!(
+            &self.state,
+            StateMachineState::Command(execution)
+                if matches!(execution.command.request, ModemRequest::GetNetworkStatus)
+        )
+    }
+
     fn classify_line(&self, content: &str) -> ModemEvent {
         let trimmed = content.trim();
 
-        // Prioritise unsolicited messages regardless of current state.
+        // Prioritise unsolicited messages regardless of current state, except the reply to an
+        // AT+CREG? query which has the same prefix as an unsolicited registration change.
         if let Some(message_type) = UnsolicitedMessageType::from_header(trimmed) {
-            return ModemEvent::UnsolicitedMessage {
-                message_type,
-                header: trimmed.to_string(),
-            };
+            if !(trimmed.starts_with("+CREG:") && self.is_querying_registration()) {
+                return ModemEvent::UnsolicitedMessage {
+                    message_type,
+                    header: trimmed.to_string(),
+                };
+            }
         }
 
         // Command completion indicators - only relevant when executing commands.
@@ -1,10 +1,13 @@
 #![cfg_attr(not(feature = "http-server"), allow(dead_code))]
 
 use crate::sms::types::{SMSIncomingDeliveryReport, SMSIncomingMessage};
-use anyhow::{anyhow, bail};
 use serde::{Deserialize, Serialize};
-use std::fmt::{Display, Formatter};
 use std::time::Duration;
+use tokio::sync::oneshot;
+
+pub use sms_api_types::modem::{
+    GNSSFixStatus, GNSSLocation, ModemResponse, ModemStatus, NetworkEvent, RegistrationDomain,
+};
 
 #[derive(Debug, Clone)]
 pub enum ModemRequest {
@@ -14,6 +17,21 @@ pub enum ModemRequest {
     GetNetworkOperator,
     GetServiceProvider,
     GetBatteryLevel,
+    GetServiceCentre,
+    GetFunctionality,
+    SetFunctionality { level: u8 },
+    GetSmsParameters,
+    SetOperator { mode: u8, operator: Option<String> },
+    GetPreferredMode,
+    SetPreferredMode { mode: u8 },
+    SetBand { network: String, bands: Vec<u16> },
+    ReadSMS { storage: Option<String>, index: u32 },
+    DeleteSMS { storage: Option<String>, index: u32 },
+    GetClock,
+    HangUp,
+
+    // A bare AT sent while idle, to check the modem is still responding.
+    Heartbeat,
 
     // These only work if GNSS is enabled in modem config.
     GetGNSSStatus,
@@ -21,79 +39,74 @@ pub enum ModemRequest {
 }
 impl ModemRequest {
     const TIMEOUT_SMS: Duration = Duration::from_secs(30);
+    const TIMEOUT_FUNCTIONALITY: Duration = Duration::from_secs(15);
+
+    /// Manual operator selection has to search for the network, so can take much longer.
+    pub(crate) const TIMEOUT_OPERATOR: Duration = Duration::from_secs(60);
+
     const TIMEOUT_DEFAULT: Duration = Duration::from_secs(5);
 
     pub const fn get_default_timeout(&self) -> Duration {
         match self {
             ModemRequest::SendSMS { .. } => Self::TIMEOUT_SMS,
+            ModemRequest::SetFunctionality { .. } => Self::TIMEOUT_FUNCTIONALITY,
+            ModemRequest::SetOperator { .. } => Self::TIMEOUT_OPERATOR,
             _ => Self::TIMEOUT_DEFAULT,
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(tag = "type", content = "data")]
-pub enum ModemResponse {
-    SendResult(u8),
-    NetworkStatus {
-        registration: u8,
-        technology: u8,
-    },
-    SignalStrength {
-        rssi: i32,
-        ber: i32,
-    },
-    NetworkOperator {
-        status: u8,
-        format: u8,
-        operator: String,
-    },
-    ServiceProvider(String),
-    BatteryLevel {
-        status: u8,
-        charge: u8,
-        voltage: f32,
-    },
-    GNSSStatus(GNSSFixStatus),
-    GNSSLocation(GNSSLocation),
-    Error(String),
+/// Preferred radio access technology, set with AT+CNMP on modems that support it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreferredRat {
+    Auto,
+    Gsm,
+    Lte,
+    GsmLte,
 }
-impl Display for ModemResponse {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+impl PreferredRat {
+    pub const fn cnmp_mode(self) -> u8 {
         match self {
-            ModemResponse::SendResult(reference_id) => write!(f, "SMSResult: Ref {reference_id}"),
-            ModemResponse::NetworkStatus {
-                registration,
-                technology,
-            } => write!(f, "NetworkStatus: Reg: {registration}, Tech: {technology}"),
-            ModemResponse::SignalStrength { rssi, ber } => {
-                write!(f, "SignalStrength: {rssi} dBm ({ber})")
-            }
-            ModemResponse::NetworkOperator { operator, .. } => {
-                write!(f, "NetworkOperator: {operator}")
-            }
-            ModemResponse::ServiceProvider(operator) => write!(f, "ServiceProvider: {operator}"),
-            ModemResponse::BatteryLevel {
-                status,
-                charge,
-                voltage,
-            } => write!(
-                f,
-                "BatteryLevel. Status: {status}, Charge: {charge}, Voltage: {voltage}"
-            ),
-            ModemResponse::GNSSStatus(status) => write!(f, "GNSS-Status: {status:?}"),
-            ModemResponse::GNSSLocation(location) => write!(f, "GNSS-Location: {location:?}"),
-            ModemResponse::Error(message) => write!(f, "Error: {message}"),
+            PreferredRat::Auto => 2,
+            PreferredRat::Gsm => 13,
+            PreferredRat::Lte => 38,
+            PreferredRat::GsmLte => 51,
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub enum ModemStatus {
-    Startup,
-    Online,
-    ShuttingDown,
-    Offline,
+/// The worker state machine state, published for introspection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateMachineStatus {
+    #[default]
+    Idle,
+    Command,
+    UnsolicitedMessage,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModemQueueStatus {
+    pub queue_length: usize,
+    pub priority_queue_length: usize,
+    pub queue_capacity: usize,
+    pub state: StateMachineStatus,
+
+    /// The in-flight command (including one interrupted by an unsolicited message).
+    pub command_sequence: Option<u32>,
+    pub command_running_ms: Option<u64>,
+}
+
+/// Requests handled by the worker itself rather than sent to the modem, so they're
+/// taken ahead of any queued commands.
+#[derive(Debug)]
+pub enum ModemControl {
+    /// Reset and reinitialize the modem, responding with its new status.
+    Reset(oneshot::Sender<ModemStatus>),
+
+    /// Cancel every queued command that hasn't started, responding with how many were cancelled.
+    FlushQueue(oneshot::Sender<usize>),
 }
 
 #[derive(Debug)]
@@ -107,26 +120,40 @@ pub enum ModemEvent {
     Prompt(String),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum UnsolicitedMessageType {
     IncomingSMS,
+
+    /// A message (+CMTI) or status report (+CDSI) was stored instead of routed directly.
+    StoredSMS,
     DeliveryReport,
     NetworkStatusChange,
     ShuttingDown,
     GNSSPositionReport,
+    IncomingCall,
+    CallerIdentification,
+    CallEnded,
 }
 impl UnsolicitedMessageType {
     pub fn from_header(header: &str) -> Option<Self> {
-        if header.starts_with("+CMT") {
+        if header.starts_with("+CMTI:") || header.starts_with("+CDSI:") {
+            Some(UnsolicitedMessageType::StoredSMS)
+        } else if header.starts_with("+CMT") {
             Some(UnsolicitedMessageType::IncomingSMS)
         } else if header.starts_with("+CDS") {
             Some(UnsolicitedMessageType::DeliveryReport)
-        } else if header.starts_with("+CGREG:") {
+        } else if header.starts_with("+CREG:") || header.starts_with("+CGREG:") {
             Some(UnsolicitedMessageType::NetworkStatusChange)
         } else if header.starts_with("+UGNSINF") {
             Some(UnsolicitedMessageType::GNSSPositionReport)
+        } else if header.starts_with("+CLIP:") {
+            Some(UnsolicitedMessageType::CallerIdentification)
+        } else if header.starts_with("MISSED_CALL") {
+            Some(UnsolicitedMessageType::CallEnded)
         } else {
             match header {
+                "RING" => Some(UnsolicitedMessageType::IncomingCall),
+                "NO CARRIER" => Some(UnsolicitedMessageType::CallEnded),
                 "NORMAL POWER DOWN" | "POWER DOWN" | "SHUTDOWN" | "POWERING DOWN" => {
                     Some(UnsolicitedMessageType::ShuttingDown)
                 }
@@ -136,15 +163,28 @@ impl UnsolicitedMessageType {
     }
 
     /// Check if the notification contains additional data on a new line.
+    #[allow(clippy::match_like_matches_macro)]
     pub fn has_next_line(&self) -> bool {
         match self {
+            UnsolicitedMessageType::StoredSMS => false,
+            UnsolicitedMessageType::NetworkStatusChange => false,
             UnsolicitedMessageType::ShuttingDown => false,
             UnsolicitedMessageType::GNSSPositionReport => false,
+            UnsolicitedMessageType::IncomingCall => false,
+            UnsolicitedMessageType::CallerIdentification => false,
+            UnsolicitedMessageType::CallEnded => false,
             _ => true,
         }
     }
 }
 
+/// The error a send that timed out after its PDU was written is failed with. Unlike a prompt
+/// timeout, the modem may still have submitted the message.
+pub const COMMAND_TIMEOUT_ERROR: &str = "Command timed out!";
+
+/// How long after a send times out that a stray `+CMGS` is taken as its late result.
+pub const LATE_SEND_RESULT_WINDOW: Duration = Duration::from_secs(60);
+
 #[derive(Debug, Clone)]
 pub enum ModemIncomingMessage {
     IncomingSMS(SMSIncomingMessage),
@@ -152,107 +192,21 @@ pub enum ModemIncomingMessage {
     ModemStatusUpdate {
         previous: ModemStatus,
         current: ModemStatus,
+        reconnect_backoff_secs: Option<u64>,
+    },
+    NetworkStatusChange {
+        domain: RegistrationDomain,
+        registration: u8,
     },
-    NetworkStatusChange(u8),
     GNSSPositionReport(GNSSLocation),
-}
+    /// A RING, repeated for as long as the call is ringing.
+    Ring,
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum GNSSFixStatus {
-    Unknown,
-    NotFix,
-    Fix2D,
-    Fix3D,
-}
-impl TryFrom<&str> for GNSSFixStatus {
-    type Error = anyhow::Error;
-
-    fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value.trim() {
-            "Location Unknown" | "Unknown" => Ok(GNSSFixStatus::Unknown),
-            "Location Not Fix" | "Not Fix" => Ok(GNSSFixStatus::NotFix),
-            "Location 2D Fix" | "2D Fix" => Ok(GNSSFixStatus::Fix2D),
-            "Location 3D Fix" | "3D Fix" => Ok(GNSSFixStatus::Fix3D),
-            _ => Err(anyhow!("Invalid GNSS fix status: '{}'", value)),
-        }
-    }
-}
-impl From<u8> for GNSSFixStatus {
-    fn from(value: u8) -> Self {
-        match value {
-            0 => GNSSFixStatus::NotFix,
-            1 => GNSSFixStatus::Fix2D,
-            2 => GNSSFixStatus::Fix3D,
-            _ => GNSSFixStatus::Unknown,
-        }
-    }
-}
+    /// The caller from a +CLIP line, which follows each RING if enabled.
+    IncomingCall(Option<String>),
+    CallEnded,
+    RawUnsolicited(String),
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GNSSLocation {
-    run_status: bool,
-    fix_status: bool,
-    utc_time: String,
-    latitude: Option<f64>,
-    longitude: Option<f64>,
-    msl_altitude: Option<f64>,
-    ground_speed: Option<f32>,
-    ground_course: Option<f32>,
-    fix_mode: GNSSFixStatus,
-    hdop: Option<f32>,
-    pdop: Option<f32>,
-    vdop: Option<f32>,
-    gps_in_view: Option<u8>,
-    gnss_used: Option<u8>,
-    glonass_in_view: Option<u8>,
-}
-impl TryFrom<Vec<&str>> for GNSSLocation {
-    type Error = anyhow::Error;
-
-    fn try_from(fields: Vec<&str>) -> Result<Self, Self::Error> {
-        if fields.len() < 15 {
-            bail!("Insufficient GNSS data fields got {}", fields.len());
-        }
-
-        // Based on: https://simcom.ee/documents/SIM868/SIM868_GNSS_Application%20Note_V1.00.pdf (2.3)
-        Ok(Self {
-            run_status: fields[0] == "1",
-            fix_status: fields[1] == "1",
-            utc_time: fields[2].to_string(),
-            latitude: fields[3].parse().ok(),
-            longitude: fields[4].parse().ok(),
-            msl_altitude: fields[5].parse().ok(),
-            ground_speed: fields[6].parse().ok(),
-            ground_course: fields[7].parse().ok(),
-            fix_mode: GNSSFixStatus::from(fields[8].parse::<u8>().unwrap_or(0)),
-            // Reserved1
-            hdop: fields[10].parse().ok(),
-            pdop: fields[11].parse().ok(),
-            vdop: fields[12].parse().ok(),
-            // Reserved2
-            gps_in_view: fields[14].parse().ok(),
-            gnss_used: fields[15].parse().ok(),
-            glonass_in_view: fields[16].parse().ok(),
-        })
-    }
-}
-impl Display for GNSSLocation {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        fn convert_opt<T: Display>(opt: &Option<T>) -> String {
-            match opt {
-                Some(value) => value.to_string(),
-                None => "None".to_string(),
-            }
-        }
-
-        write!(
-            f,
-            "Lat: {}, Lon: {}, Alt: {}, Speed: {}, Course: {}",
-            convert_opt(&self.latitude),
-            convert_opt(&self.longitude),
-            convert_opt(&self.msl_altitude),
-            convert_opt(&self.ground_speed),
-            convert_opt(&self.ground_course)
-        )
-    }
+    /// The reference of a send that timed out, reported by the modem afterwards.
+    LateSendResult(u8),
 }
@@ -1,43 +1,85 @@
 use crate::config::ModemConfig;
 use crate::modem::buffer::LineBuffer;
-use crate::modem::commands::OutgoingCommand;
-use crate::modem::state_machine::ModemStateMachine;
-use crate::modem::types::{ModemIncomingMessage, ModemResponse, ModemStatus};
+use crate::modem::commands::{next_command_sequence, OutgoingCommand};
+use crate::modem::parsers::describe_response;
+use crate::modem::port::{closed_port, SerialPortOpener};
+use crate::modem::state_machine::{ModemStateMachine, SharedStateMachineSnapshot};
+use crate::modem::types::{
+    ModemControl, ModemIncomingMessage, ModemRequest, ModemResponse, ModemStatus,
+    RegistrationDomain, UnsolicitedMessageType, COMMAND_TIMEOUT_ERROR,
+};
 use anyhow::{anyhow, Result};
+use std::future::Future;
 use std::time::Duration;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::sync::mpsc;
-use tokio::time::interval;
-use tokio_serial::SerialStream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{interval, Instant, MissedTickBehavior};
 use tracing::log::{debug, error, info, warn};
 
+/// The delay before the first reconnection attempt, doubled after each failure up to the max.
+const RECONNECT_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(60);
+
 macro_rules! init_cmd {
     ($cmd:expr, $resp:expr) => {
         ($cmd.as_bytes().to_vec(), $resp.as_bytes().to_vec())
     };
 }
 
+/// The connection to the modem, usually a serial port but tests use an in-memory stream.
+pub trait ModemTransport: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ModemTransport for T {}
+
 #[derive(Debug)]
 pub enum WorkerEvent {
     SetStatus(ModemStatus),
     WriteCommand(Vec<u8>),
+
+    /// Read a message or status report from storage after a +CMTI or +CDSI indication.
+    ReadStoredSMS {
+        storage: Option<String>,
+        index: u32,
+        message_type: UnsolicitedMessageType,
+    },
+
+    /// A PDU read from storage, to be decoded as `message_type` then optionally deleted.
+    StoredSMS {
+        storage: Option<String>,
+        index: u32,
+        message_type: UnsolicitedMessageType,
+        pdu: String,
+    },
+
+    /// The result of an idle heartbeat, false if the modem didn't respond with OK in time.
+    Heartbeat {
+        responded: bool,
+    },
 }
 
 pub struct ModemWorker {
-    port: SerialStream,
+    port: Box<dyn ModemTransport>,
+    port_opener: Option<SerialPortOpener>,
     status: ModemStatus,
     state_machine: ModemStateMachine,
     main_tx: mpsc::UnboundedSender<ModemIncomingMessage>,
+    command_tx: mpsc::Sender<OutgoingCommand>,
+    shared_snapshot: SharedStateMachineSnapshot,
+    worker_event_tx: mpsc::UnboundedSender<WorkerEvent>,
     worker_event_rx: mpsc::UnboundedReceiver<WorkerEvent>,
     config: ModemConfig,
+    heartbeat_failures: u32,
+    reconnect_backoff: Duration,
+    reconnect_at: Instant,
 
     #[cfg(feature = "gpio")]
     power_pin: Option<rppal::gpio::OutputPin>,
 }
 impl ModemWorker {
     pub fn new(
-        port: SerialStream,
+        port: Box<dyn ModemTransport>,
         main_tx: mpsc::UnboundedSender<ModemIncomingMessage>,
+        command_tx: mpsc::Sender<OutgoingCommand>,
+        shared_snapshot: SharedStateMachineSnapshot,
         config: ModemConfig,
     ) -> Result<Self> {
         let (worker_event_tx, worker_event_rx) = mpsc::unbounded_channel();
@@ -56,24 +98,42 @@ impl ModemWorker {
 
         Ok(Self {
             port,
+            port_opener: None,
             status: ModemStatus::Startup,
-            state_machine: ModemStateMachine::new(worker_event_tx),
+            state_machine: ModemStateMachine::new(
+                worker_event_tx.clone(),
+                config.raw_unsolicited_events,
+            ),
             main_tx,
+            command_tx,
+            shared_snapshot,
+            worker_event_tx,
             worker_event_rx,
             config,
+            heartbeat_failures: 0,
+            reconnect_backoff: RECONNECT_BACKOFF_MIN,
+            reconnect_at: Instant::now(),
 
             #[cfg(feature = "gpio")]
             power_pin,
         })
     }
 
+    /// Reopen the port with this before each reconnection attempt, rather than reusing the old handle.
+    pub fn with_port_opener(mut self, port_opener: SerialPortOpener) -> Self {
+        self.port_opener = Some(port_opener);
+        self
+    }
+
     pub async fn initialize_and_run(
         mut self,
         command_rx: mpsc::Receiver<OutgoingCommand>,
+        priority_command_rx: mpsc::Receiver<OutgoingCommand>,
+        control_rx: mpsc::Receiver<ModemControl>,
     ) -> Result<()> {
         // Test the initial connection, toggling GPIO power pin if it fails.
         // This should ensure the hat is always powered on just before initialization.
-        match self.test_connection().await {
+        match self.connect().await {
             Ok(_) => info!("Modem is already online for initial connection test! This could be the result of a service restart"),
             Err(_) => {
 
@@ -92,27 +152,41 @@ impl ModemWorker {
                 self.set_status(ModemStatus::Offline);
             }
         }
-        self.run(command_rx).await
+        self.run(command_rx, priority_command_rx, control_rx).await
     }
 
     pub async fn write(&mut self, data: &[u8]) -> Result<()> {
-        if self.status != ModemStatus::Online {
+        if !matches!(self.status, ModemStatus::Online | ModemStatus::RadioOff) {
             return Err(anyhow!("Modem is offline"));
         }
         self.port.write_all(data).await.map_err(|e| anyhow!(e))
     }
 
-    pub async fn run(mut self, mut command_rx: mpsc::Receiver<OutgoingCommand>) -> Result<()> {
+    pub async fn run(
+        mut self,
+        mut command_rx: mpsc::Receiver<OutgoingCommand>,
+        mut priority_command_rx: mpsc::Receiver<OutgoingCommand>,
+        mut control_rx: mpsc::Receiver<ModemControl>,
+    ) -> Result<()> {
         let mut line_buffer = LineBuffer::with_max_size(self.config.line_buffer_size);
 
         let mut timeout_interval = interval(Duration::from_secs(1));
-        let mut reconnect_interval = interval(Duration::from_secs(30));
+
+        // The heartbeat is pushed back by every command, so it only runs once the modem has been idle.
+        let heartbeat_enabled = self.config.heartbeat_interval_secs > 0;
+        let heartbeat_period = Duration::from_secs(self.config.heartbeat_interval_secs.max(1));
+        let mut heartbeat_interval =
+            tokio::time::interval_at(Instant::now() + heartbeat_period, heartbeat_period);
+        heartbeat_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
         debug!("Starting ModemWorker status loop");
         let mut read_buffer = vec![0u8; self.config.read_buffer_size];
         loop {
+            self.publish_snapshot();
             match self.status {
-                ModemStatus::Online => {
+                // The serial connection is still usable with the radio off, so commands
+                // (such as turning it back on) are accepted the same as when online.
+                ModemStatus::Online | ModemStatus::RadioOff => {
                     tokio::select! {
                         biased;
 
@@ -123,9 +197,24 @@ impl ModemWorker {
                             }
                         },
 
+                        // Control requests take priority over commands, as the current one may be stuck
+                        Some(control) = control_rx.recv() => {
+                            self.handle_control(control, &mut line_buffer, &mut command_rx, &mut priority_command_rx).await;
+                        },
+
+                        // Priority commands jump the queue, but never interrupt an in-flight command
+                        Some(cmd) = priority_command_rx.recv(), if self.state_machine.can_accept_command() => {
+                            debug!("Received new priority command sequence {}: {:?}", cmd.sequence, cmd.request);
+                            heartbeat_interval.reset();
+                            if let Err(e) = self.state_machine.start_command(cmd).await {
+                                error!("Failed to start priority command: {e}");
+                            }
+                        },
+
                         // Accept commands when online and state machine is ready
                         Some(cmd) = command_rx.recv(), if self.state_machine.can_accept_command() => {
                             debug!("Received new command sequence {}: {:?}", cmd.sequence, cmd.request);
+                            heartbeat_interval.reset();
                             if let Err(e) = self.state_machine.start_command(cmd).await {
                                 error!("Failed to start command: {e}");
                             }
@@ -163,6 +252,17 @@ impl ModemWorker {
                             if timed_out {
                                 line_buffer.clear();
                             }
+                        },
+
+                        // Heartbeat only when nothing is running or queued, so it never delays or
+                        // competes with a real command and a busy modem isn't counted as unresponsive
+                        _ = heartbeat_interval.tick(), if heartbeat_enabled => {
+                            if self.state_machine.can_accept_command()
+                                && command_rx.is_empty()
+                                && priority_command_rx.is_empty()
+                            {
+                                self.start_heartbeat().await;
+                            }
                         }
                     }
                 }
@@ -175,9 +275,12 @@ impl ModemWorker {
                     }
 
                     // Reject any pending commands
-                    while let Ok(mut cmd) = command_rx.try_recv() {
+                    while let Ok(mut cmd) = priority_command_rx
+                        .try_recv()
+                        .or_else(|_| command_rx.try_recv())
+                    {
                         let _ = cmd
-                            .respond(ModemResponse::Error("Modem is shutting down".to_string()))
+                            .respond(ModemResponse::error("Modem is shutting down"))
                             .await;
                     }
 
@@ -196,22 +299,32 @@ impl ModemWorker {
                             }
                         },
 
+                        // Resetting while offline attempts to initialize immediately
+                        Some(control) = control_rx.recv() => {
+                            self.handle_control(control, &mut line_buffer, &mut command_rx, &mut priority_command_rx).await;
+                        },
+
                         // Reject commands immediately when offline
+                        Some(mut cmd) = priority_command_rx.recv() => {
+                            let _ = cmd.respond(ModemResponse::error("Modem is offline")).await;
+                        },
                         Some(mut cmd) = command_rx.recv() => {
-                            let _ = cmd.respond(ModemResponse::Error("Modem is offline".to_string())).await;
+                            let _ = cmd.respond(ModemResponse::error("Modem is offline")).await;
                         },
 
-                        // Attempt reconnection
-                        _ = reconnect_interval.tick() => {
+                        // Attempt reconnection, backing off after each failure
+                        _ = tokio::time::sleep_until(self.reconnect_at) => {
                             match self.try_reconnect().await {
                                 Ok(true) => {
                                     info!("Successfully reconnected to modem");
                                     self.state_machine.reset_to_idle();
                                     line_buffer.clear();
+                                    heartbeat_interval.reset();
                                 },
-                                Ok(false) => { },
+                                Ok(false) => self.back_off_reconnect(),
                                 Err(e) => {
                                     error!("Error during reconnection attempt: {e}");
+                                    self.back_off_reconnect();
                                 }
                             }
                         }
@@ -222,6 +335,80 @@ impl ModemWorker {
         }
     }
 
+    /// Publish the state machine state for the queue introspection endpoint.
+    fn publish_snapshot(&self) {
+        if let Ok(mut snapshot) = self.shared_snapshot.lock() {
+            *snapshot = self.state_machine.snapshot();
+        }
+    }
+
+    async fn handle_control(
+        &mut self,
+        control: ModemControl,
+        line_buffer: &mut LineBuffer,
+        command_rx: &mut mpsc::Receiver<OutgoingCommand>,
+        priority_command_rx: &mut mpsc::Receiver<OutgoingCommand>,
+    ) {
+        match control {
+            ModemControl::Reset(response_tx) => self.reset(line_buffer, response_tx).await,
+            ModemControl::FlushQueue(response_tx) => {
+                let flushed = Self::flush_queue(command_rx, priority_command_rx).await;
+                warn!("Flushed {flushed} queued command(s)");
+                let _ = response_tx.send(flushed);
+            }
+        }
+    }
+
+    /// Cancel every queued command, leaving any in-flight one (held by the state machine) to complete.
+    async fn flush_queue(
+        command_rx: &mut mpsc::Receiver<OutgoingCommand>,
+        priority_command_rx: &mut mpsc::Receiver<OutgoingCommand>,
+    ) -> usize {
+        let mut flushed = 0;
+        while let Ok(mut cmd) = priority_command_rx
+            .try_recv()
+            .or_else(|_| command_rx.try_recv())
+        {
+            let _ = cmd
+                .respond(ModemResponse::error("Command cancelled by queue flush"))
+                .await;
+            flushed += 1;
+        }
+        flushed
+    }
+
+    async fn reset(
+        &mut self,
+        line_buffer: &mut LineBuffer,
+        response_tx: oneshot::Sender<ModemStatus>,
+    ) {
+        warn!("Resetting modem");
+        self.state_machine
+            .fail_current_command("Command cancelled by modem reset")
+            .await;
+
+        // Abort any pending SMS entry first, otherwise ATZ would be taken as PDU input.
+        if let Err(e) = self.port.write_all(b"\x1b").await {
+            warn!("Failed to abort pending SMS entry before reset: {e}");
+        }
+        line_buffer.clear();
+
+        // Initialization starts with ATZ, so this resets the modem too.
+        let status = match self.initialize_modem().await {
+            Ok(()) => {
+                info!("Modem reset and reinitialized successfully");
+                ModemStatus::Online
+            }
+            Err(e) => {
+                error!("Failed to reinitialize modem after reset: {e}");
+                ModemStatus::Offline
+            }
+        };
+
+        self.set_status(status.clone());
+        let _ = response_tx.send(status);
+    }
+
     async fn handle_worker_event(&mut self, event: WorkerEvent) -> Result<()> {
         match event {
             WorkerEvent::SetStatus(status) => self.set_status(status),
@@ -231,10 +418,166 @@ impl ModemWorker {
                     self.set_status(ModemStatus::Offline);
                 }
             }
+            WorkerEvent::ReadStoredSMS {
+                storage,
+                index,
+                message_type,
+            } => self.read_stored_sms(storage, index, message_type),
+            WorkerEvent::StoredSMS {
+                storage,
+                index,
+                message_type,
+                pdu,
+            } => {
+                // Decoded the same as a directly routed message, and only removed from storage
+                // once that succeeds so a message that fails to decode can still be recovered.
+                let handled = self
+                    .state_machine
+                    .handle_unsolicited(&self.main_tx, &message_type, &pdu)
+                    .await;
+
+                if !handled {
+                    warn!("Keeping stored message at index {index} as it couldn't be decoded");
+                } else if self.config.delete_stored_messages {
+                    self.delete_stored_sms(storage, index);
+                }
+            }
+            WorkerEvent::Heartbeat { responded } => self.handle_heartbeat(responded).await,
         }
         Ok(())
     }
 
+    /// Send a heartbeat `AT` directly, bypassing the queue as the state machine is idle.
+    async fn start_heartbeat(&mut self) {
+        let (response_tx, response_rx) = oneshot::channel();
+        let command = OutgoingCommand::new(
+            next_command_sequence(),
+            response_tx,
+            ModemRequest::Heartbeat,
+            None,
+        );
+        if let Err(e) = self.state_machine.start_command(command).await {
+            error!("Failed to start heartbeat: {e}");
+            return;
+        }
+
+        let worker_event_tx = self.worker_event_tx.clone();
+        tokio::spawn(async move {
+            // An ERROR reply still shows the modem is responding, only a timeout counts.
+            let responded = match response_rx.await {
+                Ok(ModemResponse::Error { message, .. }) => message != COMMAND_TIMEOUT_ERROR,
+                Ok(_) => true,
+                Err(_) => false,
+            };
+            let _ = worker_event_tx.send(WorkerEvent::Heartbeat { responded });
+        });
+    }
+
+    /// Count consecutive failed heartbeats, going offline to reconnect once there are too many.
+    async fn handle_heartbeat(&mut self, responded: bool) {
+        if responded {
+            if self.heartbeat_failures > 0 {
+                info!("Modem responded to heartbeat again");
+            }
+            self.heartbeat_failures = 0;
+            return;
+        }
+
+        self.heartbeat_failures += 1;
+        let limit = self.config.heartbeat_failures_before_offline.max(1);
+        warn!(
+            "Modem didn't respond to heartbeat ({}/{limit})",
+            self.heartbeat_failures
+        );
+        if self.heartbeat_failures >= limit {
+            error!("Modem stopped responding to heartbeats, going offline to reconnect");
+            self.heartbeat_failures = 0;
+            self.state_machine
+                .fail_current_command("Modem stopped responding")
+                .await;
+            self.set_status(ModemStatus::Offline);
+        }
+    }
+
+    /// Queue a command behind any pending commands, returning the response receiver.
+    fn queue_command(&self, request: ModemRequest) -> Option<oneshot::Receiver<ModemResponse>> {
+        let (response_tx, response_rx) = oneshot::channel();
+        let cmd = OutgoingCommand::new(next_command_sequence(), response_tx, request, None);
+        match self.command_tx.try_send(cmd) {
+            Ok(()) => Some(response_rx),
+            Err(e) => {
+                warn!("Failed to queue internal command: {e}");
+                None
+            }
+        }
+    }
+
+    /// Queue a command from a spawned task, waiting for space in the queue instead of dropping
+    /// the command when it's full. Used where a dropped command would lose an incoming message.
+    fn send_command(
+        &self,
+        request: ModemRequest,
+    ) -> impl Future<Output = Result<ModemResponse>> + Send + 'static {
+        let command_tx = self.command_tx.clone();
+        async move {
+            let (response_tx, response_rx) = oneshot::channel();
+            let cmd = OutgoingCommand::new(next_command_sequence(), response_tx, request, None);
+            command_tx
+                .send(cmd)
+                .await
+                .map_err(|_| anyhow!("Command queue is closed"))?;
+            response_rx.await.map_err(|e| anyhow!(e))
+        }
+    }
+
+    /// Queue a read of a stored message, passing the PDU back to the worker to decode.
+    /// The read is queued like any other command, so it never interrupts an in-flight one.
+    fn read_stored_sms(
+        &self,
+        storage: Option<String>,
+        index: u32,
+        message_type: UnsolicitedMessageType,
+    ) {
+        let request = ModemRequest::ReadSMS {
+            storage: storage.clone(),
+            index,
+        };
+        let response = self.send_command(request);
+
+        let worker_event_tx = self.worker_event_tx.clone();
+        tokio::spawn(async move {
+            match response.await {
+                Ok(ModemResponse::StoredSMS(pdu)) => {
+                    let _ = worker_event_tx.send(WorkerEvent::StoredSMS {
+                        storage,
+                        index,
+                        message_type,
+                        pdu,
+                    });
+                }
+                Ok(response) => error!(
+                    "Failed to read stored message at index {index}, it remains on the modem: {response}"
+                ),
+                Err(e) => error!(
+                    "Failed to read stored message at index {index}, it remains on the modem: {e}"
+                ),
+            }
+        });
+    }
+
+    fn delete_stored_sms(&self, storage: Option<String>, index: u32) {
+        let response = self.send_command(ModemRequest::DeleteSMS { storage, index });
+        tokio::spawn(async move {
+            match response.await {
+                Ok(ModemResponse::Success) => debug!("Deleted stored message at index {index}"),
+                Ok(response) => {
+                    warn!("Failed to delete stored message at index {index}: {response}")
+                }
+                Err(e) => warn!("Failed to delete stored message at index {index}: {e}"),
+            }
+        });
+    }
+
     fn set_status(&mut self, status: ModemStatus) {
         debug!("ModemWorker Status: {status:?}");
         if self.status == status {
@@ -244,10 +587,37 @@ impl ModemWorker {
         let previous = self.status.clone();
         self.status = status.clone();
 
-        // Send message outside of modem for webhooks etc.
+        // Registration is lost while the radio is off, so check it once it's back on.
+        if previous == ModemStatus::RadioOff && status == ModemStatus::Online {
+            self.check_registration();
+        }
+
+        // Reconnection starts from the shortest backoff each time the connection is lost.
+        if status == ModemStatus::Offline {
+            self.reconnect_backoff = RECONNECT_BACKOFF_MIN;
+            self.reconnect_at = Instant::now() + self.reconnect_backoff;
+        }
+        self.send_status_update(previous);
+    }
+
+    /// Double the reconnection backoff after a failed attempt.
+    fn back_off_reconnect(&mut self) {
+        self.reconnect_backoff = (self.reconnect_backoff * 2).min(RECONNECT_BACKOFF_MAX);
+        self.reconnect_at = Instant::now() + self.reconnect_backoff;
+        debug!(
+            "Next reconnection attempt in {}s",
+            self.reconnect_backoff.as_secs()
+        );
+    }
+
+    /// Send a status update outside of modem for webhooks etc, with the reconnection backoff when offline.
+    fn send_status_update(&self, previous: ModemStatus) {
+        let status = self.status.clone();
         let message = ModemIncomingMessage::ModemStatusUpdate {
             previous,
             current: status.clone(),
+            reconnect_backoff_secs: (status == ModemStatus::Offline)
+                .then_some(self.reconnect_backoff.as_secs()),
         };
         match self.main_tx.send(message) {
             Ok(_) => debug!("Sent ModemOnlineStatusUpdate, Status: {status:?}"),
@@ -257,12 +627,36 @@ impl ModemWorker {
         }
     }
 
+    /// Queue a network status request behind any pending commands,
+    /// forwarding the registration status once the modem responds.
+    fn check_registration(&self) {
+        let Some(response_rx) = self.queue_command(ModemRequest::GetNetworkStatus) else {
+            return;
+        };
+
+        let main_tx = self.main_tx.clone();
+        tokio::spawn(async move {
+            match response_rx.await {
+                Ok(ModemResponse::NetworkStatus { registration, .. }) => {
+                    info!("Radio enabled, network registration status: {registration}");
+                    // GetNetworkStatus queries AT+CREG, the circuit switched registration.
+                    let _ = main_tx.send(ModemIncomingMessage::NetworkStatusChange {
+                        domain: RegistrationDomain::Circuit,
+                        registration,
+                    });
+                }
+                Ok(response) => warn!("Unexpected registration check response: {response}"),
+                Err(e) => warn!("Registration check after radio was enabled failed: {e}"),
+            }
+        });
+    }
+
     async fn try_reconnect(&mut self) -> Result<bool> {
         if self.status != ModemStatus::Offline {
             return Ok(false);
         }
 
-        match self.test_connection().await {
+        match self.connect().await {
             Ok(_) => {
                 debug!("Basic connection test passed, initializing modem...");
 
@@ -293,18 +687,79 @@ impl ModemWorker {
         }
     }
 
+    /// Open each candidate device in turn until one responds, keeping it as the port. A new handle
+    /// is always opened, as the old one is dead once a USB modem is unplugged.
+    async fn connect(&mut self) -> Result<()> {
+        let Some(port_opener) = &self.port_opener else {
+            return self.test_connection().await;
+        };
+
+        let candidates = port_opener.candidates();
+        let mut last_error = anyhow!(
+            "No modem device found matching {}",
+            self.config.device.join(", ")
+        );
+        for device in candidates {
+            // The old handle is closed first, since the device is opened exclusively.
+            self.port = closed_port();
+            let Some(port_opener) = &mut self.port_opener else {
+                break;
+            };
+
+            match port_opener.open(&device) {
+                Ok(port) => self.port = port,
+                Err(e) => {
+                    debug!("{e}");
+                    last_error = e;
+                    continue;
+                }
+            }
+
+            match self.test_connection().await {
+                Ok(()) => {
+                    info!("Selected modem device {device}");
+                    return Ok(());
+                }
+                Err(e) => {
+                    debug!("Modem device {device} didn't respond: {e}");
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
     async fn initialize_modem(&mut self) -> Result<()> {
         info!("Sending modem initialization commands");
+        if self.config.stores_incoming_sms() {
+            info!("Incoming SMS messages are stored by the modem, then read on +CMTI");
+        }
+
+        let cnmi_command = format!("AT+CNMI={}\r\n", self.config.cnmi);
+        let csmp_command = format!("AT+CSMP=49,{},0,0\r\n", self.config.default_validity_period);
         let mut initialization_commands: Vec<(Vec<u8>, Vec<u8>)> = vec![
             init_cmd!("ATZ\r\n", "OK"),                              // Reset
             init_cmd!("ATE0\r\n", "OK"),                             // Disable echo
+            init_cmd!("AT+CFUN=1\r\n", "OK"), // Full functionality, the radio may have been left off
             init_cmd!("AT+CMGF=0\r\n", "OK"), // Set SMS message format to PDU
             init_cmd!("AT+CSCS=\"GSM\"\r\n", "OK"), // Use GSM 7-bit alphabet
-            init_cmd!("AT+CNMI=2,2,0,1,0\r\n", "OK"), // Receive all incoming SMS messages and delivery reports
-            init_cmd!("AT+CSMP=49,167,0,0\r\n", "OK"), // Receive delivery receipts from sent messages
+            init_cmd!(cnmi_command, "OK"), // Receive all incoming SMS messages and delivery reports
+            init_cmd!(csmp_command, "OK"), // Receive delivery receipts from sent messages
             init_cmd!("AT+CPMS=\"ME\",\"ME\",\"ME\"\r\n", "+CPMS:"), // Store all messages in memory only
+            init_cmd!("AT+CREG=1\r\n", "OK"), // Report network registration changes
         ];
 
+        // Set the SMS service centre, an invalid number is rejected by the modem here.
+        if let Some(smsc) = &self.config.smsc {
+            let smsc_command = format!("AT+CSCA=\"{smsc}\"\r\n").as_bytes().to_vec();
+            initialization_commands.push((smsc_command, b"OK".to_vec()));
+        }
+
+        // Caller identification is required to get the number for incoming calls.
+        if self.config.incoming_calls_enabled {
+            initialization_commands.push(init_cmd!("AT+CLIP=1\r\n", "OK"));
+        }
+
         // If GNSS is enabled power it on and start its receiver.
         if self.config.gnss_enabled {
             debug!(
@@ -337,36 +792,112 @@ impl ModemWorker {
                     "Initialization command '{:?}' failed. Expected: '{}', Got: '{}'",
                     command_str,
                     expected_str,
-                    response_str.trim()
+                    describe_response(&response_str)
                 ));
             }
         }
 
+        // Packet domain registration, radio mode, band selection and network time are model
+        // dependent, so these only warn if rejected.
+        let mut optional_commands = vec!["AT+CGREG=1\r\n".to_string()];
+        if let Some(rat) = self.config.preferred_rat {
+            optional_commands.push(format!("AT+CNMP={}\r\n", rat.cnmp_mode()));
+        }
+        if let Some(bands) = &self.config.lte_bands {
+            let bands = bands
+                .iter()
+                .map(|band| band.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            optional_commands.push(format!("AT+CBANDCFG=\"CAT-M\",{bands}\r\n"));
+        }
+        if self.config.network_time_sync {
+            optional_commands.push("AT+CTZU=1\r\n".to_string());
+        }
+        for command in optional_commands {
+            debug!("Sending optional initialization command: {command:?}");
+            self.port.write_all(command.as_bytes()).await?;
+
+            match self.read_response_until_ok().await {
+                Ok(response) if String::from_utf8_lossy(&response).contains("OK\r\n") => {}
+                Ok(response) => warn!(
+                    "Modem rejected {command:?}, it may not be supported by this model: {}",
+                    describe_response(&String::from_utf8_lossy(&response))
+                ),
+                Err(e) => warn!("No response to {command:?}, continuing initialization: {e}"),
+            }
+        }
+
+        if let Some(operator) = self.config.preferred_operator.clone() {
+            self.select_preferred_operator(&operator).await?;
+        }
+
         debug!("Modem initialization completed successfully!");
         Ok(())
     }
 
+    /// Manually register with the preferred operator, falling back to automatic
+    /// selection if it fails so that the modem isn't left unregistered.
+    async fn select_preferred_operator(&mut self, operator: &str) -> Result<()> {
+        debug!("Selecting preferred operator {operator}");
+        let command = format!("AT+COPS=1,2,\"{operator}\"\r\n");
+        self.port.write_all(command.as_bytes()).await?;
+
+        let error = match self
+            .read_response_with_timeout(ModemRequest::TIMEOUT_OPERATOR)
+            .await
+        {
+            Ok(response) => {
+                let response_str = String::from_utf8_lossy(&response);
+                if response_str.contains("OK\r\n") {
+                    info!("Registered with preferred operator {operator}");
+                    return Ok(());
+                }
+                describe_response(&response_str)
+            }
+            Err(e) => e.to_string(),
+        };
+
+        warn!("Failed to select preferred operator {operator}, falling back to automatic: {error}");
+        self.port.write_all(b"AT+COPS=0\r\n").await?;
+
+        let response = self
+            .read_response_with_timeout(ModemRequest::TIMEOUT_OPERATOR)
+            .await?;
+        let response_str = String::from_utf8_lossy(&response);
+        if !response_str.contains("OK\r\n") {
+            return Err(anyhow!(
+                "Automatic operator selection failed: '{}'",
+                describe_response(&response_str)
+            ));
+        }
+        Ok(())
+    }
+
     async fn read_response_until_ok(&mut self) -> Result<Vec<u8>> {
+        self.read_response_with_timeout(Duration::from_secs(10))
+            .await
+    }
+
+    async fn read_response_with_timeout(&mut self, duration: Duration) -> Result<Vec<u8>> {
         let mut response = Vec::new();
         let mut buf = [0u8; 1024];
 
-        let timeout = Duration::from_millis(50);
-        tokio::time::timeout(Duration::from_secs(10), async {
+        tokio::time::timeout(duration, async {
             loop {
-                match self.port.try_read(&mut buf) {
-                    Ok(n) if n > 0 => {
-                        response.extend_from_slice(&buf[..n]);
-                        let response_str = String::from_utf8_lossy(&response);
+                let n = self
+                    .port
+                    .read(&mut buf)
+                    .await
+                    .map_err(|e| anyhow!("Read error during initialization: {}", e))?;
+                if n == 0 {
+                    return Err(anyhow!("Serial port closed during initialization"));
+                }
 
-                        if response_str.contains("OK\r\n") || response_str.contains("ERROR") {
-                            break;
-                        }
-                    }
-                    Ok(_) => tokio::time::sleep(timeout).await,
-                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                        tokio::time::sleep(timeout).await
-                    }
-                    Err(e) => return Err(anyhow!("Read error during initialization: {}", e)),
+                response.extend_from_slice(&buf[..n]);
+                let response_str = String::from_utf8_lossy(&response);
+                if response_str.contains("OK\r\n") || response_str.contains("ERROR") {
+                    break;
                 }
             }
             Ok(())
@@ -407,3 +938,430 @@ impl ModemWorker {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::DuplexStream;
+
+    /// An SMS-DELIVER from +31641600986 containing "How are you?".
+    const DELIVER_PDU: &str =
+        "07911326040000F0040B911346610089F60000208062917314080CC8F71D14969741F977FD07";
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    /// The modem end of an in-memory transport, replying to the worker with scripted output.
+    struct MockModem {
+        stream: DuplexStream,
+        received: Vec<u8>,
+    }
+    impl MockModem {
+        /// Wait until the worker has written `expected`, discarding anything before it.
+        async fn expect(&mut self, expected: &str) {
+            let expected = expected.as_bytes();
+            let mut buf = [0u8; 256];
+            loop {
+                if let Some(position) = self
+                    .received
+                    .windows(expected.len())
+                    .position(|window| window == expected)
+                {
+                    self.received.drain(..position + expected.len());
+                    return;
+                }
+
+                let n = tokio::time::timeout(Duration::from_secs(5), self.stream.read(&mut buf))
+                    .await
+                    .expect("Timed out waiting for the worker to write")
+                    .unwrap();
+                assert!(n > 0, "The worker closed the transport");
+                self.received.extend_from_slice(&buf[..n]);
+            }
+        }
+
+        async fn send(&mut self, data: &str) {
+            self.stream.write_all(data.as_bytes()).await.unwrap();
+        }
+    }
+
+    struct Harness {
+        modem: MockModem,
+        command_tx: mpsc::Sender<OutgoingCommand>,
+        main_rx: mpsc::UnboundedReceiver<ModemIncomingMessage>,
+        control_tx: mpsc::Sender<ModemControl>,
+
+        // Kept so the worker's channels stay open.
+        _priority_command_tx: mpsc::Sender<OutgoingCommand>,
+    }
+    impl Harness {
+        /// Run a worker against a mock modem, skipping initialization.
+        fn start() -> Self {
+            Self::start_with_config(ModemConfig::default())
+        }
+
+        fn start_with_config(config: ModemConfig) -> Self {
+            let (transport, modem) = tokio::io::duplex(1024);
+            let (main_tx, main_rx) = mpsc::unbounded_channel();
+            let (command_tx, command_rx) = mpsc::channel(8);
+            let (priority_command_tx, priority_command_rx) = mpsc::channel(8);
+            let (control_tx, control_rx) = mpsc::channel(1);
+
+            let mut worker = ModemWorker::new(
+                Box::new(transport),
+                main_tx,
+                command_tx.clone(),
+                SharedStateMachineSnapshot::default(),
+                config,
+            )
+            .unwrap();
+            worker.status = ModemStatus::Online;
+            tokio::spawn(worker.run(command_rx, priority_command_rx, control_rx));
+
+            Self {
+                modem: MockModem {
+                    stream: modem,
+                    received: Vec::new(),
+                },
+                command_tx,
+                main_rx,
+                _priority_command_tx: priority_command_tx,
+                control_tx,
+            }
+        }
+
+        async fn command(&self, request: ModemRequest) -> oneshot::Receiver<ModemResponse> {
+            let (response_tx, response_rx) = oneshot::channel();
+            let command = OutgoingCommand::new(next_command_sequence(), response_tx, request, None);
+            self.command_tx.send(command).await.unwrap();
+            response_rx
+        }
+
+        /// Send an SMS and feed the modem up to and including the PDU being written.
+        async fn send_sms(&mut self) -> oneshot::Receiver<ModemResponse> {
+            let response_rx = self
+                .command(ModemRequest::SendSMS {
+                    len: 3,
+                    pdu: "0011AB".to_string(),
+                })
+                .await;
+
+            self.modem.expect("AT+CMGS=3\r\n").await;
+            self.modem.send("\r\n> ").await;
+            self.modem.expect("0011AB\x1a").await;
+            response_rx
+        }
+    }
+
+    async fn response(response_rx: oneshot::Receiver<ModemResponse>) -> ModemResponse {
+        tokio::time::timeout(Duration::from_secs(5), response_rx)
+            .await
+            .expect("Timed out waiting for the command response")
+            .unwrap()
+    }
+
+    #[test]
+    fn test_flush_queue_keeps_in_flight_command() {
+        block_on(async {
+            let mut harness = Harness::start();
+            let in_flight_rx = harness.send_sms().await;
+            let queued_rx = [
+                harness.command(ModemRequest::GetSignalStrength).await,
+                harness.command(ModemRequest::GetBatteryLevel).await,
+            ];
+
+            let (flushed_tx, flushed_rx) = oneshot::channel();
+            harness
+                .control_tx
+                .send(ModemControl::FlushQueue(flushed_tx))
+                .await
+                .unwrap();
+            assert_eq!(flushed_rx.await.unwrap(), 2);
+
+            for queued_rx in queued_rx {
+                assert!(matches!(
+                    response(queued_rx).await,
+                    ModemResponse::Error { message, .. } if message == "Command cancelled by queue flush"
+                ));
+            }
+
+            harness.modem.send("\r\n+CMGS: 7\r\n\r\nOK\r\n").await;
+            assert!(matches!(
+                response(in_flight_rx).await,
+                ModemResponse::SendResult { reference_id: 7 }
+            ));
+        });
+    }
+
+    #[test]
+    fn test_send_sms_success() {
+        block_on(async {
+            let mut harness = Harness::start();
+            let response_rx = harness.send_sms().await;
+            harness.modem.send("\r\n+CMGS: 42\r\n\r\nOK\r\n").await;
+
+            assert!(matches!(
+                response(response_rx).await,
+                ModemResponse::SendResult { reference_id: 42 }
+            ));
+        });
+    }
+
+    #[test]
+    fn test_send_sms_cms_error() {
+        block_on(async {
+            let mut harness = Harness::start();
+            let response_rx = harness.send_sms().await;
+            harness.modem.send("\r\n+CMS ERROR: 500\r\n").await;
+
+            assert!(matches!(
+                response(response_rx).await,
+                ModemResponse::Error {
+                    code: Some(500),
+                    message,
+                } if message == "CMS ERROR 500 (unknown error)"
+            ));
+
+            // The worker is idle again and accepts the next command.
+            let response_rx = harness.command(ModemRequest::HangUp).await;
+            harness.modem.expect("ATH\r\n").await;
+            harness.modem.send("\r\nOK\r\n").await;
+            assert!(matches!(
+                response(response_rx).await,
+                ModemResponse::Success
+            ));
+        });
+    }
+
+    #[test]
+    fn test_late_send_result_after_timeout() {
+        block_on(async {
+            let mut harness = Harness::start();
+
+            // A stray +CMGS is only matched if a send has timed out.
+            harness.modem.send("\r\n+CMGS: 5\r\n").await;
+
+            let (response_tx, response_rx) = oneshot::channel();
+            let request = ModemRequest::SendSMS {
+                len: 3,
+                pdu: "0011AB".to_string(),
+            };
+            let command =
+                OutgoingCommand::new(next_command_sequence(), response_tx, request, Some(1));
+            harness.command_tx.send(command).await.unwrap();
+            harness.modem.expect("AT+CMGS=3\r\n").await;
+            harness.modem.send("\r\n> ").await;
+            harness.modem.expect("0011AB\x1a").await;
+
+            assert!(matches!(
+                response(response_rx).await,
+                ModemResponse::Error { message, .. } if message == COMMAND_TIMEOUT_ERROR
+            ));
+            harness.modem.expect("\x1b").await;
+
+            // Only the first +CMGS after the timeout is reported.
+            harness
+                .modem
+                .send("\r\n+CMGS: 9\r\n\r\n+CMGS: 10\r\n")
+                .await;
+            let late = tokio::time::timeout(Duration::from_secs(5), harness.main_rx.recv())
+                .await
+                .expect("Timed out waiting for the late send result");
+            assert!(matches!(
+                late,
+                Some(ModemIncomingMessage::LateSendResult(9))
+            ));
+            let next =
+                tokio::time::timeout(Duration::from_millis(200), harness.main_rx.recv()).await;
+            assert!(next.is_err(), "Got another message: {next:?}");
+        });
+    }
+
+    #[test]
+    fn test_unsolicited_message_during_command() {
+        block_on(async {
+            let mut harness = Harness::start();
+            let response_rx = harness.send_sms().await;
+
+            // An incoming SMS arrives between the PDU being written and the +CMGS result.
+            harness
+                .modem
+                .send(&format!("\r\n+CMT: ,24\r\n{DELIVER_PDU}\r\n"))
+                .await;
+            harness.modem.send("\r\n+CMGS: 7\r\n\r\nOK\r\n").await;
+
+            assert!(matches!(
+                response(response_rx).await,
+                ModemResponse::SendResult { reference_id: 7 }
+            ));
+            let incoming = tokio::time::timeout(Duration::from_secs(5), harness.main_rx.recv())
+                .await
+                .expect("Timed out waiting for the incoming message");
+            match incoming {
+                Some(ModemIncomingMessage::IncomingSMS(message)) => {
+                    assert_eq!(message.phone_number, "+31641600986");
+                    assert_eq!(message.content, "How are you?");
+                }
+                other => panic!("Expected an incoming SMS, got {other:?}"),
+            }
+        });
+    }
+
+    #[test]
+    fn test_network_status_change_during_command() {
+        block_on(async {
+            let mut harness = Harness::start();
+            let response_rx = harness.command(ModemRequest::HangUp).await;
+            harness.modem.expect("ATH\r\n").await;
+
+            // A registration change is a single line, so the command's OK after it isn't lost.
+            harness.modem.send("\r\n+CGREG: 1\r\n\r\nOK\r\n").await;
+            assert!(matches!(
+                response(response_rx).await,
+                ModemResponse::Success
+            ));
+            let change = tokio::time::timeout(Duration::from_secs(5), harness.main_rx.recv())
+                .await
+                .expect("Timed out waiting for the network status change");
+            assert!(matches!(
+                change,
+                Some(ModemIncomingMessage::NetworkStatusChange {
+                    domain: RegistrationDomain::Packet,
+                    registration: 1,
+                })
+            ));
+
+            // A +CREG line is the reply to a registration query, but unsolicited otherwise.
+            let response_rx = harness.command(ModemRequest::GetNetworkStatus).await;
+            harness.modem.expect("AT+CREG?;+COPS?\r\n").await;
+            harness
+                .modem
+                .send("\r\n+CREG: 1,5\r\n+COPS: 0,0,\"Operator\",7\r\n\r\nOK\r\n")
+                .await;
+            assert!(matches!(
+                response(response_rx).await,
+                ModemResponse::NetworkStatus {
+                    registration: 5,
+                    ..
+                }
+            ));
+
+            harness.modem.send("\r\n+CREG: 2\r\n").await;
+            let change = tokio::time::timeout(Duration::from_secs(5), harness.main_rx.recv())
+                .await
+                .expect("Timed out waiting for the network status change");
+            assert!(matches!(
+                change,
+                Some(ModemIncomingMessage::NetworkStatusChange {
+                    domain: RegistrationDomain::Circuit,
+                    registration: 2,
+                })
+            ));
+        });
+    }
+
+    fn heartbeat_config(heartbeat_failures_before_offline: u32) -> ModemConfig {
+        ModemConfig {
+            heartbeat_interval_secs: 1,
+            heartbeat_failures_before_offline,
+            ..ModemConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_heartbeat_waits_for_idle() {
+        block_on(async {
+            let mut harness = Harness::start_with_config(heartbeat_config(1));
+            let response_rx = harness.command(ModemRequest::HangUp).await;
+            harness.modem.expect("ATH\r\n").await;
+
+            // A command running past the heartbeat interval isn't interrupted by a heartbeat.
+            let mut buf = [0u8; 64];
+            let read = tokio::time::timeout(
+                Duration::from_millis(2500),
+                harness.modem.stream.read(&mut buf),
+            )
+            .await;
+            assert!(read.is_err(), "The worker wrote during a command");
+
+            harness.modem.send("\r\nOK\r\n").await;
+            assert!(matches!(
+                response(response_rx).await,
+                ModemResponse::Success
+            ));
+
+            // Once idle, the heartbeat is sent and answered without a status change.
+            harness.modem.expect("AT\r\n").await;
+            harness.modem.send("\r\nOK\r\n").await;
+            harness.modem.expect("AT\r\n").await;
+            assert!(harness.main_rx.try_recv().is_err());
+        });
+    }
+
+    #[test]
+    fn test_heartbeat_error_is_a_response() {
+        block_on(async {
+            let mut harness = Harness::start_with_config(heartbeat_config(1));
+            harness.modem.expect("AT\r\n").await;
+            harness.modem.send("\r\nERROR\r\n").await;
+
+            // The modem answered, so it's still online and the next heartbeat is sent.
+            harness.modem.expect("AT\r\n").await;
+            assert!(harness.main_rx.try_recv().is_err());
+        });
+    }
+
+    #[test]
+    fn test_unanswered_heartbeat_goes_offline() {
+        block_on(async {
+            let mut harness = Harness::start_with_config(heartbeat_config(1));
+            harness.modem.expect("AT\r\n").await;
+
+            let update = tokio::time::timeout(Duration::from_secs(10), harness.main_rx.recv())
+                .await
+                .expect("Timed out waiting for the modem to go offline");
+            match update {
+                Some(ModemIncomingMessage::ModemStatusUpdate {
+                    previous, current, ..
+                }) => {
+                    assert_eq!(previous, ModemStatus::Online);
+                    assert_eq!(current, ModemStatus::Offline);
+                }
+                other => panic!("Expected a status update, got {other:?}"),
+            }
+        });
+    }
+
+    #[test]
+    fn test_reconnect_status_only_on_transition() {
+        block_on(async {
+            let Harness {
+                modem, mut main_rx, ..
+            } = Harness::start();
+
+            // Unplugging the modem closes the transport, so every reconnection attempt fails.
+            drop(modem);
+
+            let update = tokio::time::timeout(Duration::from_secs(10), main_rx.recv())
+                .await
+                .expect("Timed out waiting for a status update");
+            match update {
+                Some(ModemIncomingMessage::ModemStatusUpdate {
+                    current: ModemStatus::Offline,
+                    reconnect_backoff_secs,
+                    ..
+                }) => assert_eq!(reconnect_backoff_secs, Some(1)),
+                other => panic!("Expected an offline status update, got {other:?}"),
+            }
+
+            // Failed attempts after 1s and 3s back off without reporting the same status again.
+            let repeated = tokio::time::timeout(Duration::from_secs(4), main_rx.recv()).await;
+            assert!(repeated.is_err(), "Expected no update, got {repeated:?}");
+        });
+    }
+}
@@ -0,0 +1,143 @@
+use crate::config::{is_keyword, AutoReplyConfig};
+use crate::templates;
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+enum Matcher {
+    Keyword(String),
+    Regex(Regex),
+}
+
+struct AutoReplyRule {
+    matcher: Matcher,
+    response: String,
+    cooldown: Duration,
+}
+impl AutoReplyRule {
+    /// Get the vars to render the response with, if the message matches.
+    fn matches(&self, phone_number: &str, content: &str) -> Option<HashMap<String, String>> {
+        let mut vars = HashMap::from([
+            ("phone_number".to_string(), phone_number.to_string()),
+            ("message".to_string(), content.to_string()),
+        ]);
+        match &self.matcher {
+            Matcher::Keyword(keyword) => is_keyword(keyword, content).then_some(vars),
+            Matcher::Regex(regex) => {
+                let captures = regex.captures(content)?;
+                for name in regex.capture_names().flatten() {
+                    if let Some(value) = captures.name(name) {
+                        vars.insert(name.to_string(), value.as_str().to_string());
+                    }
+                }
+                Some(vars)
+            }
+        }
+    }
+}
+
+/// Picks the reply to an incoming message from the configured rules. Each rule only replies to
+/// a sender once per cooldown, so replying to another auto-responder can't loop.
+pub struct AutoReplier {
+    rules: Vec<AutoReplyRule>,
+    last_replies: Mutex<HashMap<(usize, String), Instant>>,
+}
+impl AutoReplier {
+    pub fn new(config: &[AutoReplyConfig]) -> Result<Self> {
+        let rules = config
+            .iter()
+            .map(|rule| {
+                let matcher = match (&rule.keyword, &rule.regex) {
+                    (_, Some(regex)) => Matcher::Regex(Regex::new(regex)?),
+                    (keyword, None) => Matcher::Keyword(keyword.clone().unwrap_or_default()),
+                };
+                Ok(AutoReplyRule {
+                    matcher,
+                    response: rule.response.clone(),
+                    cooldown: Duration::from_secs(rule.cooldown_secs),
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            rules,
+            last_replies: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Get the reply for a message from the first matching rule, or None if no rule matches
+    /// or the matching rule already replied to the sender within its cooldown.
+    pub fn reply(&self, phone_number: &str, content: &str) -> Result<Option<String>> {
+        let Some((idx, rule, vars)) = self
+            .rules
+            .iter()
+            .enumerate()
+            .find_map(|(idx, rule)| Some((idx, rule, rule.matches(phone_number, content)?)))
+        else {
+            return Ok(None);
+        };
+
+        let now = Instant::now();
+        let mut last_replies = self
+            .last_replies
+            .lock()
+            .map_err(|_| anyhow!("Auto-reply cooldowns lock is poisoned"))?;
+
+        // Forget replies whose cooldown has passed, so the map only holds recent senders.
+        last_replies.retain(|(idx, _), replied_at| {
+            now.duration_since(*replied_at) < self.rules[*idx].cooldown
+        });
+        let key = (idx, phone_number.to_string());
+        if last_replies.contains_key(&key) {
+            return Ok(None);
+        }
+
+        let response = templates::render_lenient(&rule.response, &vars)?;
+        last_replies.insert(key, now);
+        Ok(Some(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(keyword: Option<&str>, regex: Option<&str>, response: &str) -> AutoReplyConfig {
+        AutoReplyConfig {
+            keyword: keyword.map(str::to_string),
+            regex: regex.map(str::to_string),
+            response: response.to_string(),
+            cooldown_secs: 60,
+        }
+    }
+
+    #[test]
+    fn test_auto_reply() {
+        let replier = AutoReplier::new(&[
+            rule(Some("HOURS"), None, "Open 9-5, {phone_number}"),
+            rule(
+                None,
+                Some(r"(?i)^track (?P<order>\d+)"),
+                "Order {order}: {message}",
+            ),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            replier.reply("+44", " hours ").unwrap().as_deref(),
+            Some("Open 9-5, +44")
+        );
+        assert_eq!(
+            replier.reply("+44", "TRACK 42 please").unwrap().as_deref(),
+            Some("Order 42: TRACK 42 please")
+        );
+        assert_eq!(replier.reply("+44", "what are your hours?").unwrap(), None);
+
+        // Each rule only replies to a sender once per cooldown.
+        assert_eq!(replier.reply("+44", "hours").unwrap(), None);
+        assert_eq!(replier.reply("+44", "track 7").unwrap(), None);
+        assert!(replier.reply("+1", "hours").unwrap().is_some());
+    }
+}
@@ -1,15 +1,296 @@
 #![cfg_attr(not(feature = "http-server"), allow(dead_code))]
 
-use crate::config::DatabaseConfig;
+use crate::config::{DatabaseConfig, PruneOnly};
+use crate::modem::parsers::registration_label;
+use crate::modem::types::{NetworkEvent, RegistrationDomain};
 use crate::sms::encryption::SMSEncryption;
-use crate::types::{SMSDeliveryReport, SMSMessage, SMSStatus};
-use anyhow::{anyhow, Result};
-use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous};
-use sqlx::{Row, SqlitePool};
+use crate::sms::types::{DatabaseMaintenanceResult, MarkReadTarget, SMSMessageStats};
+use crate::types::{
+    BlocklistEntry, QueuedSendOptions, SMSConversation, SMSDataCoding, SMSDeliveryReport,
+    SMSMessage, SMSStatus,
+};
+use anyhow::{anyhow, Context, Result};
+use sqlx::{Connection, Pool, Row};
+use std::borrow::Cow;
 use std::time::Duration;
-use tracing::log::debug;
+use tracing::log::{debug, info, warn};
+
+#[cfg(feature = "db-sqlite")]
+use crate::config::{DatabaseJournalMode, DatabaseSynchronous};
+#[cfg(feature = "db-sqlite")]
+use crate::sms::types::DatabaseFileSizes;
+#[cfg(feature = "db-sqlite")]
+use anyhow::bail;
+#[cfg(feature = "db-sqlite")]
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous};
+#[cfg(feature = "db-sqlite")]
+use std::str::FromStr;
+
+/// A `database_url` for a temporary SQLite database, shared by the pool and lost on exit.
+#[cfg(feature = "db-sqlite")]
+pub const IN_MEMORY_DATABASE_URL: &str = ":memory:";
+
+#[cfg(feature = "db-postgres")]
+use sqlx::postgres::PgPoolOptions;
+
+#[cfg(feature = "db-sqlite")]
+type Db = sqlx::Sqlite;
+
+#[cfg(feature = "db-postgres")]
+type Db = sqlx::Postgres;
+
+type DbRow = <Db as sqlx::Database>::Row;
+type DbConnection = <Db as sqlx::Database>::Connection;
+
+/// Ordered schema migrations, each applied once and recorded in `schema_migrations`.
+/// Applied migrations must never be edited, add a new version to every backend instead.
+#[cfg(feature = "db-sqlite")]
+const MIGRATIONS: &[(i64, &str)] = &[
+    (1, include_str!("migrations/sqlite/0001_initial.sql")),
+    (2, include_str!("migrations/sqlite/0002_call_log.sql")),
+    (3, include_str!("migrations/sqlite/0003_expired_status.sql")),
+    (4, include_str!("migrations/sqlite/0004_resend_of.sql")),
+    (5, include_str!("migrations/sqlite/0005_modem_id.sql")),
+    (6, include_str!("migrations/sqlite/0006_dry_run_status.sql")),
+    (7, include_str!("migrations/sqlite/0007_blocklist.sql")),
+    (8, include_str!("migrations/sqlite/0008_raw_pdu.sql")),
+    (9, include_str!("migrations/sqlite/0009_data_coding.sql")),
+    (10, include_str!("migrations/sqlite/0010_stats_indexes.sql")),
+    (
+        11,
+        include_str!("migrations/sqlite/0011_hot_path_indexes.sql"),
+    ),
+    (
+        12,
+        include_str!("migrations/sqlite/0012_network_events.sql"),
+    ),
+    (13, include_str!("migrations/sqlite/0013_read_status.sql")),
+    (
+        14,
+        include_str!("migrations/sqlite/0014_queued_messages.sql"),
+    ),
+    (
+        15,
+        include_str!("migrations/sqlite/0015_delivery_report_tp_status.sql"),
+    ),
+    (
+        16,
+        include_str!("migrations/sqlite/0016_message_validity.sql"),
+    ),
+    (
+        17,
+        include_str!("migrations/sqlite/0017_network_event_domain.sql"),
+    ),
+];
+
+#[cfg(feature = "db-postgres")]
+const MIGRATIONS: &[(i64, &str)] = &[
+    (1, include_str!("migrations/postgres/0001_initial.sql")),
+    (2, include_str!("migrations/postgres/0002_call_log.sql")),
+    (
+        3,
+        include_str!("migrations/postgres/0003_expired_status.sql"),
+    ),
+    (4, include_str!("migrations/postgres/0004_resend_of.sql")),
+    (5, include_str!("migrations/postgres/0005_modem_id.sql")),
+    (
+        6,
+        include_str!("migrations/postgres/0006_dry_run_status.sql"),
+    ),
+    (7, include_str!("migrations/postgres/0007_blocklist.sql")),
+    (8, include_str!("migrations/postgres/0008_raw_pdu.sql")),
+    (9, include_str!("migrations/postgres/0009_data_coding.sql")),
+    (
+        10,
+        include_str!("migrations/postgres/0010_stats_indexes.sql"),
+    ),
+    (
+        11,
+        include_str!("migrations/postgres/0011_hot_path_indexes.sql"),
+    ),
+    (
+        12,
+        include_str!("migrations/postgres/0012_network_events.sql"),
+    ),
+    (13, include_str!("migrations/postgres/0013_read_status.sql")),
+    (
+        14,
+        include_str!("migrations/postgres/0014_queued_messages.sql"),
+    ),
+    (
+        15,
+        include_str!("migrations/postgres/0015_delivery_report_tp_status.sql"),
+    ),
+    (
+        16,
+        include_str!("migrations/postgres/0016_message_validity.sql"),
+    ),
+    (
+        17,
+        include_str!("migrations/postgres/0017_network_event_domain.sql"),
+    ),
+];
+
+#[cfg(feature = "db-sqlite")]
+const SCHEMA_MIGRATIONS_TABLE: &str = "CREATE TABLE IF NOT EXISTS schema_migrations (version INTEGER PRIMARY KEY, applied_at INTEGER NOT NULL DEFAULT (unixepoch()))";
+
+#[cfg(feature = "db-postgres")]
+/// Arbitrary key for the migration advisory lock, "sms_api" in ASCII.
+const MIGRATION_LOCK_ID: i64 = 0x0073_6d73_5f61_7069;
+
+#[cfg(feature = "db-postgres")]
+const SCHEMA_MIGRATIONS_TABLE: &str = "CREATE TABLE IF NOT EXISTS schema_migrations (version BIGINT PRIMARY KEY, applied_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM NOW()))";
+
+/// Queries are written for SQLite, and translated when running against Postgres.
+fn sql(query: &str) -> Cow<'_, str> {
+    if cfg!(feature = "db-postgres") {
+        Cow::Owned(to_postgres_sql(query))
+    } else {
+        Cow::Borrowed(query)
+    }
+}
+
+/// Number `?` placeholders as `$1, $2, ...` and replace `unixepoch()` with a Postgres equivalent.
+fn to_postgres_sql(query: &str) -> String {
+    let query = query.replace("unixepoch()", "CAST(EXTRACT(EPOCH FROM NOW()) AS BIGINT)");
+
+    let mut out = String::with_capacity(query.len() + 8);
+    let mut index = 0;
+    for c in query.chars() {
+        if c == '?' {
+            index += 1;
+            out.push_str(&format!("${index}"));
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Apply any pending migrations, each in its own transaction.
+/// For SQLite, foreign keys must be disabled on the connection so tables can be rebuilt without cascading.
+async fn run_migrations(conn: &mut DbConnection) -> Result<()> {
+    sqlx::raw_sql(SCHEMA_MIGRATIONS_TABLE)
+        .execute(&mut *conn)
+        .await?;
+
+    let applied: Vec<i64> = sqlx::query_scalar("SELECT version FROM schema_migrations")
+        .fetch_all(&mut *conn)
+        .await?;
+
+    for (version, migration) in MIGRATIONS {
+        if applied.contains(version) {
+            continue;
+        }
+
+        let mut tx = conn.begin().await?;
+        sqlx::raw_sql(migration)
+            .execute(&mut *tx)
+            .await
+            .with_context(|| format!("Failed to apply database migration {version}"))?;
+        sqlx::query(&sql("INSERT INTO schema_migrations (version) VALUES (?)"))
+            .bind(version)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit()
+            .await
+            .with_context(|| format!("Failed to commit database migration {version}"))?;
+
+        info!("Applied database migration {version}");
+    }
+
+    debug!("SMSDatabase schema is up to date!");
+    Ok(())
+}
+
+#[cfg(feature = "db-sqlite")]
+async fn connect_pool(config: &DatabaseConfig) -> Result<Pool<Db>> {
+    // An in-memory database is shared between pooled connections, and only has a memory journal.
+    let connection_options = if config.database_url == IN_MEMORY_DATABASE_URL {
+        let options = SqliteConnectOptions::from_str("sqlite::memory:")?;
+        match config.journal_mode {
+            None => options,
+            Some(mode @ (DatabaseJournalMode::Memory | DatabaseJournalMode::Off)) => {
+                options.journal_mode(sqlite_journal_mode(mode))
+            }
+            Some(mode) => bail!(
+                "The {mode:?} journal_mode isn't supported by an in-memory database, only memory or off"
+            ),
+        }
+    } else {
+        let mode = config.journal_mode.unwrap_or(DatabaseJournalMode::Wal);
+        SqliteConnectOptions::new()
+            .filename(&config.database_url)
+            .create_if_missing(true)
+            .journal_mode(sqlite_journal_mode(mode))
+    }
+    .synchronous(match config.synchronous {
+        DatabaseSynchronous::Off => SqliteSynchronous::Off,
+        DatabaseSynchronous::Normal => SqliteSynchronous::Normal,
+        DatabaseSynchronous::Full => SqliteSynchronous::Full,
+        DatabaseSynchronous::Extra => SqliteSynchronous::Extra,
+    })
+    .busy_timeout(Duration::from_secs(config.busy_timeout_secs));
+
+    // Migrate on a dedicated connection before the pool is created. It's kept open until
+    // the pool has connected, otherwise an in-memory database would be dropped.
+    let mut migration_conn =
+        DbConnection::connect_with(&connection_options.clone().foreign_keys(false)).await?;
+    run_migrations(&mut migration_conn).await?;
 
-const SCHEMA_SQL: &str = include_str!("schemas/sqlite.sql");
+    // A negative cache_size is in KiB rather than pages.
+    let cache_size_pragma = format!("PRAGMA cache_size = -{}", config.cache_size_kb);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .acquire_timeout(Duration::from_secs(30))
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .test_before_acquire(true)
+        .after_connect(move |conn, _meta| {
+            let cache_size_pragma = cache_size_pragma.clone();
+            Box::pin(async move {
+                // Optimise connection.
+                sqlx::query("PRAGMA foreign_keys = ON")
+                    .execute(&mut *conn)
+                    .await?;
+                sqlx::query(&cache_size_pragma).execute(&mut *conn).await?;
+                sqlx::query("PRAGMA temp_store = memory")
+                    .execute(&mut *conn)
+                    .await?;
+                Ok(())
+            })
+        })
+        .connect_with(connection_options)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+    migration_conn.close().await?;
+    Ok(pool)
+}
+
+#[cfg(feature = "db-postgres")]
+async fn connect_pool(config: &DatabaseConfig) -> Result<Pool<Db>> {
+    // Migrate on a dedicated connection before the pool is created, holding an advisory
+    // lock so servers sharing the database don't apply the same migration concurrently.
+    let mut migration_conn = DbConnection::connect(&config.database_url).await?;
+    sqlx::query("SELECT pg_advisory_lock($1)")
+        .bind(MIGRATION_LOCK_ID)
+        .execute(&mut migration_conn)
+        .await?;
+    let result = run_migrations(&mut migration_conn).await;
+    migration_conn.close().await?;
+    result?;
+
+    PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .acquire_timeout(Duration::from_secs(30))
+        .test_before_acquire(true)
+        .connect(&config.database_url)
+        .await
+        .map_err(|e| anyhow!(e))
+}
 
 fn build_pagination_query(
     base_query: &str,
@@ -32,84 +313,284 @@ fn build_pagination_query(
     query
 }
 
+/// The most characters of a conversation's latest message included as its preview.
+const CONVERSATION_PREVIEW_CHARS: usize = 100;
+
+/// Tables with a `phone_number` column, encrypted when `encrypt_phone_numbers` is enabled.
+const PHONE_NUMBER_TABLES: &[&str] = &["messages", "friendly_names", "blocklist", "call_log"];
+
+/// Optional unix timestamps are stored as signed 64-bit integers by every backend.
+fn timestamp_from_row(row: &DbRow, column: &str) -> Option<u64> {
+    row.get::<Option<i64>, _>(column).map(|value| value as u64)
+}
+
 pub struct SMSDatabase {
-    pool: SqlitePool,
+    pool: Pool<Db>,
     encryption: SMSEncryption,
+    delivery_report_expiry: u64,
+    store_raw_pdu: bool,
+    encrypt_phone_numbers: bool,
+    retain_days: u32,
+    prune_only: Option<PruneOnly>,
 }
 impl SMSDatabase {
     pub async fn connect(config: DatabaseConfig) -> Result<Self> {
-        let connection_options = SqliteConnectOptions::new()
-            .filename(&config.database_url)
-            .create_if_missing(true)
-            .journal_mode(SqliteJournalMode::Wal)
-            .synchronous(SqliteSynchronous::Normal)
-            .busy_timeout(Duration::from_secs(30));
-
-        let pool = SqlitePoolOptions::new()
-            .max_connections(20)
-            .min_connections(5)
-            .acquire_timeout(Duration::from_secs(30))
-            .idle_timeout(None)
-            .max_lifetime(None)
-            .test_before_acquire(true)
-            .after_connect(|conn, _meta| {
-                Box::pin(async move {
-                    // Optimise connection.
-                    sqlx::query("PRAGMA foreign_keys = ON")
-                        .execute(&mut *conn)
-                        .await?;
-                    sqlx::query("PRAGMA cache_size = -64000")
-                        .execute(&mut *conn)
-                        .await?; // 64MB Cache
-                    sqlx::query("PRAGMA temp_store = memory")
-                        .execute(&mut *conn)
-                        .await?;
-                    Ok(())
-                })
-            })
-            .connect_with(connection_options)
-            .await
-            .map_err(|e| anyhow!(e))?;
+        let pool = connect_pool(&config).await?;
 
-        let db = Self {
+        let database = Self {
             pool,
             encryption: SMSEncryption::new(config.encryption_key),
+            delivery_report_expiry: config.delivery_report_expiry,
+            store_raw_pdu: config.store_raw_pdu,
+            encrypt_phone_numbers: config.encrypt_phone_numbers,
+            retain_days: config.retain_days,
+            prune_only: config.prune_only,
         };
-        db.init_tables().await?;
-        Ok(db)
+        database.migrate_phone_numbers().await?;
+
+        Ok(database)
     }
 
-    async fn init_tables(&self) -> Result<()> {
-        sqlx::raw_sql(SCHEMA_SQL)
-            .execute(&self.pool)
+    /// Connect to an empty in-memory database, for tests that don't need a database file.
+    #[cfg(all(test, feature = "db-sqlite"))]
+    pub async fn connect_in_memory() -> Result<Self> {
+        Self::connect(DatabaseConfig {
+            database_url: IN_MEMORY_DATABASE_URL.to_string(),
+            encryption_key: [7; 32],
+            delivery_report_expiry: 60,
+            max_connections: 4,
+            min_connections: 1,
+            busy_timeout_secs: 5,
+            cache_size_kb: 256,
+            journal_mode: None,
+            synchronous: DatabaseSynchronous::Normal,
+            store_raw_pdu: false,
+            encrypt_phone_numbers: false,
+            retain_days: 0,
+            prune_only: None,
+        })
+        .await
+    }
+
+    /// The value a phone number is stored and looked up by. Encrypted deterministically when
+    /// enabled, so equality lookups, grouping and joins between tables still work.
+    fn phone_number_value(&self, phone_number: &str) -> Result<String> {
+        if self.encrypt_phone_numbers {
+            self.encryption.encrypt_deterministic(phone_number)
+        } else {
+            Ok(phone_number.to_string())
+        }
+    }
+
+    fn phone_number_from_row(&self, row: &DbRow) -> Result<String> {
+        let phone_number: String = row.get("phone_number");
+        if self.encrypt_phone_numbers {
+            self.encryption.decrypt(&phone_number)
+        } else {
+            Ok(phone_number)
+        }
+    }
+
+    /// Bring stored phone numbers in line with `encrypt_phone_numbers`, encrypting plaintext
+    /// numbers when enabled or decrypting them when disabled, so an existing database can switch
+    /// either way. Values that don't decrypt with the key are taken to be plaintext.
+    async fn migrate_phone_numbers(&self) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let mut migrated = 0;
+        for table in PHONE_NUMBER_TABLES {
+            let stored: Vec<String> = sqlx::query_scalar(&format!(
+                "SELECT DISTINCT phone_number FROM {table} WHERE phone_number IS NOT NULL"
+            ))
+            .fetch_all(&mut *tx)
+            .await?;
+
+            let update = format!("UPDATE {table} SET phone_number = ? WHERE phone_number = ?");
+            let update = sql(&update);
+            for value in stored {
+                let migrated_value = match self.encryption.decrypt(&value) {
+                    Ok(_) if self.encrypt_phone_numbers => continue,
+                    Ok(plaintext) => plaintext,
+                    Err(_) if self.encrypt_phone_numbers => {
+                        self.encryption.encrypt_deterministic(&value)?
+                    }
+                    Err(_) => continue,
+                };
+                sqlx::query(&update)
+                    .bind(migrated_value)
+                    .bind(value)
+                    .execute(&mut *tx)
+                    .await?;
+                migrated += 1;
+            }
+        }
+        tx.commit()
             .await
-            .map_err(|e| anyhow!(e))?;
+            .context("Failed to commit phone number migration")?;
 
-        debug!("SMSDatabase tables initialized successfully!");
+        if migrated > 0 {
+            let action = if self.encrypt_phone_numbers {
+                "Encrypted"
+            } else {
+                "Decrypted"
+            };
+            info!("{action} {migrated} stored phone number(s)");
+        }
         Ok(())
     }
 
     pub async fn insert_message(&self, message: &SMSMessage, is_final: bool) -> Result<i64> {
         let encrypted_content = self.encryption.encrypt(&message.message_content)?;
-        let result = if is_final {
-            sqlx::query(
-                "INSERT INTO messages (phone_number, message_content, message_reference, is_outgoing, status, completed_at) VALUES (?, ?, ?, ?, ?, unixepoch())"
-            )
+        let encrypted_raw_pdu = match &message.raw_pdu {
+            Some(raw_pdu) if self.store_raw_pdu => Some(self.encryption.encrypt(raw_pdu)?),
+            _ => None,
+        };
+        let query = if is_final {
+            "INSERT INTO messages (phone_number, message_content, message_reference, is_outgoing, status, modem_id, raw_pdu, data_coding, is_read, completed_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, unixepoch()) RETURNING message_id"
         } else {
-            sqlx::query(
-                "INSERT INTO messages (phone_number, message_content, message_reference, is_outgoing, status) VALUES (?, ?, ?, ?, ?)"
-            )
-        }
-            .bind(&message.phone_number)
+            "INSERT INTO messages (phone_number, message_content, message_reference, is_outgoing, status, modem_id, raw_pdu, data_coding, is_read) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING message_id"
+        };
+
+        sqlx::query_scalar(&sql(query))
+            .bind(self.phone_number_value(&message.phone_number)?)
             .bind(encrypted_content)
-            .bind(message.message_reference)
+            .bind(message.message_reference.map(i16::from))
             .bind(message.is_outgoing)
-            .bind(u8::from(&message.status))
+            .bind(i16::from(u8::from(&message.status)))
+            .bind(&message.modem_id)
+            .bind(encrypted_raw_pdu)
+            .bind(i16::from(u8::from(message.data_coding)))
+            .bind(message.is_read || message.is_outgoing)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| anyhow!(e))
+    }
+
+    /// Store an outgoing message as pending before it's handed to the modem, with the send options
+    /// its row doesn't otherwise keep, so it can be sent if recovered after a restart. The validity
+    /// period it's sent with is kept, to expire it once the service centre stops trying to deliver.
+    pub async fn insert_queued_message(
+        &self,
+        message: &SMSMessage,
+        send_options: &QueuedSendOptions,
+        validity: Duration,
+    ) -> Result<i64> {
+        sqlx::query_scalar(&sql(
+            "INSERT INTO messages (phone_number, message_content, is_outgoing, status, modem_id, data_coding, is_read, queued, send_options, validity_secs) VALUES (?, ?, TRUE, ?, ?, ?, TRUE, TRUE, ?, ?) RETURNING message_id"
+        ))
+            .bind(self.phone_number_value(&message.phone_number)?)
+            .bind(self.encryption.encrypt(&message.message_content)?)
+            .bind(i16::from(u8::from(&SMSStatus::Pending)))
+            .bind(&message.modem_id)
+            .bind(i16::from(u8::from(message.data_coding)))
+            .bind(serde_json::to_string(send_options)?)
+            .bind(validity.as_secs() as i64)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| anyhow!(e))
+    }
+
+    /// Record the reference of the first part the modem sent of a queued message, showing it was
+    /// sent at least in part so it's never sent again by recovery.
+    pub async fn set_queued_reference(&self, message_id: i64, reference_id: u8) -> Result<()> {
+        sqlx::query(&sql(
+            "UPDATE messages SET message_reference = ? WHERE message_id = ? AND queued = TRUE AND message_reference IS NULL"
+        ))
+            .bind(i16::from(reference_id))
+            .bind(message_id)
             .execute(&self.pool)
             .await
             .map_err(|e| anyhow!(e))?;
 
-        Ok(result.last_insert_rowid())
+        Ok(())
+    }
+
+    /// Store the outcome of sending a queued message, which is no longer queued. Its `created_at`
+    /// becomes the time it was sent, as a recovered message may have been queued long before.
+    /// The send options are kept, so a resend can use them.
+    pub async fn complete_queued_message(
+        &self,
+        message_id: i64,
+        message: &SMSMessage,
+        is_final: bool,
+    ) -> Result<()> {
+        let query = if is_final {
+            "UPDATE messages SET status = ?, message_reference = ?, modem_id = ?, queued = FALSE, created_at = unixepoch(), completed_at = unixepoch() WHERE message_id = ?"
+        } else {
+            "UPDATE messages SET status = ?, message_reference = ?, modem_id = ?, queued = FALSE, created_at = unixepoch() WHERE message_id = ?"
+        };
+
+        sqlx::query(&sql(query))
+            .bind(i16::from(u8::from(&message.status)))
+            .bind(message.message_reference.map(i16::from))
+            .bind(&message.modem_id)
+            .bind(message_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        Ok(())
+    }
+
+    /// Remove a queued message that wasn't sent, as failed sends aren't stored.
+    pub async fn delete_queued_message(&self, message_id: i64) -> Result<()> {
+        sqlx::query(&sql(
+            "DELETE FROM messages WHERE message_id = ? AND queued = TRUE",
+        ))
+        .bind(message_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        Ok(())
+    }
+
+    /// Get every message still queued, oldest first, with its send options if they can be read.
+    pub async fn get_queued_messages(
+        &self,
+    ) -> Result<Vec<(SMSMessage, Option<QueuedSendOptions>)>> {
+        let result = sqlx::query(&sql(
+            "SELECT message_id, phone_number, message_content, message_reference, is_outgoing, status, created_at, completed_at, modem_id, data_coding, is_read, send_options FROM messages WHERE queued = TRUE ORDER BY message_id ASC"
+        ))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        result
+            .iter()
+            .map(|row| {
+                let message = self.message_from_row(row)?;
+                let send_options =
+                    send_options_from_row(row, message.message_id.unwrap_or_default());
+                Ok((message, send_options))
+            })
+            .collect()
+    }
+
+    /// Get the options a message was sent with, if it was queued and they can be read.
+    pub async fn get_send_options(&self, message_id: i64) -> Result<Option<QueuedSendOptions>> {
+        let result = sqlx::query(&sql(
+            "SELECT send_options FROM messages WHERE message_id = ?",
+        ))
+        .bind(message_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        Ok(result.and_then(|row| send_options_from_row(&row, message_id)))
+    }
+
+    /// Link a message to the message it was resent from.
+    pub async fn set_resend_of(&self, message_id: i64, original_message_id: i64) -> Result<()> {
+        sqlx::query(&sql(
+            "UPDATE messages SET resend_of = ? WHERE message_id = ?",
+        ))
+        .bind(original_message_id)
+        .bind(message_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        Ok(())
     }
 
     pub async fn insert_send_failure(
@@ -117,194 +598,1952 @@ impl SMSDatabase {
         message_id: i64,
         error_message: &String,
     ) -> Result<i64> {
-        let result =
-            sqlx::query("INSERT INTO send_failures (message_id, error_message) VALUES (?, ?)")
-                .bind(message_id)
-                .bind(error_message)
-                .execute(&self.pool)
-                .await
-                .map_err(|e| anyhow!(e))?;
-
-        Ok(result.last_insert_rowid())
+        sqlx::query_scalar(&sql(
+            "INSERT INTO send_failures (message_id, error_message) VALUES (?, ?) RETURNING message_id",
+        ))
+        .bind(message_id)
+        .bind(error_message)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
     }
 
     pub async fn insert_delivery_report(
         &self,
         message_id: i64,
         status: u8,
+        tp_status: u8,
         is_final: bool,
     ) -> Result<i64> {
-        let result = sqlx::query(
-            "INSERT INTO delivery_reports (message_id, status, is_final) VALUES (?, ?, ?)",
-        )
+        sqlx::query_scalar(&sql(
+            "INSERT INTO delivery_reports (message_id, status, tp_status, is_final) VALUES (?, ?, ?, ?) RETURNING report_id",
+        ))
         .bind(message_id)
-        .bind(status)
+        .bind(i16::from(status))
+        .bind(i16::from(tp_status))
         .bind(is_final)
-        .execute(&self.pool)
+        .fetch_one(&self.pool)
         .await
-        .map_err(|e| anyhow!(e))?;
-
-        Ok(result.last_insert_rowid())
+        .map_err(|e| anyhow!(e))
     }
 
-    pub async fn get_delivery_report_target_message(
-        &self,
-        phone_number: &String,
-        reference_id: u8,
-    ) -> Result<Option<i64>> {
-        let result = sqlx::query_scalar(
-            "SELECT message_id FROM messages WHERE completed_at IS NULL AND is_outgoing = 1 AND phone_number = ? AND message_reference = ? ORDER BY message_id DESC LIMIT 1"
+    pub async fn insert_call(&self, phone_number: Option<&str>, rejected: bool) -> Result<i64> {
+        sqlx::query_scalar(&sql(
+            "INSERT INTO call_log (phone_number, rejected) VALUES (?, ?) RETURNING call_id",
+        ))
+        .bind(
+            phone_number
+                .map(|phone_number| self.phone_number_value(phone_number))
+                .transpose()?,
         )
-            .bind(phone_number)
-            .bind(reference_id)
-            .fetch_optional(&self.pool)
-            .await
-            .map_err(|e| anyhow!(e))?;
-
-        Ok(result)
+        .bind(rejected)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
     }
 
-    pub async fn update_message_status(
+    pub async fn insert_network_event(
         &self,
-        message_id: i64,
-        status: &SMSStatus,
-        completed: bool,
-    ) -> Result<()> {
-        let query = if completed {
-            sqlx::query(
-                "UPDATE messages SET status = ?, completed_at = unixepoch() WHERE message_id = ?",
-            )
-        } else {
-            sqlx::query("UPDATE messages SET status = ? WHERE message_id = ?")
-        };
+        modem_id: &str,
+        domain: RegistrationDomain,
+        registration: u8,
+    ) -> Result<i64> {
+        sqlx::query_scalar(&sql(
+            "INSERT INTO network_events (modem_id, domain, registration) VALUES (?, ?, ?) RETURNING event_id",
+        ))
+        .bind(modem_id)
+        .bind(i16::from(u8::from(domain)))
+        .bind(i16::from(registration))
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
 
-        query
-            .bind(u8::from(status))
-            .bind(message_id)
+    /// Update a stored network event to a new state, when a change soon after is merged into it.
+    pub async fn update_network_event(&self, event_id: i64, registration: u8) -> Result<()> {
+        sqlx::query(&sql(
+            "UPDATE network_events SET registration = ? WHERE event_id = ?",
+        ))
+        .bind(i16::from(registration))
+        .bind(event_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+        Ok(())
+    }
+
+    /// Remove a network event, when the state soon reverts to the one before it.
+    pub async fn delete_network_event(&self, event_id: i64) -> Result<()> {
+        sqlx::query(&sql("DELETE FROM network_events WHERE event_id = ?"))
+            .bind(event_id)
             .execute(&self.pool)
             .await
             .map_err(|e| anyhow!(e))?;
-
         Ok(())
     }
 
-    pub async fn update_friendly_name(
+    /// The registration state of the last change stored for a modem's domain, if any.
+    pub async fn get_last_network_registration(
         &self,
-        phone_number: String,
-        friendly_name: Option<String>,
-    ) -> Result<()> {
-        match friendly_name {
-            Some(name) => {
-                sqlx::query(
-                    "INSERT INTO friendly_names (phone_number, friendly_name) VALUES (?, ?) ON CONFLICT(phone_number) DO UPDATE SET friendly_name = excluded.friendly_name"
-                )
-                    .bind(&phone_number)
-                    .bind(&name)
-                    .execute(&self.pool)
-                    .await
-                    .map_err(|e| anyhow!(e))?;
-            }
-            None => {
-                sqlx::query("DELETE FROM friendly_names WHERE phone_number = ?")
-                    .bind(&phone_number)
-                    .execute(&self.pool)
-                    .await
-                    .map_err(|e| anyhow!(e))?;
-            }
-        }
-
-        Ok(())
-    }
+        modem_id: &str,
+        domain: RegistrationDomain,
+    ) -> Result<Option<u8>> {
+        let registration: Option<i16> = sqlx::query_scalar(&sql(
+            "SELECT registration FROM network_events WHERE modem_id = ? AND domain = ? ORDER BY event_id DESC LIMIT 1",
+        ))
+        .bind(modem_id)
+        .bind(i16::from(u8::from(domain)))
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
 
-    pub async fn get_friendly_name(&self, phone_number: String) -> Result<Option<String>> {
-        sqlx::query_scalar("SELECT friendly_name FROM friendly_names WHERE phone_number = ?")
-            .bind(phone_number)
-            .fetch_optional(&self.pool)
-            .await
-            .map_err(|e| anyhow!(e))
+        Ok(registration.map(|registration| registration as u8))
     }
 
-    pub async fn get_latest_numbers(
+    pub async fn get_network_events(
         &self,
         limit: Option<u64>,
         offset: Option<u64>,
         reverse: bool,
-    ) -> Result<Vec<(String, Option<String>)>> {
+    ) -> Result<Vec<NetworkEvent>> {
         let query = build_pagination_query(
-            "SELECT m.phone_number, f.friendly_name FROM messages m LEFT JOIN friendly_names f ON f.phone_number = m.phone_number GROUP BY m.phone_number",
-            "MAX(m.created_at)",
+            "SELECT event_id, modem_id, domain, registration, created_at FROM network_events",
+            "event_id",
             limit,
             offset,
-            reverse
+            reverse,
         );
 
-        let result: Vec<(String, Option<String>)> = sqlx::query_as(&query)
+        let result = sqlx::query(&sql(&query))
             .fetch_all(&self.pool)
             .await
             .map_err(|e| anyhow!(e))?;
 
-        Ok(result)
+        Ok(result
+            .iter()
+            .map(|row| {
+                let registration = row.get::<i16, _>("registration") as u8;
+                NetworkEvent {
+                    event_id: row.get("event_id"),
+                    modem_id: row.get("modem_id"),
+                    domain: row
+                        .get::<Option<i16>, _>("domain")
+                        .and_then(|domain| RegistrationDomain::try_from(domain as u8).ok()),
+                    registration,
+                    registration_label: registration_label(registration).to_string(),
+                    created_at: timestamp_from_row(row, "created_at"),
+                }
+            })
+            .collect())
     }
 
-    pub async fn get_messages(
+    /// Find the pending outgoing message a delivery report is for. If the report has the time the
+    /// service centre received the message, the closest sent message is used as references wrap.
+    /// References are per modem, so only messages sent by the reporting modem are matched. Queued
+    /// messages aren't matched, as an early part's report can arrive before the rest are sent.
+    pub async fn get_delivery_report_target_message(
         &self,
         phone_number: &str,
-        limit: Option<u64>,
-        offset: Option<u64>,
-        reverse: bool,
-    ) -> Result<Vec<SMSMessage>> {
-        let query = build_pagination_query(
-            "SELECT message_id, phone_number, message_content, message_reference, is_outgoing, status, created_at, completed_at FROM messages WHERE phone_number = ?",
-            "created_at",
-            limit,
-            offset,
-            reverse
+        reference_id: u8,
+        submitted_at: Option<u64>,
+        modem_id: &str,
+    ) -> Result<Option<i64>> {
+        let order_by = if submitted_at.is_some() {
+            "ABS(created_at - ?) ASC, message_id DESC"
+        } else {
+            "message_id DESC"
+        };
+        let query = format!(
+            "SELECT message_id FROM messages WHERE completed_at IS NULL AND is_outgoing = TRUE AND queued = FALSE AND phone_number = ? AND message_reference = ? AND (modem_id = ? OR modem_id IS NULL) ORDER BY {order_by} LIMIT 1"
         );
+        let query = sql(&query);
 
-        let result = sqlx::query(&query)
-            .bind(phone_number)
-            .fetch_all(&self.pool)
+        let mut query = sqlx::query_scalar(&query)
+            .bind(self.phone_number_value(phone_number)?)
+            .bind(i16::from(reference_id))
+            .bind(modem_id);
+        if let Some(submitted_at) = submitted_at {
+            query = query.bind(submitted_at as i64);
+        }
+
+        let result = query
+            .fetch_optional(&self.pool)
             .await
             .map_err(|e| anyhow!(e))?;
 
-        result
-            .into_iter()
-            .map(|row| -> Result<SMSMessage> {
-                Ok(SMSMessage {
-                    message_id: row.get("message_id"),
-                    phone_number: row.get("phone_number"),
-                    message_content: self
-                        .encryption
-                        .decrypt(&row.get::<String, _>("message_content"))?,
-                    message_reference: row.get("message_reference"),
-                    is_outgoing: row.get("is_outgoing"),
-                    status: SMSStatus::try_from(row.get::<u8, _>("status"))?,
-                    created_at: row.get("created_at"),
-                    completed_at: row.get("completed_at"),
-                })
-            })
-            .collect::<Result<Vec<_>, _>>()
+        Ok(result)
     }
 
-    pub async fn get_delivery_reports(
-        &self,
-        message_id: i64,
-        limit: Option<u64>,
-        offset: Option<u64>,
-        reverse: bool,
-    ) -> Result<Vec<SMSDeliveryReport>> {
-        let query = build_pagination_query(
-            "SELECT report_id, message_id, status, is_final, created_at FROM delivery_reports WHERE message_id = ?",
+    /// Count messages created within the last `window` by direction and outcome, with the average
+    /// time from sending to the final delivery report. Dry runs and queued messages aren't counted.
+    pub async fn get_stats(&self, window: Duration) -> Result<SMSMessageStats> {
+        let rows = sqlx::query(&sql(
+            "SELECT is_outgoing, status, completed_at IS NOT NULL AS completed, COUNT(*) AS count, CAST(SUM(completed_at - created_at) AS BIGINT) AS delivery_secs FROM messages WHERE created_at >= unixepoch() - ? GROUP BY is_outgoing, status, completed"
+        ))
+            .bind(window.as_secs() as i64)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        let mut stats = SMSMessageStats {
+            window_secs: window.as_secs(),
+            ..Default::default()
+        };
+        let (mut delivery_secs, mut delivery_count) = (0i64, 0i64);
+        for row in &rows {
+            let count = row.get::<i64, _>("count");
+            let completed = row.get::<bool, _>("completed");
+            if !row.get::<bool, _>("is_outgoing") {
+                stats.received += count as u64;
+                continue;
+            }
+
+            match status_from_i16(row.get("status"))? {
+                SMSStatus::DryRun | SMSStatus::Pending => continue,
+                SMSStatus::Received | SMSStatus::Delivered => {
+                    stats.delivered += count as u64;
+                    if completed {
+                        delivery_secs += row.get::<Option<i64>, _>("delivery_secs").unwrap_or(0);
+                        delivery_count += count;
+                    }
+                }
+                SMSStatus::PermanentFailure | SMSStatus::Expired => stats.failed += count as u64,
+                SMSStatus::TemporaryFailure if completed => stats.failed += count as u64,
+                _ => {}
+            }
+            stats.sent += count as u64;
+        }
+        stats.average_delivery_secs =
+            (delivery_count > 0).then(|| delivery_secs as f64 / delivery_count as f64);
+
+        stats.delivery_reports = sqlx::query_scalar::<_, i64>(&sql(
+            "SELECT COUNT(*) FROM delivery_reports WHERE created_at >= unixepoch() - ?",
+        ))
+        .bind(window.as_secs() as i64)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))? as u64;
+
+        Ok(stats)
+    }
+
+    /// Get the status of a message, only if it has completed with a final status.
+    pub async fn get_final_message_status(&self, message_id: i64) -> Result<Option<SMSStatus>> {
+        let status: Option<i16> = sqlx::query_scalar(&sql(
+            "SELECT status FROM messages WHERE message_id = ? AND completed_at IS NOT NULL",
+        ))
+        .bind(message_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        status.map(status_from_i16).transpose()
+    }
+
+    pub async fn update_message_status(
+        &self,
+        message_id: i64,
+        status: &SMSStatus,
+        completed: bool,
+    ) -> Result<()> {
+        let query = if completed {
+            "UPDATE messages SET status = ?, completed_at = unixepoch() WHERE message_id = ?"
+        } else {
+            "UPDATE messages SET status = ? WHERE message_id = ?"
+        };
+
+        sqlx::query(&sql(query))
+            .bind(i16::from(u8::from(status)))
+            .bind(message_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        Ok(())
+    }
+
+    /// Mark outgoing messages still awaiting a final delivery report past the expiry or their
+    /// validity period, whichever is sooner, as expired. They're completed so they can't be matched
+    /// by a later report with a wrapped reference.
+    pub async fn expire_pending_messages(&self) -> Result<u64> {
+        let result = sqlx::query(&sql(
+            "UPDATE messages SET status = ?, completed_at = unixepoch() WHERE completed_at IS NULL AND is_outgoing = TRUE AND queued = FALSE AND (created_at < unixepoch() - ? OR created_at < unixepoch() - validity_secs)"
+        ))
+            .bind(i16::from(u8::from(&SMSStatus::Expired)))
+            .bind(self.delivery_report_expiry as i64)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Delete messages created more than `retain_days` ago along with their delivery reports and
+    /// send failures, and network events as old, in one transaction, returning how many messages
+    /// were deleted.
+    pub async fn prune_messages(&self) -> Result<u64> {
+        if self.retain_days == 0 {
+            return Ok(0);
+        }
+
+        let filter = match self.prune_only {
+            None => String::new(),
+            Some(PruneOnly::Received) => " AND is_outgoing = FALSE".to_string(),
+            Some(PruneOnly::Delivered) => format!(
+                " AND is_outgoing = TRUE AND completed_at IS NOT NULL AND status IN ({}, {})",
+                u8::from(&SMSStatus::Delivered),
+                u8::from(&SMSStatus::Received)
+            ),
+        };
+        // Queued messages are kept until they're sent, however long the server was stopped for.
+        let selection = format!("created_at < unixepoch() - ? AND queued = FALSE{filter}");
+        let cutoff_secs = i64::from(self.retain_days) * 24 * 60 * 60;
+
+        // Children are deleted explicitly, rather than relying on foreign keys being enabled.
+        let mut transaction = self.pool.begin().await.map_err(|e| anyhow!(e))?;
+        for table in ["delivery_reports", "send_failures"] {
+            let query = sql(&format!(
+                "DELETE FROM {table} WHERE message_id IN (SELECT message_id FROM messages WHERE {selection})"
+            ))
+            .into_owned();
+            sqlx::query(&query)
+                .bind(cutoff_secs)
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| anyhow!(e))?;
+        }
+
+        let query = sql(&format!("DELETE FROM messages WHERE {selection}")).into_owned();
+        let pruned = sqlx::query(&query)
+            .bind(cutoff_secs)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow!(e))?
+            .rows_affected();
+
+        // Network history isn't a message, so it's pruned by age alone whatever `prune_only` is.
+        sqlx::query(&sql(
+            "DELETE FROM network_events WHERE created_at < unixepoch() - ?",
+        ))
+        .bind(cutoff_secs)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| anyhow!(e))?;
+        transaction.commit().await.map_err(|e| anyhow!(e))?;
+
+        // Return the WAL's space to the filesystem, the deleted pages are reused by new messages.
+        #[cfg(feature = "db-sqlite")]
+        if pruned > 0 {
+            sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+                .execute(&self.pool)
+                .await
+                .map_err(|e| anyhow!(e))?;
+        }
+
+        Ok(pruned)
+    }
+
+    /// Checkpoint and truncate the WAL, then optionally `VACUUM` to shrink the database file.
+    /// This uses its own connection so the pool stays free, but a vacuum blocks writes until done.
+    #[cfg(feature = "db-sqlite")]
+    pub async fn run_maintenance(&self, vacuum: bool) -> Result<DatabaseMaintenanceResult> {
+        let options = self.pool.connect_options();
+        let before = database_file_sizes(options.get_filename()).await;
+
+        let mut conn = DbConnection::connect_with(&options).await?;
+        if vacuum {
+            info!("Vacuuming database, writes are blocked until it completes");
+            sqlx::query("VACUUM").execute(&mut conn).await?;
+        }
+        sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .execute(&mut conn)
+            .await?;
+        conn.close().await?;
+
+        Ok(DatabaseMaintenanceResult {
+            vacuumed: vacuum,
+            before,
+            after: database_file_sizes(options.get_filename()).await,
+        })
+    }
+
+    #[cfg(feature = "db-postgres")]
+    pub async fn run_maintenance(&self, _vacuum: bool) -> Result<DatabaseMaintenanceResult> {
+        Err(anyhow!(
+            "Database maintenance is only supported with SQLite, Postgres vacuums automatically"
+        ))
+    }
+
+    /// Recompute outgoing message statuses from their stored delivery reports, returning how many
+    /// messages changed. A message takes its first final report, or its latest report if still pending.
+    pub async fn reconcile_delivery_reports(&self) -> Result<u64> {
+        let mut transaction = self.pool.begin().await.map_err(|e| anyhow!(e))?;
+
+        // Messages with a final report are completed at the time of that report.
+        let completed = sqlx::query(&sql(
+            "UPDATE messages SET status = f.status, completed_at = COALESCE(messages.completed_at, f.created_at) \
+             FROM (SELECT d.message_id, d.status, d.created_at FROM delivery_reports d WHERE d.is_final = TRUE AND d.report_id = \
+                (SELECT MIN(earliest.report_id) FROM delivery_reports earliest WHERE earliest.message_id = d.message_id AND earliest.is_final = TRUE)) AS f \
+             WHERE messages.message_id = f.message_id AND messages.is_outgoing = TRUE \
+             AND (messages.status <> f.status OR messages.completed_at IS NULL)"
+        ))
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        // Messages still pending follow their latest report.
+        let pending = sqlx::query(&sql(
+            "UPDATE messages SET status = l.status \
+             FROM (SELECT d.message_id, d.status FROM delivery_reports d WHERE d.report_id = \
+                (SELECT MAX(latest.report_id) FROM delivery_reports latest WHERE latest.message_id = d.message_id)) AS l \
+             WHERE messages.message_id = l.message_id AND messages.is_outgoing = TRUE \
+             AND messages.completed_at IS NULL AND messages.status <> l.status"
+        ))
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        transaction.commit().await.map_err(|e| anyhow!(e))?;
+        Ok(completed.rows_affected() + pending.rows_affected())
+    }
+
+    pub async fn update_friendly_name(
+        &self,
+        phone_number: String,
+        friendly_name: Option<String>,
+    ) -> Result<()> {
+        let phone_number = self.phone_number_value(&phone_number)?;
+        match friendly_name {
+            Some(name) => {
+                sqlx::query(&sql(
+                    "INSERT INTO friendly_names (phone_number, friendly_name) VALUES (?, ?) ON CONFLICT(phone_number) DO UPDATE SET friendly_name = excluded.friendly_name"
+                ))
+                    .bind(&phone_number)
+                    .bind(&name)
+                    .execute(&self.pool)
+                    .await
+                    .map_err(|e| anyhow!(e))?;
+            }
+            None => {
+                sqlx::query(&sql("DELETE FROM friendly_names WHERE phone_number = ?"))
+                    .bind(&phone_number)
+                    .execute(&self.pool)
+                    .await
+                    .map_err(|e| anyhow!(e))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_friendly_name(&self, phone_number: String) -> Result<Option<String>> {
+        sqlx::query_scalar(&sql(
+            "SELECT friendly_name FROM friendly_names WHERE phone_number = ?",
+        ))
+        .bind(self.phone_number_value(&phone_number)?)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    /// Add a phone number to the blocklist, returning false if it was already there.
+    pub async fn add_to_blocklist(&self, phone_number: &str, reason: Option<&str>) -> Result<bool> {
+        let result = sqlx::query(&sql(
+            "INSERT INTO blocklist (phone_number, reason) VALUES (?, ?) ON CONFLICT(phone_number) DO NOTHING",
+        ))
+        .bind(self.phone_number_value(phone_number)?)
+        .bind(reason)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Remove a phone number from the blocklist, returning false if it wasn't there.
+    pub async fn remove_from_blocklist(&self, phone_number: &str) -> Result<bool> {
+        let result = sqlx::query(&sql("DELETE FROM blocklist WHERE phone_number = ?"))
+            .bind(self.phone_number_value(phone_number)?)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Mark unread messages as read, returning how many were changed. A conversation is marked
+    /// with one update, and outgoing messages are always stored as read so are never changed.
+    pub async fn mark_read(&self, target: &MarkReadTarget) -> Result<u64> {
+        let result = match target {
+            MarkReadTarget::Message(message_id) => {
+                sqlx::query(&sql(
+                    "UPDATE messages SET is_read = ? WHERE message_id = ? AND is_read = ?",
+                ))
+                .bind(true)
+                .bind(*message_id)
+                .bind(false)
+                .execute(&self.pool)
+                .await
+            }
+            MarkReadTarget::PhoneNumber(phone_number) => {
+                sqlx::query(&sql(
+                    "UPDATE messages SET is_read = ? WHERE phone_number = ? AND is_read = ?",
+                ))
+                .bind(true)
+                .bind(self.phone_number_value(phone_number)?)
+                .bind(false)
+                .execute(&self.pool)
+                .await
+            }
+        }
+        .map_err(|e| anyhow!(e))?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn is_blocklisted(&self, phone_number: &str) -> Result<bool> {
+        sqlx::query_scalar(&sql(
+            "SELECT EXISTS(SELECT 1 FROM blocklist WHERE phone_number = ?)",
+        ))
+        .bind(self.phone_number_value(phone_number)?)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    pub async fn get_blocklist(
+        &self,
+        limit: Option<u64>,
+        offset: Option<u64>,
+        reverse: bool,
+    ) -> Result<Vec<BlocklistEntry>> {
+        let query = build_pagination_query(
+            "SELECT phone_number, reason, created_at FROM blocklist",
+            "created_at",
+            limit,
+            offset,
+            reverse,
+        );
+
+        let result = sqlx::query(&sql(&query))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        result
+            .iter()
+            .map(|row| {
+                Ok(BlocklistEntry {
+                    phone_number: self.phone_number_from_row(row)?,
+                    reason: row.get("reason"),
+                    created_at: timestamp_from_row(row, "created_at"),
+                })
+            })
+            .collect()
+    }
+
+    pub async fn get_latest_numbers(
+        &self,
+        limit: Option<u64>,
+        offset: Option<u64>,
+        reverse: bool,
+    ) -> Result<Vec<(String, Option<String>)>> {
+        let query = build_pagination_query(
+            "SELECT m.phone_number, f.friendly_name FROM messages m LEFT JOIN friendly_names f ON f.phone_number = m.phone_number GROUP BY m.phone_number, f.friendly_name",
+            "MAX(m.created_at)",
+            limit,
+            offset,
+            reverse
+        );
+
+        let result = sqlx::query(&sql(&query))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        result
+            .iter()
+            .map(|row| Ok((self.phone_number_from_row(row)?, row.get("friendly_name"))))
+            .collect()
+    }
+
+    /// Get every phone number messaged with its latest message and message count, most recent
+    /// first. The latest message is picked with a window function, so it's one query.
+    pub async fn get_conversations(
+        &self,
+        limit: Option<u64>,
+        offset: Option<u64>,
+        reverse: bool,
+    ) -> Result<Vec<SMSConversation>> {
+        let query = build_pagination_query(
+            "SELECT c.phone_number, f.friendly_name, c.message_count, c.unread_count, c.message_id, c.message_content, c.is_outgoing, c.created_at FROM (SELECT phone_number, message_id, message_content, is_outgoing, created_at, ROW_NUMBER() OVER (PARTITION BY phone_number ORDER BY created_at DESC, message_id DESC) AS position, COUNT(*) OVER (PARTITION BY phone_number) AS message_count, SUM(CASE WHEN is_outgoing OR is_read THEN 0 ELSE 1 END) OVER (PARTITION BY phone_number) AS unread_count FROM messages) c LEFT JOIN friendly_names f ON f.phone_number = c.phone_number WHERE c.position = 1",
+            "c.created_at",
+            limit,
+            offset,
+            reverse,
+        );
+
+        let result = sqlx::query(&sql(&query))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        result
+            .iter()
+            .map(|row| {
+                let message_id: i64 = row.get("message_id");
+                let preview = match self
+                    .encryption
+                    .decrypt(&row.get::<String, _>("message_content"))
+                {
+                    Ok(content) => Some(content.chars().take(CONVERSATION_PREVIEW_CHARS).collect()),
+                    Err(e) => {
+                        warn!("Failed to decrypt preview of message #{message_id}: {e:?}");
+                        None
+                    }
+                };
+
+                Ok(SMSConversation {
+                    phone_number: self.phone_number_from_row(row)?,
+                    friendly_name: row.get("friendly_name"),
+                    message_count: row.get::<i64, _>("message_count") as u64,
+                    unread_count: row.get::<i64, _>("unread_count") as u64,
+                    latest_message_id: message_id,
+                    preview,
+                    is_outgoing: row.get("is_outgoing"),
+                    created_at: timestamp_from_row(row, "created_at"),
+                })
+            })
+            .collect()
+    }
+
+    pub async fn get_messages(
+        &self,
+        phone_number: &str,
+        limit: Option<u64>,
+        offset: Option<u64>,
+        reverse: bool,
+    ) -> Result<Vec<SMSMessage>> {
+        let query = build_pagination_query(
+            "SELECT message_id, phone_number, message_content, message_reference, is_outgoing, status, created_at, completed_at, modem_id, data_coding, is_read FROM messages WHERE phone_number = ?",
             "created_at",
             limit,
             offset,
             reverse
         );
 
-        sqlx::query_as(&query)
+        let result = sqlx::query(&sql(&query))
+            .bind(self.phone_number_value(phone_number)?)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        result
+            .iter()
+            .map(|row| self.message_from_row(row))
+            .collect::<Result<Vec<_>, _>>()
+    }
+
+    /// Get a single message, including its raw PDU if one was stored.
+    pub async fn get_message(&self, message_id: i64) -> Result<Option<SMSMessage>> {
+        let result = sqlx::query(&sql(
+            "SELECT message_id, phone_number, message_content, message_reference, is_outgoing, status, created_at, completed_at, modem_id, data_coding, is_read, raw_pdu FROM messages WHERE message_id = ?"
+        ))
             .bind(message_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        let Some(row) = result else {
+            return Ok(None);
+        };
+        let mut message = self.message_from_row(&row)?;
+        message.raw_pdu = row
+            .get::<Option<String>, _>("raw_pdu")
+            .map(|raw_pdu| self.encryption.decrypt(&raw_pdu))
+            .transpose()?;
+
+        Ok(Some(message))
+    }
+
+    /// Get every message created from `from` until before `to` (unix seconds), oldest first.
+    pub async fn get_messages_between(&self, from: u64, to: u64) -> Result<Vec<SMSMessage>> {
+        let result = sqlx::query(&sql(
+            "SELECT message_id, phone_number, message_content, message_reference, is_outgoing, status, created_at, completed_at, modem_id, data_coding, is_read FROM messages WHERE created_at >= ? AND created_at < ? ORDER BY created_at ASC, message_id ASC"
+        ))
+            .bind(from as i64)
+            .bind(to as i64)
             .fetch_all(&self.pool)
             .await
-            .map_err(|e| anyhow!(e))
+            .map_err(|e| anyhow!(e))?;
+
+        result
+            .iter()
+            .map(|row| self.message_from_row(row))
+            .collect::<Result<Vec<_>, _>>()
+    }
+
+    /// Get every delivery report received from `from` until before `to` (unix seconds) with
+    /// its message ID and raw TP-Status, oldest first. The TP-Status is `None` for reports
+    /// stored before it was kept.
+    pub async fn get_delivery_reports_between(
+        &self,
+        from: u64,
+        to: u64,
+    ) -> Result<Vec<(i64, Option<u8>, SMSDeliveryReport)>> {
+        let result = sqlx::query(&sql(
+            "SELECT report_id, message_id, status, tp_status, is_final, created_at FROM delivery_reports WHERE created_at >= ? AND created_at < ? ORDER BY created_at ASC, report_id ASC"
+        ))
+            .bind(from as i64)
+            .bind(to as i64)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        result
+            .iter()
+            .map(|row| {
+                Ok((
+                    row.get("message_id"),
+                    row.get::<Option<i16>, _>("tp_status")
+                        .map(u8::try_from)
+                        .transpose()?,
+                    delivery_report_from_row(row)?,
+                ))
+            })
+            .collect()
+    }
+
+    fn message_from_row(&self, row: &DbRow) -> Result<SMSMessage> {
+        Ok(SMSMessage {
+            message_id: row.get("message_id"),
+            phone_number: self.phone_number_from_row(row)?,
+            message_content: self
+                .encryption
+                .decrypt(&row.get::<String, _>("message_content"))?,
+            message_reference: row
+                .get::<Option<i16>, _>("message_reference")
+                .map(u8::try_from)
+                .transpose()?,
+            is_outgoing: row.get("is_outgoing"),
+            status: status_from_i16(row.get("status"))?,
+            created_at: timestamp_from_row(row, "created_at"),
+            completed_at: timestamp_from_row(row, "completed_at"),
+            modem_id: row.get("modem_id"),
+            raw_pdu: None,
+            data_coding: SMSDataCoding::try_from(u8::try_from(row.get::<i16, _>("data_coding"))?)?,
+            is_read: row.get("is_read"),
+        })
+    }
+
+    pub async fn get_delivery_reports(
+        &self,
+        message_id: i64,
+        limit: Option<u64>,
+        offset: Option<u64>,
+        reverse: bool,
+    ) -> Result<Vec<SMSDeliveryReport>> {
+        let query = build_pagination_query(
+            "SELECT report_id, message_id, status, is_final, created_at FROM delivery_reports WHERE message_id = ?",
+            "created_at",
+            limit,
+            offset,
+            reverse
+        );
+
+        let result = sqlx::query(&sql(&query))
+            .bind(message_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        result.iter().map(delivery_report_from_row).collect()
+    }
+}
+
+fn delivery_report_from_row(row: &DbRow) -> Result<SMSDeliveryReport> {
+    Ok(SMSDeliveryReport {
+        report_id: row.get("report_id"),
+        status: status_from_i16(row.get("status"))?,
+        is_final: row.get("is_final"),
+        created_at: timestamp_from_row(row, "created_at"),
+    })
+}
+
+/// Send options that can't be read are logged and skipped, as the message itself is still usable.
+fn send_options_from_row(row: &DbRow, message_id: i64) -> Option<QueuedSendOptions> {
+    let options = row.get::<Option<String>, _>("send_options")?;
+    match serde_json::from_str(&options) {
+        Ok(options) => Some(options),
+        Err(e) => {
+            warn!("Failed to read the send options of message #{message_id}: {e}");
+            None
+        }
+    }
+}
+
+#[cfg(feature = "db-sqlite")]
+fn sqlite_journal_mode(mode: DatabaseJournalMode) -> SqliteJournalMode {
+    match mode {
+        DatabaseJournalMode::Delete => SqliteJournalMode::Delete,
+        DatabaseJournalMode::Truncate => SqliteJournalMode::Truncate,
+        DatabaseJournalMode::Persist => SqliteJournalMode::Persist,
+        DatabaseJournalMode::Memory => SqliteJournalMode::Memory,
+        DatabaseJournalMode::Wal => SqliteJournalMode::Wal,
+        DatabaseJournalMode::Off => SqliteJournalMode::Off,
+    }
+}
+
+/// Get the database and WAL file sizes, which are 0 for an in-memory database.
+#[cfg(feature = "db-sqlite")]
+async fn database_file_sizes(path: &std::path::Path) -> DatabaseFileSizes {
+    let size = |path: std::path::PathBuf| async move {
+        tokio::fs::metadata(path)
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or(0)
+    };
+
+    let mut wal_path = path.as_os_str().to_owned();
+    wal_path.push("-wal");
+    DatabaseFileSizes {
+        database_bytes: size(path.to_path_buf()).await,
+        wal_bytes: size(wal_path.into()).await,
+    }
+}
+
+/// Statuses are stored as SMALLINT, since Postgres has no unsigned 8-bit type.
+fn status_from_i16(value: i16) -> Result<SMSStatus> {
+    SMSStatus::try_from(u8::try_from(value)?)
+}
+
+#[cfg(all(test, feature = "db-sqlite"))]
+mod tests {
+    use super::*;
+    use crate::types::SMSEncoding;
+    use sqlx::SqliteConnection;
+
+    const LEGACY_SCHEMA_SQL: &str = include_str!("migrations/sqlite/0001_initial.sql");
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    async fn memory_connection() -> SqliteConnection {
+        let options = SqliteConnectOptions::new()
+            .filename(":memory:")
+            .foreign_keys(false);
+        SqliteConnection::connect_with(&options).await.unwrap()
+    }
+
+    /// A message with defaults for every field a test doesn't set.
+    fn test_message(phone_number: &str, is_outgoing: bool, status: SMSStatus) -> SMSMessage {
+        SMSMessage {
+            message_id: None,
+            phone_number: phone_number.to_string(),
+            message_content: "hello".to_string(),
+            message_reference: None,
+            is_outgoing,
+            status,
+            created_at: None,
+            completed_at: None,
+            modem_id: None,
+            raw_pdu: None,
+            data_coding: SMSDataCoding::Text,
+            is_read: false,
+        }
+    }
+
+    #[test]
+    fn test_migrations_are_idempotent() {
+        block_on(async {
+            let mut conn = memory_connection().await;
+            run_migrations(&mut conn).await.unwrap();
+            run_migrations(&mut conn).await.unwrap();
+
+            let versions: Vec<i64> =
+                sqlx::query_scalar("SELECT version FROM schema_migrations ORDER BY version")
+                    .fetch_all(&mut conn)
+                    .await
+                    .unwrap();
+            assert_eq!(
+                versions,
+                MIGRATIONS.iter().map(|(v, _)| *v).collect::<Vec<_>>()
+            );
+        });
+    }
+
+    #[test]
+    fn test_migrations_upgrade_legacy_database() {
+        block_on(async {
+            // A database created by the old CREATE-on-boot schema, with no migrations table.
+            let mut conn = memory_connection().await;
+            sqlx::raw_sql(LEGACY_SCHEMA_SQL)
+                .execute(&mut conn)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO messages (phone_number, message_content, is_outgoing, status) VALUES ('+44', 'hello', 1, 0)")
+                .execute(&mut conn)
+                .await
+                .unwrap();
+            sqlx::query(
+                "INSERT INTO delivery_reports (message_id, status, is_final) VALUES (1, 0, 1)",
+            )
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+            run_migrations(&mut conn).await.unwrap();
+
+            // Existing rows are kept, and the Expired status is now allowed.
+            let reports: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM delivery_reports")
+                .fetch_one(&mut conn)
+                .await
+                .unwrap();
+            assert_eq!(reports, 1);
+
+            sqlx::query("UPDATE messages SET status = 5 WHERE message_id = 1")
+                .execute(&mut conn)
+                .await
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn test_pagination_query_direction() {
+        let query =
+            build_pagination_query("SELECT * FROM messages", "created_at", None, None, false);
+        assert_eq!(query, "SELECT * FROM messages ORDER BY created_at DESC");
+
+        let query = build_pagination_query(
+            "SELECT * FROM messages",
+            "created_at",
+            Some(10),
+            Some(20),
+            true,
+        );
+        assert_eq!(
+            query,
+            "SELECT * FROM messages ORDER BY created_at ASC LIMIT 10 OFFSET 20"
+        );
+
+        block_on(async {
+            let mut conn = memory_connection().await;
+            run_migrations(&mut conn).await.unwrap();
+            for (content, created_at) in [("old", 100), ("new", 300), ("middle", 200)] {
+                sqlx::query("INSERT INTO messages (phone_number, message_content, is_outgoing, status, created_at) VALUES ('+44', ?, 0, 0, ?)")
+                    .bind(content)
+                    .bind(created_at)
+                    .execute(&mut conn)
+                    .await
+                    .unwrap();
+            }
+
+            let fetch = |reverse| {
+                build_pagination_query(
+                    "SELECT message_content FROM messages WHERE phone_number = '+44'",
+                    "created_at",
+                    Some(2),
+                    None,
+                    reverse,
+                )
+            };
+            let newest: Vec<String> = sqlx::query_scalar(&fetch(false))
+                .fetch_all(&mut conn)
+                .await
+                .unwrap();
+            assert_eq!(newest, vec!["new", "middle"]);
+
+            let oldest: Vec<String> = sqlx::query_scalar(&fetch(true))
+                .fetch_all(&mut conn)
+                .await
+                .unwrap();
+            assert_eq!(oldest, vec!["old", "middle"]);
+        });
+    }
+
+    #[test]
+    fn test_postgres_sql_translation() {
+        assert_eq!(
+            to_postgres_sql("UPDATE messages SET status = ?, completed_at = unixepoch() WHERE message_id = ?"),
+            "UPDATE messages SET status = $1, completed_at = CAST(EXTRACT(EPOCH FROM NOW()) AS BIGINT) WHERE message_id = $2"
+        );
+        assert_eq!(sql("SELECT 1 WHERE ? = ?"), "SELECT 1 WHERE ? = ?");
+    }
+
+    #[test]
+    fn test_inserts_return_ids() {
+        block_on(async {
+            let database = SMSDatabase::connect_in_memory().await.unwrap();
+            let message = SMSMessage {
+                message_reference: Some(200),
+                modem_id: Some("uk".to_string()),
+                ..test_message("+44", true, SMSStatus::Sent)
+            };
+            let first = database.insert_message(&message, false).await.unwrap();
+            let second = database.insert_message(&message, true).await.unwrap();
+            assert_eq!((first, second), (1, 2));
+
+            let stored = database.get_message(second).await.unwrap().unwrap();
+            assert_eq!(stored.message_content, "hello");
+            assert_eq!(stored.message_reference, Some(200));
+            assert!(stored.completed_at.is_some());
+            assert_eq!(
+                database
+                    .get_delivery_report_target_message(&message.phone_number, 200, None, "uk")
+                    .await
+                    .unwrap(),
+                Some(first)
+            );
+
+            let report_id = database
+                .insert_delivery_report(first, 1, 0, true)
+                .await
+                .unwrap();
+            let reports = database
+                .get_delivery_reports(first, None, None, false)
+                .await
+                .unwrap();
+            assert_eq!(reports.len(), 1);
+            assert_eq!(reports[0].report_id, Some(report_id));
+            assert!(matches!(reports[0].status, SMSStatus::Delivered));
+        });
+    }
+
+    #[test]
+    fn test_in_memory_database_is_shared() {
+        block_on(async {
+            let database = SMSDatabase::connect_in_memory().await.unwrap();
+
+            // Hold every pooled connection at once, so each must see the migrated schema.
+            let mut connections = Vec::new();
+            for _ in 0..4 {
+                connections.push(database.pool.acquire().await.unwrap());
+            }
+            for conn in &mut connections {
+                let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM messages")
+                    .fetch_one(&mut **conn)
+                    .await
+                    .unwrap();
+                assert_eq!(count, 0);
+            }
+            drop(connections);
+
+            // Separate in-memory databases aren't shared with each other.
+            let other = SMSDatabase::connect_in_memory().await.unwrap();
+            database
+                .update_friendly_name("+44".to_string(), Some("Alice".to_string()))
+                .await
+                .unwrap();
+            assert_eq!(
+                database.get_friendly_name("+44".to_string()).await.unwrap(),
+                Some("Alice".to_string())
+            );
+            assert_eq!(
+                other.get_friendly_name("+44".to_string()).await.unwrap(),
+                None
+            );
+        });
+    }
+
+    #[test]
+    fn test_in_memory_journal_mode() {
+        block_on(async {
+            let config = |journal_mode| DatabaseConfig {
+                database_url: IN_MEMORY_DATABASE_URL.to_string(),
+                encryption_key: [7; 32],
+                delivery_report_expiry: 60,
+                max_connections: 1,
+                min_connections: 1,
+                busy_timeout_secs: 5,
+                cache_size_kb: 256,
+                journal_mode,
+                synchronous: DatabaseSynchronous::Full,
+                store_raw_pdu: false,
+                encrypt_phone_numbers: false,
+                retain_days: 0,
+                prune_only: None,
+            };
+
+            let database = SMSDatabase::connect(config(Some(DatabaseJournalMode::Off)))
+                .await
+                .unwrap();
+            let mode: String = sqlx::query_scalar("PRAGMA journal_mode")
+                .fetch_one(&database.pool)
+                .await
+                .unwrap();
+            assert_eq!(mode, "off");
+
+            let Err(e) = SMSDatabase::connect(config(Some(DatabaseJournalMode::Wal))).await else {
+                panic!("WAL shouldn't be allowed for an in-memory database");
+            };
+            assert!(e.to_string().contains("journal_mode"), "{e}");
+        });
+    }
+
+    #[test]
+    fn test_delivery_report_matches_closest_submit_time() {
+        block_on(async {
+            let database = SMSDatabase::connect_in_memory().await.unwrap();
+            let message = SMSMessage {
+                message_reference: Some(5),
+                modem_id: Some("uk".to_string()),
+                ..test_message("+44", true, SMSStatus::Sent)
+            };
+
+            // The reference has wrapped, so two pending messages share it.
+            let older = database.insert_message(&message, false).await.unwrap();
+            let newer = database.insert_message(&message, false).await.unwrap();
+            for (message_id, created_at) in [(older, 1_000), (newer, 2_000)] {
+                sqlx::query("UPDATE messages SET created_at = ? WHERE message_id = ?")
+                    .bind(created_at)
+                    .bind(message_id)
+                    .execute(&database.pool)
+                    .await
+                    .unwrap();
+            }
+
+            let target = |submitted_at| {
+                database.get_delivery_report_target_message(
+                    &message.phone_number,
+                    5,
+                    submitted_at,
+                    "uk",
+                )
+            };
+            assert_eq!(target(Some(1_003)).await.unwrap(), Some(older));
+            assert_eq!(target(Some(1_990)).await.unwrap(), Some(newer));
+            assert_eq!(target(None).await.unwrap(), Some(newer));
+        });
+    }
+
+    #[test]
+    fn test_delivery_report_matches_sending_modem() {
+        block_on(async {
+            let database = SMSDatabase::connect_in_memory().await.unwrap();
+            let message = |modem_id: Option<&str>| SMSMessage {
+                message_reference: Some(9),
+                modem_id: modem_id.map(str::to_string),
+                ..test_message("+44", true, SMSStatus::Sent)
+            };
+
+            // Each modem numbers its own messages, so both can use the same reference.
+            let uk = database
+                .insert_message(&message(Some("uk")), false)
+                .await
+                .unwrap();
+            let us = database
+                .insert_message(&message(Some("us")), false)
+                .await
+                .unwrap();
+            let phone_number = "+44".to_string();
+            let target = |modem_id| {
+                database.get_delivery_report_target_message(&phone_number, 9, None, modem_id)
+            };
+            assert_eq!(target("uk").await.unwrap(), Some(uk));
+            assert_eq!(target("us").await.unwrap(), Some(us));
+            assert_eq!(target("fr").await.unwrap(), None);
+            assert_eq!(
+                database
+                    .get_message(us)
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .modem_id
+                    .as_deref(),
+                Some("us")
+            );
+
+            // Messages stored before modems had ids can be matched by any modem.
+            let legacy = database
+                .insert_message(&message(None), false)
+                .await
+                .unwrap();
+            assert_eq!(target("fr").await.unwrap(), Some(legacy));
+        });
+    }
+
+    #[test]
+    fn test_reconcile_delivery_reports_is_idempotent() {
+        block_on(async {
+            let database = SMSDatabase::connect_in_memory().await.unwrap();
+            let message = SMSMessage {
+                message_reference: Some(1),
+                modem_id: Some("uk".to_string()),
+                ..test_message("+44", true, SMSStatus::Sent)
+            };
+
+            // Reports were stored without the message statuses following them.
+            let delivered = database.insert_message(&message, false).await.unwrap();
+            let pending = database.insert_message(&message, false).await.unwrap();
+            let untouched = database.insert_message(&message, false).await.unwrap();
+            for (message_id, status, is_final) in [
+                (delivered, SMSStatus::TemporaryFailure, false),
+                (delivered, SMSStatus::Delivered, true),
+                (delivered, SMSStatus::PermanentFailure, true),
+                (pending, SMSStatus::TemporaryFailure, false),
+            ] {
+                database
+                    .insert_delivery_report(message_id, u8::from(&status), 0, is_final)
+                    .await
+                    .unwrap();
+            }
+
+            assert_eq!(database.reconcile_delivery_reports().await.unwrap(), 2);
+            assert_eq!(database.reconcile_delivery_reports().await.unwrap(), 0);
+
+            let delivered = database.get_message(delivered).await.unwrap().unwrap();
+            assert!(matches!(delivered.status, SMSStatus::Delivered));
+            assert!(delivered.completed_at.is_some());
+
+            let pending = database.get_message(pending).await.unwrap().unwrap();
+            assert!(matches!(pending.status, SMSStatus::TemporaryFailure));
+            assert!(pending.completed_at.is_none());
+
+            let untouched = database.get_message(untouched).await.unwrap().unwrap();
+            assert!(matches!(untouched.status, SMSStatus::Sent));
+        });
+    }
+
+    #[test]
+    fn test_get_conversations() {
+        block_on(async {
+            let database = SMSDatabase::connect_in_memory().await.unwrap();
+            let message = |phone_number: &str, content: &str, is_outgoing: bool| SMSMessage {
+                message_content: content.to_string(),
+                ..test_message(phone_number, is_outgoing, SMSStatus::Received)
+            };
+
+            let mut message_ids = Vec::new();
+            for (created_at, message) in [
+                (1_000, message("+441", "first", false)),
+                (2_000, message("+442", "other", false)),
+                (3_000, message("+441", &"x".repeat(150), true)),
+                (4_000, message("+443", "unreadable", false)),
+            ] {
+                let message_id = database.insert_message(&message, true).await.unwrap();
+                sqlx::query("UPDATE messages SET created_at = ? WHERE message_id = ?")
+                    .bind(created_at)
+                    .bind(message_id)
+                    .execute(&database.pool)
+                    .await
+                    .unwrap();
+                message_ids.push(message_id);
+            }
+            database
+                .update_friendly_name("+442".to_string(), Some("Sam".to_string()))
+                .await
+                .unwrap();
+
+            // Content that doesn't decrypt only loses its preview.
+            sqlx::query("UPDATE messages SET message_content = 'corrupt' WHERE message_id = ?")
+                .bind(message_ids[3])
+                .execute(&database.pool)
+                .await
+                .unwrap();
+
+            let conversations = database.get_conversations(None, None, false).await.unwrap();
+            let summary = conversations
+                .iter()
+                .map(|c| {
+                    (
+                        c.phone_number.as_str(),
+                        c.message_count,
+                        c.latest_message_id,
+                    )
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                summary,
+                [
+                    ("+443", 1, message_ids[3]),
+                    ("+441", 2, message_ids[2]),
+                    ("+442", 1, message_ids[1]),
+                ]
+            );
+            assert_eq!(conversations[0].preview, None);
+            assert_eq!(conversations[1].preview, Some("x".repeat(100)));
+            assert!(conversations[1].is_outgoing);
+            assert_eq!(conversations[1].created_at, Some(3_000));
+            assert_eq!(conversations[2].friendly_name.as_deref(), Some("Sam"));
+
+            let oldest = database
+                .get_conversations(Some(1), None, true)
+                .await
+                .unwrap();
+            assert_eq!(oldest[0].phone_number, "+442");
+        });
+    }
+
+    #[test]
+    fn test_queued_messages() {
+        block_on(async {
+            let database = SMSDatabase::connect_in_memory().await.unwrap();
+            let mut message = SMSMessage {
+                modem_id: Some("uk".to_string()),
+                is_read: true,
+                ..test_message("+441", true, SMSStatus::Sent)
+            };
+            let send_options = QueuedSendOptions {
+                flash: true,
+                validity_period: Some(10),
+                timeout: None,
+                encoding: SMSEncoding::Ucs2,
+                user_data_header: Some(vec![0x06, 0x05, 0x04, 0x0b, 0x84, 0x23, 0xf0]),
+                priority: false,
+                modem_id: None,
+                override_blocklist: false,
+            };
+
+            let sent_id = database
+                .insert_queued_message(&message, &send_options, Duration::from_secs(3_600))
+                .await
+                .unwrap();
+            let unsent_id = database
+                .insert_queued_message(&message, &send_options, Duration::from_secs(3_600))
+                .await
+                .unwrap();
+            database.set_queued_reference(sent_id, 7).await.unwrap();
+            database.set_queued_reference(sent_id, 8).await.unwrap();
+
+            // Only the first part's reference is kept, and a report can't match it while queued.
+            let queued = database.get_queued_messages().await.unwrap();
+            assert_eq!(queued.len(), 2);
+            let (sent, options) = &queued[0];
+            assert!(matches!(sent.status, SMSStatus::Pending));
+            assert_eq!(sent.message_reference, Some(7));
+            assert_eq!(queued[1].0.message_reference, None);
+            let options = options.as_ref().unwrap();
+            assert_eq!(options.encoding, SMSEncoding::Ucs2);
+            assert_eq!(options.user_data_header, send_options.user_data_header);
+            let target = || database.get_delivery_report_target_message("+441", 7, None, "uk");
+            assert_eq!(target().await.unwrap(), None);
+
+            message.message_reference = Some(7);
+            database
+                .complete_queued_message(sent_id, &message, false)
+                .await
+                .unwrap();
+            assert_eq!(target().await.unwrap(), Some(sent_id));
+            assert_eq!(database.expire_pending_messages().await.unwrap(), 0);
+
+            // Sent messages keep their options for resending.
+            let options = database.get_send_options(sent_id).await.unwrap().unwrap();
+            assert!(options.flash);
+            assert_eq!(options.validity_period, Some(10));
+
+            database.delete_queued_message(unsent_id).await.unwrap();
+            assert!(database.get_queued_messages().await.unwrap().is_empty());
+            assert!(database.get_message(unsent_id).await.unwrap().is_none());
+
+            // Sent messages aren't removed as if they were unsent queued ones.
+            database.delete_queued_message(sent_id).await.unwrap();
+            let sent = database.get_message(sent_id).await.unwrap().unwrap();
+            assert!(matches!(sent.status, SMSStatus::Sent));
+        });
+    }
+
+    #[test]
+    fn test_expire_pending_messages() {
+        block_on(async {
+            let database = SMSDatabase::connect_in_memory().await.unwrap();
+            let send_options = QueuedSendOptions {
+                flash: false,
+                validity_period: None,
+                timeout: None,
+                encoding: SMSEncoding::Auto,
+                user_data_header: None,
+                priority: false,
+                modem_id: None,
+                override_blocklist: false,
+            };
+
+            // Expired once either the validity period or the 60 second expiry passes.
+            let mut message_ids = Vec::new();
+            for (validity_secs, age) in [(10, 30), (3_600, 30), (3_600, 120)] {
+                let message = test_message("+44", true, SMSStatus::Sent);
+                let message_id = database
+                    .insert_queued_message(
+                        &message,
+                        &send_options,
+                        Duration::from_secs(validity_secs),
+                    )
+                    .await
+                    .unwrap();
+                database
+                    .complete_queued_message(message_id, &message, false)
+                    .await
+                    .unwrap();
+                sqlx::query(
+                    "UPDATE messages SET created_at = unixepoch() - ? WHERE message_id = ?",
+                )
+                .bind(age)
+                .bind(message_id)
+                .execute(&database.pool)
+                .await
+                .unwrap();
+                message_ids.push(message_id);
+            }
+
+            assert_eq!(database.expire_pending_messages().await.unwrap(), 2);
+            let mut statuses = Vec::new();
+            for message_id in message_ids {
+                let message = database.get_message(message_id).await.unwrap().unwrap();
+                statuses.push(message.status.as_str());
+            }
+            assert_eq!(statuses, ["expired", "sent", "expired"]);
+        });
+    }
+
+    #[test]
+    fn test_mark_read() {
+        block_on(async {
+            let database = SMSDatabase::connect_in_memory().await.unwrap();
+            let mut message_ids = Vec::new();
+            for message in [
+                test_message("+441", false, SMSStatus::Received),
+                test_message("+441", false, SMSStatus::Received),
+                test_message("+441", true, SMSStatus::Received),
+                test_message("+442", false, SMSStatus::Received),
+            ] {
+                message_ids.push(database.insert_message(&message, true).await.unwrap());
+            }
+            let unread_counts = || async {
+                let mut counts = database
+                    .get_conversations(None, None, false)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|c| (c.phone_number, c.unread_count))
+                    .collect::<Vec<_>>();
+                counts.sort();
+                counts
+            };
+
+            // Outgoing messages are stored as read even if the message says otherwise.
+            let outgoing = database.get_message(message_ids[2]).await.unwrap().unwrap();
+            assert!(outgoing.is_read);
+            assert_eq!(
+                unread_counts().await,
+                [("+441".to_string(), 2), ("+442".to_string(), 1)]
+            );
+
+            let target = MarkReadTarget::Message(message_ids[0]);
+            assert_eq!(database.mark_read(&target).await.unwrap(), 1);
+            assert_eq!(database.mark_read(&target).await.unwrap(), 0);
+
+            let target = MarkReadTarget::PhoneNumber("+441".to_string());
+            assert_eq!(database.mark_read(&target).await.unwrap(), 1);
+            assert_eq!(
+                unread_counts().await,
+                [("+441".to_string(), 0), ("+442".to_string(), 1)]
+            );
+        });
+    }
+
+    #[test]
+    fn test_get_between_is_half_open() {
+        block_on(async {
+            let database = SMSDatabase::connect_in_memory().await.unwrap();
+            let message = SMSMessage {
+                message_reference: Some(1),
+                ..test_message("+44", true, SMSStatus::Sent)
+            };
+
+            let mut message_ids = Vec::new();
+            for created_at in [3_000, 1_000, 2_000] {
+                let message_id = database.insert_message(&message, false).await.unwrap();
+                let report_id = database
+                    .insert_delivery_report(message_id, SMSStatus::Received as u8, 0, true)
+                    .await
+                    .unwrap();
+                for (table, id_column, id) in [
+                    ("messages", "message_id", message_id),
+                    ("delivery_reports", "report_id", report_id),
+                ] {
+                    sqlx::query(&format!(
+                        "UPDATE {table} SET created_at = ? WHERE {id_column} = ?"
+                    ))
+                    .bind(created_at)
+                    .bind(id)
+                    .execute(&database.pool)
+                    .await
+                    .unwrap();
+                }
+                message_ids.push(message_id);
+            }
+
+            // Includes `from` but not `to`, oldest first.
+            let messages = database.get_messages_between(1_000, 3_000).await.unwrap();
+            assert_eq!(
+                messages.iter().map(|m| m.message_id).collect::<Vec<_>>(),
+                vec![Some(message_ids[1]), Some(message_ids[2])]
+            );
+
+            let reports = database
+                .get_delivery_reports_between(2_000, 3_001)
+                .await
+                .unwrap();
+            assert_eq!(
+                reports
+                    .iter()
+                    .map(|(id, tp_status, _)| (*id, *tp_status))
+                    .collect::<Vec<_>>(),
+                vec![(message_ids[2], Some(0)), (message_ids[0], Some(0))]
+            );
+        });
+    }
+
+    #[test]
+    fn test_message_stats() {
+        block_on(async {
+            let database = SMSDatabase::connect_in_memory().await.unwrap();
+            // Delivered after 10 and 20 seconds, then one failed, one pending and one too old.
+            for (is_outgoing, status, age, delivery_secs) in [
+                (true, SMSStatus::Received, 100, Some(10)),
+                (true, SMSStatus::Received, 100, Some(20)),
+                (true, SMSStatus::PermanentFailure, 100, Some(5)),
+                (true, SMSStatus::TemporaryFailure, 100, None),
+                (true, SMSStatus::DryRun, 100, Some(0)),
+                (false, SMSStatus::Received, 100, Some(0)),
+                (true, SMSStatus::Received, 7_200, Some(30)),
+            ] {
+                let message_id = database
+                    .insert_message(&test_message("+44", is_outgoing, status), false)
+                    .await
+                    .unwrap();
+                sqlx::query("UPDATE messages SET created_at = unixepoch() - ?, completed_at = unixepoch() - ? + ? WHERE message_id = ?")
+                    .bind(age)
+                    .bind(age)
+                    .bind(delivery_secs)
+                    .bind(message_id)
+                    .execute(&database.pool)
+                    .await
+                    .unwrap();
+                database
+                    .insert_delivery_report(message_id, 0, 0, true)
+                    .await
+                    .unwrap();
+            }
+
+            let stats = database
+                .get_stats(Duration::from_secs(3_600))
+                .await
+                .unwrap();
+            assert_eq!(stats.window_secs, 3_600);
+            assert_eq!(
+                (stats.sent, stats.received, stats.delivered, stats.failed),
+                (4, 1, 2, 1)
+            );
+            assert_eq!(stats.average_delivery_secs, Some(15.0));
+
+            // Reports are counted by when they were received, so include the old message's.
+            assert_eq!(stats.delivery_reports, 7);
+        });
+    }
+
+    #[test]
+    fn test_hot_queries_use_indexes() {
+        block_on(async {
+            let mut conn = memory_connection().await;
+            run_migrations(&mut conn).await.unwrap();
+
+            async fn plan(conn: &mut SqliteConnection, query: &str) -> String {
+                sqlx::query(&format!("EXPLAIN QUERY PLAN {query}"))
+                    .bind("+44")
+                    .bind(1)
+                    .bind("uk")
+                    .fetch_all(conn)
+                    .await
+                    .unwrap()
+                    .iter()
+                    .map(|row| row.get::<String, _>("detail"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+
+            let target = plan(&mut conn, "SELECT message_id FROM messages WHERE completed_at IS NULL AND is_outgoing = TRUE AND queued = FALSE AND phone_number = ? AND message_reference = ? AND (modem_id = ? OR modem_id IS NULL) ORDER BY message_id DESC LIMIT 1").await;
+            assert!(target.contains("idx_messages_delivery_target"), "{target}");
+
+            // Paging a conversation reads the index in order, rather than sorting every message.
+            let query = build_pagination_query(
+                "SELECT message_id FROM messages WHERE phone_number = ?",
+                "created_at",
+                Some(10),
+                None,
+                false,
+            );
+            let messages = plan(&mut conn, &query).await;
+            assert!(
+                messages.contains("idx_messages_phone_number_created_at"),
+                "{messages}"
+            );
+            assert!(!messages.contains("TEMP B-TREE"), "{messages}");
+        });
+    }
+
+    /// Insert a completed message with a delivery report, created `age_days` ago.
+    async fn insert_aged_message(
+        database: &SMSDatabase,
+        is_outgoing: bool,
+        status: SMSStatus,
+        age_days: i64,
+    ) -> i64 {
+        let message_id = database
+            .insert_message(&test_message("+44", is_outgoing, status), true)
+            .await
+            .unwrap();
+        database
+            .insert_delivery_report(message_id, 0, 0, true)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE messages SET created_at = unixepoch() - ? WHERE message_id = ?")
+            .bind(age_days * 24 * 60 * 60 + 60)
+            .bind(message_id)
+            .execute(&database.pool)
+            .await
+            .unwrap();
+        message_id
+    }
+
+    #[test]
+    fn test_network_events() {
+        block_on(async {
+            let database = SMSDatabase::connect_in_memory().await.unwrap();
+            let circuit = RegistrationDomain::Circuit;
+            database
+                .insert_network_event("modem-a", circuit, 1)
+                .await
+                .unwrap();
+            database
+                .insert_network_event("modem-a", circuit, 3)
+                .await
+                .unwrap();
+            database
+                .insert_network_event("modem-b", RegistrationDomain::Packet, 5)
+                .await
+                .unwrap();
+
+            let events = database
+                .get_network_events(None, None, false)
+                .await
+                .unwrap();
+            let states = events
+                .iter()
+                .map(|event| (event.modem_id.as_str(), event.registration))
+                .collect::<Vec<_>>();
+            assert_eq!(states, [("modem-b", 5), ("modem-a", 3), ("modem-a", 1)]);
+            assert_eq!(events[1].registration_label, "denied");
+            assert_eq!(events[0].domain, Some(RegistrationDomain::Packet));
+            assert!(events[0].created_at.is_some());
+
+            // The last state is per modem and domain.
+            let last = |modem_id: &'static str, domain| {
+                let database = &database;
+                async move {
+                    database
+                        .get_last_network_registration(modem_id, domain)
+                        .await
+                        .unwrap()
+                }
+            };
+            assert_eq!(last("modem-a", circuit).await, Some(3));
+            assert_eq!(last("modem-a", RegistrationDomain::Packet).await, None);
+            assert_eq!(last("modem-b", RegistrationDomain::Packet).await, Some(5));
+
+            // A merged change updates the last event, and a reverted one removes it.
+            let event_id = events[1].event_id;
+            database.update_network_event(event_id, 2).await.unwrap();
+            assert_eq!(last("modem-a", circuit).await, Some(2));
+            database.delete_network_event(event_id).await.unwrap();
+            assert_eq!(last("modem-a", circuit).await, Some(1));
+
+            let oldest = database
+                .get_network_events(Some(1), None, true)
+                .await
+                .unwrap();
+            assert_eq!(oldest.len(), 1);
+            assert_eq!(oldest[0].registration, 1);
+        });
+    }
+
+    #[test]
+    fn test_prune_messages() {
+        block_on(async {
+            let mut database = SMSDatabase::connect_in_memory().await.unwrap();
+            let count = |database: &SMSDatabase, table: &str| {
+                let query = format!("SELECT COUNT(*) FROM {table}");
+                let pool = database.pool.clone();
+                async move {
+                    sqlx::query_scalar::<_, i64>(&query)
+                        .fetch_one(&pool)
+                        .await
+                        .unwrap()
+                }
+            };
+
+            let old_received = insert_aged_message(&database, false, SMSStatus::Received, 10).await;
+            let old_delivered = insert_aged_message(&database, true, SMSStatus::Received, 10).await;
+            insert_aged_message(&database, true, SMSStatus::PermanentFailure, 10).await;
+            insert_aged_message(&database, false, SMSStatus::Received, 1).await;
+            let old_queued = insert_aged_message(&database, true, SMSStatus::Pending, 10).await;
+            sqlx::query("UPDATE messages SET queued = TRUE WHERE message_id = ?")
+                .bind(old_queued)
+                .execute(&database.pool)
+                .await
+                .unwrap();
+            for age_days in [10, 1] {
+                let event_id = database
+                    .insert_network_event("modem-a", RegistrationDomain::Circuit, 1)
+                    .await
+                    .unwrap();
+                sqlx::query(
+                    "UPDATE network_events SET created_at = unixepoch() - ? WHERE event_id = ?",
+                )
+                .bind(age_days * 24 * 60 * 60)
+                .bind(event_id)
+                .execute(&database.pool)
+                .await
+                .unwrap();
+            }
+
+            // Disabled by default.
+            assert_eq!(database.prune_messages().await.unwrap(), 0);
+
+            assert_eq!(count(&database, "network_events").await, 2);
+
+            // Network history is pruned by age, whatever `prune_only` is.
+            database.retain_days = 7;
+            database.prune_only = Some(PruneOnly::Received);
+            assert_eq!(database.prune_messages().await.unwrap(), 1);
+            assert!(database.get_message(old_received).await.unwrap().is_none());
+            assert_eq!(count(&database, "network_events").await, 1);
+
+            database.prune_only = Some(PruneOnly::Delivered);
+            assert_eq!(database.prune_messages().await.unwrap(), 1);
+            assert!(database.get_message(old_delivered).await.unwrap().is_none());
+
+            // Everything past the cutoff, with the reports of pruned messages, except the queued
+            // message which hasn't been sent yet.
+            database.prune_only = None;
+            assert_eq!(database.prune_messages().await.unwrap(), 1);
+            assert_eq!(count(&database, "messages").await, 2);
+            assert_eq!(count(&database, "delivery_reports").await, 2);
+            assert!(database.get_message(old_queued).await.unwrap().is_some());
+
+            // Once sent its age starts from then, so it isn't pruned straight away.
+            let mut queued = database.get_message(old_queued).await.unwrap().unwrap();
+            queued.status = SMSStatus::Sent;
+            database
+                .complete_queued_message(old_queued, &queued, false)
+                .await
+                .unwrap();
+            assert_eq!(database.prune_messages().await.unwrap(), 0);
+        });
+    }
+
+    #[test]
+    fn test_maintenance_truncates_wal() {
+        block_on(async {
+            let path =
+                std::env::temp_dir().join(format!("sms-api-maintenance-{}.db", std::process::id()));
+            let database = SMSDatabase::connect(DatabaseConfig {
+                database_url: path.to_string_lossy().to_string(),
+                encryption_key: [7; 32],
+                delivery_report_expiry: 60,
+                max_connections: 2,
+                min_connections: 1,
+                busy_timeout_secs: 5,
+                cache_size_kb: 256,
+                journal_mode: None,
+                synchronous: DatabaseSynchronous::Normal,
+                store_raw_pdu: false,
+                encrypt_phone_numbers: false,
+                retain_days: 0,
+                prune_only: None,
+            })
+            .await
+            .unwrap();
+
+            for _ in 0..50 {
+                insert_aged_message(&database, false, SMSStatus::Received, 0).await;
+            }
+            sqlx::query("DELETE FROM delivery_reports")
+                .execute(&database.pool)
+                .await
+                .unwrap();
+            sqlx::query("DELETE FROM messages")
+                .execute(&database.pool)
+                .await
+                .unwrap();
+
+            let result = database.run_maintenance(true).await.unwrap();
+            database.pool.close().await;
+            for suffix in ["", "-wal", "-shm"] {
+                let mut file = path.clone().into_os_string();
+                file.push(suffix);
+                let _ = std::fs::remove_file(file);
+            }
+
+            assert!(result.vacuumed);
+            assert!(result.before.wal_bytes > 0);
+            assert_eq!(result.after.wal_bytes, 0);
+            assert!(result.after.database_bytes > 0);
+        });
+    }
+
+    #[test]
+    fn test_blocklist() {
+        block_on(async {
+            let database = SMSDatabase::connect_in_memory().await.unwrap();
+            assert!(!database.is_blocklisted("+44").await.unwrap());
+
+            assert!(database
+                .add_to_blocklist("+44", Some("STOP"))
+                .await
+                .unwrap());
+            assert!(!database.add_to_blocklist("+44", None).await.unwrap());
+            assert!(database.add_to_blocklist("+1", None).await.unwrap());
+            assert!(database.is_blocklisted("+44").await.unwrap());
+
+            let entries = database.get_blocklist(None, None, true).await.unwrap();
+            let entries: Vec<_> = entries
+                .iter()
+                .map(|entry| (entry.phone_number.as_str(), entry.reason.as_deref()))
+                .collect();
+            assert_eq!(entries.len(), 2);
+            assert!(entries.contains(&("+44", Some("STOP"))));
+            assert!(entries.contains(&("+1", None)));
+
+            assert!(database.remove_from_blocklist("+44").await.unwrap());
+            assert!(!database.remove_from_blocklist("+44").await.unwrap());
+            assert!(!database.is_blocklisted("+44").await.unwrap());
+        });
+    }
+
+    #[test]
+    fn test_raw_pdu_storage() {
+        block_on(async {
+            let mut database = SMSDatabase::connect_in_memory().await.unwrap();
+            let message = SMSMessage {
+                raw_pdu: Some("07914477".to_string()),
+                ..test_message("+44", false, SMSStatus::Received)
+            };
+
+            // Dropped unless enabled, and only returned when fetching a single message.
+            let dropped = database.insert_message(&message, false).await.unwrap();
+            database.store_raw_pdu = true;
+            let stored = database.insert_message(&message, false).await.unwrap();
+
+            let get_raw_pdu = |message: Option<SMSMessage>| message.unwrap().raw_pdu;
+            assert_eq!(
+                get_raw_pdu(database.get_message(dropped).await.unwrap()),
+                None
+            );
+            assert_eq!(
+                get_raw_pdu(database.get_message(stored).await.unwrap()).as_deref(),
+                Some("07914477")
+            );
+            let messages = database
+                .get_messages("+44", None, None, false)
+                .await
+                .unwrap();
+            assert!(messages.iter().all(|message| message.raw_pdu.is_none()));
+
+            // Encrypted at rest like the message content.
+            let raw: Option<String> =
+                sqlx::query_scalar("SELECT raw_pdu FROM messages WHERE message_id = ?")
+                    .bind(stored)
+                    .fetch_one(&database.pool)
+                    .await
+                    .unwrap();
+            assert_ne!(raw.as_deref(), Some("07914477"));
+        });
+    }
+
+    #[test]
+    fn test_phone_number_encryption() {
+        block_on(async {
+            let mut database = SMSDatabase::connect_in_memory().await.unwrap();
+            let message = SMSMessage {
+                message_reference: Some(9),
+                modem_id: Some("uk".to_string()),
+                ..test_message("+44", true, SMSStatus::Sent)
+            };
+            let message_id = database.insert_message(&message, false).await.unwrap();
+            database
+                .update_friendly_name("+44".to_string(), Some("Alice".to_string()))
+                .await
+                .unwrap();
+            database.add_to_blocklist("+44", None).await.unwrap();
+            database.insert_call(Some("+44"), false).await.unwrap();
+
+            let stored_numbers = |database: &SMSDatabase| {
+                let pool = database.pool.clone();
+                async move {
+                    let mut numbers = Vec::new();
+                    for table in PHONE_NUMBER_TABLES {
+                        let query = format!("SELECT phone_number FROM {table}");
+                        let stored: String =
+                            sqlx::query_scalar(&query).fetch_one(&pool).await.unwrap();
+                        numbers.push(stored);
+                    }
+                    numbers
+                }
+            };
+
+            // Existing plaintext numbers are encrypted, and still looked up by number.
+            database.encrypt_phone_numbers = true;
+            database.migrate_phone_numbers().await.unwrap();
+            database.insert_message(&message, false).await.unwrap();
+            let encrypted = stored_numbers(&database).await;
+            assert!(encrypted.iter().all(|stored| stored != "+44"));
+
+            let messages = database
+                .get_messages("+44", None, None, false)
+                .await
+                .unwrap();
+            assert_eq!(messages.len(), 2);
+            assert!(messages.iter().all(|message| message.phone_number == "+44"));
+            assert_eq!(
+                database
+                    .get_latest_numbers(None, None, false)
+                    .await
+                    .unwrap(),
+                vec![("+44".to_string(), Some("Alice".to_string()))]
+            );
+            assert!(database.is_blocklisted("+44").await.unwrap());
+            assert_eq!(
+                database.get_blocklist(None, None, false).await.unwrap()[0].phone_number,
+                "+44"
+            );
+            assert_eq!(
+                database
+                    .get_friendly_name("+44".to_string())
+                    .await
+                    .unwrap()
+                    .as_deref(),
+                Some("Alice")
+            );
+            assert!(database
+                .get_delivery_report_target_message("+44", 9, None, "uk")
+                .await
+                .unwrap()
+                .is_some_and(|id| id > message_id));
+
+            // Migrating again changes nothing, and disabling it restores plaintext numbers.
+            database.migrate_phone_numbers().await.unwrap();
+            assert_eq!(stored_numbers(&database).await, encrypted);
+            database.encrypt_phone_numbers = false;
+            database.migrate_phone_numbers().await.unwrap();
+            assert!(stored_numbers(&database)
+                .await
+                .iter()
+                .all(|stored| stored == "+44"));
+        });
     }
 }
@@ -9,20 +9,41 @@ use base64::engine::general_purpose;
 use base64::Engine;
 use cipher::consts::U12;
 use cipher::Key;
+use hmac::{Hmac, Mac};
 use rand::{rng, RngCore};
+use sha2::Sha256;
 
 pub struct SMSEncryption {
     cipher: AesGcm<Aes256, U12>,
+    nonce_key: [u8; 32],
 }
 impl SMSEncryption {
     pub fn new(key: SMSEncryptionKey) -> Self {
         let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
-        Self { cipher }
+
+        // A separate key for deriving deterministic nonces, so they never reveal the cipher key.
+        let nonce_key = hmac_sha256(&key, b"sms-api deterministic nonce");
+
+        Self { cipher, nonce_key }
     }
 
     pub fn encrypt(&self, plaintext: &str) -> Result<String> {
         let mut nonce_bytes = [0u8; 12];
         rng().fill_bytes(&mut nonce_bytes);
+        self.encrypt_with_nonce(plaintext, nonce_bytes)
+    }
+
+    /// Encrypt so the same plaintext always gives the same ciphertext, for values that are
+    /// looked up by equality. The nonce is derived from the plaintext, so this reveals which
+    /// values are equal but nothing else. Decrypted with `decrypt` like any other value.
+    pub fn encrypt_deterministic(&self, plaintext: &str) -> Result<String> {
+        let digest = hmac_sha256(&self.nonce_key, plaintext.as_bytes());
+        let mut nonce_bytes = [0u8; 12];
+        nonce_bytes.copy_from_slice(&digest[..12]);
+        self.encrypt_with_nonce(plaintext, nonce_bytes)
+    }
+
+    fn encrypt_with_nonce(&self, plaintext: &str, nonce_bytes: [u8; 12]) -> Result<String> {
         let nonce = Nonce::from_slice(&nonce_bytes);
 
         let ciphertext = self
@@ -56,3 +77,36 @@ impl SMSEncryption {
         String::from_utf8(plaintext).map_err(|e| anyhow!("UTF-8 conversion failed: {}", e))
     }
 }
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deterministic_encryption() {
+        let encryption = SMSEncryption::new([7; 32]);
+        let first = encryption.encrypt_deterministic("+447700900123").unwrap();
+        assert_eq!(
+            first,
+            encryption.encrypt_deterministic("+447700900123").unwrap()
+        );
+        assert_ne!(
+            first,
+            encryption.encrypt_deterministic("+447700900124").unwrap()
+        );
+        assert_ne!(first, encryption.encrypt("+447700900123").unwrap());
+        assert_eq!(encryption.decrypt(&first).unwrap(), "+447700900123");
+
+        // Another key gives different ciphertext that fails to decrypt.
+        let other = SMSEncryption::new([8; 32]);
+        assert_ne!(first, other.encrypt_deterministic("+447700900123").unwrap());
+        assert!(other.decrypt(&first).is_err());
+    }
+}
@@ -17,7 +17,7 @@ CREATE TABLE IF NOT EXISTS friendly_names (
 CREATE TABLE IF NOT EXISTS send_failures (
     message_id BIGINT PRIMARY KEY,
     error_message TEXT NOT NULL,
-    created_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM NOW()),
+    created_at BIGINT DEFAULT EXTRACT(EPOCH FROM NOW()),
     FOREIGN KEY (message_id) REFERENCES messages(message_id) ON DELETE CASCADE
 );
 
@@ -35,4 +35,4 @@ CREATE INDEX IF NOT EXISTS idx_messages_status ON messages(status);
 CREATE INDEX IF NOT EXISTS idx_messages_is_outgoing ON messages(is_outgoing);
 CREATE INDEX IF NOT EXISTS idx_messages_created_at ON messages(created_at);
 CREATE INDEX IF NOT EXISTS idx_messages_completed_at ON messages(completed_at);
-CREATE INDEX IF NOT EXISTS idx_friendly_name ON friendly_names(friendly_name);
\ No newline at end of file
+CREATE INDEX IF NOT EXISTS idx_friendly_name ON friendly_names(friendly_name);
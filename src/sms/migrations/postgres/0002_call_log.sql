@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS call_log (
+    call_id BIGSERIAL PRIMARY KEY,
+    phone_number TEXT,
+    rejected BOOLEAN NOT NULL,
+    created_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM NOW())
+);
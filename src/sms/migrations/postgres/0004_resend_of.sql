@@ -0,0 +1,2 @@
+-- Link resent messages to the message they were resent from.
+ALTER TABLE messages ADD COLUMN resend_of BIGINT DEFAULT NULL REFERENCES messages(message_id) ON DELETE SET NULL;
@@ -0,0 +1,2 @@
+-- Record which configured modem sent or received each message.
+ALTER TABLE messages ADD COLUMN modem_id TEXT DEFAULT NULL;
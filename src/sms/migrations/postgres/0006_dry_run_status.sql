@@ -0,0 +1,3 @@
+-- Allow the DryRun (6) message status.
+ALTER TABLE messages DROP CONSTRAINT IF EXISTS messages_status_check;
+ALTER TABLE messages ADD CONSTRAINT messages_status_check CHECK (status >= 0 AND status <= 6);
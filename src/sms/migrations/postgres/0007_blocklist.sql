@@ -0,0 +1,6 @@
+-- Phone numbers that opted out or were blocked manually, which messages aren't sent to.
+CREATE TABLE IF NOT EXISTS blocklist (
+    phone_number TEXT PRIMARY KEY,
+    reason TEXT,
+    created_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM NOW())
+);
@@ -0,0 +1,2 @@
+-- 0 for text, 1 for 8-bit data stored as hex.
+ALTER TABLE messages ADD COLUMN data_coding SMALLINT NOT NULL DEFAULT 0;
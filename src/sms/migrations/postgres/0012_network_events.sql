@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS network_events (
+    event_id BIGSERIAL PRIMARY KEY,
+    modem_id TEXT NOT NULL,
+    registration SMALLINT NOT NULL,
+    created_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM NOW())
+);
+CREATE INDEX IF NOT EXISTS idx_network_events_created_at ON network_events(created_at);
@@ -0,0 +1,4 @@
+-- Outgoing messages are always read, so only incoming messages are ever unread.
+ALTER TABLE messages ADD COLUMN is_read BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE messages SET is_read = TRUE WHERE is_outgoing = TRUE;
+CREATE INDEX IF NOT EXISTS idx_messages_unread ON messages(phone_number) WHERE is_read = FALSE;
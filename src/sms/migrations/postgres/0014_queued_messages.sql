@@ -0,0 +1,7 @@
+-- Allow the Pending (7) message status, for outgoing messages queued for the modem but not sent yet. Their send
+-- options are kept until sent, so they can be recovered after a restart.
+ALTER TABLE messages DROP CONSTRAINT IF EXISTS messages_status_check;
+ALTER TABLE messages ADD CONSTRAINT messages_status_check CHECK (status >= 0 AND status <= 7);
+ALTER TABLE messages ADD COLUMN queued BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE messages ADD COLUMN send_options TEXT DEFAULT NULL;
+CREATE INDEX IF NOT EXISTS idx_messages_queued ON messages(message_id) WHERE queued = TRUE;
@@ -0,0 +1,3 @@
+-- Whether a registration change is for the circuit switched (CREG) or packet (CGREG) domain, as they're tracked
+-- separately. NULL for changes stored before it was recorded.
+ALTER TABLE network_events ADD COLUMN domain SMALLINT DEFAULT NULL;
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS call_log (
+    call_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    phone_number TEXT,
+    rejected BOOLEAN NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (unixepoch())
+);
@@ -0,0 +1,23 @@
+-- Allow the Expired (5) message status. SQLite can't alter a CHECK constraint, so the table is rebuilt.
+CREATE TABLE messages_new (
+    message_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    phone_number TEXT NOT NULL,
+    message_content TEXT NOT NULL,
+    message_reference INTEGER CHECK (message_reference >= 0 AND message_reference <= 255),
+    is_outgoing BOOLEAN NOT NULL,
+    status INTEGER NOT NULL CHECK (status >= 0 AND status <= 5),
+    created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    completed_at INTEGER DEFAULT NULL
+);
+
+INSERT INTO messages_new (message_id, phone_number, message_content, message_reference, is_outgoing, status, created_at, completed_at)
+SELECT message_id, phone_number, message_content, message_reference, is_outgoing, status, created_at, completed_at FROM messages;
+
+DROP TABLE messages;
+ALTER TABLE messages_new RENAME TO messages;
+
+CREATE INDEX IF NOT EXISTS idx_messages_phone_number ON messages(phone_number);
+CREATE INDEX IF NOT EXISTS idx_messages_status ON messages(status);
+CREATE INDEX IF NOT EXISTS idx_messages_is_outgoing ON messages(is_outgoing);
+CREATE INDEX IF NOT EXISTS idx_messages_created_at ON messages(created_at);
+CREATE INDEX IF NOT EXISTS idx_messages_completed_at ON messages(completed_at);
@@ -0,0 +1,2 @@
+-- Link resent messages to the message they were resent from.
+ALTER TABLE messages ADD COLUMN resend_of INTEGER DEFAULT NULL REFERENCES messages(message_id) ON DELETE SET NULL;
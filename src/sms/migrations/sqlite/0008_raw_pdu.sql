@@ -0,0 +1,2 @@
+-- The hex PDU of incoming messages, only stored with `store_raw_pdu` enabled.
+ALTER TABLE messages ADD COLUMN raw_pdu TEXT DEFAULT NULL;
@@ -0,0 +1,3 @@
+-- Covers the message stats query, which filters on created_at and groups by the rest.
+CREATE INDEX IF NOT EXISTS idx_messages_stats ON messages(created_at, is_outgoing, status, completed_at);
+CREATE INDEX IF NOT EXISTS idx_delivery_reports_created_at ON delivery_reports(created_at);
@@ -0,0 +1,5 @@
+-- Delivery report correlation only looks at pending outgoing messages for a number and reference.
+CREATE INDEX IF NOT EXISTS idx_messages_delivery_target ON messages(phone_number, message_reference, is_outgoing) WHERE completed_at IS NULL;
+
+-- Fetching a conversation filters on the number and pages by creation time.
+CREATE INDEX IF NOT EXISTS idx_messages_phone_number_created_at ON messages(phone_number, created_at);
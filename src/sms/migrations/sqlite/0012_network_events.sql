@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS network_events (
+    event_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    modem_id TEXT NOT NULL,
+    registration INTEGER NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (unixepoch())
+);
+CREATE INDEX IF NOT EXISTS idx_network_events_created_at ON network_events(created_at);
@@ -0,0 +1,4 @@
+-- Outgoing messages are always read, so only incoming messages are ever unread.
+ALTER TABLE messages ADD COLUMN is_read BOOLEAN NOT NULL DEFAULT 0;
+UPDATE messages SET is_read = 1 WHERE is_outgoing = 1;
+CREATE INDEX IF NOT EXISTS idx_messages_unread ON messages(phone_number) WHERE is_read = 0;
@@ -0,0 +1,37 @@
+-- Allow the Pending (7) message status, for outgoing messages queued for the modem but not sent yet. Their send
+-- options are kept until sent, so they can be recovered after a restart. SQLite can't alter a CHECK constraint,
+-- so the table is rebuilt.
+CREATE TABLE messages_new (
+    message_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    phone_number TEXT NOT NULL,
+    message_content TEXT NOT NULL,
+    message_reference INTEGER CHECK (message_reference >= 0 AND message_reference <= 255),
+    is_outgoing BOOLEAN NOT NULL,
+    status INTEGER NOT NULL CHECK (status >= 0 AND status <= 7),
+    created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    completed_at INTEGER DEFAULT NULL,
+    resend_of INTEGER DEFAULT NULL REFERENCES messages(message_id) ON DELETE SET NULL,
+    modem_id TEXT DEFAULT NULL,
+    raw_pdu TEXT DEFAULT NULL,
+    data_coding INTEGER NOT NULL DEFAULT 0,
+    is_read BOOLEAN NOT NULL DEFAULT 0,
+    queued BOOLEAN NOT NULL DEFAULT 0,
+    send_options TEXT DEFAULT NULL
+);
+
+INSERT INTO messages_new (message_id, phone_number, message_content, message_reference, is_outgoing, status, created_at, completed_at, resend_of, modem_id, raw_pdu, data_coding, is_read)
+SELECT message_id, phone_number, message_content, message_reference, is_outgoing, status, created_at, completed_at, resend_of, modem_id, raw_pdu, data_coding, is_read FROM messages;
+
+DROP TABLE messages;
+ALTER TABLE messages_new RENAME TO messages;
+
+CREATE INDEX IF NOT EXISTS idx_messages_phone_number ON messages(phone_number);
+CREATE INDEX IF NOT EXISTS idx_messages_status ON messages(status);
+CREATE INDEX IF NOT EXISTS idx_messages_is_outgoing ON messages(is_outgoing);
+CREATE INDEX IF NOT EXISTS idx_messages_created_at ON messages(created_at);
+CREATE INDEX IF NOT EXISTS idx_messages_completed_at ON messages(completed_at);
+CREATE INDEX IF NOT EXISTS idx_messages_stats ON messages(created_at, is_outgoing, status, completed_at);
+CREATE INDEX IF NOT EXISTS idx_messages_delivery_target ON messages(phone_number, message_reference, is_outgoing) WHERE completed_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_messages_phone_number_created_at ON messages(phone_number, created_at);
+CREATE INDEX IF NOT EXISTS idx_messages_unread ON messages(phone_number) WHERE is_read = 0;
+CREATE INDEX IF NOT EXISTS idx_messages_queued ON messages(message_id) WHERE queued = 1;
@@ -0,0 +1,3 @@
+-- The raw TP-Status each delivery report was received with, so replayed reports carry the original. NULL for
+-- reports stored before it was kept.
+ALTER TABLE delivery_reports ADD COLUMN tp_status INTEGER DEFAULT NULL CHECK (tp_status >= 0 AND tp_status <= 255);
@@ -0,0 +1,3 @@
+-- How long the service centre keeps trying to deliver an outgoing message, from the validity period it was sent with,
+-- so it's expired once that passes if sooner than `delivery_report_expiry`. NULL when it isn't known.
+ALTER TABLE messages ADD COLUMN validity_secs INTEGER DEFAULT NULL;
@@ -1,78 +1,215 @@
 #![cfg_attr(not(feature = "http-server"), allow(dead_code))]
 
+mod auto_reply;
 mod database;
 mod encryption;
 mod multipart;
 pub mod types;
 
-use crate::config::DatabaseConfig;
-use crate::events::{Event, EventBroadcaster};
-use crate::modem::sender::ModemSender;
-use crate::modem::types::{ModemRequest, ModemResponse};
+use crate::config::{AutoReplyConfig, DatabaseConfig, OptOutConfig};
+use crate::events::{Event, EventBroadcaster, EventType};
+use crate::modem::pool::ModemPool;
+use crate::modem::types::{
+    ModemQueueStatus, ModemRequest, ModemResponse, ModemStatus, RegistrationDomain,
+    COMMAND_TIMEOUT_ERROR, LATE_SEND_RESULT_WINDOW,
+};
+use crate::sms::auto_reply::AutoReplier;
 use crate::sms::database::SMSDatabase;
-use crate::sms::multipart::SMSMultipartMessages;
-use crate::sms::types::{SMSIncomingDeliveryReport, SMSIncomingMessage};
-use crate::types::{SMSMessage, SMSOutgoingMessage, SMSStatus};
-use anyhow::{bail, Result};
+use crate::sms::multipart::{remove_stalled, SMSMultipartMessages};
+
+pub use crate::sms::multipart::MultipartOptions;
+use crate::sms::types::{BlocklistedNumber, SMSIncomingDeliveryReport, SMSIncomingMessage};
+use crate::types::{
+    sms_status_from_message_status, QueuedSendOptions, SMSDataCoding, SMSEncoding, SMSMessage,
+    SMSOutgoingMessage, SMSStatus,
+};
+use anyhow::{anyhow, bail, Result};
+use sms_api_types::http::{SendRoute, SendSmsPdu};
+use sms_api_types::sms::IncomingDeliveryReport;
+use sms_pdu::pdu::PduAddress;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing::log::{debug, error, warn};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, Mutex};
+use tracing::log::{debug, error, info, warn};
+
+/// A registration change within this long of the last one stored is merged into it, so a
+/// flapping signal doesn't store every change.
+const NETWORK_EVENT_DEBOUNCE: Duration = Duration::from_secs(5);
+
+/// RING repeats every few seconds while a call rings, so a call without one for this long has
+/// ended, for modems that don't report it.
+const CALL_RING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Senders waiting for the final delivery status of a message, keyed by message ID.
+type DeliveryWaiters = Arc<Mutex<HashMap<i64, oneshot::Sender<SMSStatus>>>>;
 
 #[derive(Clone)]
 pub struct SMSManager {
-    modem: ModemSender,
+    modems: ModemPool,
     database: Arc<SMSDatabase>,
     broadcaster: Option<EventBroadcaster>,
+    delivery_waiters: DeliveryWaiters,
+    opt_out: Option<Arc<OptOutConfig>>,
+    auto_replier: Option<Arc<AutoReplier>>,
+
+    /// The latest single part send per modem that timed out after its PDU was written, kept
+    /// in case the modem reports a late `+CMGS` showing it was sent after all.
+    timed_out_sends: Arc<Mutex<HashMap<String, (SMSMessage, Instant)>>>,
 }
 impl SMSManager {
     pub async fn connect(
         config: DatabaseConfig,
-        modem: ModemSender,
+        modems: ModemPool,
         broadcaster: Option<EventBroadcaster>,
+        opt_out: Option<OptOutConfig>,
+        auto_replies: &[AutoReplyConfig],
     ) -> Result<Self> {
+        let auto_replier = match auto_replies {
+            [] => None,
+            rules => Some(Arc::new(AutoReplier::new(rules)?)),
+        };
         let database = Arc::new(SMSDatabase::connect(config).await?);
         Ok(Self {
-            modem,
+            modems,
             database,
             broadcaster,
+            delivery_waiters: DeliveryWaiters::default(),
+            opt_out: opt_out.map(Arc::new),
+            auto_replier,
+            timed_out_sends: Arc::new(Mutex::new(HashMap::new())),
         })
     }
 
-    /// Returns the database row ID and final modem response.
+    /// Returns the database row ID, final modem response and how the modem was chosen.
     pub async fn send_sms(
         &self,
         message: SMSOutgoingMessage,
-    ) -> Result<(Option<i64>, ModemResponse)> {
-        let last_response = match self.modem.send_sms(&message).await? {
+    ) -> Result<(Option<i64>, ModemResponse, SendRoute, Vec<SendSmsPdu>)> {
+        self.send_queued_sms(message, None).await
+    }
+
+    /// Send a message, stored as pending until the modem has sent it so it can be recovered if
+    /// the server stops first. A recovered message is sent with the row it's already queued as.
+    async fn send_queued_sms(
+        &self,
+        message: SMSOutgoingMessage,
+        queued_id: Option<i64>,
+    ) -> Result<(Option<i64>, ModemResponse, SendRoute, Vec<SendSmsPdu>)> {
+        let phone_number = message.phone_number.to_string();
+        if !message.override_blocklist && self.database.is_blocklisted(&phone_number).await? {
+            bail!(BlocklistedNumber(phone_number));
+        }
+
+        let (route, modem) = self
+            .modems
+            .route(message.modem_id.as_deref(), &phone_number)?;
+        debug!("Sending SMS with route {route:?}");
+
+        // Dry runs never reach the modem, so there's nothing to recover.
+        let created_queued = queued_id.is_none() && !message.dry_run;
+        let queued_id = if created_queued {
+            let mut queued = SMSMessage::from(&message);
+            queued.modem_id = Some(route.modem_id.clone());
+            let send_options = QueuedSendOptions::from(&message);
+            Some(
+                self.database
+                    .insert_queued_message(&queued, &send_options, modem.validity(&message))
+                    .await?,
+            )
+        } else {
+            queued_id
+        };
+
+        // Record the first part the modem sends, so recovery never sends it again. It's written
+        // before the send continues, so the part is never reported sent without its reference.
+        let part_sent = AtomicBool::new(false);
+        let on_part_sent = |reference_id: u8| {
+            let first_part = queued_id.filter(|_| !part_sent.swap(true, Ordering::Relaxed));
+            let database = Arc::clone(&self.database);
+            async move {
+                let Some(message_id) = first_part else {
+                    return;
+                };
+                if let Err(e) = database
+                    .set_queued_reference(message_id, reference_id)
+                    .await
+                {
+                    error!("Failed to record queued message #{message_id} as sent! {e:?}");
+                }
+            }
+        };
+
+        // A message that wasn't sent isn't stored, unless it's being recovered.
+        let (sent_all, last_response, pdus) = match modem.send_sms(&message, on_part_sent).await {
+            Ok(result) => result,
+            Err(e) => {
+                if created_queued {
+                    self.discard_queued(queued_id).await;
+                }
+                return Err(e);
+            }
+        };
+        let last_response = match (sent_all, last_response) {
             // If all requests were not sent, then don't store any in the database as it must
             // be a failed multipart message. Instead, return the error response.
-            (false, Some(response)) => return Ok((None, response)),
+            (false, Some(response)) => {
+                if let ModemResponse::Error {
+                    message: error_message,
+                    ..
+                } = &response
+                {
+                    if error_message == COMMAND_TIMEOUT_ERROR {
+                        self.remember_timed_out_send(&message, &route.modem_id)
+                            .await;
+                    }
+                    self.broadcast_send_failure(None, &message, error_message)
+                        .await;
+                }
+                if created_queued {
+                    self.discard_queued(queued_id).await;
+                }
+                return Ok((None, response, route, pdus));
+            }
             (true, Some(response)) => response,
             _ => bail!("Missing any valid SendSMS response!"),
         };
         debug!("SMSManager last_response: {last_response:?}");
 
         let mut new_message = SMSMessage::from(&message);
+        new_message.modem_id = Some(route.modem_id.clone());
         let send_failure = match &last_response {
-            ModemResponse::SendResult(reference_id) => {
+            ModemResponse::SendResult { reference_id } => {
                 new_message.message_reference.replace(*reference_id);
+                if message.dry_run {
+                    new_message.status = SMSStatus::DryRun;
+                }
                 None
             }
-            ModemResponse::Error(error_message) => {
+            ModemResponse::Error {
+                message: error_message,
+                ..
+            } => {
                 new_message.status = SMSStatus::PermanentFailure;
                 Some(error_message)
             }
             _ => bail!("Got invalid ModemResponse back from sending SMS message!"),
         };
 
-        // Store sent message + send failure in database.
-        let message_id_result = match self
-            .database
-            .insert_message(&new_message, send_failure.is_some())
-            .await
-        {
+        // Store sent message + send failure in database. Dry runs never get a delivery report.
+        let is_final = send_failure.is_some() || message.dry_run;
+        let stored = match queued_id {
+            Some(message_id) => self
+                .database
+                .complete_queued_message(message_id, &new_message, is_final)
+                .await
+                .map(|()| message_id),
+            None => self.database.insert_message(&new_message, is_final).await,
+        };
+        let message_id_result = match stored {
             Ok(row_id) => {
                 if let Some(failure) = send_failure {
                     if let Err(e) = self.database.insert_send_failure(row_id, failure).await {
@@ -85,27 +222,294 @@ impl SMSManager {
         };
 
         // Broadcast event.
+        let message_id = message_id_result.as_ref().ok().copied();
         if let Some(broadcaster) = &self.broadcaster {
             broadcaster
                 .broadcast(Event::OutgoingMessage(
-                    new_message.with_message_id(message_id_result.as_ref().ok().copied()),
+                    new_message.with_message_id(message_id),
                 ))
                 .await;
         }
+        if let Some(failure) = send_failure {
+            self.broadcast_send_failure(message_id, &message, failure)
+                .await;
+        }
 
         match message_id_result {
-            Ok(message_id) => Ok((Some(message_id), last_response)),
+            Ok(message_id) => Ok((Some(message_id), last_response, route, pdus)),
             Err(e) => Err(e),
         }
     }
 
-    pub async fn send_command(&self, request: ModemRequest) -> Result<ModemResponse> {
-        self.modem.send_request(request, None).await
+    /// Remove the queued row of a message that wasn't sent.
+    async fn discard_queued(&self, queued_id: Option<i64>) {
+        let Some(message_id) = queued_id else {
+            return;
+        };
+        if let Err(e) = self.database.delete_queued_message(message_id).await {
+            error!("Failed to remove unsent queued message #{message_id}! {e:?}");
+        }
+    }
+
+    /// Get the messages left queued when the server last stopped, to recover them.
+    pub async fn queued_messages(&self) -> Result<Vec<(SMSMessage, Option<QueuedSendOptions>)>> {
+        self.database.get_queued_messages().await
+    }
+
+    /// Send messages that were queued but not sent when the server last stopped, one at a time.
+    /// A message the modem had started sending has a reference, so is marked sent rather than
+    /// sent again. One that can't be sent is stored as a permanent failure.
+    pub async fn recover_queued_messages(
+        &self,
+        queued: Vec<(SMSMessage, Option<QueuedSendOptions>)>,
+    ) {
+        info!("Recovering {} queued message(s)", queued.len());
+        for (mut message, send_options) in queued {
+            let Some(message_id) = message.message_id else {
+                continue;
+            };
+            if message.message_reference.is_some() {
+                warn!("Queued message #{message_id} was sent before stopping, so isn't sent again");
+                message.status = SMSStatus::Sent;
+                if let Err(e) = self
+                    .database
+                    .complete_queued_message(message_id, &message, false)
+                    .await
+                {
+                    error!("Failed to store queued message #{message_id} as sent! {e:?}");
+                }
+                continue;
+            }
+
+            let outgoing = match send_options {
+                Some(send_options) => send_options.into_outgoing(&message),
+                None => Err(anyhow!("Its send options couldn't be read")),
+            };
+            let result = match outgoing {
+                Ok(outgoing) => self.send_queued_sms(outgoing, Some(message_id)).await,
+                Err(e) => Err(e),
+            };
+            let error_message = match result {
+                Ok((Some(_), ..)) => {
+                    info!("Recovered queued message #{message_id}");
+                    continue;
+                }
+                Ok((None, ModemResponse::Error { message, .. }, ..)) => message,
+                Ok((None, response, ..)) => format!("Unexpected modem response {response:?}"),
+                Err(e) => e.to_string(),
+            };
+
+            error!("Failed to recover queued message #{message_id}: {error_message}");
+            message.status = SMSStatus::PermanentFailure;
+            let stored = async {
+                self.database
+                    .complete_queued_message(message_id, &message, true)
+                    .await?;
+                self.database
+                    .insert_send_failure(message_id, &error_message)
+                    .await
+            };
+            if let Err(e) = stored.await {
+                error!("Failed to store queued message #{message_id} as failed! {e:?}");
+            }
+        }
+    }
+
+    /// A multipart message can't be recovered, as earlier parts may have been sent or not.
+    async fn remember_timed_out_send(&self, message: &SMSOutgoingMessage, modem_id: &str) {
+        let is_single_part = message.encode().is_ok_and(|segments| segments.len() == 1);
+        if !is_single_part {
+            return;
+        }
+
+        let mut timed_out = SMSMessage::from(message);
+        timed_out.modem_id = Some(modem_id.to_string());
+        self.timed_out_sends
+            .lock()
+            .await
+            .insert(modem_id.to_string(), (timed_out, Instant::now()));
+    }
+
+    async fn broadcast_send_failure(
+        &self,
+        message_id: Option<i64>,
+        message: &SMSOutgoingMessage,
+        error_message: &str,
+    ) {
+        if let Some(broadcaster) = &self.broadcaster {
+            broadcaster
+                .broadcast(Event::SendFailure {
+                    message_id,
+                    phone_number: message.phone_number.to_string(),
+                    error_message: error_message.to_string(),
+                })
+                .await;
+        }
+    }
+
+    /// Wait for a sent message to get a final delivery status, returning None on timeout.
+    /// This only holds the caller, the modem command queue is free while waiting.
+    pub async fn wait_for_delivery(
+        &self,
+        message_id: i64,
+        timeout: Duration,
+    ) -> Result<Option<SMSStatus>> {
+        let (tx, rx) = oneshot::channel();
+        self.delivery_waiters.lock().await.insert(message_id, tx);
+
+        // The report may have been handled before the waiter was registered.
+        if let Some(status) = self.database.get_final_message_status(message_id).await? {
+            self.delivery_waiters.lock().await.remove(&message_id);
+            return Ok(Some(status));
+        }
+
+        let result = tokio::time::timeout(timeout, rx).await;
+        self.delivery_waiters.lock().await.remove(&message_id);
+        match result {
+            Ok(Ok(status)) => Ok(Some(status)),
+            Ok(Err(_)) => bail!("Delivery waiter for message #{message_id} was dropped"),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Send a command to a modem, or the default (first configured) modem if no id is given.
+    pub async fn send_command(
+        &self,
+        modem_id: Option<&str>,
+        request: ModemRequest,
+    ) -> Result<ModemResponse> {
+        self.modems
+            .get_or_default(modem_id)?
+            .send_request(request, None)
+            .await
+    }
+
+    pub async fn set_operator(
+        &self,
+        modem_id: Option<&str>,
+        operator: Option<String>,
+    ) -> Result<ModemResponse> {
+        self.modems
+            .get_or_default(modem_id)?
+            .set_operator(operator)
+            .await
+    }
+
+    pub fn modem_queue_status(&self, modem_id: Option<&str>) -> Result<ModemQueueStatus> {
+        Ok(self.modems.get_or_default(modem_id)?.queue_status())
+    }
+
+    pub async fn reset_modem(&self, modem_id: Option<&str>) -> Result<ModemStatus> {
+        self.modems.get_or_default(modem_id)?.reset().await
+    }
+
+    /// Cancel the queued commands for a modem, or every modem, returning how many were cancelled.
+    pub async fn flush_modem_queue(&self, modem_id: Option<&str>) -> Result<usize> {
+        if let Some(modem_id) = modem_id {
+            return self.modems.get(modem_id)?.flush_queue().await;
+        }
+
+        let mut flushed = 0;
+        for (_, sender) in self.modems.all() {
+            flushed += sender.flush_queue().await?;
+        }
+        Ok(flushed)
+    }
+
+    /// Check every event type is stored, so can be replayed.
+    #[cfg_attr(not(feature = "http-server"), allow(dead_code))]
+    pub fn check_replayable(event_types: &[EventType]) -> Result<()> {
+        match event_types.iter().find(|event_type| {
+            !matches!(
+                event_type,
+                EventType::IncomingMessage | EventType::OutgoingMessage | EventType::DeliveryReport
+            )
+        }) {
+            Some(event_type) => bail!("{event_type:?} events aren't stored, so can't be replayed!"),
+            None => Ok(()),
+        }
+    }
+
+    /// Rebuild the events for messages and delivery reports stored from `from` until before
+    /// `to` (unix seconds), oldest first. Other event types aren't stored, so can't be replayed.
+    #[cfg_attr(not(feature = "http-server"), allow(dead_code))]
+    pub async fn replay_events(
+        &self,
+        from: u64,
+        to: u64,
+        event_types: &[EventType],
+    ) -> Result<Vec<Event>> {
+        Self::check_replayable(event_types)?;
+
+        // Sorted by time, then messages before the delivery reports received in the same second.
+        let mut events: Vec<(u64, u8, Event)> = Vec::new();
+        let messages = self.database.get_messages_between(from, to).await?;
+        for message in &messages {
+            let event = if message.is_outgoing {
+                Event::OutgoingMessage(message.clone())
+            } else {
+                Event::IncomingMessage(message.clone())
+            };
+            if event_types.contains(&event.to_event_type()) {
+                events.push((message.created_at.unwrap_or_default(), 0, event));
+            }
+        }
+
+        if event_types.contains(&EventType::DeliveryReport) {
+            let mut messages: HashMap<i64, SMSMessage> = messages
+                .into_iter()
+                .filter_map(|message| Some((message.message_id?, message)))
+                .collect();
+
+            for (message_id, tp_status, report) in
+                self.database.get_delivery_reports_between(from, to).await?
+            {
+                // Only the status it mapped onto was stored before the TP-Status was kept.
+                let Some(tp_status) = tp_status else {
+                    continue;
+                };
+                let message = match messages.entry(message_id) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => match self.database.get_message(message_id).await? {
+                        Some(message) => entry.insert(message),
+                        None => continue,
+                    },
+                };
+                let Some(reference_id) = message.message_reference else {
+                    continue;
+                };
+
+                let report_event = Event::DeliveryReport {
+                    message_id,
+                    report: IncomingDeliveryReport::new(
+                        message.phone_number.clone(),
+                        reference_id,
+                        tp_status,
+                    ),
+                };
+                events.push((report.created_at.unwrap_or_default(), 1, report_event));
+            }
+        }
+
+        events.sort_by_key(|(created_at, order, _)| (*created_at, *order));
+        Ok(events.into_iter().map(|(_, _, event)| event).collect())
+    }
+
+    pub fn set_modem_status(&self, modem_id: &str, status: ModemStatus) {
+        self.modems.set_status(modem_id, status);
     }
 
     pub fn borrow_database(&self) -> &Arc<SMSDatabase> {
         &self.database
     }
+
+    pub async fn prune_messages(&self) {
+        match self.database.prune_messages().await {
+            Ok(0) => debug!("No messages are past the retention period"),
+            Ok(count) => info!("Pruned {count} message(s) past the retention period"),
+            Err(e) => error!("Failed to prune messages: {e:?}"),
+        }
+    }
 }
 
 /// The multipart key is (phone_number, message_ref), meaning that even if the
@@ -115,16 +519,42 @@ type MultipartReference = (Arc<str>, u8);
 #[derive(Clone)]
 pub struct SMSReceiver {
     manager: SMSManager,
+    modem_id: String,
     multipart: Arc<Mutex<HashMap<MultipartReference, SMSMultipartMessages>>>,
+    auto_reject_calls: bool,
+    multipart_options: MultipartOptions,
+
+    network: NetworkState,
+    call: CallState,
 }
 impl SMSReceiver {
-    pub fn new(manager: SMSManager) -> Self {
+    /// Handles messages from one modem, as multipart parts and delivery reports only match within it.
+    pub fn new(
+        manager: SMSManager,
+        modem_id: String,
+        auto_reject_calls: bool,
+        multipart_options: MultipartOptions,
+    ) -> Self {
         Self {
             manager,
+            modem_id,
             multipart: Arc::new(Mutex::new(HashMap::new())),
+            auto_reject_calls,
+            multipart_options,
+            network: NetworkState::default(),
+            call: CallState::default(),
         }
     }
 
+    pub fn modem_id(&self) -> &str {
+        &self.modem_id
+    }
+
+    /// Track the modem's status, so sends are only routed to it while it's online.
+    pub fn handle_status_update(&self, status: ModemStatus) {
+        self.manager.set_modem_status(&self.modem_id, status);
+    }
+
     /// Store + emit incoming SMS message.
     /// Option for multipart messages, as individual parts aren't stored only compiled result.
     pub async fn handle_incoming_sms(
@@ -132,15 +562,17 @@ impl SMSReceiver {
         incoming_message: SMSIncomingMessage,
     ) -> Option<Result<i64>> {
         // Handle incoming message, discarding if it's a multipart message and not final.
-        let message = match self.get_incoming_sms_message(incoming_message).await {
+        let mut message = match self.get_incoming_sms_message(incoming_message).await {
             Some(Ok(message)) => message,
             Some(Err(e)) => return Some(Err(e)),
             None => return None,
         };
+        message.modem_id = Some(self.modem_id.clone());
 
         let row_id_result = self.manager.database.insert_message(&message, false).await;
 
-        // Send incoming event.
+        // Send incoming event, the raw PDU is only for fetching the stored message.
+        message.raw_pdu = None;
         if let Some(broadcaster) = &self.manager.broadcaster {
             broadcaster
                 .broadcast(Event::IncomingMessage(
@@ -149,17 +581,153 @@ impl SMSReceiver {
                 .await;
         }
 
+        // Keywords and auto-replies only apply to text, not hex encoded 8-bit data.
+        if !message.data_coding.is_text() {
+            return Some(row_id_result);
+        }
+        if let Some(opt_out) = &self.manager.opt_out {
+            if let Err(e) = self.handle_opt_out(opt_out, &message).await {
+                error!(
+                    "Failed to update blocklist for {}: {e:?}",
+                    message.phone_number
+                );
+            }
+        }
+        if let Some(auto_replier) = &self.manager.auto_replier {
+            if let Err(e) = self.handle_auto_reply(auto_replier, &message) {
+                error!("Failed to auto-reply to {}: {e:?}", message.phone_number);
+            }
+        }
+
         Some(row_id_result)
     }
 
+    /// Queue a reply if an auto-reply rule matches, sent by the modem that received the message.
+    /// It's sent and stored like any other outgoing message, so it's refused if the sender opted out.
+    fn handle_auto_reply(&self, auto_replier: &AutoReplier, message: &SMSMessage) -> Result<()> {
+        let phone_number = &message.phone_number;
+        let Some(content) = auto_replier.reply(phone_number, &message.message_content)? else {
+            return Ok(());
+        };
+
+        let outgoing = SMSOutgoingMessage {
+            phone_number: PduAddress::from_str(phone_number)?,
+            content,
+            flash: false,
+            validity_period: None,
+            timeout: None,
+            encoding: SMSEncoding::Auto,
+            data_coding: SMSDataCoding::Text,
+            user_data_header: None,
+            priority: false,
+            modem_id: Some(self.modem_id.clone()),
+            dry_run: false,
+            override_blocklist: false,
+        };
+
+        // Sent in the background, so the reply doesn't hold up handling other incoming messages.
+        let manager = self.manager.clone();
+        let phone_number = phone_number.clone();
+        tokio::spawn(async move {
+            match manager.send_sms(outgoing).await {
+                Ok((message_id, ModemResponse::SendResult { .. }, ..)) => {
+                    info!("Sent auto-reply to {phone_number} as message {message_id:?}")
+                }
+                Ok((_, response, ..)) => {
+                    warn!("Failed to auto-reply to {phone_number}: {response}")
+                }
+                Err(e) => warn!("Failed to auto-reply to {phone_number}: {e:?}"),
+            }
+        });
+        Ok(())
+    }
+
+    /// Add or remove the sender from the blocklist if the message is an opt-out or resubscribe keyword.
+    async fn handle_opt_out(&self, opt_out: &OptOutConfig, message: &SMSMessage) -> Result<()> {
+        let phone_number = &message.phone_number;
+        let event = if let Some(keyword) = opt_out.opt_out_keyword(&message.message_content) {
+            let database = &self.manager.database;
+            if database
+                .add_to_blocklist(phone_number, Some(keyword))
+                .await?
+            {
+                info!("{phone_number} opted out with '{keyword}', added to the blocklist");
+            }
+            Event::OptOut {
+                phone_number: phone_number.clone(),
+                keyword: keyword.to_string(),
+            }
+        } else if let Some(keyword) = opt_out.resubscribe_keyword(&message.message_content) {
+            if self
+                .manager
+                .database
+                .remove_from_blocklist(phone_number)
+                .await?
+            {
+                info!("{phone_number} resubscribed with '{keyword}', removed from the blocklist");
+            }
+            Event::OptIn {
+                phone_number: phone_number.clone(),
+                keyword: keyword.to_string(),
+            }
+        } else {
+            return Ok(());
+        };
+
+        if let Some(broadcaster) = &self.manager.broadcaster {
+            broadcaster.broadcast(event).await;
+        }
+        Ok(())
+    }
+
+    /// Mark the most recent send from this modem that timed out as sent, now the modem has
+    /// reported its reference. Nothing is stored if no timed out send matches.
+    pub async fn handle_late_send_result(&self, reference_id: u8) -> Result<Option<i64>> {
+        let timed_out = self
+            .manager
+            .timed_out_sends
+            .lock()
+            .await
+            .remove(&self.modem_id);
+        let Some((mut message, timed_out_at)) = timed_out else {
+            return Ok(None);
+        };
+        if timed_out_at.elapsed() > LATE_SEND_RESULT_WINDOW {
+            return Ok(None);
+        }
+
+        // Stored like any other sent message, so its delivery report is matched.
+        message.message_reference = Some(reference_id);
+        let message_id = self
+            .manager
+            .database
+            .insert_message(&message, false)
+            .await?;
+        info!("Message #{message_id} was sent with reference {reference_id} after timing out");
+
+        if let Some(broadcaster) = &self.manager.broadcaster {
+            broadcaster
+                .broadcast(Event::OutgoingMessage(
+                    message.with_message_id(Some(message_id)),
+                ))
+                .await;
+        }
+        Ok(Some(message_id))
+    }
+
     /// Store + emit delivery report.
     pub async fn handle_delivery_report(&self, report: SMSIncomingDeliveryReport) -> Result<i64> {
-        // Find the target message from phone number and message reference. This will be fine unless we send 255
-        // messages to the client before they reply with delivery reports as then there's no way to properly track.
+        // Find the target message from phone number and message reference. References wrap after 255 messages,
+        // so the service centre timestamp picks between pending messages that share one.
         let message_id = match self
             .manager
             .database
-            .get_delivery_report_target_message(&report.phone_number, report.reference_id)
+            .get_delivery_report_target_message(
+                &report.phone_number,
+                report.reference_id,
+                report.submitted_at,
+                &self.modem_id,
+            )
             .await?
         {
             Some(message_id) => message_id,
@@ -167,19 +735,22 @@ impl SMSReceiver {
         };
 
         let is_final = report.status.is_success() || report.status.is_permanent_error();
-        let status = u8::from(&SMSStatus::from(&report.status));
+        let sms_status = sms_status_from_message_status(&report.status);
+        let status = u8::from(&sms_status);
 
         // Send delivery report event.
-        let sms_status = SMSStatus::from(&report.status);
         if let Some(broadcaster) = &self.manager.broadcaster {
             broadcaster
-                .broadcast(Event::DeliveryReport { message_id, report })
+                .broadcast(Event::DeliveryReport {
+                    message_id,
+                    report: IncomingDeliveryReport::from(&report),
+                })
                 .await;
         }
 
         self.manager
             .database
-            .insert_delivery_report(message_id, status, is_final)
+            .insert_delivery_report(message_id, status, report.status as u8, is_final)
             .await?;
 
         self.manager
@@ -187,24 +758,143 @@ impl SMSReceiver {
             .update_message_status(message_id, &sms_status, is_final)
             .await?;
 
+        // Resolve anyone waiting on this message, temporary failures keep them waiting.
+        if is_final {
+            if let Some(waiter) = self
+                .manager
+                .delivery_waiters
+                .lock()
+                .await
+                .remove(&message_id)
+            {
+                let _ = waiter.send(sms_status);
+            }
+        }
+
         Ok(message_id)
     }
 
+    /// Hang up on the first RING of a call if calls are auto rejected, even if they aren't logged.
+    pub fn handle_ring(&mut self) {
+        if !self.call.ring(Instant::now()) || !self.auto_reject_calls {
+            return;
+        }
+
+        // The hang-up is queued like any other command, so it can't interrupt an active one.
+        let manager = self.manager.clone();
+        let modem_id = self.modem_id.clone();
+        tokio::spawn(async move {
+            match manager
+                .send_command(Some(&modem_id), ModemRequest::HangUp)
+                .await
+            {
+                Ok(ModemResponse::Success) => debug!("Rejected incoming call"),
+                Ok(response) => warn!("Failed to reject incoming call: {response}"),
+                Err(e) => warn!("Failed to reject incoming call: {e:?}"),
+            }
+        });
+    }
+
+    pub fn handle_call_ended(&mut self) {
+        self.call.end();
+    }
+
+    /// Store + emit an identified incoming call, once per call. Returns None for the
+    /// caller identification repeated with each RING of a call that's already stored.
+    pub async fn handle_incoming_call(
+        &mut self,
+        phone_number: Option<String>,
+    ) -> Option<Result<i64>> {
+        if !self.call.identify(phone_number.as_deref(), Instant::now()) {
+            return None;
+        }
+
+        let row_id_result = self
+            .manager
+            .database
+            .insert_call(phone_number.as_deref(), self.auto_reject_calls)
+            .await;
+
+        // Send incoming call event.
+        if let Some(broadcaster) = &self.manager.broadcaster {
+            broadcaster
+                .broadcast(Event::IncomingCall { phone_number })
+                .await;
+        }
+
+        Some(row_id_result)
+    }
+
+    /// Store a network registration change for a domain, returning the new event's ID. The same
+    /// state as the last one stored isn't a change, and a change within `NETWORK_EVENT_DEBOUNCE`
+    /// of the last one stored is merged into it, so neither stores a new event.
+    pub async fn handle_network_status_change(
+        &mut self,
+        domain: RegistrationDomain,
+        registration: u8,
+    ) -> Result<Option<i64>> {
+        // After a restart, the last state is whatever was stored before it.
+        if !self.network.is_known(domain) {
+            let stored = self
+                .manager
+                .database
+                .get_last_network_registration(&self.modem_id, domain)
+                .await?;
+            self.network.load(domain, stored);
+        }
+
+        let now = Instant::now();
+        let database = &self.manager.database;
+        match self.network.change(domain, registration, now) {
+            NetworkChange::Unchanged => Ok(None),
+            NetworkChange::Insert => {
+                let event_id = database
+                    .insert_network_event(&self.modem_id, domain, registration)
+                    .await?;
+                self.network.inserted(domain, registration, event_id, now);
+                Ok(Some(event_id))
+            }
+            NetworkChange::Merge(event_id) => {
+                database
+                    .update_network_event(event_id, registration)
+                    .await?;
+                self.network.merged(domain, registration);
+                debug!("Merged network status {registration} into event #{event_id}");
+                Ok(None)
+            }
+            NetworkChange::Revert(event_id) => {
+                database.delete_network_event(event_id).await?;
+                self.network.reverted(domain);
+                debug!("Network status reverted to {registration}, removed event #{event_id}");
+                Ok(None)
+            }
+        }
+    }
+
     /// **Call only from cleanup task!**
     /// Holds multipart lock and removes all stalled receivers.
     pub async fn cleanup_stalled_multipart(&mut self) {
         debug!("Cleaning up stalled multipart messages");
         let mut guard = self.multipart.lock().await;
-        guard.retain(|(phone_number, message_reference), messages| {
-            // Show a warning whenever a message group has stalled.
-            let stalled = messages.is_stalled();
-            if stalled {
-                warn!(
-                    "Removing received multipart message '{phone_number}' (#{message_reference}) has stalled!"
-                );
-            }
-            !stalled
-        });
+
+        // Show a warning whenever a message group has stalled.
+        for (phone_number, message_reference) in
+            remove_stalled(&mut guard, tokio::time::Instant::now())
+        {
+            warn!(
+                "Removing received multipart message '{phone_number}' (#{message_reference}) has stalled!"
+            );
+        }
+    }
+
+    /// **Call only from cleanup task!**
+    /// Expires any outgoing messages that never received a final delivery report.
+    pub async fn expire_pending_messages(&self) {
+        match self.manager.database.expire_pending_messages().await {
+            Ok(0) => debug!("No pending messages have expired"),
+            Ok(count) => warn!("Marked {count} pending message(s) as expired!"),
+            Err(e) => error!("Failed to expire pending messages: {e:?}"),
+        }
     }
 
     /// Get the final SMSMessage to broadcast/store, which is either just the
@@ -234,32 +924,274 @@ impl SMSReceiver {
                     "Creating new multipart handler, expecting {} parts",
                     header.total
                 );
-                let mut mulipart = SMSMultipartMessages::with_capacity(header.total as usize);
+                let mut mulipart = match SMSMultipartMessages::with_capacity(
+                    header.total as usize,
+                    self.multipart_options,
+                ) {
+                    Ok(mulipart) => mulipart,
+                    Err(e) => {
+                        warn!("Rejecting multipart message from {phone_number}: {e}");
+                        return Some(Err(e));
+                    }
+                };
 
-                if mulipart.add_message(incoming_message, header.index) {
-                    warn!("Got a 1 part multipart message from {phone_number}, that's odd!");
+                match mulipart.add_message(incoming_message, header.index) {
+                    Ok(true) => {
+                        warn!("Got a 1 part multipart message from {phone_number}, that's odd!");
 
-                    // Compile message, and don't insert into map since it's complete.
-                    Some(mulipart.compile())
-                } else {
-                    entry.insert(mulipart);
-                    None
+                        // Compile message, and don't insert into map since it's complete.
+                        Some(mulipart.compile())
+                    }
+                    Ok(false) => {
+                        entry.insert(mulipart);
+                        None
+                    }
+                    Err(e) => {
+                        warn!("Rejecting multipart message from {phone_number}: {e}");
+                        Some(Err(e))
+                    }
                 }
             }
             Entry::Occupied(mut entry) => {
-                // Add message part.
-                if entry.get_mut().add_message(incoming_message, header.index) {
-                    debug!(
-                        "Multipart message complete, compiling {} parts!",
-                        header.total
-                    );
-
-                    let complete = entry.remove();
-                    return Some(complete.compile());
+                // Add message part, dropping every part received so far if it's oversized.
+                match entry.get_mut().add_message(incoming_message, header.index) {
+                    Ok(true) => {
+                        debug!(
+                            "Multipart message complete, compiling {} parts!",
+                            header.total
+                        );
+
+                        let complete = entry.remove();
+                        Some(complete.compile())
+                    }
+                    Ok(false) => None,
+                    Err(e) => {
+                        warn!("Rejecting multipart message from {phone_number}: {e}");
+                        entry.remove();
+                        Some(Err(e))
+                    }
                 }
+            }
+        }
+    }
+}
 
-                None
+/// How a network registration change is stored.
+#[derive(Debug, PartialEq)]
+enum NetworkChange {
+    /// The same state as the last one stored.
+    Unchanged,
+    Insert,
+
+    /// Soon after the last stored event, so it's updated to the new state.
+    Merge(i64),
+
+    /// Soon after the last stored event and back to the state before it, so it's removed.
+    Revert(i64),
+}
+
+/// The last network registration state stored for each domain of a modem.
+#[derive(Debug, Clone, Default)]
+struct NetworkState {
+    last: HashMap<RegistrationDomain, StoredNetworkState>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct StoredNetworkState {
+    registration: Option<u8>,
+
+    /// The event last stored this run, the state before it, and when it was stored.
+    event: Option<(i64, Option<u8>, Instant)>,
+}
+
+impl NetworkState {
+    fn is_known(&self, domain: RegistrationDomain) -> bool {
+        self.last.contains_key(&domain)
+    }
+
+    /// Note the last state stored before this run, if any.
+    fn load(&mut self, domain: RegistrationDomain, registration: Option<u8>) {
+        self.last.insert(
+            domain,
+            StoredNetworkState {
+                registration,
+                event: None,
+            },
+        );
+    }
+
+    fn change(&self, domain: RegistrationDomain, registration: u8, now: Instant) -> NetworkChange {
+        let Some(last) = self.last.get(&domain) else {
+            return NetworkChange::Insert;
+        };
+        if last.registration == Some(registration) {
+            return NetworkChange::Unchanged;
+        }
+
+        match last.event {
+            Some((event_id, previous, stored_at))
+                if now.duration_since(stored_at) < NETWORK_EVENT_DEBOUNCE =>
+            {
+                if previous == Some(registration) {
+                    NetworkChange::Revert(event_id)
+                } else {
+                    NetworkChange::Merge(event_id)
+                }
             }
+            _ => NetworkChange::Insert,
+        }
+    }
+
+    fn inserted(
+        &mut self,
+        domain: RegistrationDomain,
+        registration: u8,
+        event_id: i64,
+        now: Instant,
+    ) {
+        let previous = self.last.get(&domain).and_then(|last| last.registration);
+        self.last.insert(
+            domain,
+            StoredNetworkState {
+                registration: Some(registration),
+                event: Some((event_id, previous, now)),
+            },
+        );
+    }
+
+    /// The last event was updated to a new state, keeping when it was stored so a signal that
+    /// keeps flapping is still stored once the window has passed.
+    fn merged(&mut self, domain: RegistrationDomain, registration: u8) {
+        if let Some(last) = self.last.get_mut(&domain) {
+            last.registration = Some(registration);
         }
     }
+
+    /// The last event was removed, so the state before it is the last stored again.
+    fn reverted(&mut self, domain: RegistrationDomain) {
+        if let Some(last) = self.last.get_mut(&domain) {
+            last.registration = last.event.take().and_then(|(_, previous, _)| previous);
+        }
+    }
+}
+
+/// The call currently ringing on a modem, so its repeated RING and +CLIP lines count as one call.
+#[derive(Debug, Clone, Default)]
+struct CallState {
+    active: Option<ActiveCall>,
+}
+
+#[derive(Debug, Clone)]
+struct ActiveCall {
+    phone_number: Option<String>,
+    identified: bool,
+    last_seen: Instant,
+}
+
+impl CallState {
+    /// Note a RING, returning true if it's the first of a new call.
+    fn ring(&mut self, now: Instant) -> bool {
+        match &mut self.active {
+            Some(call) if now.duration_since(call.last_seen) < CALL_RING_TIMEOUT => {
+                call.last_seen = now;
+                false
+            }
+            _ => {
+                self.active = Some(ActiveCall {
+                    phone_number: None,
+                    identified: false,
+                    last_seen: now,
+                });
+                true
+            }
+        }
+    }
+
+    /// Note the caller of the ringing call, returning true the first time it's identified.
+    /// A different caller means the previous call ended without being reported.
+    fn identify(&mut self, phone_number: Option<&str>, now: Instant) -> bool {
+        if let Some(call) = &mut self.active {
+            let same_caller = !call.identified || call.phone_number.as_deref() == phone_number;
+            if same_caller && now.duration_since(call.last_seen) < CALL_RING_TIMEOUT {
+                call.last_seen = now;
+                call.phone_number = phone_number.map(str::to_string);
+                return !std::mem::replace(&mut call.identified, true);
+            }
+        }
+
+        self.active = Some(ActiveCall {
+            phone_number: phone_number.map(str::to_string),
+            identified: true,
+            last_seen: now,
+        });
+        true
+    }
+
+    fn end(&mut self) {
+        self.active = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_call_state() {
+        let start = Instant::now();
+        let mut call = CallState::default();
+
+        // Each RING is followed by the same +CLIP, which is only stored once.
+        assert!(call.ring(start));
+        assert!(call.identify(Some("+447771115678"), start));
+        let later = start + Duration::from_secs(4);
+        assert!(!call.ring(later));
+        assert!(!call.identify(Some("+447771115678"), later));
+
+        // A different caller, or the same one after the call ended, is a new call.
+        assert!(call.identify(Some("+447771110000"), later));
+        call.end();
+        assert!(call.ring(later));
+        assert!(call.identify(Some("+447771110000"), later));
+
+        // Without an end being reported, a call that stopped ringing has ended.
+        let after_timeout = later + CALL_RING_TIMEOUT;
+        assert!(call.ring(after_timeout));
+        assert!(call.identify(Some("+447771110000"), after_timeout));
+    }
+
+    #[test]
+    fn test_network_state() {
+        let start = Instant::now();
+        let circuit = RegistrationDomain::Circuit;
+        let mut network = NetworkState::default();
+
+        // The state stored before a restart isn't stored again.
+        network.load(circuit, Some(1));
+        assert_eq!(network.change(circuit, 1, start), NetworkChange::Unchanged);
+        assert_eq!(network.change(circuit, 2, start), NetworkChange::Insert);
+        network.inserted(circuit, 2, 10, start);
+
+        // A change soon after is merged into the last event, or removes it if back to before.
+        let soon = start + Duration::from_secs(1);
+        assert_eq!(network.change(circuit, 3, soon), NetworkChange::Merge(10));
+        network.merged(circuit, 3);
+        assert_eq!(network.change(circuit, 3, soon), NetworkChange::Unchanged);
+        assert_eq!(network.change(circuit, 1, soon), NetworkChange::Revert(10));
+        network.reverted(circuit);
+        assert_eq!(network.change(circuit, 1, soon), NetworkChange::Unchanged);
+
+        // Once the window from the first change has passed, each change is stored again.
+        assert_eq!(network.change(circuit, 5, soon), NetworkChange::Insert);
+        network.inserted(circuit, 5, 11, soon);
+        network.merged(circuit, 2);
+        let later = soon + NETWORK_EVENT_DEBOUNCE;
+        assert_eq!(network.change(circuit, 1, later), NetworkChange::Insert);
+
+        // Domains are tracked separately.
+        assert_eq!(
+            network.change(RegistrationDomain::Packet, 2, later),
+            NetworkChange::Insert
+        );
+    }
 }
@@ -1,13 +1,50 @@
+use crate::config::ModemConfig;
 use crate::sms::types::SMSIncomingMessage;
 use crate::types::SMSMessage;
-use anyhow::anyhow;
 use anyhow::Result;
+use anyhow::{anyhow, bail};
+use std::collections::HashMap;
+use std::hash::Hash;
 use std::time::Duration;
 use tokio::time::Instant;
 use tracing::log::debug;
 
 const MULTIPART_MESSAGES_STALLED_DURATION: Duration = Duration::from_secs(30 * 60); // 30 minutes
 
+/// Remove any multipart messages stalled as of `now`, keeping those still being received.
+/// Returns the references of the removed messages.
+pub fn remove_stalled<K: Clone + Eq + Hash>(
+    multipart: &mut HashMap<K, SMSMultipartMessages>,
+    now: Instant,
+) -> Vec<K> {
+    let mut removed = Vec::new();
+    multipart.retain(|reference, messages| {
+        let stalled = messages.is_stalled(now);
+        if stalled {
+            removed.push(reference.clone());
+        }
+        !stalled
+    });
+    removed
+}
+
+/// How incoming multipart messages are assembled, and the limits placed on them.
+#[derive(Debug, Clone, Copy)]
+pub struct MultipartOptions {
+    pub strip_padding: bool,
+    pub max_parts: u8,
+    pub max_content_bytes: usize,
+}
+impl From<&ModemConfig> for MultipartOptions {
+    fn from(config: &ModemConfig) -> Self {
+        Self {
+            strip_padding: config.strip_multipart_padding,
+            max_parts: config.max_multipart_parts,
+            max_content_bytes: config.max_total_content_bytes,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SMSMultipartHeader {
     pub message_reference: u8,
@@ -22,38 +59,56 @@ pub struct SMSMultipartMessages {
     first_message: Option<SMSIncomingMessage>,
     text_len: usize,
     text_parts: Vec<Option<String>>,
+    raw_pdu_parts: Vec<Option<String>>,
     received_count: usize,
+    options: MultipartOptions,
 }
 impl SMSMultipartMessages {
-    pub fn with_capacity(total_size: usize) -> Self {
-        Self {
+    /// With `strip_padding`, a trailing `@` is removed from every part. This is only for modems
+    /// that report an incorrect user data length, as it corrupts parts that really end in `@`.
+    /// The part count comes from the sender, so it's rejected before allocating if over the limit.
+    pub fn with_capacity(total_size: usize, options: MultipartOptions) -> Result<Self> {
+        if total_size > options.max_parts as usize {
+            bail!(
+                "Multipart message claims {total_size} parts, more than the limit of {}",
+                options.max_parts
+            );
+        }
+
+        Ok(Self {
             total_size,
             last_updated: Instant::now(),
             first_message: None,
             text_len: 0,
             text_parts: vec![None; total_size],
+            raw_pdu_parts: vec![None; total_size],
             received_count: 0,
-        }
+            options,
+        })
     }
 
-    pub fn add_message(&mut self, message: SMSIncomingMessage, index: u8) -> bool {
+    /// Add a message part, returning if all parts have been received.
+    /// Errors if the assembled content would exceed the size limit, the message should be dropped.
+    pub fn add_message(&mut self, message: SMSIncomingMessage, index: u8) -> Result<bool> {
         self.last_updated = Instant::now();
 
         let idx = (index as usize).saturating_sub(1);
         if idx < self.text_parts.len() && self.text_parts[idx].is_none() {
-            // Remove message separator char.
-            let content = if message.content.ends_with("@") {
-                message
-                    .content
-                    .strip_suffix("@")
-                    .unwrap_or(&message.content)
-                    .to_string()
-            } else {
-                message.content.to_string()
+            let content = match message.content.strip_suffix("@") {
+                Some(stripped) if self.options.strip_padding => stripped.to_string(),
+                _ => message.content.to_string(),
             };
 
+            if self.text_len + content.len() > self.options.max_content_bytes {
+                bail!(
+                    "Multipart message content exceeds the limit of {} bytes",
+                    self.options.max_content_bytes
+                );
+            }
+
             self.text_len += content.len();
             self.text_parts[idx] = Some(content);
+            self.raw_pdu_parts[idx] = message.raw_pdu.clone();
             self.received_count += 1;
         }
 
@@ -65,7 +120,7 @@ impl SMSMultipartMessages {
             "Received Multipart SMS Count: {:?} | Max: {:?}",
             self.received_count, self.total_size
         );
-        self.received_count >= self.total_size
+        Ok(self.received_count >= self.total_size)
     }
 
     pub fn compile(&self) -> Result<SMSMessage> {
@@ -83,64 +138,132 @@ impl SMSMultipartMessages {
             content.push_str(text);
         }
 
+        // Each part's PDU on its own line, in part order.
+        let raw_pdus: Vec<&str> = self
+            .raw_pdu_parts
+            .iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+
         let mut message = SMSMessage::from(first_message);
         message.message_content = content;
+        message.raw_pdu = (!raw_pdus.is_empty()).then(|| raw_pdus.join("\n"));
 
         Ok(message)
     }
 
     #[inline]
-    pub fn is_stalled(&self) -> bool {
-        self.last_updated.elapsed() > MULTIPART_MESSAGES_STALLED_DURATION
+    pub fn is_stalled(&self, now: Instant) -> bool {
+        now.duration_since(self.last_updated) > MULTIPART_MESSAGES_STALLED_DURATION
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::SMSDataCoding;
     const TEST_NUMBER: &str = "+123456789";
 
+    fn options(strip_padding: bool) -> MultipartOptions {
+        MultipartOptions {
+            strip_padding,
+            max_parts: 255,
+            max_content_bytes: 16384,
+        }
+    }
+
     fn create_test_message(content: &str) -> SMSIncomingMessage {
         SMSIncomingMessage {
             phone_number: TEST_NUMBER.to_string(),
             user_data_header: None,
             content: content.to_string(),
+            raw_pdu: None,
+            data_coding: SMSDataCoding::Text,
         }
     }
 
     #[test]
     fn test_multipart_assembly() {
-        let mut multipart_ordered = SMSMultipartMessages::with_capacity(3);
-        assert!(!multipart_ordered.add_message(create_test_message("First @"), 1));
-        assert!(!multipart_ordered.add_message(create_test_message("Second @"), 2));
-        assert!(multipart_ordered.add_message(create_test_message("Third"), 3));
+        let mut multipart_ordered = SMSMultipartMessages::with_capacity(3, options(true)).unwrap();
+        assert!(!multipart_ordered
+            .add_message(create_test_message("First @"), 1)
+            .unwrap());
+        assert!(!multipart_ordered
+            .add_message(create_test_message("Second @"), 2)
+            .unwrap());
+        assert!(multipart_ordered
+            .add_message(create_test_message("Third"), 3)
+            .unwrap());
 
         let result = multipart_ordered.compile().unwrap();
         assert_eq!(result.message_content, "First Second Third");
 
-        let mut multipart_random = SMSMultipartMessages::with_capacity(5);
-        assert!(!multipart_random.add_message(create_test_message("Part3 @"), 3));
-        assert!(!multipart_random.add_message(create_test_message("Part5!"), 5));
-        assert!(!multipart_random.add_message(create_test_message("Part1 @"), 1));
-        assert!(!multipart_random.add_message(create_test_message("Part4 @"), 4));
-        assert!(multipart_random.add_message(create_test_message("Part2 @"), 2));
+        let mut multipart_random = SMSMultipartMessages::with_capacity(5, options(true)).unwrap();
+        assert!(!multipart_random
+            .add_message(create_test_message("Part3 @"), 3)
+            .unwrap());
+        assert!(!multipart_random
+            .add_message(create_test_message("Part5!"), 5)
+            .unwrap());
+        assert!(!multipart_random
+            .add_message(create_test_message("Part1 @"), 1)
+            .unwrap());
+        assert!(!multipart_random
+            .add_message(create_test_message("Part4 @"), 4)
+            .unwrap());
+        assert!(multipart_random
+            .add_message(create_test_message("Part2 @"), 2)
+            .unwrap());
 
         let result = multipart_random.compile().unwrap();
         assert_eq!(result.message_content, "Part1 Part2 Part3 Part4 Part5!");
     }
 
+    #[test]
+    fn test_multipart_raw_pdus() {
+        let part = |content: &str, raw_pdu: &str| SMSIncomingMessage {
+            raw_pdu: Some(raw_pdu.to_string()),
+            ..create_test_message(content)
+        };
+
+        let mut multipart = SMSMultipartMessages::with_capacity(2, options(false)).unwrap();
+        assert!(!multipart.add_message(part("World", "BB"), 2).unwrap());
+        assert!(multipart.add_message(part("Hello ", "AA"), 1).unwrap());
+
+        let result = multipart.compile().unwrap();
+        assert_eq!(result.message_content, "Hello World");
+        assert_eq!(result.raw_pdu.as_deref(), Some("AA\nBB"));
+    }
+
     #[test]
     fn test_special_characters() {
-        let mut multipart = SMSMultipartMessages::with_capacity(8);
+        let mut multipart = SMSMultipartMessages::with_capacity(8, options(true)).unwrap();
 
-        multipart.add_message(create_test_message("Hello\nWorld\t@"), 1);
-        multipart.add_message(create_test_message("🚀🌟😀 emojis @"), 2);
-        multipart.add_message(create_test_message("\"quotes\" & 'apostrophes' @"), 3);
-        multipart.add_message(create_test_message("<html>&nbsp;</html> @"), 4);
-        multipart.add_message(create_test_message("Ñoño José María @"), 5);
-        multipart.add_message(create_test_message("Здравствуйте @"), 6);
-        multipart.add_message(create_test_message("你好世界 @"), 7);
-        multipart.add_message(create_test_message("Math: ∑∏∫√ End"), 8);
+        multipart
+            .add_message(create_test_message("Hello\nWorld\t@"), 1)
+            .unwrap();
+        multipart
+            .add_message(create_test_message("🚀🌟😀 emojis @"), 2)
+            .unwrap();
+        multipart
+            .add_message(create_test_message("\"quotes\" & 'apostrophes' @"), 3)
+            .unwrap();
+        multipart
+            .add_message(create_test_message("<html>&nbsp;</html> @"), 4)
+            .unwrap();
+        multipart
+            .add_message(create_test_message("Ñoño José María @"), 5)
+            .unwrap();
+        multipart
+            .add_message(create_test_message("Здравствуйте @"), 6)
+            .unwrap();
+        multipart
+            .add_message(create_test_message("你好世界 @"), 7)
+            .unwrap();
+        multipart
+            .add_message(create_test_message("Math: ∑∏∫√ End"), 8)
+            .unwrap();
 
         let result = multipart.compile().unwrap();
         assert_eq!(
@@ -148,18 +271,83 @@ mod tests {
             "Hello\nWorld\t🚀🌟😀 emojis \"quotes\" & 'apostrophes' <html>&nbsp;</html> Ñoño José María Здравствуйте 你好世界 Math: ∑∏∫√ End"
         );
 
-        let mut multipart2 = SMSMultipartMessages::with_capacity(3);
+        let mut multipart2 = SMSMultipartMessages::with_capacity(3, options(true)).unwrap();
         assert_eq!(multipart2.text_len, 0);
 
-        multipart2.add_message(create_test_message("😀😀😀@"), 1);
+        multipart2
+            .add_message(create_test_message("😀😀😀@"), 1)
+            .unwrap();
         let emoji_len = "😀😀😀".len();
         assert_eq!(multipart2.text_len, emoji_len);
 
-        multipart2.add_message(create_test_message("ABC@"), 2);
+        multipart2
+            .add_message(create_test_message("ABC@"), 2)
+            .unwrap();
         assert_eq!(multipart2.text_len, emoji_len + 3);
 
-        multipart2.add_message(create_test_message("世界"), 3);
+        multipart2
+            .add_message(create_test_message("世界"), 3)
+            .unwrap();
         let chinese_len = "世界".len();
         assert_eq!(multipart2.text_len, emoji_len + 3 + chinese_len);
     }
+
+    #[test]
+    fn test_keeps_trailing_at_without_strip_padding() {
+        let mut multipart = SMSMultipartMessages::with_capacity(2, options(false)).unwrap();
+        assert!(!multipart
+            .add_message(create_test_message("Email me at user@"), 1)
+            .unwrap());
+        assert!(multipart
+            .add_message(create_test_message("example.com or user@"), 2)
+            .unwrap());
+
+        let result = multipart.compile().unwrap();
+        assert_eq!(
+            result.message_content,
+            "Email me at user@example.com or user@"
+        );
+    }
+
+    #[test]
+    fn test_remove_stalled() {
+        let mut stale = SMSMultipartMessages::with_capacity(2, options(false)).unwrap();
+        stale.add_message(create_test_message("Old"), 1).unwrap();
+
+        // Times are only ever added to, as the monotonic clock may not be far past zero.
+        let mut fresh = SMSMultipartMessages::with_capacity(2, options(false)).unwrap();
+        fresh.add_message(create_test_message("New"), 1).unwrap();
+        fresh.last_updated = stale.last_updated + Duration::from_secs(60);
+
+        let now = stale.last_updated + MULTIPART_MESSAGES_STALLED_DURATION + Duration::from_secs(1);
+        let mut multipart = HashMap::from([(1, stale), (2, fresh)]);
+        assert_eq!(remove_stalled(&mut multipart, now), vec![1]);
+        assert!(multipart.contains_key(&2));
+        assert_eq!(multipart.len(), 1);
+
+        assert!(remove_stalled(&mut multipart, now).is_empty());
+        assert_eq!(multipart.len(), 1);
+    }
+
+    #[test]
+    fn test_size_limits() {
+        let limited = MultipartOptions {
+            strip_padding: false,
+            max_parts: 4,
+            max_content_bytes: 10,
+        };
+
+        // A claimed part count over the limit is rejected before anything is allocated.
+        assert!(SMSMultipartMessages::with_capacity(255, limited).is_err());
+        assert!(SMSMultipartMessages::with_capacity(4, limited).is_ok());
+
+        let mut multipart = SMSMultipartMessages::with_capacity(3, limited).unwrap();
+        assert!(!multipart
+            .add_message(create_test_message("12345"), 1)
+            .unwrap());
+        assert!(multipart
+            .add_message(create_test_message("678901"), 2)
+            .is_err());
+        assert_eq!(multipart.text_len, 5);
+    }
 }
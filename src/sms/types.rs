@@ -1,10 +1,12 @@
 use crate::sms::multipart::SMSMultipartHeader;
-use crate::types::{SMSMessage, SMSStatus};
+use crate::types::{SMSDataCoding, SMSMessage, SMSStatus};
 use anyhow::{anyhow, Result};
 use num_traits::cast::FromPrimitive;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sms_api_types::sms::IncomingDeliveryReport;
 use sms_pdu::gsm_encoding::udh::UserDataHeader;
-use sms_pdu::pdu::MessageStatus;
+use sms_pdu::gsm_encoding::{try_gsm_encode_char, GsmMessageData};
+use sms_pdu::pdu::{MessageEncoding, MessageStatus, SmscTimestamp};
 
 pub type SMSEncryptionKey = [u8; 32];
 
@@ -13,8 +15,64 @@ pub struct SMSIncomingMessage {
     pub phone_number: String,
     pub user_data_header: Option<UserDataHeader>,
     pub content: String,
+
+    /// The hex PDU the message was decoded from.
+    pub raw_pdu: Option<String>,
+
+    /// 8-bit data is kept as hex encoded content, rather than decoded as text.
+    pub data_coding: SMSDataCoding,
 }
 impl SMSIncomingMessage {
+    /// Decode the message text and user data header from PDU user data.
+    pub fn decode(phone_number: String, data: &GsmMessageData) -> Result<Self> {
+        if matches!(data.encoding, MessageEncoding::EightBit) {
+            return Self::decode_8bit(phone_number, data);
+        }
+
+        let decoded = data
+            .decode_message()
+            .map_err(|e| anyhow!("Failed to parse incoming SMS data: {:?}", e))?;
+
+        // Fill bits after the last septet can decode as an extra `@` character, so only
+        // keep as many septets as the user data length says there are.
+        let content = match data.encoding {
+            MessageEncoding::Gsm7Bit => truncate_to_septets(decoded.text, gsm7_text_septets(data)),
+            _ => decoded.text,
+        };
+
+        Ok(Self {
+            phone_number,
+            user_data_header: decoded.udh,
+            content,
+            raw_pdu: None,
+            data_coding: SMSDataCoding::Text,
+        })
+    }
+
+    /// The PDU decoder doesn't support 8-bit data, so the user data header is split off here.
+    fn decode_8bit(phone_number: String, data: &GsmMessageData) -> Result<Self> {
+        let (user_data_header, content) = match data.bytes.split_first() {
+            Some((&header_len, rest)) if data.udh => {
+                if rest.len() < header_len as usize {
+                    return Err(anyhow!("User data header goes past end of data"));
+                }
+                let (header, content) = rest.split_at(header_len as usize);
+                let header = UserDataHeader::try_from(header)
+                    .map_err(|e| anyhow!("Failed to parse user data header: {:?}", e))?;
+                (Some(header), content)
+            }
+            _ => (None, data.bytes.as_slice()),
+        };
+
+        Ok(Self {
+            phone_number,
+            user_data_header,
+            content: hex::encode(content),
+            raw_pdu: None,
+            data_coding: SMSDataCoding::EightBit,
+        })
+    }
+
     pub fn decode_multipart_data(&self) -> Option<Result<SMSMultipartHeader>> {
         // Find header component with multipart ID.
         let component = self
@@ -46,6 +104,10 @@ impl From<&SMSIncomingMessage> for SMSMessage {
             status: SMSStatus::Received,
             created_at: None,
             completed_at: None,
+            modem_id: None,
+            raw_pdu: incoming.raw_pdu.clone(),
+            data_coding: incoming.data_coding,
+            is_read: false,
         }
     }
 }
@@ -58,6 +120,149 @@ pub struct SMSIncomingDeliveryReport {
     #[serde(serialize_with = "serialize_message_status")]
     #[serde(deserialize_with = "deserialize_message_status")]
     pub status: MessageStatus,
+
+    /// When the service centre received the original message, as a unix timestamp.
+    #[serde(default)]
+    pub submitted_at: Option<u64>,
+}
+
+impl From<&SMSIncomingDeliveryReport> for IncomingDeliveryReport {
+    fn from(report: &SMSIncomingDeliveryReport) -> Self {
+        IncomingDeliveryReport::new(
+            report.phone_number.clone(),
+            report.reference_id,
+            report.status as u8,
+        )
+    }
+}
+
+/// A send refused as the number is on the blocklist, responded to with 403 rather than 500.
+#[derive(Debug)]
+pub struct BlocklistedNumber(pub String);
+impl std::fmt::Display for BlocklistedNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} is on the blocklist, set override_blocklist to send anyway",
+            self.0
+        )
+    }
+}
+impl std::error::Error for BlocklistedNumber {}
+
+/// The incoming messages to mark read, either one message or a whole conversation.
+#[derive(Debug)]
+pub enum MarkReadTarget {
+    Message(i64),
+    PhoneNumber(String),
+}
+
+/// Message counts over a recent window, for delivery rate reporting.
+#[derive(Debug, Default, Serialize)]
+pub struct SMSMessageStats {
+    pub window_secs: u64,
+
+    /// Outgoing messages, of which some may still be waiting for a final delivery report.
+    pub sent: u64,
+    pub received: u64,
+    pub delivered: u64,
+    pub failed: u64,
+    pub delivery_reports: u64,
+
+    /// The average seconds from sending to the final delivery report, of delivered messages.
+    pub average_delivery_secs: Option<f64>,
+}
+
+/// The size in bytes of the SQLite database file and its write-ahead log.
+#[derive(Debug, Serialize)]
+pub struct DatabaseFileSizes {
+    pub database_bytes: u64,
+    pub wal_bytes: u64,
+}
+
+/// File sizes before and after checkpointing the WAL, and optionally vacuuming.
+#[derive(Debug, Serialize)]
+pub struct DatabaseMaintenanceResult {
+    pub vacuumed: bool,
+    pub before: DatabaseFileSizes,
+    pub after: DatabaseFileSizes,
+}
+
+/// Days since the epoch for a civil date, from Howard Hinnant's days_from_civil.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The civil date as (year, month, day) for days since the epoch, the inverse of `days_from_civil`.
+#[cfg_attr(not(feature = "http-server"), allow(dead_code))]
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (year_of_era + era * 400 + i64::from(month <= 2), month, day)
+}
+
+/// Convert a service centre timestamp to a unix timestamp, or None if it isn't a valid date.
+pub fn smsc_timestamp_to_unix(timestamp: &SmscTimestamp) -> Option<u64> {
+    let (month, day) = (timestamp.month as i64, timestamp.day as i64);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || timestamp.hour > 23
+        || timestamp.minute > 59
+        || timestamp.second > 59
+    {
+        return None;
+    }
+
+    let days = days_from_civil(2000 + timestamp.year as i64, month, day);
+
+    // The timezone is in quarter hours, with the sign in the high bit of the swapped tens digit.
+    let quarter_hours = match timestamp.timezone {
+        tz if tz >= 80 => -(tz as i64 - 80),
+        tz => tz as i64,
+    };
+
+    let local = days * 86_400
+        + timestamp.hour as i64 * 3_600
+        + timestamp.minute as i64 * 60
+        + timestamp.second as i64;
+    u64::try_from(local - quarter_hours * 900).ok()
+}
+
+/// The number of GSM 7-bit text septets in user data, excluding the user data header.
+fn gsm7_text_septets(data: &GsmMessageData) -> usize {
+    let header_septets = match (data.udh, data.bytes.first()) {
+        (true, Some(udhl)) => ((*udhl as usize + 1) * 8).div_ceil(7),
+        _ => 0,
+    };
+    (data.user_data_len as usize).saturating_sub(header_septets)
+}
+
+/// Truncate decoded GSM 7-bit text to at most `septets`, counting escaped characters as two.
+fn truncate_to_septets(text: String, septets: usize) -> String {
+    let mut buffer = Vec::with_capacity(2);
+    let mut used = 0;
+    for (index, c) in text.char_indices() {
+        buffer.clear();
+        try_gsm_encode_char(c, &mut buffer);
+
+        used += buffer.len();
+        if used > septets {
+            return text[..index].to_string();
+        }
+    }
+    text
 }
 
 fn serialize_message_status<S>(status: &MessageStatus, serializer: S) -> Result<S::Ok, S::Error>
@@ -76,3 +281,91 @@ where
         serde::de::Error::custom(format!("Invalid MessageStatus value: 0x{value:02x}"))
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_parts(content: &str) -> Vec<String> {
+        GsmMessageData::encode_message(content)
+            .iter()
+            .map(|data| {
+                SMSIncomingMessage::decode(String::new(), data)
+                    .unwrap()
+                    .content
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_removes_fill_bits() {
+        // The final part is 50 septets after a 7 septet header, so has fill bits.
+        let content = format!("{}xyz", "b".repeat(200));
+        assert_eq!(decode_parts(&content).concat(), content);
+
+        let content = format!("{}user@", "a".repeat(310));
+        let parts = decode_parts(&content);
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts.concat(), content);
+
+        assert_eq!(decode_parts("user@"), vec!["user@".to_string()]);
+        assert_eq!(
+            decode_parts("Price: 10€ @"),
+            vec!["Price: 10€ @".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_decode_8bit_data() {
+        let data = GsmMessageData {
+            encoding: MessageEncoding::EightBit,
+            udh: true,
+            bytes: hex::decode("0605040b8423f000ff").unwrap(),
+            user_data_len: 9,
+        };
+        let message = SMSIncomingMessage::decode(String::new(), &data).unwrap();
+        assert_eq!(message.content, "00ff");
+        assert!(matches!(message.data_coding, SMSDataCoding::EightBit));
+
+        let component = &message.user_data_header.unwrap().components[0];
+        assert_eq!(
+            (component.id, component.data.clone()),
+            (0x05, vec![0x0b, 0x84, 0x23, 0xf0])
+        );
+    }
+
+    #[test]
+    fn test_smsc_timestamp_to_unix() {
+        let timestamp = |timezone| SmscTimestamp {
+            year: 24,
+            month: 3,
+            day: 5,
+            hour: 12,
+            minute: 34,
+            second: 56,
+            timezone,
+        };
+
+        // 12:34:56 at +01:00 and -05:00.
+        assert_eq!(smsc_timestamp_to_unix(&timestamp(4)), Some(1709638496));
+        assert_eq!(smsc_timestamp_to_unix(&timestamp(100)), Some(1709660096));
+
+        let invalid = SmscTimestamp {
+            month: 13,
+            ..timestamp(0)
+        };
+        assert_eq!(smsc_timestamp_to_unix(&invalid), None);
+    }
+
+    #[test]
+    fn test_civil_days_round_trip() {
+        for (date, days) in [
+            ((1970, 1, 1), 0),
+            ((2000, 2, 29), 11_016),
+            ((1969, 12, 31), -1),
+        ] {
+            assert_eq!(days_from_civil(date.0, date.1, date.2), days);
+            assert_eq!(civil_from_days(days), date);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+#![cfg_attr(not(feature = "http-server"), allow(dead_code))]
+
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+
+/// A piece of a message template, either literal text or a `{name}` placeholder.
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+/// Split a template into text and placeholders. Literal braces are written as `{{` and `}}`,
+/// and placeholder names may only contain ASCII letters, digits and underscores.
+fn tokenize(template: &str) -> Result<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut rest = template;
+    while let Some(idx) = rest.find(['{', '}']) {
+        if idx > 0 {
+            tokens.push(Token::Text(&rest[..idx]));
+        }
+
+        let brace = &rest[idx..=idx];
+        let after = &rest[idx + 1..];
+        if after.starts_with(brace) {
+            tokens.push(Token::Text(brace));
+            rest = &after[1..];
+            continue;
+        }
+        if brace == "}" {
+            bail!("Unmatched '}}', use '}}}}' for a literal brace");
+        }
+
+        let end = after
+            .find('}')
+            .ok_or_else(|| anyhow!("Unclosed '{{', use '{{{{' for a literal brace"))?;
+        let name = &after[..end];
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            bail!("Invalid placeholder '{{{name}}}'");
+        }
+        tokens.push(Token::Placeholder(name));
+        rest = &after[end + 1..];
+    }
+
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest));
+    }
+    Ok(tokens)
+}
+
+/// Check a template's syntax, so a bad template is rejected when the config is loaded.
+pub fn validate(template: &str) -> Result<()> {
+    tokenize(template).map(|_| ())
+}
+
+/// Get the placeholder names a template uses, in order of first use.
+pub fn placeholders(template: &str) -> Result<Vec<&str>> {
+    let mut names = Vec::new();
+    for token in tokenize(template)? {
+        match token {
+            Token::Placeholder(name) if !names.contains(&name) => names.push(name),
+            _ => {}
+        }
+    }
+    Ok(names)
+}
+
+/// Render a configured template, failing if it doesn't exist or any placeholder has no value.
+/// Vars that the template doesn't use are ignored.
+pub fn render(
+    templates: &HashMap<String, String>,
+    name: &str,
+    vars: &HashMap<String, String>,
+) -> Result<String> {
+    let template = templates
+        .get(name)
+        .ok_or_else(|| anyhow!("Unknown template '{name}'"))?;
+
+    let (content, missing) = substitute(template, vars)?;
+    if !missing.is_empty() {
+        bail!("Template '{name}' has no value for: {}", missing.join(", "));
+    }
+    Ok(content)
+}
+
+/// Render a template string, with placeholders that have no value left empty.
+pub fn render_lenient(template: &str, vars: &HashMap<String, String>) -> Result<String> {
+    substitute(template, vars).map(|(content, _)| content)
+}
+
+/// Fill in a template's placeholders, returning the content and any placeholders without a value.
+fn substitute<'a>(
+    template: &'a str,
+    vars: &HashMap<String, String>,
+) -> Result<(String, Vec<&'a str>)> {
+    let mut content = String::with_capacity(template.len());
+    let mut missing = Vec::new();
+    for token in tokenize(template)? {
+        match token {
+            Token::Text(text) => content.push_str(text),
+            Token::Placeholder(placeholder) => match vars.get(placeholder) {
+                Some(value) => content.push_str(value),
+                None if !missing.contains(&placeholder) => missing.push(placeholder),
+                None => {}
+            },
+        }
+    }
+    Ok((content, missing))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("{host} is {{down}} at {time}").unwrap(),
+            [
+                Token::Placeholder("host"),
+                Token::Text(" is "),
+                Token::Text("{"),
+                Token::Text("down"),
+                Token::Text("}"),
+                Token::Text(" at "),
+                Token::Placeholder("time"),
+            ]
+        );
+        assert_eq!(tokenize("").unwrap(), []);
+
+        for invalid in ["{host", "host}", "{}", "{host name}", "{a{b}"] {
+            assert!(validate(invalid).is_err(), "{invalid} should be invalid");
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let templates = map(&[("alert", "{host} is down ({host}, {service})")]);
+        let rendered = render(
+            &templates,
+            "alert",
+            &map(&[("host", "db1"), ("service", "{postgres}"), ("unused", "x")]),
+        )
+        .unwrap();
+        assert_eq!(rendered, "db1 is down (db1, {postgres})");
+
+        let error = render(&templates, "alert", &HashMap::new()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Template 'alert' has no value for: host, service"
+        );
+        assert!(render(&templates, "missing", &HashMap::new()).is_err());
+
+        let template = "{host} is down ({host}, {service})";
+        assert_eq!(placeholders(template).unwrap(), ["host", "service"]);
+        assert_eq!(
+            render_lenient(template, &map(&[("host", "db1")])).unwrap(),
+            "db1 is down (db1, )"
+        );
+    }
+}
@@ -1,27 +1,32 @@
-use anyhow::{anyhow, Error};
+use anyhow::{anyhow, bail};
+use base64::engine::general_purpose;
+use base64::Engine;
 use serde::{Deserialize, Serialize};
-use sms_pdu::pdu::{MessageStatus, PduAddress};
-use sqlx::FromRow;
-
-#[derive(Serialize, Deserialize, Clone, Debug, FromRow)]
-pub struct SMSMessage {
-    pub message_id: Option<i64>,
-    pub phone_number: String,
-    pub message_content: String,
-    pub message_reference: Option<u8>,
-    pub is_outgoing: bool,
-    pub status: SMSStatus,
-    pub created_at: Option<u64>,
-    pub completed_at: Option<u64>,
-}
-impl SMSMessage {
-    /// Returns a clone of the message with the message_id option replaced.
-    pub fn with_message_id(&self, id: Option<i64>) -> Self {
-        SMSMessage {
-            message_id: id,
-            ..self.clone()
-        }
-    }
+use sms_pdu::gsm_encoding;
+use sms_pdu::gsm_encoding::udh::{UdhComponent, UserDataHeader};
+use sms_pdu::gsm_encoding::GsmMessageData;
+use sms_pdu::pdu::{MessageEncoding, MessageStatus, PduAddress};
+use std::str::FromStr;
+use std::time::Duration;
+
+pub use sms_api_types::sms::{
+    BlocklistEntry, SMSContentEncoding, SMSConversation, SMSDataCoding, SMSDeliveryReport,
+    SMSEncoding, SMSMessage, SMSStatus,
+};
+
+/// The relative validity period requested for outgoing messages, 24 hours.
+pub const DEFAULT_VALIDITY_PERIOD: u8 = 167;
+
+/// Convert a relative TP-Validity-Period value into the duration it represents.
+/// Based on: https://www.etsi.org/deliver/etsi_ts/123000_123099/123040/16.00.00_60/ts_123040v160000p.pdf (9.2.3.12.1)
+pub const fn validity_period_to_duration(validity_period: u8) -> Duration {
+    let minutes = match validity_period {
+        0..=143 => (validity_period as u64 + 1) * 5,
+        144..=167 => 12 * 60 + (validity_period as u64 - 143) * 30,
+        168..=196 => (validity_period as u64 - 166) * 24 * 60,
+        _ => (validity_period as u64 - 192) * 7 * 24 * 60,
+    };
+    Duration::from_secs(minutes * 60)
 }
 
 #[derive(Debug)]
@@ -31,12 +36,304 @@ pub struct SMSOutgoingMessage {
     pub flash: bool,
     pub validity_period: Option<u8>,
     pub timeout: Option<u32>,
+    pub encoding: SMSEncoding,
+
+    /// For 8-bit data the content is hex encoded user data, sent after any user data header.
+    pub data_coding: SMSDataCoding,
+    pub user_data_header: Option<Vec<u8>>,
+
+    /// Send ahead of any normal queued modem commands.
+    pub priority: bool,
+
+    /// The configured modem to send with, otherwise one is picked automatically.
+    pub modem_id: Option<String>,
+
+    /// Encode the message but don't send it, responding with a fabricated reference.
+    pub dry_run: bool,
+
+    /// Send even if the phone number is on the blocklist.
+    pub override_blocklist: bool,
 }
 impl SMSOutgoingMessage {
-    pub fn get_validity_period(&self) -> u8 {
-        self.validity_period.unwrap_or(167) // 24hr
+    pub fn get_validity_period(&self, default_validity_period: u8) -> u8 {
+        self.validity_period.unwrap_or(default_validity_period)
+    }
+
+    /// Encode the content into one or more message segments, to be sent in order.
+    pub fn encode(&self) -> anyhow::Result<Vec<GsmMessageData>> {
+        match self.data_coding {
+            SMSDataCoding::Text => self.encoding.encode(&self.content),
+            SMSDataCoding::EightBit => encode_8bit_message(
+                &hex::decode(&self.content)?,
+                self.user_data_header.as_deref(),
+            ),
+        }
+    }
+}
+
+/// The send options of a queued message that its row doesn't store, kept until it's sent so it's
+/// sent as requested if recovered after a restart.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueuedSendOptions {
+    pub flash: bool,
+    pub validity_period: Option<u8>,
+    pub timeout: Option<u32>,
+    pub encoding: SMSEncoding,
+    pub user_data_header: Option<Vec<u8>>,
+    pub priority: bool,
+
+    /// The requested modem, rather than the one it was routed to.
+    pub modem_id: Option<String>,
+    pub override_blocklist: bool,
+}
+impl From<&SMSOutgoingMessage> for QueuedSendOptions {
+    fn from(outgoing: &SMSOutgoingMessage) -> Self {
+        Self {
+            flash: outgoing.flash,
+            validity_period: outgoing.validity_period,
+            timeout: outgoing.timeout,
+            encoding: outgoing.encoding,
+            user_data_header: outgoing.user_data_header.clone(),
+            priority: outgoing.priority,
+            modem_id: outgoing.modem_id.clone(),
+            override_blocklist: outgoing.override_blocklist,
+        }
+    }
+}
+impl QueuedSendOptions {
+    /// Rebuild the outgoing message from its stored row.
+    pub fn into_outgoing(self, message: &SMSMessage) -> anyhow::Result<SMSOutgoingMessage> {
+        Ok(SMSOutgoingMessage {
+            phone_number: PduAddress::from_str(&message.phone_number)?,
+            content: message.message_content.clone(),
+            flash: self.flash,
+            validity_period: self.validity_period,
+            timeout: self.timeout,
+            encoding: self.encoding,
+            data_coding: message.data_coding,
+            user_data_header: self.user_data_header,
+            priority: self.priority,
+            modem_id: self.modem_id,
+            dry_run: false,
+            override_blocklist: self.override_blocklist,
+        })
     }
 }
+
+/// Decode 8-bit message content given as hex or base64.
+pub fn decode_data_content(content: &str, encoding: SMSContentEncoding) -> anyhow::Result<Vec<u8>> {
+    match encoding {
+        SMSContentEncoding::Hex => {
+            hex::decode(content).map_err(|_| anyhow!("8-bit message content must be hex encoded"))
+        }
+        SMSContentEncoding::Base64 => general_purpose::STANDARD
+            .decode(content)
+            .map_err(|_| anyhow!("8-bit message content must be base64 encoded")),
+    }
+}
+
+/// Encode 8-bit data with an optional user data header (including its length octet), adding a
+/// concatenation element to the header of each part if it doesn't fit into a single message.
+fn encode_8bit_message(data: &[u8], udh: Option<&[u8]>) -> anyhow::Result<Vec<GsmMessageData>> {
+    let header = match udh {
+        Some([length, header @ ..]) if *length as usize == header.len() => header,
+        Some(_) => bail!("The user data header length octet doesn't match its length"),
+        None => &[],
+    };
+
+    let segment = |header: &[u8], part: &[u8]| {
+        let udh = !header.is_empty();
+        let mut bytes = Vec::with_capacity(header.len() + 1 + part.len());
+        if udh {
+            bytes.push(header.len() as u8);
+            bytes.extend_from_slice(header);
+        }
+        bytes.extend_from_slice(part);
+        GsmMessageData {
+            encoding: MessageEncoding::EightBit,
+            user_data_len: bytes.len() as u8,
+            bytes,
+            udh,
+        }
+    };
+
+    let udh_len = if header.is_empty() {
+        0
+    } else {
+        header.len() + 1
+    };
+    if udh_len + data.len() <= 140 {
+        return Ok(vec![segment(header, data)]);
+    }
+
+    // Each part's header gains a 5 byte concatenation element, and a length octet if it had none.
+    let part_len = 140usize.saturating_sub(header.len() + 6);
+    if part_len == 0 {
+        bail!("The user data header leaves no room for data in a concatenated message");
+    }
+    let parts = data.chunks(part_len).collect::<Vec<_>>();
+    if parts.len() > u8::MAX as usize {
+        bail!("8-bit message data is too long");
+    }
+
+    let reference = rand::random::<u8>();
+    Ok(parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| {
+            let mut part_header = header.to_vec();
+            part_header.extend_from_slice(&[0x00, 3, reference, parts.len() as u8, i as u8 + 1]);
+            segment(&part_header, part)
+        })
+        .collect())
+}
+
+/// Set the reference of the concatenation element in each part's user data header, which the
+/// handset uses to keep the parts of overlapping messages apart. Every encoder puts the header
+/// before the data as whole octets, so it can be rewritten after encoding.
+pub fn set_concatenation_reference(parts: &mut [GsmMessageData], reference: u8) {
+    for part in parts.iter_mut().filter(|part| part.udh) {
+        let Some((header_len, rest)) = part.bytes.split_first_mut() else {
+            continue;
+        };
+        let header_len = (*header_len as usize).min(rest.len());
+        let header = &mut rest[..header_len];
+
+        // Each information element is its id, a length octet and that many octets of data.
+        let mut offset = 0;
+        while offset + 2 < header.len() {
+            let (id, len) = (header[offset], header[offset + 1] as usize);
+            if id == 0x00 && len == 3 {
+                header[offset + 2] = reference;
+            }
+            offset += 2 + len;
+        }
+    }
+}
+
+/// Resolving and encoding content with an SMSEncoding, which needs the PDU encoder.
+pub trait SMSEncodingExt {
+    /// Get the encoding that content will actually be sent with, never `Auto`.
+    fn resolve(self, content: &str) -> anyhow::Result<SMSEncoding>;
+
+    /// Encode content into one or more message segments.
+    fn encode(self, content: &str) -> anyhow::Result<Vec<GsmMessageData>>;
+}
+impl SMSEncodingExt for SMSEncoding {
+    fn resolve(self, content: &str) -> anyhow::Result<SMSEncoding> {
+        let is_gsm7 = gsm_encoding::try_gsm_encode_string(content).is_some();
+        match self {
+            SMSEncoding::Auto if is_gsm7 => Ok(SMSEncoding::Gsm7),
+            SMSEncoding::Auto => Ok(SMSEncoding::Ucs2),
+            SMSEncoding::Gsm7 if !is_gsm7 => {
+                bail!("Message content contains characters that can't be encoded as GSM 7-bit")
+            }
+            encoding => Ok(encoding),
+        }
+    }
+
+    fn encode(self, content: &str) -> anyhow::Result<Vec<GsmMessageData>> {
+        // Forced GSM 7-bit content is representable, so encodes the same as auto.
+        Ok(match self.resolve(content)? {
+            SMSEncoding::Ucs2 if self == SMSEncoding::Ucs2 => encode_ucs2_message(content)?,
+            _ => GsmMessageData::encode_message(content),
+        })
+    }
+}
+
+/// Encode content as UCS2 even if it could be GSM 7-bit, splitting the same way as
+/// `GsmMessageData::encode_message` (140 bytes, or 134 bytes and a UDH when concatenated).
+/// Parts are split between characters, so a surrogate pair is never divided between two parts.
+fn encode_ucs2_message(content: &str) -> anyhow::Result<Vec<GsmMessageData>> {
+    let bytes = content
+        .encode_utf16()
+        .flat_map(u16::to_be_bytes)
+        .collect::<Vec<u8>>();
+
+    if bytes.len() <= 140 {
+        return Ok(vec![GsmMessageData {
+            encoding: MessageEncoding::Ucs2,
+            user_data_len: bytes.len() as u8,
+            bytes,
+            udh: false,
+        }]);
+    }
+
+    let mut parts = vec![Vec::with_capacity(134)];
+    for c in content.chars() {
+        let mut units = [0u16; 2];
+        let encoded = c.encode_utf16(&mut units);
+        if parts
+            .last()
+            .is_some_and(|part| part.len() + encoded.len() * 2 > 134)
+        {
+            parts.push(Vec::with_capacity(134));
+        }
+        if let Some(part) = parts.last_mut() {
+            part.extend(encoded.iter().flat_map(|unit| unit.to_be_bytes()));
+        }
+    }
+    if parts.len() > u8::MAX as usize {
+        bail!("Message content is too long, it would need more than 255 parts");
+    }
+
+    let reference = rand::random::<u8>();
+    Ok(parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| {
+            let udh = UserDataHeader {
+                components: vec![UdhComponent {
+                    id: 0,
+                    data: vec![reference, parts.len() as u8, i as u8 + 1],
+                }],
+            };
+            let mut bytes = udh.as_bytes();
+            bytes.extend_from_slice(part);
+            GsmMessageData {
+                encoding: MessageEncoding::Ucs2,
+                user_data_len: bytes.len() as u8,
+                bytes,
+                udh: true,
+            }
+        })
+        .collect())
+}
+
+/// How a message body would be encoded and split, without sending it.
+#[derive(Serialize, Debug)]
+pub struct SMSEncodingEstimate {
+    pub encoding: &'static str,
+    pub segments: usize,
+    pub chars_per_segment: usize,
+    pub total_chars: usize,
+}
+impl SMSEncodingEstimate {
+    /// Uses the same encoder as sending, so GSM 7-bit is only used if every character fits.
+    pub fn from_content(content: &str) -> Self {
+        let segments = gsm_encoding::GsmMessageData::encode_message(content);
+        let is_ucs2 = segments
+            .first()
+            .is_some_and(|data| matches!(data.encoding, MessageEncoding::Ucs2));
+
+        // Concatenated messages lose space to the user data header.
+        let concatenated = segments.len() > 1;
+        let (encoding, chars_per_segment) = match (is_ucs2, concatenated) {
+            (false, false) => ("gsm7", 160),
+            (false, true) => ("gsm7", 153),
+            (true, false) => ("ucs2", 70),
+            (true, true) => ("ucs2", 67),
+        };
+
+        Self {
+            encoding,
+            segments: segments.len(),
+            chars_per_segment,
+            total_chars: content.chars().count(),
+        }
+    }
+}
+
 impl From<&SMSOutgoingMessage> for SMSMessage {
     fn from(outgoing: &SMSOutgoingMessage) -> Self {
         SMSMessage {
@@ -48,59 +345,222 @@ impl From<&SMSOutgoingMessage> for SMSMessage {
             status: SMSStatus::Sent,
             created_at: None,
             completed_at: None,
+            modem_id: None,
+            raw_pdu: None,
+            data_coding: outgoing.data_coding,
+            is_read: true,
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub enum SMSStatus {
-    Sent,
-    Delivered,
-    Received,
-    TemporaryFailure,
-    PermanentFailure,
-}
-impl From<&SMSStatus> for u8 {
-    fn from(status: &SMSStatus) -> Self {
-        match status {
-            SMSStatus::Sent => 0,
-            SMSStatus::Delivered => 1,
-            SMSStatus::Received => 2,
-            SMSStatus::TemporaryFailure => 3,
-            SMSStatus::PermanentFailure => 4,
-        }
+/// Map a delivery report TP-Status onto the stored message status.
+pub fn sms_status_from_message_status(status: &MessageStatus) -> SMSStatus {
+    if status.is_success() {
+        SMSStatus::Received
+    } else if status.is_temporary_error() {
+        SMSStatus::TemporaryFailure
+    } else {
+        SMSStatus::PermanentFailure
     }
 }
-impl From<&MessageStatus> for SMSStatus {
-    fn from(status: &MessageStatus) -> Self {
-        if status.is_success() {
-            SMSStatus::Received
-        } else if status.is_temporary_error() {
-            SMSStatus::TemporaryFailure
-        } else {
-            SMSStatus::PermanentFailure
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validity_period_to_duration() {
+        // 5 minute intervals
+        assert_eq!(validity_period_to_duration(0), Duration::from_secs(5 * 60));
+        assert_eq!(
+            validity_period_to_duration(143),
+            Duration::from_secs(12 * 3600)
+        );
+
+        // 30 minute intervals
+        assert_eq!(
+            validity_period_to_duration(144),
+            Duration::from_secs(12 * 3600 + 30 * 60)
+        );
+        assert_eq!(
+            validity_period_to_duration(DEFAULT_VALIDITY_PERIOD),
+            Duration::from_secs(24 * 3600),
+            "Expected default validity period to be 24 hours"
+        );
+
+        // Day and week intervals
+        assert_eq!(
+            validity_period_to_duration(168),
+            Duration::from_secs(2 * 86400)
+        );
+        assert_eq!(
+            validity_period_to_duration(196),
+            Duration::from_secs(30 * 86400)
+        );
+        assert_eq!(
+            validity_period_to_duration(197),
+            Duration::from_secs(5 * 7 * 86400)
+        );
+        assert_eq!(
+            validity_period_to_duration(255),
+            Duration::from_secs(63 * 7 * 86400)
+        );
+    }
+
+    #[test]
+    fn test_sms_encoding_override() {
+        assert_eq!(
+            SMSEncoding::Auto.resolve("Hello").unwrap(),
+            SMSEncoding::Gsm7
+        );
+        assert_eq!(
+            SMSEncoding::Auto.resolve("Hello 👋").unwrap(),
+            SMSEncoding::Ucs2
+        );
+        assert_eq!(
+            SMSEncoding::Ucs2.resolve("Hello").unwrap(),
+            SMSEncoding::Ucs2
+        );
+        assert!(SMSEncoding::Gsm7.resolve("Hello 👋").is_err());
+
+        // Forcing UCS2 on GSM-safe content matches the automatic UCS2 split.
+        let segments = SMSEncoding::Ucs2.encode("Hello").unwrap();
+        assert_eq!(segments.len(), 1);
+        assert!(matches!(segments[0].encoding, MessageEncoding::Ucs2));
+        assert_eq!(segments[0].bytes.len(), 10);
+
+        let segments = SMSEncoding::Ucs2.encode(&"a".repeat(71)).unwrap();
+        let expected = GsmMessageData::encode_message(&format!("{}👋", "a".repeat(69)));
+        assert_eq!(segments.len(), expected.len());
+        assert!(segments.iter().all(|segment| segment.udh));
+        assert_eq!(segments[0].user_data_len, expected[0].user_data_len);
+
+        // A surrogate pair that would straddle a part boundary starts the next part instead.
+        let segments = SMSEncoding::Ucs2
+            .encode(&format!("{}👋{}", "a".repeat(66), "a".repeat(10)))
+            .unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].bytes.len(), 6 + 132);
+        assert_eq!(segments[1].bytes[6..10], [0xd8, 0x3d, 0xdc, 0x4b]);
+
+        // Concatenated messages are limited to 255 parts.
+        assert!(SMSEncoding::Ucs2.encode(&"a".repeat(67 * 255)).is_ok());
+        assert!(SMSEncoding::Ucs2.encode(&"a".repeat(67 * 255 + 1)).is_err());
+    }
+
+    #[test]
+    fn test_8bit_message_encoding() {
+        let hex = SMSContentEncoding::Hex;
+        let base64 = SMSContentEncoding::Base64;
+        assert_eq!(decode_data_content("00ff", hex).unwrap(), vec![0x00, 0xff]);
+        assert_eq!(
+            decode_data_content("AP8=", base64).unwrap(),
+            vec![0x00, 0xff]
+        );
+        assert!(decode_data_content("AP8=", hex).is_err());
+        assert!(decode_data_content("not data!", base64).is_err());
+
+        // Content that is valid in both is decoded as the encoding given, never guessed.
+        assert_eq!(decode_data_content("abcd", hex).unwrap(), vec![0xab, 0xcd]);
+        assert_eq!(
+            decode_data_content("abcd", base64).unwrap(),
+            vec![0x69, 0xb7, 0x1d]
+        );
+
+        let single = encode_8bit_message(&[0xab; 140], None).unwrap();
+        assert_eq!(single.len(), 1);
+        assert!(!single[0].udh);
+        assert_eq!(single[0].user_data_len, 140);
+
+        // The supplied header is kept in each part, with a concatenation element added.
+        let udh = hex::decode("0605040b8423f0").unwrap();
+        let parts = encode_8bit_message(&[0xab; 200], Some(&udh)).unwrap();
+        assert_eq!(parts.len(), 2);
+        for (i, part) in parts.iter().enumerate() {
+            assert!(part.udh);
+            assert!(matches!(part.encoding, MessageEncoding::EightBit));
+            assert_eq!(part.bytes[..7], [0x0b, 0x05, 0x04, 0x0b, 0x84, 0x23, 0xf0]);
+            assert_eq!(part.bytes[7..10], [0x00, 0x03, part.bytes[9]]);
+            assert_eq!(part.bytes[10..12], [2, i as u8 + 1]);
+            assert_eq!(part.user_data_len as usize, part.bytes.len());
         }
+        assert_eq!(parts[0].bytes.len(), 140);
+        assert_eq!(parts[0].bytes.len() + parts[1].bytes.len() - 24, 200);
+
+        assert!(encode_8bit_message(&[0xab], Some(&[0x05, 0x04])).is_err());
     }
-}
-impl TryFrom<u8> for SMSStatus {
-    type Error = Error;
 
-    fn try_from(value: u8) -> anyhow::Result<Self, Self::Error> {
-        match value {
-            0 => Ok(SMSStatus::Sent),
-            1 => Ok(SMSStatus::Delivered),
-            2 => Ok(SMSStatus::Received),
-            3 => Ok(SMSStatus::TemporaryFailure),
-            4 => Ok(SMSStatus::PermanentFailure),
-            _ => Err(anyhow!("Invalid SMS status value: {}", value)),
+    #[test]
+    fn test_set_concatenation_reference() {
+        let mut parts = GsmMessageData::encode_message(&"a".repeat(200));
+        parts.extend(encode_ucs2_message(&"a".repeat(71)).unwrap());
+        let udh = hex::decode("0605040b8423f0").unwrap();
+        parts.extend(encode_8bit_message(&[0xab; 200], Some(&udh)).unwrap());
+        let data = parts
+            .iter()
+            .map(|part| part.bytes[part.bytes[0] as usize + 1..].to_vec())
+            .collect::<Vec<_>>();
+
+        set_concatenation_reference(&mut parts, 0x42);
+        for (part, data) in parts.iter().zip(data) {
+            let header = &part.bytes[1..part.bytes[0] as usize + 1];
+            assert_eq!(
+                header[header.len() - 5..header.len() - 2],
+                [0x00, 0x03, 0x42]
+            );
+            assert_eq!(part.bytes[header.len() + 1..], data);
         }
+
+        // The port addressing element before the concatenation element is left alone.
+        assert_eq!(
+            parts[4].bytes[..7],
+            [0x0b, 0x05, 0x04, 0x0b, 0x84, 0x23, 0xf0]
+        );
+
+        // A single part message has no concatenation element to set.
+        let mut single = encode_8bit_message(&[0xab; 10], Some(&udh)).unwrap();
+        set_concatenation_reference(&mut single, 0x42);
+        assert_eq!(
+            single[0].bytes[..7],
+            [0x06, 0x05, 0x04, 0x0b, 0x84, 0x23, 0xf0]
+        );
     }
-}
 
-#[derive(Serialize, Deserialize, FromRow)]
-pub struct SMSDeliveryReport {
-    pub report_id: Option<i64>,
-    pub status: u8,
-    pub is_final: bool,
-    pub created_at: Option<u64>,
+    #[test]
+    fn test_sms_encoding_estimate() {
+        let estimate = SMSEncodingEstimate::from_content("Hello world");
+        assert_eq!(estimate.encoding, "gsm7");
+        assert_eq!(estimate.segments, 1);
+        assert_eq!(estimate.chars_per_segment, 160);
+
+        let estimate = SMSEncodingEstimate::from_content(&"a".repeat(161));
+        assert_eq!(estimate.encoding, "gsm7");
+        assert_eq!(estimate.segments, 2);
+        assert_eq!(estimate.chars_per_segment, 153);
+
+        // A single emoji forces the whole message into UCS2.
+        let estimate = SMSEncodingEstimate::from_content("Hello 👋");
+        assert_eq!(estimate.encoding, "ucs2");
+        assert_eq!(estimate.segments, 1);
+        assert_eq!(estimate.chars_per_segment, 70);
+        assert_eq!(estimate.total_chars, 7);
+
+        // Accented characters in the GSM alphabet don't.
+        let estimate = SMSEncodingEstimate::from_content("Café");
+        assert_eq!(estimate.encoding, "gsm7");
+
+        let estimate = SMSEncodingEstimate::from_content(&"ж".repeat(71));
+        assert_eq!(estimate.encoding, "ucs2");
+        assert_eq!(estimate.segments, 2);
+        assert_eq!(estimate.chars_per_segment, 67);
+    }
+
+    #[test]
+    fn test_sms_status_round_trip() {
+        for value in 0..=7u8 {
+            let status = SMSStatus::try_from(value).unwrap();
+            assert_eq!(u8::from(&status), value);
+        }
+        assert!(SMSStatus::try_from(8).is_err());
+    }
 }